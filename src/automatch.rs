@@ -0,0 +1,195 @@
+use std::{path::{Path, PathBuf}, sync::mpsc::{Receiver, channel}, time::{Duration, Instant}};
+
+use eframe::egui;
+
+use crate::{data::RiderDataJSON, gpx::{Point, Track, haversine_distance}, library::{CourseInfo, CourseLibrary, scan_courses},
+            ui::frame::read_rider_data, units::Units};
+
+/// How often the broadcast file is read while looking for a ride to match.
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Only rides which have just started are matched, as the library only records where each course starts.
+const MAX_RIDDEN: f64 = 2000.0; // metres
+/// A course matches when it starts this close to where the rider started ...
+const START_RADIUS: f64 = 250.0; // metres
+/// ... and its length is within this fraction of the event distance (or one lap of it).
+const LENGTH_TOLERANCE: f64 = 0.05;
+
+/// Start of the ride from the broadcast data.
+#[derive(Debug, Clone, Copy)]
+struct RideStart
+{
+   position:       Point,
+   ridden:         f64, // metres
+   event_distance: f64, // metres, 0 when not in an event
+   event_laps:     i32,
+}
+
+impl RideStart
+{
+   fn from_broadcast(rider: &RiderDataJSON) -> Option<Self>
+   //------------------------------------------------------
+   {
+      let ridden = rider.distance as f64;
+      if (rider.latitude == 0.0 && rider.longitude == 0.0) || !(0.0..=MAX_RIDDEN).contains(&ridden)
+      {
+         return None;
+      }
+      Some(Self { position: Point { lat: rider.latitude, lon: rider.longitude }, ridden,
+                  event_distance: rider.event_distance_total.max(0) as f64, event_laps: rider.event_laps_total.max(1) })
+   }
+
+   /// How well a course starting at `start` and `length` metres long fits the ride (lower is better), None if not at
+   /// all.
+   fn score(&self, start: Point, length: f64) -> Option<f64>
+   //-------------------------------------------------------
+   {
+      // The rider may have ridden up to `ridden` metres away from the start already
+      let offset = (haversine_distance(start, self.position) - self.ridden).max(0.0);
+      if offset > START_RADIUS || length <= 0.0
+      {
+         return None;
+      }
+      let length_error = if self.event_distance > 0.0
+      {
+         let error = |expected: f64| (length - expected).abs() / expected;
+         let error = error(self.event_distance).min(error(self.event_distance / self.event_laps as f64));
+         if error > LENGTH_TOLERANCE
+         {
+            return None;
+         }
+         error
+      }
+      else { LENGTH_TOLERANCE / 2.0 }; // free ride, so only the start can be compared
+      Some(offset / START_RADIUS + length_error / LENGTH_TOLERANCE)
+   }
+}
+
+enum MatchState
+{
+   Watching,
+   Scanning(Receiver<Option<CourseInfo>>),
+   Offered(CourseInfo),
+   Done, // for this ride
+}
+
+/// Watches the broadcast file for a new ride when no course, or a course which does not fit the ride, is open and
+/// offers to open the best match from the course library (by where it starts and its length).
+pub struct CourseMatcher
+//======================
+{
+   state:      MatchState,
+   last_check: Option<Instant>,
+   ride:       Option<(Option<PathBuf>, f64)>, // open course and distance ridden when the ride was last checked
+}
+
+impl Default for CourseMatcher
+{
+   fn default() -> Self { Self { state: MatchState::Watching, last_check: None, ride: None } }
+}
+
+impl CourseMatcher
+{
+   /// Check the ride against the `loaded` course and `track` while riding (not simulating), returning a course to
+   /// open when the user accepts the offer.
+   pub fn update(&mut self, ctx: &egui::Context, loaded: Option<&Path>, track: &Track, units: Units) -> Option<PathBuf>
+   //------------------------------------------------------------------------------------------------------------------
+   {
+      match &mut self.state
+      {
+         | MatchState::Watching | MatchState::Done => self.watch(ctx, loaded, track),
+         | MatchState::Scanning(receiver) =>
+         {
+            match receiver.try_recv()
+            {
+               | Ok(Some(course)) if loaded != Some(course.path.as_path()) =>
+               {
+                  log_info!("Broadcast ride matches course {} ({})", course.name, course.path.display());
+                  self.state = MatchState::Offered(course);
+               }
+               | Ok(_) | Err(std::sync::mpsc::TryRecvError::Disconnected) => self.state = MatchState::Done,
+               | Err(std::sync::mpsc::TryRecvError::Empty) => (),
+            }
+            None
+         }
+         | MatchState::Offered(course) =>
+         {
+            let (mut is_accepted, mut is_dismissed) = (false, false);
+            egui::Window::new("Matching course found")
+               .collapsible(false)
+               .resizable(false)
+               .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 80.0))
+               .show(ctx, |ui|
+               {
+                  let what = if loaded.is_some() { "The ride does not match the open course, but" } else { "The ride matches" };
+                  ui.label(format!("{what} {} ({}, {}) from the course library starts here.", course.name,
+                                   units.format_distance(course.distance, 1), units.format_length(course.elevation_gain)));
+                  ui.horizontal(|ui|
+                  {
+                     is_accepted = ui.button("Open course").clicked();
+                     is_dismissed = ui.button("Not now").clicked();
+                  });
+               });
+            if is_accepted || is_dismissed
+            {
+               let path = course.path.clone();
+               self.state = MatchState::Done;
+               return is_accepted.then_some(path);
+            }
+            None
+         }
+      }
+   }
+
+   /// Read the broadcast file every CHECK_INTERVAL and scan the library when a new ride does not fit the open course.
+   fn watch(&mut self, ctx: &egui::Context, loaded: Option<&Path>, track: &Track) -> Option<PathBuf>
+   //-----------------------------------------------------------------------------------------------
+   {
+      ctx.request_repaint_after(CHECK_INTERVAL); // nothing else may be repainting without a course open
+      if self.last_check.is_some_and(|time| time.elapsed() < CHECK_INTERVAL)
+      {
+         return None;
+      }
+      self.last_check = Some(Instant::now());
+      let Some(ride) = read_rider_data(1, Duration::ZERO).as_ref().and_then(RideStart::from_broadcast) else { return None; };
+      let is_new_ride = match &self.ride
+      {
+         | Some((course, ridden)) => course.as_deref() != loaded || ride.ridden < *ridden,
+         | None => true,
+      };
+      self.ride = Some((loaded.map(Path::to_path_buf), ride.ridden));
+      if !is_new_ride && matches!(self.state, MatchState::Done)
+      {
+         return None;
+      }
+      if loaded.is_some() && !track.is_empty() && ride.score(track.point(0).point, track.total_distance()).is_some()
+      {  // The open course fits
+         self.state = MatchState::Done;
+         return None;
+      }
+      let directory = CourseLibrary::courses_directory();
+      if !directory.is_dir()
+      {
+         self.state = MatchState::Done;
+         return None;
+      }
+      let (sender, receiver) = channel();
+      let ctxx = ctx.clone();
+      std::thread::spawn(move ||
+      {
+         let courses = scan_courses(&directory).inspect_err(|e| log_warn!("Could not scan for a matching course: {e}"))
+                                               .unwrap_or_default();
+         let best = courses.into_iter()
+                           .filter_map(|course|
+                           {
+                              let (lat, lon) = course.start?;
+                              ride.score(Point { lat, lon }, course.distance).map(|score| (score, course))
+                           })
+                           .min_by(|a, b| a.0.total_cmp(&b.0))
+                           .map(|(_, course)| course);
+         let _ = sender.send(best);
+         ctxx.request_repaint();
+      });
+      self.state = MatchState::Scanning(receiver);
+      None
+   }
+}
@@ -0,0 +1,1002 @@
+use std::{fs::{self, File}, io::{BufWriter, IsTerminal, Write}, path::{Path, PathBuf}, time::Duration};
+
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use crate::{garmin::{GARMIN_TOKEN_VARIABLE, GarminConnect},
+            geojson::track_to_geojson,
+            gradients::{GRADIENT_EDGES, GRADIENT_STEP, STEEPEST_LENGTH, STEEPEST_STEP, band_label},
+            gpx::{Track, TrackPoint, course_name, elevation_gain_loss, find_climbs, find_closest_point, gradient_at, haversine_length,
+                  process_gpx, track_data_from_gpx},
+            http, import::read_course_file, komoot::{self, Komoot},
+            precache::{OSM_OFFLINE_TILE_LIMIT, StreetViewPrecache, corridor_tiles, download_tiles},
+            settings::{Settings, TILE_CACHE},
+            ui::frame::{ProfileStyle, draw_profile, fetch_bytes_from_url, streetview_url, test_streetview_api_key}, units::Units,
+            validate::{Issue, Severity, ValidationLimits, validate_gpx}};
+
+/// Commands that run without starting the user interface.
+#[derive(Subcommand, Debug)]
+pub enum Command
+{
+   /// Render course images
+   #[command(subcommand)]
+   Render(RenderCommand),
+
+   /// Print distance, elevation, gradient and climb statistics for GPX files
+   Stats(StatsArgs),
+
+   /// Check course files for problems, exiting with a nonzero status if any are found
+   Validate(ValidateArgs),
+
+   /// Convert TCX, FIT and GeoJSON course files (or a directory of them) to GPX
+   Convert(ConvertArgs),
+
+   /// Export a course as GeoJSON with per-segment gradients for web maps
+   Export(ExportArgs),
+
+   /// Download data for a course ahead of a ride
+   #[command(subcommand)]
+   Precache(PrecacheCommand),
+
+   /// List and download courses from a Garmin Connect account into the course library
+   #[command(subcommand)]
+   Garmin(GarminCommand),
+
+   /// Connect a Komoot account, whose planned tours can then be opened from the course library
+   #[command(subcommand)]
+   Komoot(KomootCommand),
+
+   /// Manage the Street View API key, which is stored encrypted for this machine and user
+   #[command(subcommand)]
+   Key(KeyCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand
+{
+   /// Encrypt a Street View API key into the settings
+   Set(KeySetArgs),
+
+   /// Print the Street View API key, masked unless --reveal is given
+   Show(KeyShowArgs),
+
+   /// Remove the Street View API key from the settings
+   Clear,
+
+   /// Check that the Street View API key (or KEY) is accepted by Google, exiting with a nonzero status if not
+   Test(KeyTestArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct KeySetArgs
+{
+   /// The API key, read from standard input when omitted so it doesn't appear in the process list or shell history
+   key: Option<String>,
+
+   /// Check the key with Google before saving it
+   #[arg(long)]
+   test: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyShowArgs
+{
+   /// Print the whole key instead of only its first and last characters
+   #[arg(long)]
+   reveal: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyTestArgs
+{
+   /// Key to test instead of the one in the settings
+   key: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RenderCommand
+{
+   /// Gradient coloured elevation profile of a whole course as a PNG, e.g. for event pages
+   Profile(ProfileArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PrecacheCommand
+{
+   /// Street View images along a course, used by the Street View in place of requests during the ride
+   #[command(name = "streetview")]
+   StreetView(StreetViewArgs),
+
+   /// Map tiles along a course for riding the Map view without a connection
+   Tiles(TilesArgs),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GarminCommand
+{
+   /// List the courses in the account
+   List(GarminArgs),
+
+   /// Download courses (by id, see list) as GPX files
+   Download(GarminDownloadArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GarminArgs
+{
+   /// Garmin Connect OAuth2 access token (defaults to the GARMIN_CONNECT_TOKEN environment variable)
+   #[arg(long)]
+   token: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct GarminDownloadArgs
+{
+   #[command(flatten)]
+   account: GarminArgs,
+
+   /// Course ids
+   #[arg(required_unless_present = "all")]
+   ids: Vec<u64>,
+
+   /// Download every course in the account
+   #[arg(long)]
+   all: bool,
+
+   /// Directory for the GPX files (defaults to the course library directory)
+   #[arg(short = 'o', long = "output")]
+   output: Option<PathBuf>,
+
+   /// Replace existing GPX files
+   #[arg(long)]
+   overwrite: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KomootCommand
+{
+   /// Sign in to Komoot in a browser and save the authorisation in the settings
+   Login(KomootLoginArgs),
+
+   /// List the planned tours in the connected account
+   List,
+}
+
+#[derive(Args, Debug)]
+pub struct KomootLoginArgs
+{
+   /// Komoot API client id (register http://127.0.0.1:8687/callback as its redirect URI)
+   #[arg(long)]
+   client_id: String,
+
+   /// Komoot API client secret
+   #[arg(long)]
+   client_secret: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ProfileArgs
+{
+   /// GPX file
+   file: PathBuf,
+
+   /// Output PNG file (defaults to the GPX file with a .png extension)
+   #[arg(short = 'o', long = "output")]
+   output: Option<PathBuf>,
+
+   /// Image width in pixels
+   #[arg(long, default_value_t = 1920)]
+   width: u32,
+
+   /// Image height in pixels
+   #[arg(long, default_value_t = 480)]
+   height: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs
+{
+   /// GPX files
+   #[arg(required = true)]
+   files: Vec<PathBuf>,
+
+   /// Print JSON (distances in metres) instead of a table
+   #[arg(long)]
+   json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs
+{
+   /// Course files
+   #[arg(required = true)]
+   files: Vec<PathBuf>,
+
+   /// Largest allowed distance between consecutive points in metres
+   #[arg(long, default_value_t = ValidationLimits::default().max_gap)]
+   max_gap: f64,
+
+   /// Largest allowed single point elevation spike in metres
+   #[arg(long, default_value_t = ValidationLimits::default().max_spike)]
+   max_spike: f64,
+
+   /// Also fail on warnings (multiple track segments, repeated points)
+   #[arg(long)]
+   strict: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConvertArgs
+{
+   /// TCX, FIT or GeoJSON files or directories containing them
+   #[arg(required = true)]
+   inputs: Vec<PathBuf>,
+
+   /// Directory for the GPX files (defaults to beside each input file)
+   #[arg(short = 'o', long = "output")]
+   output: Option<PathBuf>,
+
+   /// Replace existing GPX files
+   #[arg(long)]
+   overwrite: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs
+{
+   /// Course file (GPX, TCX, FIT or GeoJSON)
+   file: PathBuf,
+
+   /// GeoJSON file to write (defaults to the course file with a .geojson extension)
+   #[arg(short = 'o', long = "output")]
+   output: Option<PathBuf>,
+
+   /// Length of the gradient segments in metres
+   #[arg(long, default_value_t = 100.0)]
+   segment: f64,
+}
+
+#[derive(Args, Debug)]
+pub struct StreetViewArgs
+{
+   /// GPX file
+   file: PathBuf,
+
+   /// Distance between images in metres
+   #[arg(long, default_value_t = 50.0)]
+   interval: f64,
+
+   /// Image width in pixels (at most 640)
+   #[arg(long, default_value_t = 640)]
+   width: u32,
+
+   /// Image height in pixels (at most 640)
+   #[arg(long, default_value_t = 480)]
+   height: u32,
+
+   /// Price in US dollars per 1000 images, for the cost estimate
+   #[arg(long, default_value_t = 7.0)]
+   price: f64,
+
+   /// Only report coverage and cost, without downloading
+   #[arg(long)]
+   dry_run: bool,
+
+   /// Download without asking for confirmation
+   #[arg(short = 'y', long)]
+   yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TilesArgs
+{
+   /// GPX file
+   file: PathBuf,
+
+   /// Lowest zoom level
+   #[arg(long, default_value_t = 10)]
+   min_zoom: u8,
+
+   /// Highest zoom level (the OpenStreetMap tile policy limits downloads at 13 and above)
+   #[arg(long, default_value_t = 14)]
+   max_zoom: u8,
+
+   /// Width of the corridor either side of the course in metres
+   #[arg(long, default_value_t = 300.0)]
+   corridor: f64,
+
+   /// Only report the number of tiles, without downloading
+   #[arg(long)]
+   dry_run: bool,
+
+   /// Download without asking for confirmation
+   #[arg(short = 'y', long)]
+   yes: bool,
+}
+
+/// Run a command line command. Colours, gradient thresholds, vertical exaggeration and units come from the settings
+/// (including any --set overrides).
+pub fn run(command: Command, settings: &Settings) -> Result<(), String>
+//--------------------------------------------------------------------
+{
+   match command
+   {
+      | Command::Render(RenderCommand::Profile(args)) => render_profile(&args, settings),
+      | Command::Stats(args) => print_stats(&args, settings.units),
+      | Command::Validate(args) => validate_files(&args, settings.units),
+      | Command::Convert(args) => convert_files(&args),
+      | Command::Export(args) => export_geojson(&args),
+      | Command::Precache(PrecacheCommand::StreetView(args)) => precache_streetview(&args, settings),
+      | Command::Precache(PrecacheCommand::Tiles(args)) => precache_tiles(&args, settings),
+      | Command::Garmin(GarminCommand::List(args)) => list_garmin_courses(&args, settings),
+      | Command::Garmin(GarminCommand::Download(args)) => download_garmin_courses(&args, settings),
+      | Command::Komoot(KomootCommand::Login(args)) => komoot_login(&args, settings),
+      | Command::Komoot(KomootCommand::List) => list_komoot_tours(settings),
+      | Command::Key(KeyCommand::Set(args)) => set_api_key(&args, settings),
+      | Command::Key(KeyCommand::Show(args)) => show_api_key(&args, settings),
+      | Command::Key(KeyCommand::Clear) => clear_api_key(settings),
+      | Command::Key(KeyCommand::Test(args)) => test_api_key(&args, settings),
+   }
+}
+
+fn render_profile(args: &ProfileArgs, settings: &Settings) -> Result<(), String>
+//------------------------------------------------------------------------------
+{
+   if args.width < 200 || args.height < 150
+   {
+      return Err("The image must be at least 200x150 pixels.".to_string());
+   }
+   let track = process_gpx(&args.file.to_string_lossy()).map_err(|e| format!("Error reading {}: {}", args.file.display(), e))?;
+   let total_distance = track.last().map_or(0.0, |p| p.distance);
+   let theme = settings.active_theme();
+   let style = ProfileStyle
+   {
+      flat_gradient: settings.flat_gradient_percentage,
+      extreme_gradient: settings.extreme_gradient_percentage,
+      vertical_exaggeration: settings.vertical_exaggeration,
+      background: theme.gradient_background,
+      label_color: theme.gradient_label,
+      label_width: label_spacing(total_distance, args.width as f64 / 200.0),
+      units: settings.units,
+      is_patterned: settings.gradient_patterns,
+      surfaces: Vec::new(),
+   };
+   let mut pixmap = draw_profile(&track, 0.0, total_distance, args.width as f32, args.height as f32, &style)?;
+   // The renderer draws with red and blue swapped for egui (see Theme::skia_color), so swap them back for the PNG.
+   for pixel in pixmap.data_mut().chunks_exact_mut(4)
+   {
+      pixel.swap(0, 2);
+   }
+   let output = args.output.clone().unwrap_or_else(|| args.file.with_extension("png"));
+   pixmap.save_png(&output).map_err(|e| format!("Error writing {}: {}", output.display(), e))?;
+   println!("Wrote {} ({}x{}, {})", output.display(), args.width, args.height, settings.units.format_distance(total_distance, 1));
+   Ok(())
+}
+
+/// A 1, 2 or 5 × 10ⁿ metre spacing giving roughly `count` distance labels over `distance`.
+fn label_spacing(distance: f64, count: f64) -> f64
+//------------------------------------------------
+{
+   let raw = (distance / count.max(1.0)).max(1.0);
+   let magnitude = 10f64.powf(raw.log10().floor());
+   [1.0, 2.0, 5.0, 10.0].into_iter().map(|m| m * magnitude).find(|step| *step >= raw).unwrap_or(raw)
+}
+
+/// Statistics printed by the `stats` command. Distances and elevations are in metres, gradients in percent.
+#[derive(Serialize, Debug)]
+struct CourseStats
+{
+   file:               String,
+   points:             usize,
+   distance:           f64, // ECEF, as used by GPXAssist
+   distance_haversine: f64,
+   elevation_gain:     f64,
+   elevation_loss:     f64,
+   max_gradient:       f64, // over 100m
+   min_gradient:       f64,
+   average_climbing:   f64, // gain over the uphill distance
+   steepest_500m:      Option<SteepestStats>,
+   gradient_bands:     Vec<BandStats>,
+   climbs:             Vec<ClimbStats>,
+}
+
+#[derive(Serialize, Debug)]
+struct SteepestStats
+{
+   start:    f64,
+   gradient: f64,
+}
+
+#[derive(Serialize, Debug)]
+struct BandStats
+{
+   band:     String,
+   distance: f64,
+   percent:  f64, // of the course distance
+   gain:     f64,
+}
+
+#[derive(Serialize, Debug)]
+struct ClimbStats
+{
+   start:            f64,
+   length:           f64,
+   gain:             f64,
+   average_gradient: f64,
+   max_gradient:     f64,
+}
+
+fn course_stats(file: &Path, track: &[TrackPoint]) -> CourseStats
+//---------------------------------------------------------------------------
+{
+   let distance = track.last().map_or(0.0, |p| p.distance);
+   let (elevation_gain, elevation_loss) = elevation_gain_loss(track, 2.0);
+   let mut max_gradient = 0.0f64;
+   let mut min_gradient = 0.0f64;
+   let mut position = 50.0;
+   while position < distance
+   {
+      let gradient = gradient_at(track, position, 100.0);
+      max_gradient = max_gradient.max(gradient);
+      min_gradient = min_gradient.min(gradient);
+      position += 50.0;
+   }
+   let (rise, run) = track.windows(2)
+                          .filter(|w| w[1].altitude > w[0].altitude)
+                          .fold((0.0, 0.0), |(rise, run), w| (rise + w[1].altitude - w[0].altitude, run + w[1].distance - w[0].distance));
+   let climbs = find_climbs(track, 20.0, 2.0).into_iter()
+                                             .map(|c| ClimbStats { start: c.start, length: c.length(), gain: c.gain,
+                                                                   average_gradient: c.average_gradient, max_gradient: c.max_gradient })
+                                             .collect();
+   let columns = Track::from(track);
+   let steepest = columns.steepest_window(STEEPEST_LENGTH, STEEPEST_STEP);
+   let gradient_bands = columns.gradient_distribution(&GRADIENT_EDGES, GRADIENT_STEP).iter().rev()
+                               .map(|band| BandStats { band: band_label(band), distance: band.distance, gain: band.gain,
+                                                       percent: if distance > 0.0 { band.distance / distance * 100.0 } else { 0.0 } })
+                               .collect();
+   CourseStats
+   {
+      file: file.display().to_string(),
+      points: track.len(),
+      distance,
+      distance_haversine: haversine_length(track),
+      elevation_gain,
+      elevation_loss,
+      max_gradient,
+      min_gradient,
+      average_climbing: if run > 0.0 { rise / run * 100.0 } else { 0.0 },
+      steepest_500m: steepest.map(|(start, gradient)| SteepestStats { start, gradient }),
+      gradient_bands,
+      climbs,
+   }
+}
+
+fn print_stats(args: &StatsArgs, units: Units) -> Result<(), String>
+//------------------------------------------------------------------
+{
+   let mut all_stats = Vec::new();
+   for file in &args.files
+   {
+      let track = process_gpx(&file.to_string_lossy()).map_err(|e| format!("Error reading {}: {}", file.display(), e))?;
+      all_stats.push(course_stats(file, &track));
+   }
+   if args.json
+   {
+      let json = serde_json::to_string_pretty(&all_stats).map_err(|e| format!("Error writing JSON: {}", e))?;
+      println!("{json}");
+      return Ok(());
+   }
+
+   for (i, stats) in all_stats.iter().enumerate()
+   {
+      if i > 0
+      {
+         println!();
+      }
+      println!("{}", stats.file);
+      let rows = [("Points", stats.points.to_string()),
+                  ("Distance (ECEF)", units.format_distance(stats.distance, 2)),
+                  ("Distance (Haversine)", units.format_distance(stats.distance_haversine, 2)),
+                  ("Elevation gain", units.format_length(stats.elevation_gain)),
+                  ("Elevation loss", units.format_length(stats.elevation_loss)),
+                  ("Max gradient", format!("{:.1}%", stats.max_gradient)),
+                  ("Min gradient", format!("{:.1}%", stats.min_gradient)),
+                  ("Average climbing", format!("{:.1}%", stats.average_climbing))];
+      for (label, value) in rows
+      {
+         println!("  {label:<22}{value}");
+      }
+      if let Some(steepest) = &stats.steepest_500m
+      {
+         println!("  {:<22}{:.1}% at {}", "Steepest 500 m", steepest.gradient, units.format_distance(steepest.start, 2));
+      }
+      println!("  {:<12}{:>12}{:>8}{:>10}", "Gradient", "Distance", "Course", "Climbing");
+      for band in stats.gradient_bands.iter().filter(|band| band.distance > 0.0)
+      {
+         println!("  {:<12}{:>12}{:>7.1}%{:>10}", band.band, units.format_distance(band.distance, 2), band.percent,
+                  units.format_length(band.gain));
+      }
+      if stats.climbs.is_empty()
+      {
+         println!("  No climbs");
+         continue;
+      }
+      println!("  {:<6}{:>12}{:>12}{:>10}{:>8}{:>8}", "Climb", "Start", "Length", "Gain", "Avg", "Max");
+      for (n, climb) in stats.climbs.iter().enumerate()
+      {
+         println!("  {:<6}{:>12}{:>12}{:>10}{:>7.1}%{:>7.1}%", n + 1, units.format_distance(climb.start, 2),
+                  units.format_distance(climb.length, 2), units.format_length(climb.gain), climb.average_gradient,
+                  climb.max_gradient);
+      }
+   }
+   Ok(())
+}
+
+/// Problems found in a GPX, TCX or FIT course file.
+fn validate_file(file: &Path, limits: ValidationLimits) -> Vec<Issue>
+//-------------------------------------------------------------------
+{
+   match read_course_file(file)
+   {
+      | Ok(gpx) => validate_gpx(&gpx, limits),
+      | Err(e) => vec![Issue { severity: Severity::Error, distance: None, message: format!("Could not read the file: {e}") }],
+   }
+}
+
+fn validate_files(args: &ValidateArgs, units: Units) -> Result<(), String>
+//------------------------------------------------------------------------
+{
+   let limits = ValidationLimits { max_gap: args.max_gap, max_spike: args.max_spike };
+   let fail_at = if args.strict { Severity::Warning } else { Severity::Error };
+   let mut failed = 0;
+   for file in &args.files
+   {
+      let issues = validate_file(file, limits);
+      let is_failed = issues.iter().any(|issue| issue.severity >= fail_at);
+      if is_failed
+      {
+         failed += 1;
+      }
+      println!("{}: {}", file.display(), if is_failed { "FAILED" } else if issues.is_empty() { "OK" } else { "OK with warnings" });
+      for issue in issues
+      {
+         let severity = match issue.severity
+         {
+            | Severity::Warning => "warning",
+            | Severity::Error => "error",
+         };
+         match issue.distance
+         {
+            | Some(distance) => println!("  {severity} at {}: {}", units.format_distance(distance, 2), issue.message),
+            | None => println!("  {severity}: {}", issue.message),
+         }
+      }
+   }
+   if failed > 0
+   {
+      return Err(format!("{failed} of {} file(s) failed validation", args.files.len()));
+   }
+   Ok(())
+}
+
+/// The TCX, FIT and GeoJSON files in `inputs`, expanding directories (not recursively).
+fn course_files(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String>
+//-----------------------------------------------------------------
+{
+   let is_course = |path: &Path| path.extension().is_some_and(|e| ["tcx", "fit", "geojson"].iter().any(|x| e.eq_ignore_ascii_case(x)));
+   let mut files = Vec::new();
+   for input in inputs
+   {
+      if input.is_dir()
+      {
+         let entries = fs::read_dir(input).map_err(|e| format!("Error reading {}: {}", input.display(), e))?;
+         let mut found: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).filter(|p| p.is_file() && is_course(p)).collect();
+         found.sort();
+         files.extend(found);
+      }
+      else
+      {
+         files.push(input.clone());
+      }
+   }
+   Ok(files)
+}
+
+fn convert_files(args: &ConvertArgs) -> Result<(), String>
+//--------------------------------------------------------
+{
+   if let Some(output) = &args.output
+   {
+      fs::create_dir_all(output).map_err(|e| format!("Error creating {}: {}", output.display(), e))?;
+   }
+   let files = course_files(&args.inputs)?;
+   if files.is_empty()
+   {
+      return Err("No TCX, FIT or GeoJSON files found".to_string());
+   }
+   let (mut converted, mut skipped, mut failed) = (0, 0, 0);
+   for file in &files
+   {
+      let name = file.with_extension("gpx");
+      let target = match &args.output
+      {
+         | Some(output) => output.join(name.file_name().unwrap_or_default()),
+         | None => name,
+      };
+      if target.exists() && !args.overwrite
+      {
+         println!("{}: skipped, {} exists", file.display(), target.display());
+         skipped += 1;
+         continue;
+      }
+      let result = read_course_file(file).and_then(|gpx|
+      {
+         let writer = BufWriter::new(File::create(&target).map_err(|e| e.to_string())?);
+         gpx::write(&gpx, writer).map_err(|e| e.to_string())
+      });
+      match result
+      {
+         | Ok(_) =>
+         {
+            println!("{} -> {}", file.display(), target.display());
+            converted += 1;
+         }
+         | Err(e) =>
+         {
+            eprintln!("{}: {}", file.display(), e);
+            failed += 1;
+         }
+      }
+   }
+   println!("Converted {converted}, skipped {skipped}, failed {failed}");
+   if failed > 0
+   {
+      return Err(format!("{failed} file(s) could not be converted"));
+   }
+   Ok(())
+}
+
+fn export_geojson(args: &ExportArgs) -> Result<(), String>
+//--------------------------------------------------------
+{
+   let gpx = read_course_file(&args.file).map_err(|e| format!("Error reading {}: {}", args.file.display(), e))?;
+   let track = track_data_from_gpx(&gpx).map_err(|e| format!("Error reading {}: {}", args.file.display(), e))?;
+   let name = course_name(&gpx).unwrap_or_else(|| args.file.file_stem().unwrap_or_default().to_string_lossy().to_string());
+   let geojson = track_to_geojson(&name, &track, args.segment);
+   let output = args.output.clone().unwrap_or_else(|| args.file.with_extension("geojson"));
+   let json = serde_json::to_string_pretty(&geojson).map_err(|e| format!("Error writing JSON: {}", e))?;
+   fs::write(&output, json).map_err(|e| format!("Error writing {}: {}", output.display(), e))?;
+   println!("{} -> {}", args.file.display(), output.display());
+   Ok(())
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no.
+fn confirm(question: &str) -> Result<bool, String>
+//------------------------------------------------
+{
+   print!("{question} [y/N] ");
+   let _ = std::io::stdout().flush();
+   let mut answer = String::new();
+   std::io::stdin().read_line(&mut answer).map_err(|e| e.to_string())?;
+   Ok(answer.trim().eq_ignore_ascii_case("y") || answer.trim().eq_ignore_ascii_case("yes"))
+}
+
+/// Whether Google has Street View imagery near `position`, using a (free) metadata request.
+async fn has_streetview_coverage(client: &reqwest::Client, api_key: &str, position: &TrackPoint) -> Result<bool, String>
+//-------------------------------------------------------------------------------------------------------------------
+{
+   let location = format!("{},{}", position.point.lat, position.point.lon);
+   let response = client.get("https://maps.googleapis.com/maps/api/streetview/metadata")
+                        .query(&[("location", location.as_str()), ("key", api_key)])
+                        .timeout(Duration::from_secs(10))
+                        .send()
+                        .await
+                        .map_err(|e| format!("Could not reach Google: {}", e))?;
+   let text = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+   let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("Unexpected response from Google: {}", e))?;
+   match json["status"].as_str().unwrap_or_default()
+   {
+      | "OK" => Ok(true),
+      | "ZERO_RESULTS" | "NOT_FOUND" => Ok(false),
+      | status => Err(format!("Google returned {status}: {}", json["error_message"].as_str().unwrap_or_default())),
+   }
+}
+
+fn precache_streetview(args: &StreetViewArgs, settings: &Settings) -> Result<(), String>
+//--------------------------------------------------------------------------------------
+{
+   if args.interval < 5.0
+   {
+      return Err("The interval must be at least 5 metres.".to_string());
+   }
+   if !(1 ..= 640).contains(&args.width) || !(1 ..= 640).contains(&args.height)
+   {
+      return Err("Street View images can be at most 640x640 pixels.".to_string());
+   }
+   let api_key = settings.get_streetview_api_key()
+                         .map_err(|e| format!("No Street View API key ({e}), set one in the settings or with the key set command"))?;
+   let track = process_gpx(&args.file.to_string_lossy()).map_err(|e| format!("Error reading {}: {}", args.file.display(), e))?;
+   let total_distance = track.last().map_or(0.0, |p| p.distance);
+
+   // The track points nearest each interval, as the Street View shows the track point nearest the rider
+   let mut positions: Vec<TrackPoint> = Vec::new();
+   let mut last_index = -1;
+   let mut distance = 0.0;
+   while distance <= total_distance
+   {
+      if let (Some(position), index) = find_closest_point(&track, distance)
+         && index != last_index
+      {
+         positions.push(position);
+         last_index = index;
+      }
+      distance += args.interval;
+   }
+
+   let client = http::client()?;
+   let mut covered = Vec::new();
+   for (i, position) in positions.iter().enumerate()
+   {
+      print!("\rChecking coverage {}/{}", i + 1, positions.len());
+      let _ = std::io::stdout().flush();
+      if http::block_on(has_streetview_coverage(&client, &api_key, position))?
+      {
+         covered.push(*position);
+      }
+   }
+   println!();
+   let coverage = covered.len() as f64 / positions.len().max(1) as f64 * 100.0;
+   println!("{} positions every {} over {}, {} ({:.1}%) with Street View coverage", positions.len(),
+            settings.units.format_distance(args.interval, 2), settings.units.format_distance(total_distance, 1),
+            covered.len(), coverage);
+   println!("Downloading {} {}x{} images costs about ${:.2} (at ${:.2} per 1000; coverage checks are free)",
+            covered.len(), args.width, args.height, covered.len() as f64 * args.price / 1000.0, args.price);
+   if args.dry_run || covered.is_empty()
+   {
+      return Ok(());
+   }
+   if !args.yes && !confirm(&format!("Download {} images?", covered.len()))?
+   {
+      println!("Nothing downloaded");
+      return Ok(());
+   }
+
+   let mut precache = StreetViewPrecache::new(&args.file, args.interval, settings)?;
+   let mut failed = 0;
+   for (i, position) in covered.iter().enumerate()
+   {
+      print!("\rDownloading {}/{}", i + 1, covered.len());
+      let _ = std::io::stdout().flush();
+      let url = streetview_url(&api_key, position, args.width, args.height, true);
+      if let Err(e) = http::block_on(fetch_bytes_from_url(&url, true)).map_err(String::from).and_then(|bytes| precache.add(position.distance, &bytes))
+      {
+         eprintln!("\nImage at {}: {}", settings.units.format_distance(position.distance, 2), e);
+         failed += 1;
+      }
+   }
+   println!();
+   precache.save()?;
+   println!("Cached {} Street View images for {}", precache.images.len(), args.file.display());
+   if failed > 0
+   {
+      return Err(format!("{failed} image(s) could not be downloaded"));
+   }
+   Ok(())
+}
+
+fn precache_tiles(args: &TilesArgs, settings: &Settings) -> Result<(), String>
+//----------------------------------------------------------------------------
+{
+   if args.min_zoom > args.max_zoom || args.max_zoom > 19
+   {
+      return Err("The zoom levels must be in order and no higher than 19.".to_string());
+   }
+   if !(0.0 ..= 5000.0).contains(&args.corridor)
+   {
+      return Err("The corridor must be between 0 and 5000 metres.".to_string());
+   }
+   let track = process_gpx(&args.file.to_string_lossy()).map_err(|e| format!("Error reading {}: {}", args.file.display(), e))?;
+   let tiles = corridor_tiles(&track, args.min_zoom ..= args.max_zoom, args.corridor);
+   for zoom in args.min_zoom ..= args.max_zoom
+   {
+      println!("  zoom {zoom:>2}: {} tiles", tiles.iter().filter(|t| t.zoom == zoom).count());
+   }
+   let detailed = tiles.iter().filter(|t| t.zoom >= 13).count();
+   println!("{} tiles within {} of the course", tiles.len(), settings.units.format_length(args.corridor));
+   if detailed > OSM_OFFLINE_TILE_LIMIT
+   {
+      return Err(format!("{detailed} tiles at zoom 13 or above exceeds the {OSM_OFFLINE_TILE_LIMIT} allowed for offline use by the \
+                          OpenStreetMap tile usage policy; lower --max-zoom or narrow --corridor."));
+   }
+   if args.dry_run || tiles.is_empty()
+   {
+      return Ok(());
+   }
+   if !args.yes && !confirm(&format!("Download {} tiles?", tiles.len()))?
+   {
+      println!("Nothing downloaded");
+      return Ok(());
+   }
+
+   let failed = download_tiles(&tiles, settings.cache_path(TILE_CACHE), &settings.proxy, |done|
+   {
+      print!("\rDownloading {}/{}", done, tiles.len());
+      let _ = std::io::stdout().flush();
+   })?;
+   println!();
+   for (tile, e) in &failed
+   {
+      eprintln!("Tile {}/{}/{}: {}", tile.zoom, tile.x, tile.y, e);
+   }
+   println!("Cached {} map tiles for {}", tiles.len() - failed.len(), args.file.display());
+   if !failed.is_empty()
+   {
+      return Err(format!("{} tile(s) could not be downloaded", failed.len()));
+   }
+   Ok(())
+}
+
+fn garmin_connect(args: &GarminArgs) -> Result<GarminConnect, String>
+//------------------------------------------------------------------
+{
+   let token = args.token.clone().or_else(|| std::env::var(GARMIN_TOKEN_VARIABLE).ok()).unwrap_or_default();
+   GarminConnect::new(&token)
+}
+
+fn list_garmin_courses(args: &GarminArgs, settings: &Settings) -> Result<(), String>
+//----------------------------------------------------------------------------------
+{
+   let courses = garmin_connect(args)?.list_courses()?;
+   if courses.is_empty()
+   {
+      println!("No courses in the Garmin Connect account");
+      return Ok(());
+   }
+   let units = settings.units;
+   println!("{:>12}  {:>10}  {:>8}  Name", "Id", format!("Dist ({})", units.distance_unit()), format!("Up ({})", units.length_unit()));
+   for course in courses
+   {
+      println!("{:>12}  {:>10.1}  {:>8.0}  {}", course.id, units.to_distance(course.distance), units.to_length(course.ascent), course.name);
+   }
+   Ok(())
+}
+
+fn download_garmin_courses(args: &GarminDownloadArgs, settings: &Settings) -> Result<(), String>
+//----------------------------------------------------------------------------------------------
+{
+   let garmin = garmin_connect(&args.account)?;
+   let directory = args.output.clone().unwrap_or_else(|| settings.courses_directory.clone());
+   fs::create_dir_all(&directory).map_err(|e| format!("Error creating {}: {}", directory.display(), e))?;
+   let courses: Vec<_> = garmin.list_courses()?.into_iter().filter(|c| args.all || args.ids.contains(&c.id)).collect();
+   if let Some(missing) = args.ids.iter().find(|id| !courses.iter().any(|c| c.id == **id))
+   {
+      return Err(format!("No course {missing} in the Garmin Connect account"));
+   }
+   let (mut downloaded, mut skipped, mut failed) = (0, 0, 0);
+   for course in &courses
+   {
+      match garmin.import_course(course, &directory, args.overwrite)
+      {
+         | Ok(Some(path)) =>
+         {
+            println!("{} -> {}", course.name, path.display());
+            downloaded += 1;
+         }
+         | Ok(None) =>
+         {
+            println!("{}: skipped, already in {}", course.name, directory.display());
+            skipped += 1;
+         }
+         | Err(e) =>
+         {
+            eprintln!("{}: {}", course.name, e);
+            failed += 1;
+         }
+      }
+   }
+   println!("Downloaded {downloaded}, skipped {skipped}, failed {failed}");
+   if failed > 0
+   {
+      return Err(format!("{failed} course(s) could not be downloaded"));
+   }
+   Ok(())
+}
+
+fn komoot_login(args: &KomootLoginArgs, settings: &Settings) -> Result<(), String>
+//--------------------------------------------------------------------------------
+{
+   let account = komoot::login(&args.client_id, &args.client_secret)?;
+   let mut settings = settings.clone();
+   settings.komoot = account;
+   let path = settings.write_settings().map_err(|e| format!("Error saving settings: {}", e))?;
+   println!("Connected to Komoot; saved to {}", path.display());
+   Ok(())
+}
+
+fn set_api_key(args: &KeySetArgs, settings: &Settings) -> Result<(), String>
+//--------------------------------------------------------------------------
+{
+   let key = match &args.key
+   {
+      | Some(key) => key.trim().to_string(),
+      | None =>
+      {
+         if std::io::stdin().is_terminal()
+         {
+            print!("Street View API key: ");
+            let _ = std::io::stdout().flush();
+         }
+         let mut key = String::new();
+         std::io::stdin().read_line(&mut key).map_err(|e| format!("Error reading the key: {}", e))?;
+         key.trim().to_string()
+      }
+   };
+   if key.is_empty()
+   {
+      return Err("No API key given, use the key clear command to remove the key.".to_string());
+   }
+   if args.test
+   {
+      println!("{}", http::block_on(test_streetview_api_key(&key))?);
+   }
+   settings.clone().set_streetview_api_key(&key)?;
+   println!("Street View API key encrypted and saved to the settings file");
+   Ok(())
+}
+
+fn show_api_key(args: &KeyShowArgs, settings: &Settings) -> Result<(), String>
+//----------------------------------------------------------------------------
+{
+   let key = settings.get_streetview_api_key()?;
+   let chars: Vec<char> = key.chars().collect();
+   if args.reveal || chars.len() <= 8
+   {
+      println!("{key}");
+   }
+   else
+   {
+      let (start, end): (String, String) = (chars[..4].iter().collect(), chars[chars.len() - 4..].iter().collect());
+      println!("{start}…{end} ({} characters, use --reveal to show it all)", chars.len());
+   }
+   Ok(())
+}
+
+fn clear_api_key(settings: &Settings) -> Result<(), String>
+//---------------------------------------------------------
+{
+   settings.clone().clear_streetview_api_key()?;
+   println!("Street View API key removed from the settings file");
+   Ok(())
+}
+
+fn test_api_key(args: &KeyTestArgs, settings: &Settings) -> Result<(), String>
+//----------------------------------------------------------------------------
+{
+   let key = match &args.key
+   {
+      | Some(key) => key.trim().to_string(),
+      | None => settings.get_streetview_api_key()?,
+   };
+   println!("{}", http::block_on(test_streetview_api_key(&key))?);
+   Ok(())
+}
+
+fn list_komoot_tours(settings: &Settings) -> Result<(), String>
+//-------------------------------------------------------------
+{
+   let tours = Komoot::connect(&settings.komoot)?.list_tours()?;
+   if tours.is_empty()
+   {
+      println!("No planned tours in the Komoot account");
+      return Ok(());
+   }
+   let units = settings.units;
+   println!("{:>12}  {:>10}  {:>8}  {:<14}  Name", "Id", format!("Dist ({})", units.distance_unit()), format!("Up ({})", units.length_unit()),
+            "Sport");
+   for tour in tours
+   {
+      println!("{:>12}  {:>10.1}  {:>8.0}  {:<14}  {}", tour.id, units.to_distance(tour.distance), units.to_length(tour.ascent), tour.sport,
+               tour.name);
+   }
+   Ok(())
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use eframe::egui;
+
+use crate::{gpx::{Climb, Track}, units::Units};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClimbSort
+{
+   Start,
+   Length,
+   Gradient,
+   Category, // by length × gradient, as the category is
+}
+
+/// What the user asked for in the climbs list.
+pub enum ClimbAction
+{
+   Show(Climb), // centre the views on the climb (or jump there when simulating)
+   FollowRider, // go back to following the rider after showing a climb
+}
+
+/// Sortable list of the climbs on the course. Clicking a climb shows it in the map and gradient views.
+pub struct ClimbList
+//==================
+{
+   pub is_open:  bool,
+   track:        Option<Arc<Track>>, // the climbs are for
+   climbs:       Vec<Climb>,
+   sort:         ClimbSort,
+   is_ascending: bool,
+}
+
+impl Default for ClimbList
+{
+   fn default() -> Self { Self { is_open: false, track: None, climbs: Vec::new(), sort: ClimbSort::Start, is_ascending: true } }
+}
+
+impl ClimbList
+{
+   fn sort_climbs(&mut self)
+   //-----------------------
+   {
+      let sort = self.sort;
+      let key = |climb: &Climb| match sort
+      {
+         | ClimbSort::Start => climb.start,
+         | ClimbSort::Length => climb.length(),
+         | ClimbSort::Gradient => climb.average_gradient,
+         | ClimbSort::Category => climb.length() * climb.average_gradient,
+      };
+      self.climbs.sort_by(|a, b| key(a).total_cmp(&key(b)));
+      if !self.is_ascending
+      {
+         self.climbs.reverse();
+      }
+   }
+
+   /// Show the climbs of `track` with those behind `distance` (the rider) dimmed, and a Follow rider button while
+   /// `is_showing_climb`.
+   pub fn show(&mut self, ctx: &egui::Context, track: &Arc<Track>, distance: f64, is_showing_climb: bool, units: Units)
+               -> Option<ClimbAction>
+   //------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.is_open
+      {
+         return None;
+      }
+      if !self.track.as_ref().is_some_and(|t| Arc::ptr_eq(t, track))
+      {
+         self.climbs = track.climbs(20.0, 2.0);
+         self.track = Some(track.clone());
+         self.sort_climbs();
+      }
+      let mut action = None;
+      let mut is_open = self.is_open;
+      egui::Window::new("Climbs")
+         .open(&mut is_open)
+         .resizable(true)
+         .default_height(400.0)
+         .show(ctx, |ui|
+         {
+            if is_showing_climb && ui.button("⌖ Follow rider").on_hover_text("Go back to following the rider").clicked()
+            {
+               action = Some(ClimbAction::FollowRider);
+            }
+            if self.climbs.is_empty()
+            {
+               ui.label("No climbs on this course.");
+               return;
+            }
+            let mut new_sort = None;
+            egui::ScrollArea::vertical().auto_shrink([true, false]).show(ui, |ui|
+            {
+               egui::Grid::new("climb_list_grid").num_columns(6).striped(true).spacing([16.0, 6.0]).show(ui, |ui|
+               {
+                  for (sort, title) in [(ClimbSort::Start, "Start"), (ClimbSort::Length, "Length"), (ClimbSort::Gradient, "Average"),
+                                        (ClimbSort::Category, "Category")]
+                  {
+                     let arrow = if self.sort == sort { if self.is_ascending { " ⏶" } else { " ⏷" } } else { "" };
+                     if ui.add(egui::Button::new(egui::RichText::new(format!("{title}{arrow}")).strong()).frame(false)).clicked()
+                     {
+                        new_sort = Some(sort);
+                     }
+                  }
+                  ui.label(egui::RichText::new("Gain").strong());
+                  ui.label("");
+                  ui.end_row();
+                  for climb in &self.climbs
+                  {
+                     let is_behind = climb.end < distance;
+                     let text = |text: String| if is_behind { egui::RichText::new(text).weak() } else { egui::RichText::new(text) };
+                     ui.label(text(units.format_distance(climb.start, 2)));
+                     ui.label(text(units.format_distance(climb.length(), 2)));
+                     ui.label(text(format!("{:.1}%", climb.average_gradient)));
+                     ui.label(text(climb.category().unwrap_or("–").to_string()));
+                     ui.label(text(units.format_length(climb.gain)));
+                     if ui.button("Show").on_hover_text(format!("Show this climb, max {:.1}% over 100 m", climb.max_gradient)).clicked()
+                     {
+                        action = Some(ClimbAction::Show(*climb));
+                     }
+                     ui.end_row();
+                  }
+               });
+            });
+            if let Some(sort) = new_sort
+            {
+               if self.sort == sort { self.is_ascending = !self.is_ascending; } else { self.sort = sort; self.is_ascending = true; }
+               self.sort_climbs();
+            }
+         });
+      self.is_open = is_open;
+      action
+   }
+}
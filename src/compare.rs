@@ -0,0 +1,293 @@
+use std::{fs::File, io::BufReader, path::{Path, PathBuf}, sync::{Arc, mpsc::{Receiver, Sender, channel}}};
+
+use eframe::egui::{self, Color32, Context};
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+
+use crate::{SETTINGS, gpx::{Track, course_name, track_data_from_gpx}, settings::Settings, units::Units};
+
+/// Profiles are drawn and compared with at most this many samples.
+const SAMPLES: usize = 1000;
+const COLORS: [Color32; 2] = [Color32::from_rgb(66, 133, 244), Color32::from_rgb(234, 67, 53)];
+const DIFFERENCE_COLOR: Color32 = Color32::from_rgb(120, 120, 120);
+
+/// A course file loaded for comparison.
+#[derive(Clone)]
+pub struct ComparedCourse
+{
+   pub name:  String,
+   pub path:  PathBuf,
+   pub track: Arc<Track>,
+   gain:      f64,
+}
+
+impl ComparedCourse
+{
+   pub fn new(name: String, path: PathBuf, track: Arc<Track>) -> Self
+   //-----------------------------------------------------------------
+   {
+      let (gain, _) = track.elevation_gain_loss(2.0);
+      Self { name, path, track, gain }
+   }
+
+   fn load(path: &Path) -> Result<Self, String>
+   //-------------------------------------------
+   {
+      let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+      let gpx = gpx::read(BufReader::new(file)).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+      let points = track_data_from_gpx(&gpx).map_err(|e| e.to_string())?;
+      if points.len() < 2
+      {
+         return Err(format!("{} does not contain a track", path.display()));
+      }
+      let name = course_name(&gpx)
+         .unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default());
+      Ok(Self::new(name, path.to_path_buf(), Arc::new(Track::from(points))))
+   }
+}
+
+/// Differences in altitude (second course minus first) over the distance both courses cover.
+struct Difference
+{
+   mean:        f64, // of the absolute differences
+   max:         f64, // largest absolute difference, signed
+   max_at:      f64, // distance of max along the first course
+   gain_change: f64,
+   series:      Vec<[f64; 2]>,
+}
+
+/// Window overlaying the elevation profiles of two course files, e.g. the original and a corrected elevation or two
+/// route options, on the same distance axis with the altitude difference between them.
+#[derive(Default)]
+pub struct ProfileComparison
+//===========================
+{
+   pub is_open:    bool,
+   courses:        [Option<ComparedCourse>; 2],
+   offset:         f64,  // metres the second course is shifted along the first
+   is_stretched:   bool, // scale the second course to the length of the first
+   is_difference:  bool, // also plot the difference
+   is_loading:     [bool; 2],
+   error:          Option<String>,
+   channel:        Option<(Sender<(usize, Result<ComparedCourse, String>)>, Receiver<(usize, Result<ComparedCourse, String>)>)>,
+}
+
+impl ProfileComparison
+{
+   /// Open the window, comparing against `current` (normally the open course) when nothing was compared yet.
+   pub fn open(&mut self, current: Option<ComparedCourse>)
+   //-----------------------------------------------------
+   {
+      self.is_open = true;
+      if self.courses[0].is_none()
+      {
+         self.courses[0] = current;
+      }
+   }
+
+   /// Distance along the first course of `distance` metres along the second.
+   fn aligned(&self, distance: f64) -> f64
+   //-------------------------------------
+   {
+      let scale = match &self.courses
+      {
+         | [Some(first), Some(second)] if self.is_stretched && second.track.total_distance() > 0.0 =>
+         {
+            first.track.total_distance() / second.track.total_distance()
+         }
+         | _ => 1.0,
+      };
+      distance * scale + self.offset
+   }
+
+   fn difference(&self, first: &Track, second: &Track) -> Option<Difference>
+   //-----------------------------------------------------------------------
+   {
+      let start = self.aligned(0.0).max(0.0);
+      let end = self.aligned(second.total_distance()).min(first.total_distance());
+      if end <= start
+      {
+         return None;
+      }
+      let scale = (self.aligned(1000.0) - self.aligned(0.0)) / 1000.0;
+      let mut difference = Difference { mean: 0.0, max: 0.0, max_at: start, gain_change: 0.0, series: Vec::with_capacity(SAMPLES + 1) };
+      for i in 0..=SAMPLES
+      {
+         let distance = start + (end - start) * i as f64 / SAMPLES as f64;
+         let (Some(a), Some(b)) = (first.altitude_at(distance), second.altitude_at((distance - self.offset) / scale)) else { continue; };
+         let delta = b - a;
+         difference.mean += delta.abs();
+         if delta.abs() > difference.max.abs()
+         {
+            difference.max = delta;
+            difference.max_at = distance;
+         }
+         difference.series.push([distance, delta]);
+      }
+      difference.mean /= difference.series.len().max(1) as f64;
+      let (first_gain, _) = first.section(start, end).elevation_gain_loss(2.0);
+      let (second_gain, _) = second.section((start - self.offset) / scale, (end - self.offset) / scale).elevation_gain_loss(2.0);
+      difference.gain_change = second_gain - first_gain;
+      Some(difference)
+   }
+
+   fn pick(&mut self, index: usize, ctx: &Context)
+   //---------------------------------------------
+   {
+      let directory = {
+         let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+         settings.lock().get_last_directorybuf()
+      };
+      let Some(path) = rfd::FileDialog::new().set_directory(directory).add_filter("GPX course", &["gpx"]).pick_file() else { return; };
+      let sender = self.channel.get_or_insert_with(channel).0.clone();
+      let ctxx = ctx.clone();
+      self.is_loading[index] = true;
+      std::thread::spawn(move ||
+      {
+         let _ = sender.send((index, ComparedCourse::load(&path)));
+         ctxx.request_repaint();
+      });
+   }
+
+   pub fn show(&mut self, ctx: &Context, units: Units)
+   //-------------------------------------------------
+   {
+      if let Some((_, receiver)) = &self.channel
+      {
+         while let Ok((index, result)) = receiver.try_recv()
+         {
+            self.is_loading[index] = false;
+            match result
+            {
+               | Ok(course) =>
+               {
+                  log_info!("Comparing profile of {} ({})", course.name, course.path.display());
+                  self.courses[index] = Some(course);
+                  self.error = None;
+               }
+               | Err(e) => self.error = Some(e),
+            }
+         }
+      }
+      if !self.is_open
+      {
+         return;
+      }
+      let mut is_open = self.is_open;
+      egui::Window::new("Compare Profiles")
+         .open(&mut is_open)
+         .resizable(true)
+         .default_width(800.0)
+         .default_height(450.0)
+         .show(ctx, |ui|
+         {
+            egui::Grid::new("compare_profiles_courses").num_columns(4).spacing([12.0, 4.0]).show(ui, |ui|
+            {
+               for index in 0..2
+               {
+                  ui.colored_label(COLORS[index], if index == 0 { "⏺ First:" } else { "⏺ Second:" });
+                  match &self.courses[index]
+                  {
+                     | Some(course) =>
+                     {
+                        ui.label(&course.name).on_hover_text(course.path.display().to_string());
+                        ui.label(format!("{}, {} gain", units.format_distance(course.track.total_distance(), 2),
+                                         units.format_length(course.gain)));
+                     }
+                     | None => { ui.label("(none)"); ui.label(""); }
+                  }
+                  ui.horizontal(|ui|
+                  {
+                     if ui.add_enabled(!self.is_loading[index], egui::Button::new("Open…")).clicked()
+                     {
+                        self.pick(index, ui.ctx());
+                     }
+                     if self.is_loading[index]
+                     {
+                        ui.spinner();
+                     }
+                  });
+                  ui.end_row();
+               }
+            });
+            if ui.button("⇅ Swap").clicked()
+            {
+               self.courses.swap(0, 1);
+               self.offset = -self.offset;
+            }
+            ui.horizontal(|ui|
+            {
+               ui.label("Second course offset:");
+               let mut offset = units.to_distance(self.offset);
+               if ui.add(egui::DragValue::new(&mut offset).speed(0.01).suffix(format!(" {}", units.distance_unit()))).changed()
+               {
+                  self.offset = units.from_distance(offset);
+               }
+               ui.checkbox(&mut self.is_stretched, "Stretch to the same length")
+                 .on_hover_text("Scale the second course's distances so both courses start and end together");
+               ui.checkbox(&mut self.is_difference, "Plot difference");
+            });
+            if let Some(e) = &self.error
+            {
+               ui.label(egui::RichText::new(e).color(Color32::RED));
+            }
+            let [Some(first), Some(second)] = &self.courses
+            else
+            {
+               ui.label("Open two courses to compare their profiles.");
+               return;
+            };
+            let difference = self.difference(&first.track, &second.track);
+            match &difference
+            {
+               | Some(difference) =>
+               {
+                  ui.label(format!("Second − first: mean {} apart, largest {:+.0} {} at {}, climbing {:+.0} {}",
+                                   units.format_length(difference.mean),
+                                   units.to_length(difference.max), units.length_unit(), units.format_distance(difference.max_at, 2),
+                                   units.to_length(difference.gain_change), units.length_unit()));
+               }
+               | None => { ui.label("The courses do not overlap with this offset."); }
+            }
+            let to_plot = |course: &ComparedCourse, is_second: bool| -> Vec<[f64; 2]>
+            {
+               let step = (course.track.len() / SAMPLES).max(1);
+               course.track.iter().step_by(step)
+                     .map(|p| [units.to_distance(if is_second { self.aligned(p.distance) } else { p.distance }), units.to_length(p.altitude)])
+                     .collect()
+            };
+            let (first_points, second_points) = (to_plot(first, false), to_plot(second, true));
+            let hover = Plot::new("compare_profiles_plot")
+               .legend(Legend::default())
+               .x_axis_label(format!("Distance ({})", units.distance_unit()))
+               .y_axis_label(format!("Altitude ({})", units.length_unit()))
+               .allow_scroll(false)
+               .height((ui.available_height() - 2.0 * ui.spacing().interact_size.y).max(150.0))
+               .show(ui, |plot_ui|
+               {
+                  plot_ui.line(Line::new(first.name.clone(), PlotPoints::from(first_points)).color(COLORS[0]));
+                  plot_ui.line(Line::new(second.name.clone(), PlotPoints::from(second_points)).color(COLORS[1]));
+                  if self.is_difference && let Some(difference) = &difference
+                  {
+                     let series = difference.series.iter().map(|&[d, delta]| [units.to_distance(d), units.to_length(delta)]).collect::<Vec<_>>();
+                     plot_ui.line(Line::new("Difference", PlotPoints::from(series)).color(DIFFERENCE_COLOR));
+                  }
+                  plot_ui.pointer_coordinate().map(|p| units.from_distance(p.x))
+               }).inner;
+            if let Some(distance) = hover
+               && let Some(a) = first.track.altitude_at(distance)
+            {
+               let scale = (self.aligned(1000.0) - self.aligned(0.0)) / 1000.0;
+               let along = (distance - self.offset) / scale;
+               let b = second.track.altitude_at(along).filter(|_| (0.0..=second.track.total_distance()).contains(&along));
+               let readout = match b
+               {
+                  | Some(b) => format!("At {}: first {}, second {}, difference {:+.0} {}", units.format_distance(distance, 2),
+                                       units.format_length(a), units.format_length(b), units.to_length(b - a), units.length_unit()),
+                  | None => format!("At {}: first {}", units.format_distance(distance, 2), units.format_length(a)),
+               };
+               ui.label(readout);
+            }
+         });
+      self.is_open = is_open;
+   }
+}
@@ -1,6 +1,10 @@
 use eframe::egui::{self, Button, Response, Ui};
 use walkers::{HttpTiles, Map, MapMemory, Plugin, Position, Projector, lon_lat, sources::OpenStreetMap};
-use std::time::{Duration, Instant};
+use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::{Duration, Instant}};
+
+use chrono::{DateTime, Local};
+
+use crate::{gpx::{Climb, Track, Turn, Waypoint}, settings::{ClimbAlerts, MarkerStyle, RiderMarker}, summary::LapSplit, ui::Theme, units::Units};
 
 /// Walkers Plugin that renders a directional arrow showing the heading based on movement
 /// from previous_position to current_position.
@@ -11,8 +15,9 @@ pub struct DirectionalArrow
    pub(crate) current_position:  Position,
    pub(crate) heading: f64, // Heading in degrees (0-360)
    pub(crate) wind_angle: i32, // Wind direction in degrees (0-360)
-   pub(crate) wind_speed: f64 // Wind speed in metres per second
-
+   pub(crate) wind_speed: f64, // Wind speed in metres per second
+   pub(crate) marker: RiderMarker,
+   pub(crate) avatar: Option<egui::TextureHandle>,
 }
 
 impl Plugin for DirectionalArrow
@@ -28,8 +33,8 @@ impl Plugin for DirectionalArrow
       // Convert current position to screen coordinates
       let screen_pos = projector.project(self.current_position).to_pos2();
 
-      // Draw the directional arrow (movement direction)
-      draw_directional_arrow(ui, screen_pos, bearing_rad as f32);
+      // Draw the rider marker (movement direction)
+      draw_rider_marker(ui.painter(), screen_pos, Some(bearing_rad as f32), &self.marker, self.avatar.as_ref());
 
       // Draw the wind arrow if wind speed is significant
       if self.wind_speed.abs() > 0.5
@@ -40,12 +45,54 @@ impl Plugin for DirectionalArrow
    }
 }
 
-/// Draw an arrow pointing in the specified direction (bearing in radians)
-fn draw_directional_arrow(ui: &mut egui::Ui, position: egui::Pos2, bearing: f32)
-//------------------------------------------------------------------------------
+/// Size the rider avatar is drawn at (and scaled down to when loaded).
+const AVATAR_SIZE: f32 = 36.0;
+
+/// Draw the rider marker in the chosen style at `position`, pointing along `bearing` (radians) when given.
+pub(crate) fn draw_rider_marker(painter: &egui::Painter, position: egui::Pos2, bearing: Option<f32>, marker: &RiderMarker,
+                                avatar: Option<&egui::TextureHandle>)
+//-------------------------------------------------------------------------------------------------------------------
 {
-   let painter = ui.painter();
+   match (marker.style, avatar)
+   {
+      | (MarkerStyle::Avatar, Some(texture)) =>
+      {
+         let rect = egui::Rect::from_center_size(position, egui::Vec2::splat(AVATAR_SIZE));
+         painter.image(texture.id(), rect, egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)), egui::Color32::WHITE);
+         painter.rect_stroke(rect, 4.0, egui::Stroke::new(2.0, marker.color), egui::StrokeKind::Outside);
+         if let Some(bearing) = bearing
+         {
+            draw_heading_tick(painter, position, bearing, AVATAR_SIZE / 2.0 + 4.0, marker.color);
+         }
+      }
+      | (MarkerStyle::Dot, _) =>
+      {
+         painter.circle_filled(position, 8.0, marker.color);
+         painter.circle_stroke(position, 8.0, egui::Stroke::new(2.0, egui::Color32::WHITE));
+         if let Some(bearing) = bearing
+         {
+            draw_heading_tick(painter, position, bearing, 12.0, marker.color);
+         }
+      }
+      | _ => draw_directional_arrow(painter, position, bearing.unwrap_or(0.0), marker.color),
+   }
+}
+
+/// Small triangle `distance` pixels from `position` showing the direction of travel around a Dot or Avatar marker.
+fn draw_heading_tick(painter: &egui::Painter, position: egui::Pos2, bearing: f32, distance: f32, color: egui::Color32)
+//-------------------------------------------------------------------------------------------------------------------
+{
+   let direction = egui::vec2(bearing.sin(), -bearing.cos());
+   let normal = egui::vec2(-direction.y, direction.x);
+   let base = position + direction * distance;
+   painter.add(egui::Shape::convex_polygon(vec![base + direction * 8.0, base + normal * 5.0, base - normal * 5.0], color,
+                                           egui::Stroke::new(1.0, egui::Color32::WHITE)));
+}
 
+/// Draw an arrow pointing in the specified direction (bearing in radians)
+fn draw_directional_arrow(painter: &egui::Painter, position: egui::Pos2, bearing: f32, color: egui::Color32)
+//---------------------------------------------------------------------------------------------------------
+{
    // Arrow dimensions
    let arrow_length = 20.0;
    let arrow_width = 12.0;
@@ -66,17 +113,17 @@ fn draw_directional_arrow(ui: &mut egui::Ui, position: egui::Pos2, bearing: f32)
    let points = vec![position + rotate(tip), position + rotate(left_base), position + rotate(right_base),];
 
    // Draw filled arrow
-   painter.add(egui::Shape::convex_polygon(points.clone(), egui::Color32::from_rgb(255, 100, 100), egui::Stroke::new(2.0, egui::Color32::WHITE)));
+   painter.add(egui::Shape::convex_polygon(points.clone(), color, egui::Stroke::new(2.0, egui::Color32::WHITE)));
 
    // Draw a small circle at the center for visibility
-   painter.circle_filled(position, 5.0, egui::Color32::from_rgb(255, 128, 128));
+   painter.circle_filled(position, 5.0, color.gamma_multiply(0.8));
    painter.circle_stroke(position, 5.0, egui::Stroke::new(1.5, egui::Color32::ORANGE));
 }
 
 /// Draw a wind arrow pointing in the wind direction (bearing in radians)
 /// Length is derived from wind_speed (in m/s)
 /// The arrow point (tip) ends at the position (directional arrow center)
-fn draw_wind_arrow(ui: &mut egui::Ui, position: egui::Pos2, wind_bearing: f32, wind_speed: f32)
+pub(crate) fn draw_wind_arrow(ui: &mut egui::Ui, position: egui::Pos2, wind_bearing: f32, wind_speed: f32)
 //------------------------------------------------------------------------------------------------
 {
    let painter = ui.painter();
@@ -134,6 +181,49 @@ fn draw_wind_arrow(ui: &mut egui::Ui, position: egui::Pos2, wind_bearing: f32, w
    );
 }
 
+/// The rider avatar texture loaded from the `RiderMarker` image, reloaded when the file is changed.
+#[derive(Default)]
+pub struct RiderAvatar
+//=====================
+{
+   path:    PathBuf,
+   texture: Option<egui::TextureHandle>,
+}
+
+impl RiderAvatar
+{
+   /// The avatar for `marker`, or None when the style is not Avatar or the image could not be loaded (the arrow is drawn
+   /// instead).
+   pub fn texture(&mut self, ctx: &egui::Context, marker: &RiderMarker) -> Option<egui::TextureHandle>
+   //-------------------------------------------------------------------------------------------------
+   {
+      if marker.style != MarkerStyle::Avatar
+      {
+         return None;
+      }
+      if self.path != marker.avatar
+      {
+         self.path = marker.avatar.clone();
+         self.texture = match image::open(&self.path)
+         {
+            | Ok(image) =>
+            {
+               let size = (AVATAR_SIZE * 2.0) as u32; // sharp on high DPI displays
+               let rgba = image.thumbnail(size, size).to_rgba8();
+               let image = egui::ColorImage::from_rgba_unmultiplied([rgba.width() as usize, rgba.height() as usize], rgba.as_raw());
+               Some(ctx.load_texture("rider_avatar", image, egui::TextureOptions::LINEAR))
+            }
+            | Err(e) =>
+            {
+               log_warn!("Could not load the rider avatar {}: {}", self.path.display(), e);
+               None
+            }
+         };
+      }
+      self.texture.clone()
+   }
+}
+
 //-----------------------------------------------------------------------------------------------------------------------------------------------
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -147,14 +237,14 @@ pub enum ToastLevel
 
 impl ToastLevel
 {
-   fn color(&self) -> egui::Color32
+   fn color(&self, theme: &Theme) -> egui::Color32
    {
       match self
       {
-         | ToastLevel::Info => egui::Color32::from_rgb(60, 120, 216),     // Blue
-         | ToastLevel::Warning => egui::Color32::from_rgb(255, 165, 0),   // Orange
-         | ToastLevel::Error => egui::Color32::from_rgb(220, 53, 69),     // Red
-         | ToastLevel::Success => egui::Color32::from_rgb(40, 167, 69),   // Green
+         | ToastLevel::Info => theme.toast_info,
+         | ToastLevel::Warning => theme.toast_warning,
+         | ToastLevel::Error => theme.toast_error,
+         | ToastLevel::Success => theme.toast_success,
       }
    }
 
@@ -177,6 +267,7 @@ pub struct Toast
    level: ToastLevel,
    created_at: Instant,
    duration: Option<Duration>, // None = indefinite (requires dismissal)
+   link: Option<(String, String)>, // (text, url)
 }
 
 impl Toast
@@ -188,9 +279,16 @@ impl Toast
          level,
          created_at: Instant::now(),
          duration: Some(Duration::from_secs(4)),
+         link: None,
       }
    }
 
+   pub fn with_link(mut self, text: impl Into<String>, url: impl Into<String>) -> Self
+   {
+      self.link = Some((text.into(), url.into()));
+      self
+   }
+
    pub fn with_duration(mut self, duration: Duration) -> Self
    {
       self.duration = Some(duration);
@@ -229,9 +327,25 @@ impl Toast
    }
 }
 
+/// A toast message kept after it has been dismissed or has expired so it can be reviewed later.
+#[derive(Clone)]
+pub struct ToastRecord
+{
+   pub message: String,
+   pub level: ToastLevel,
+   pub time: DateTime<Local>,
+}
+
+const MAX_TOAST_HISTORY: usize = 100;
+const MAX_VISIBLE_TOASTS: usize = 4; // The rest are summarised by a "+N more" indicator
+const TOAST_REPAINT_MS: u64 = 50;
+
 pub struct ToastManager
 {
    toasts: Vec<Toast>,
+   history: VecDeque<ToastRecord>,
+   unseen_count: usize,
+   show_history: bool,
 }
 
 impl Default for ToastManager
@@ -246,11 +360,20 @@ impl ToastManager
 {
    pub fn new() -> Self
    {
-      Self { toasts: Vec::new() }
+      Self { toasts: Vec::new(), history: VecDeque::new(), unseen_count: 0, show_history: false }
    }
 
    pub fn add(&mut self, toast: Toast)
    {
+      if self.history.len() >= MAX_TOAST_HISTORY
+      {
+         self.history.pop_front();
+      }
+      self.history.push_back(ToastRecord { message: toast.message.clone(), level: toast.level, time: Local::now() });
+      if ! self.show_history
+      {
+         self.unseen_count += 1;
+      }
       self.toasts.push(toast);
    }
 
@@ -294,10 +417,26 @@ impl ToastManager
       self.add(toast);
    }
 
-   pub fn show(&mut self, ctx: &egui::Context)
+   /// Bell button (for the toolbar) which opens the history of recent toasts.
+   pub fn history_button(&mut self, ui: &mut Ui) -> Response
+   {
+      let text = if self.unseen_count > 0 { format!("🔔{}", self.unseen_count) } else { "🔔".to_string() };
+      let response = ui.add(Button::new(egui::RichText::new(text).size(20.0)).selected(self.show_history))
+                       .labelled(&format!("Notifications, {} unseen", self.unseen_count))
+                       .on_hover_text("Show recent notifications");
+      if response.clicked()
+      {
+         self.show_history = !self.show_history;
+         self.unseen_count = 0;
+      }
+      response
+   }
+
+   pub fn show(&mut self, ctx: &egui::Context, theme: &Theme)
    {
       // Remove expired toasts
       self.toasts.retain(|toast| !toast.is_expired());
+      self.show_history_window(ctx, theme);
 
       if self.toasts.is_empty()
       {
@@ -313,11 +452,11 @@ impl ToastManager
       let mut y_offset = margin;
       let mut toasts_to_remove = Vec::new();
 
-      for (index, toast) in self.toasts.iter().enumerate()
+      for (index, toast) in self.toasts.iter().enumerate().take(MAX_VISIBLE_TOASTS)
       {
          let toast_id = egui::Id::new("toast").with(index);
 
-         egui::Area::new(toast_id)
+         let area_response = egui::Area::new(toast_id)
             .fixed_pos(egui::pos2(
                screen_rect.right() - toast_width - margin,
                screen_rect.top() + y_offset,
@@ -325,9 +464,9 @@ impl ToastManager
             .order(egui::Order::Foreground)
             .show(ctx, |ui|
             {
-               egui::Frame::new()
-                  .fill(egui::Color32::from_black_alpha(230))
-                  .stroke(egui::Stroke::new(2.0, toast.level.color()))
+               let frame_response = egui::Frame::new()
+                  .fill(theme.toast_background)
+                  .stroke(egui::Stroke::new(2.0, toast.level.color(theme)))
                   .corner_radius(8.0)
                   .inner_margin(12.0)
                   .show(ui, |ui|
@@ -339,28 +478,34 @@ impl ToastManager
                         // Icon
                         ui.label(
                            egui::RichText::new(toast.level.icon())
-                              .color(toast.level.color())
+                              .color(toast.level.color(theme))
                               .size(24.0),
                         );
 
                         ui.add_space(8.0);
 
-                        // Message
+                        // Message (wrapped, leaving room for the dismiss button)
                         ui.vertical(|ui|
                         {
-                           ui.label(
+                           ui.set_max_width(toast_width - 110.0);
+                           ui.add(egui::Label::new(
                               egui::RichText::new(&toast.message)
-                                 .color(egui::Color32::WHITE)
+                                 .color(theme.toast_text)
                                  .size(14.0),
-                           );
+                           ).wrap());
+                           if let Some((text, url)) = &toast.link
+                           {
+                              ui.hyperlink_to(egui::RichText::new(text).size(14.0), url);
+                           }
                         });
 
                         // Add dismiss button for all toasts
                         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui|
                         {
                            if ui.button(egui::RichText::new("✖")
-                              .color(egui::Color32::WHITE)
+                              .color(theme.toast_text)
                               .size(16.0))
+                              .labelled("Dismiss")
                               .on_hover_text("Click to dismiss")
                               .clicked()
                            {
@@ -379,9 +524,9 @@ impl ToastManager
                      );
 
                      let bar_color = if toast.is_indefinite() {
-                        toast.level.color() // Full brightness for indefinite
+                        toast.level.color(theme) // Full brightness for indefinite
                      } else {
-                        toast.level.color().linear_multiply(0.8)
+                        toast.level.color(theme).linear_multiply(0.8)
                      };
 
                      ui.painter().rect_filled(
@@ -393,31 +538,226 @@ impl ToastManager
                         bar_color,
                      );
 
-                     // Show "Click X to dismiss" hint for indefinite toasts
+                     // Show "Click to dismiss" hint for indefinite toasts
                      if toast.is_indefinite()
                      {
                         ui.add_space(2.0);
                         ui.label(
-                           egui::RichText::new("Click ✖ to dismiss")
-                              .color(egui::Color32::from_white_alpha(180))
+                           egui::RichText::new("Click to dismiss")
+                              .color(theme.toast_text.gamma_multiply(0.7))
                               .size(11.0)
                               .italics(),
                         );
                      }
+                  }).response;
+
+               // Clicking anywhere on the toast dismisses it
+               if frame_response.interact(egui::Sense::click()).on_hover_cursor(egui::CursorIcon::PointingHand).clicked()
+                  && !toasts_to_remove.contains(&index)
+               {
+                  toasts_to_remove.push(index);
+               }
+            }).response;
+
+         y_offset += area_response.rect.height() + toast_spacing;
+      }
+
+      let hidden = self.toasts.len().saturating_sub(MAX_VISIBLE_TOASTS);
+      if hidden > 0
+      {
+         egui::Area::new(egui::Id::new("toast_more"))
+            .fixed_pos(egui::pos2(screen_rect.right() - toast_width - margin, screen_rect.top() + y_offset))
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui|
+            {
+               egui::Frame::new()
+                  .fill(theme.toast_background)
+                  .corner_radius(8.0)
+                  .inner_margin(6.0)
+                  .show(ui, |ui|
+                  {
+                     if ui.add(egui::Label::new(egui::RichText::new(format!("+{hidden} more")).color(theme.toast_text).size(14.0))
+                                 .sense(egui::Sense::click()))
+                          .on_hover_text("Show all notifications")
+                          .clicked()
+                     {
+                        self.show_history = true;
+                        self.unseen_count = 0;
+                     }
                   });
             });
-
-         y_offset += 100.0 + toast_spacing;
       }
 
       // Remove dismissed toasts
+      toasts_to_remove.sort_unstable();
       for &index in toasts_to_remove.iter().rev()
       {
          self.toasts.remove(index);
       }
 
-      // Request repaint to animate the progress bar
-      ctx.request_repaint();
+      // Animate the progress bars of timed toasts at a modest frame rate; indefinite toasts are static until clicked so
+      // they don't need continuous repaints.
+      if self.toasts.iter().any(|toast| !toast.is_indefinite())
+      {
+         ctx.request_repaint_after(Duration::from_millis(TOAST_REPAINT_MS));
+      }
+   }
+
+   fn show_history_window(&mut self, ctx: &egui::Context, theme: &Theme)
+   {
+      if ! self.show_history
+      {
+         return;
+      }
+      let mut is_open = true;
+      let mut clear = false;
+      egui::Window::new("Notifications")
+         .open(&mut is_open)
+         .collapsible(false)
+         .resizable(true)
+         .default_width(450.0)
+         .show(ctx, |ui|
+         {
+            if self.history.is_empty()
+            {
+               ui.label("No notifications.");
+               return;
+            }
+            egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui|
+            {
+               for record in self.history.iter().rev()
+               {
+                  ui.horizontal_wrapped(|ui|
+                  {
+                     ui.label(egui::RichText::new(record.level.icon()).color(record.level.color(theme)));
+                     ui.label(egui::RichText::new(record.time.format("%H:%M:%S").to_string()).small());
+                     ui.label(&record.message);
+                  });
+               }
+            });
+            ui.separator();
+            if ui.button("Clear").clicked()
+            {
+               clear = true;
+            }
+         });
+      if clear
+      {
+         self.history.clear();
+      }
+      if ! is_open
+      {
+         self.show_history = false;
+      }
+   }
+}
+
+/// Tracks distance over time to show ride progress with an ETA based on a rolling average speed.
+pub struct RideProgress
+//======================
+{
+   samples: VecDeque<(Instant, f64)>, // (time, distance in metres)
+   window:  Duration,
+}
+
+impl Default for RideProgress
+{
+   fn default() -> Self { Self::new(Duration::from_secs(60)) }
+}
+
+impl RideProgress
+{
+   pub fn new(window: Duration) -> Self { Self { samples: VecDeque::new(), window } }
+
+   pub fn reset(&mut self) { self.samples.clear(); }
+
+   /// Record the latest distance. Samples are only added when the distance changes, and are dropped when they
+   /// fall outside the averaging window (one sample older than the window is kept as the reference point).
+   pub fn update(&mut self, distance: f64)
+   //-------------------------------------
+   {
+      let now = Instant::now();
+      match self.samples.back()
+      {
+         | Some((_, last)) if (*last - distance).abs() < f64::EPSILON => (),
+         | Some((_, last)) if distance < *last => // Jumped backwards (new ride or restart)
+         {
+            self.samples.clear();
+            self.samples.push_back((now, distance));
+         }
+         | _ => self.samples.push_back((now, distance)),
+      }
+      while self.samples.len() > 2 && now.duration_since(self.samples[1].0) > self.window
+      {
+         self.samples.pop_front();
+      }
+   }
+
+   /// Rolling average speed in m/s, or None if there is not enough data.
+   pub fn average_speed(&self) -> Option<f64>
+   //-----------------------------------------
+   {
+      let (t0, d0) = self.samples.front()?;
+      let (_, d1) = self.samples.back()?;
+      let elapsed = t0.elapsed().as_secs_f64();
+      if elapsed < 1.0 || d1 <= d0
+      {
+         return None;
+      }
+      Some((d1 - d0) / elapsed)
+   }
+
+   pub fn eta(&self, distance: f64, total_distance: f64) -> Option<Duration>
+   //-----------------------------------------------------------------------
+   {
+      let speed = self.average_speed()?;
+      let remaining = (total_distance - distance).max(0.0);
+      if speed < 0.1
+      {
+         return None;
+      }
+      Some(Duration::from_secs_f64(remaining / speed))
+   }
+
+   /// Draw a thin progress bar with percentage and ETA.
+   pub fn show(&self, ui: &mut Ui, distance: f64, total_distance: f64, theme: &Theme, units: Units)
+   //----------------------------------------------------------------------------------------------
+   {
+      if total_distance <= 0.0
+      {
+         return;
+      }
+      let fraction = (distance / total_distance).clamp(0.0, 1.0) as f32;
+      let eta_text = match self.eta(distance, total_distance)
+      {
+         | Some(eta) =>
+         {
+            let secs = eta.as_secs();
+            format!("ETA {}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60)
+         }
+         | None => "ETA --:--:--".to_string(),
+      };
+      let text = format!("{:.1}/{}  {:.1}%  {}", units.to_distance(distance), units.format_distance(total_distance, 1), fraction * 100.0, eta_text);
+      ui.add(egui::ProgressBar::new(fraction)
+                .desired_height(16.0)
+                .fill(theme.top_panel_fill)
+                .text(egui::RichText::new(text).size(13.0)));
+   }
+}
+
+/// Accessible names for icon-only widgets, which screen readers would otherwise read out as the emoji.
+pub trait Labelled
+{
+   /// Name the widget `label` for screen readers.
+   fn labelled(self, label: &str) -> Self;
+}
+
+impl Labelled for Response
+{
+   fn labelled(self, label: &str) -> Self
+   {
+      self.widget_info(|| egui::WidgetInfo::labeled(egui::WidgetType::Button, self.enabled(), label));
+      self
    }
 }
 
@@ -435,4 +775,607 @@ fn toggle_button(ui: &mut Ui, text: &str, state: &mut bool) -> Response
       *state = !*state;
    }
    response
-}
\ No newline at end of file
+}
+/// Table of lap times, distances, ascents and average powers.
+pub fn splits_grid(ui: &mut Ui, id: &str, laps: &[LapSplit], color: egui::Color32, units: Units)
+//----------------------------------------------------------------------------------------------
+{
+   egui::Grid::new(id).num_columns(5).striped(true).spacing([20.0, 4.0]).show(ui, |ui|
+   {
+      for heading in ["Lap", "Time", "Distance", "Ascent", "Power"]
+      {
+         ui.label(egui::RichText::new(heading).color(color));
+      }
+      ui.end_row();
+      for lap in laps
+      {
+         let secs = lap.duration.round() as u64;
+         for value in [lap.lap.to_string(), format!("{}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60),
+                       units.format_distance(lap.distance, 2), units.format_length(lap.ascent),
+                       format!("{:.0} W", lap.average_power)]
+         {
+            ui.label(egui::RichText::new(value).color(color).strong());
+         }
+         ui.end_row();
+      }
+   });
+}
+
+/// Statistics shown on the ride completion card.
+#[derive(Debug, Clone, Copy)]
+pub struct RideSummary
+{
+   pub distance:      f64, // metres
+   pub ascent:        f64, // metres
+   pub elapsed:       Duration,
+   pub biggest_climb: Option<Climb>,
+}
+
+struct ConfettiPiece
+{
+   x:     f32, // Fraction of the screen width
+   speed: f32, // Fraction of the screen height per second
+   phase: f32,
+   size:  f32,
+   color: egui::Color32,
+}
+
+const COMPLETION_TOLERANCE: f64 = 20.0; // metres from the end of the track counted as finished
+const CONFETTI_PIECES: usize = 150;
+const CONFETTI_DURATION: Duration = Duration::from_secs(6);
+
+/// Detects the end of the course and shows a celebratory stats card with confetti. The card can be saved as a PNG
+/// (cropped from a viewport screenshot) for sharing.
+#[derive(Default)]
+pub struct RideCompletion
+//========================
+{
+   started:     Option<Instant>,
+   summary:     Option<RideSummary>,
+   shown_at:    Option<Instant>,
+   is_finished: bool,
+   confetti:    Vec<ConfettiPiece>,
+   card_rect:   Option<egui::Rect>,
+   is_saving:   bool,
+}
+
+impl RideCompletion
+{
+   pub fn reset(&mut self) { *self = Self::default(); }
+
+   /// Record the current distance, returning true when the course has just been completed.
+   pub fn update(&mut self, distance: f64, total_distance: f64, track: &Track) -> bool
+   //----------------------------------------------------------------------------------
+   {
+      if distance <= 0.0 || total_distance <= 0.0
+      {
+         return false;
+      }
+      let started = *self.started.get_or_insert_with(Instant::now);
+      if self.is_finished || distance < total_distance - COMPLETION_TOLERANCE
+      {
+         return false;
+      }
+      self.is_finished = true;
+      let (ascent, _) = track.elevation_gain_loss(2.0);
+      let biggest_climb = track.climbs(20.0, 2.0).into_iter().max_by(|a, b| a.gain.total_cmp(&b.gain));
+      self.summary = Some(RideSummary { distance: total_distance, ascent, elapsed: started.elapsed(), biggest_climb });
+      self.shown_at = Some(Instant::now());
+      self.confetti = Self::new_confetti();
+      true
+   }
+
+   fn new_confetti() -> Vec<ConfettiPiece>
+   //--------------------------------------
+   {
+      const COLORS: [egui::Color32; 6] = [egui::Color32::from_rgb(239, 71, 111), egui::Color32::from_rgb(255, 209, 102),
+                                          egui::Color32::from_rgb(6, 214, 160), egui::Color32::from_rgb(17, 138, 178),
+                                          egui::Color32::from_rgb(255, 140, 0), egui::Color32::from_rgb(180, 90, 230)];
+      // Cheap xorshift; the confetti only has to look random.
+      let mut seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64) | 1;
+      let mut next = move ||
+      {
+         seed ^= seed << 13;
+         seed ^= seed >> 7;
+         seed ^= seed << 17;
+         (seed % 10_000) as f32 / 10_000.0
+      };
+      (0..CONFETTI_PIECES).map(|i| ConfettiPiece { x: next(),
+                                                   speed: 0.15 + next() * 0.25,
+                                                   phase: next() * std::f32::consts::TAU,
+                                                   size: 4.0 + next() * 6.0,
+                                                   color: COLORS[i % COLORS.len()] })
+                           .collect()
+   }
+
+   /// Show the card, with the `laps` when the ride had more than one.
+   pub fn show(&mut self, ctx: &egui::Context, laps: &[LapSplit], theme: &Theme, units: Units)
+   //------------------------------------------------------------------------------------------
+   {
+      let (Some(summary), Some(shown_at)) = (self.summary, self.shown_at) else { return; };
+      self.save_screenshot(ctx);
+
+      let screen_rect = ctx.content_rect();
+      let age = shown_at.elapsed();
+      if age < CONFETTI_DURATION && !self.is_saving
+      {
+         let t = age.as_secs_f32();
+         let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("confetti")));
+         for piece in &self.confetti
+         {
+            let y = screen_rect.top() + ((t * piece.speed + piece.phase / 10.0) % 1.2 - 0.1) * screen_rect.height();
+            let x = screen_rect.left() + piece.x * screen_rect.width() + (t * 2.0 + piece.phase).sin() * 20.0;
+            let size = egui::vec2(piece.size, piece.size * 0.5 * (1.0 + (t * 6.0 + piece.phase).cos()));
+            painter.rect_filled(egui::Rect::from_center_size(egui::pos2(x, y), size), 1.0, piece.color);
+         }
+         ctx.request_repaint_after(Duration::from_millis(33));
+      }
+
+      let mut is_closed = false;
+      let response = egui::Area::new(egui::Id::new("ride_completion"))
+         .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+         .order(egui::Order::Foreground)
+         .show(ctx, |ui|
+         {
+            egui::Frame::new()
+               .fill(theme.toast_background)
+               .stroke(egui::Stroke::new(3.0, theme.toast_success))
+               .corner_radius(12.0)
+               .inner_margin(24.0)
+               .show(ui, |ui|
+               {
+                  ui.set_width(420.0);
+                  ui.vertical_centered(|ui|
+                  {
+                     ui.label(egui::RichText::new("🏁 Course complete!").size(32.0).strong().color(theme.toast_success));
+                     ui.add_space(12.0);
+                  });
+                  egui::Grid::new("ride_summary_grid").num_columns(2).spacing([30.0, 8.0]).show(ui, |ui|
+                  {
+                     let secs = summary.elapsed.as_secs();
+                     for (label, value) in [("Distance", units.format_distance(summary.distance, 1)),
+                                            ("Total ascent", units.format_length(summary.ascent)),
+                                            ("Time", format!("{}:{:02}:{:02}", secs / 3600, (secs % 3600) / 60, secs % 60))]
+                     {
+                        ui.label(egui::RichText::new(label).color(theme.toast_text));
+                        ui.label(egui::RichText::new(value).color(theme.toast_text).strong());
+                        ui.end_row();
+                     }
+                     if let Some(climb) = summary.biggest_climb
+                     {
+                        ui.label(egui::RichText::new("Biggest climb").color(theme.toast_text));
+                        ui.label(egui::RichText::new(format!("{} at {:.1}% (+{}, max {:.1}%)", units.format_distance(climb.length(), 1),
+                                                             climb.average_gradient, units.format_length(climb.gain), climb.max_gradient))
+                                    .color(theme.toast_text).strong());
+                        ui.end_row();
+                     }
+                  });
+                  if !laps.is_empty()
+                  {
+                     ui.add_space(12.0);
+                     splits_grid(ui, "ride_summary_laps", laps, theme.toast_text, units);
+                  }
+                  if !self.is_saving
+                  {
+                     ui.add_space(16.0);
+                     ui.horizontal(|ui|
+                     {
+                        if ui.button("💾 Save image").on_hover_text("Save this summary as a PNG image").clicked()
+                        {
+                           self.is_saving = true;
+                           ui.ctx().send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::default()));
+                        }
+                        if ui.button("Close").clicked()
+                        {
+                           is_closed = true;
+                        }
+                     });
+                  }
+               });
+         }).response;
+      self.card_rect = Some(response.rect);
+      if is_closed
+      {
+         self.summary = None;
+         self.confetti.clear();
+      }
+   }
+
+   /// Crop a pending viewport screenshot to the summary card and ask where to save it.
+   fn save_screenshot(&mut self, ctx: &egui::Context)
+   //------------------------------------------------
+   {
+      if !self.is_saving
+      {
+         return;
+      }
+      let image = ctx.input(|i| i.raw.events.iter().find_map(|e|
+      {
+         if let egui::Event::Screenshot { image, .. } = e { Some(image.clone()) } else { None }
+      }));
+      let (Some(image), Some(rect)) = (image, self.card_rect) else { return; };
+      self.is_saving = false;
+      let card = image.region(&rect, Some(ctx.pixels_per_point()));
+      if let Some(path) = rfd::FileDialog::new().set_file_name("ride-summary.png").add_filter("PNG image", &["png"]).save_file()
+      {
+         match crate::ui::ui::save_image(&card, path.display().to_string())
+         {
+            | Ok(_) => log_info!("Saved ride summary to {:?}", path),
+            | Err(e) => log_error!("Failed to save ride summary: {}", e),
+         }
+      }
+   }
+}
+
+/// Warns ahead of climbs whose steepest section exceeds the configured threshold.
+#[derive(Default)]
+pub struct ClimbAlerter
+//=====================
+{
+   config:        ClimbAlerts,
+   climbs:        Option<Vec<Climb>>, // Steep climbs on the current track, found on first use
+   next_alert:    usize,
+   last_distance: f64,
+}
+
+impl ClimbAlerter
+{
+   pub fn new(config: ClimbAlerts) -> Self { Self { config, ..Default::default() } }
+
+   pub fn configure(&mut self, config: ClimbAlerts)
+   {
+      if self.config != config
+      {
+         self.config = config;
+         self.reset();
+      }
+   }
+
+   /// Forget the detected climbs, e.g. when a new track is opened.
+   pub fn reset(&mut self)
+   {
+      self.climbs = None;
+      self.next_alert = 0;
+      self.last_distance = 0.0;
+   }
+
+   /// Check the current distance against the upcoming climbs, raising a toast (and optionally a sound) once per climb.
+   pub fn update(&mut self, distance: f64, track: &Track, toast_manager: &mut ToastManager, units: Units)
+   //----------------------------------------------------------------------------------------------------
+   {
+      if !self.config.is_enabled || distance <= 0.0
+      {
+         return;
+      }
+      let threshold = self.config.threshold;
+      let climbs = self.climbs.get_or_insert_with(||
+      {
+         track.climbs(10.0, 1.0).into_iter().filter(|climb| climb.max_gradient >= threshold).collect()
+      });
+      if distance < self.last_distance
+      {  // Moved backwards (restart or scrub) so re-arm the alerts ahead of the new position
+         self.next_alert = climbs.iter().position(|climb| climb.start > distance).unwrap_or(climbs.len());
+      }
+      self.last_distance = distance;
+      while let Some(climb) = climbs.get(self.next_alert)
+      {
+         if distance >= climb.end
+         {
+            self.next_alert += 1;
+            continue;
+         }
+         if distance < climb.start - self.config.distance
+         {
+            break;
+         }
+         let to_go = (climb.start - distance).max(0.0);
+         toast_manager.warning(format!("Climb in {}: {} at {:.1}%, max {:.1}%", units.format_length(to_go), units.format_distance(climb.length(), 1),
+                                        climb.average_gradient, climb.max_gradient),
+                              Some(Duration::from_secs(8)));
+         if self.config.is_sound
+         {
+            crate::ut::play_alert_sound();
+         }
+         self.next_alert += 1;
+      }
+   }
+}
+
+/// Tags the screenshots requested by `ViewCapture` so other screenshots (such as the ride completion card) are ignored.
+const VIEW_CAPTURE_TAG: &str = "view_capture";
+
+/// Captures the current view with a heads-up display of the ride (course, distance, gradient) to a PNG file and the
+/// clipboard for sharing. The HUD is drawn over the view until the screenshot arrives a frame or two later.
+#[derive(Default)]
+pub struct ViewCapture
+//====================
+{
+   is_pending: bool,
+   rect:       Option<egui::Rect>, // of the view, set by `show_hud`
+}
+
+impl ViewCapture
+{
+   /// Ask for a screenshot of the next frame.
+   pub fn request(&mut self, ctx: &egui::Context)
+   //--------------------------------------------
+   {
+      self.is_pending = true;
+      ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::new(VIEW_CAPTURE_TAG)));
+   }
+
+   pub fn is_pending(&self) -> bool { self.is_pending }
+
+   /// Draw the `lines` of the HUD over the bottom left of the view `rect` while a capture is pending.
+   pub fn show_hud(&mut self, ctx: &egui::Context, rect: egui::Rect, lines: &[String])
+   //---------------------------------------------------------------------------------
+   {
+      if !self.is_pending
+      {
+         return;
+      }
+      self.rect = Some(rect);
+      let painter = ctx.layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("capture_hud"))).with_clip_rect(rect);
+      let galley = painter.layout_no_wrap(lines.join("\n"), egui::FontId::proportional(20.0), egui::Color32::WHITE);
+      let margin = egui::vec2(10.0, 8.0);
+      let background = egui::Rect::from_min_size(rect.left_bottom() + egui::vec2(12.0, -12.0 - galley.size().y - margin.y * 2.0),
+                                                 galley.size() + margin * 2.0);
+      painter.rect_filled(background, 8.0, egui::Color32::from_black_alpha(160));
+      painter.galley(background.min + margin, galley, egui::Color32::WHITE);
+   }
+
+   /// Once the screenshot has arrived, save the view to a PNG in `directory` named after `name` and copy it to the
+   /// clipboard, returning where it was saved.
+   pub fn take(&mut self, ctx: &egui::Context, directory: &std::path::Path, name: &str) -> Option<Result<std::path::PathBuf, String>>
+   //-----------------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.is_pending
+      {
+         return None;
+      }
+      let image = ctx.input(|i| i.raw.events.iter().find_map(|e| match e
+      {
+         | egui::Event::Screenshot { image, user_data, .. }
+            if user_data.data.as_ref().and_then(|data| data.downcast_ref::<&str>()) == Some(&VIEW_CAPTURE_TAG) => Some(image.clone()),
+         | _ => None,
+      }))?;
+      self.is_pending = false;
+      let view = match self.rect.take()
+      {
+         | Some(rect) => image.region(&rect, Some(ctx.pixels_per_point())),
+         | None => (*image).clone(),
+      };
+      ctx.copy_image(view.clone());
+      let result = std::fs::create_dir_all(directory).map_err(|e| format!("Error creating {}: {}", directory.display(), e)).and_then(|_|
+      {
+         let path = directory.join(format!("{}-{}.png", crate::ut::safe_file_name(name), Local::now().format("%Y%m%d-%H%M%S")));
+         crate::ui::ui::save_image(&view, path.display().to_string()).map(|_| path)
+      });
+      Some(result)
+   }
+}
+
+/// Elevation changes smaller than this are not counted in the remaining ascent (as for the course summary).
+const ASCENT_THRESHOLD: f64 = 3.0; // metres
+
+/// Percent complete, remaining ascent and current altitude drawn over the view, the same in every view mode.
+#[derive(Default)]
+pub struct CourseStats
+//=====================
+{
+   is_enabled: bool,
+   ascent:     Vec<f64>, // cumulative ascent at each point of the current track, found on first use
+}
+
+impl CourseStats
+{
+   pub fn new(is_enabled: bool) -> Self { Self { is_enabled, ascent: Vec::new() } }
+
+   pub fn set_enabled(&mut self, is_enabled: bool) { self.is_enabled = is_enabled; }
+
+   /// Forget the track, e.g. when a new one is opened.
+   pub fn reset(&mut self) { self.ascent.clear(); }
+
+   /// Show the stats for `distance` metres along `track` in the top left corner of the view `rect`.
+   pub fn show(&mut self, ctx: &egui::Context, rect: egui::Rect, distance: f64, track: &Track, theme: &Theme, units: Units)
+   //----------------------------------------------------------------------------------------------------------------------
+   {
+      let total_distance = track.total_distance();
+      if !self.is_enabled || total_distance <= 0.0
+      {
+         return;
+      }
+      if self.ascent.len() != track.len()
+      {
+         self.ascent = track.cumulative_ascent(ASCENT_THRESHOLD);
+      }
+      let Some(index) = track.closest_index(distance) else { return; };
+      let remaining = self.ascent.last().copied().unwrap_or(0.0) - self.ascent[index];
+      let altitude = track.point(index).altitude;
+      let percent = (distance / total_distance).clamp(0.0, 1.0) * 100.0;
+      egui::Area::new(egui::Id::new("course_stats"))
+         .order(egui::Order::Foreground)
+         .fixed_pos(rect.left_top() + egui::vec2(12.0, 12.0))
+         .interactable(false)
+         .show(ctx, |ui|
+         {
+            egui::Frame::new().fill(egui::Color32::from_black_alpha(160)).corner_radius(8.0).inner_margin(8.0).show(ui, |ui|
+            {
+               egui::Grid::new("course_stats_grid").num_columns(2).spacing([10.0, 2.0]).show(ui, |ui|
+               {
+                  for (label, value) in [("Complete", format!("{percent:.1}%")), ("To climb", units.format_length(remaining)),
+                                         ("Altitude", units.format_length(altitude))]
+                  {
+                     ui.label(egui::RichText::new(label).color(theme.gradient_label).size(14.0));
+                     ui.label(egui::RichText::new(value).color(egui::Color32::WHITE).strong().size(16.0));
+                     ui.end_row();
+                  }
+               });
+            });
+         });
+   }
+}
+
+/// Turns are looked for this far ahead of the rider ...
+const TURN_LOOK_AHEAD: f64 = 500.0; // metres
+/// ... when the direction changes by at least TURN_THRESHOLD degrees. Turns under BEAR_ANGLE are shown as bearing
+/// left or right and turns over SHARP_ANGLE as sharp.
+const TURN_THRESHOLD: f64 = 30.0;
+const BEAR_ANGLE: f64 = 60.0;
+const SHARP_ANGLE: f64 = 110.0;
+const TURN_ICON_SIZE: f32 = 56.0;
+
+/// Arrow and countdown for the next significant change of direction, drawn over the Map and Street View modes.
+#[derive(Default)]
+pub struct TurnIndicator
+//======================
+{
+   is_enabled: bool,
+}
+
+impl TurnIndicator
+{
+   pub fn new(is_enabled: bool) -> Self { Self { is_enabled } }
+
+   pub fn set_enabled(&mut self, is_enabled: bool) { self.is_enabled = is_enabled; }
+
+   /// e.g. "Bear left", "Turn right" or "Sharp left".
+   fn label(turn: &Turn) -> String
+   //-----------------------------
+   {
+      let kind = match turn.angle.abs()
+      {
+         | a if a < BEAR_ANGLE => "Bear",
+         | a if a < SHARP_ANGLE => "Turn",
+         | _ => "Sharp",
+      };
+      format!("{kind} {}", if turn.angle < 0.0 { "left" } else { "right" })
+   }
+
+   /// Show the next turn after `distance` metres along `track` at the top centre of the view `rect`.
+   pub fn show(&self, ctx: &egui::Context, rect: egui::Rect, distance: f64, track: &Track, theme: &Theme, units: Units)
+   //------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.is_enabled
+      {
+         return;
+      }
+      let Some(turn) = track.next_turn(distance, TURN_LOOK_AHEAD, TURN_THRESHOLD) else { return; };
+      let countdown = (turn.distance - distance).max(0.0);
+      egui::Area::new(egui::Id::new("turn_indicator"))
+         .order(egui::Order::Foreground)
+         .pivot(egui::Align2::CENTER_TOP)
+         .fixed_pos(rect.center_top() + egui::vec2(0.0, 12.0))
+         .interactable(false)
+         .show(ctx, |ui|
+         {
+            egui::Frame::new().fill(egui::Color32::from_black_alpha(160)).corner_radius(8.0).inner_margin(8.0).show(ui, |ui|
+            {
+               ui.horizontal(|ui|
+               {
+                  let (response, painter) = ui.allocate_painter(egui::Vec2::splat(TURN_ICON_SIZE), egui::Sense::hover());
+                  draw_turn_arrow(&painter, response.rect, turn.angle as f32, theme.gradient_label);
+                  ui.vertical(|ui|
+                  {
+                     ui.label(egui::RichText::new(Self::label(&turn)).color(theme.gradient_label).size(14.0));
+                     let countdown = if countdown < 1000.0 { units.format_length(countdown) } else { units.format_distance(countdown, 1) };
+                     ui.label(egui::RichText::new(countdown).color(egui::Color32::WHITE).strong().size(20.0));
+                  });
+               });
+            });
+         });
+   }
+}
+
+/// Draw a road arrow in `rect`, coming up from the bottom and bending `angle` degrees (positive right) at the centre.
+fn draw_turn_arrow(painter: &egui::Painter, rect: egui::Rect, angle: f32, color: egui::Color32)
+//---------------------------------------------------------------------------------------------
+{
+   let stroke = egui::Stroke::new(rect.width() / 8.0, color);
+   let centre = rect.center() + egui::vec2(0.0, rect.height() * 0.1);
+   let length = rect.height() * 0.35;
+   let direction = egui::vec2(angle.to_radians().sin(), -angle.to_radians().cos());
+   let tip = centre + direction * length;
+   painter.line_segment([egui::pos2(centre.x, rect.bottom()), centre], stroke);
+   painter.line_segment([centre, tip - direction * stroke.width], stroke);
+   let side = direction.rot90() * stroke.width * 1.4;
+   let base = tip - direction * stroke.width * 2.2;
+   painter.add(egui::Shape::convex_polygon(vec![tip, base + side, base - side], color, egui::Stroke::NONE));
+   painter.circle_filled(centre, stroke.width / 2.0, color);
+}
+
+/// Waypoints closer together on screen than this are drawn as one cluster.
+const CLUSTER_RADIUS: f32 = 36.0; // pixels
+
+/// Walkers Plugin drawing the course waypoints, with nearby waypoints clustered into a count badge so the map stays
+/// legible when zoomed out. Clusters split up as the map is zoomed in.
+pub struct WaypointMarkers
+//========================
+{
+   pub(crate) waypoints: Arc<Vec<Waypoint>>,
+   pub(crate) color:     egui::Color32,
+}
+
+impl Plugin for WaypointMarkers
+{
+   fn run(self: Box<Self>, ui: &mut egui::Ui, response: &egui::Response, projector: &Projector, _map_memory: &MapMemory)
+   //--------------------------------------------------------------------------------------------------------------------
+   {
+      let visible = response.rect.expand(CLUSTER_RADIUS);
+      // (screen position of the first waypoint, sum of positions, waypoint indices)
+      let mut clusters: Vec<(egui::Pos2, egui::Vec2, Vec<usize>)> = Vec::new();
+      for (i, waypoint) in self.waypoints.iter().enumerate()
+      {
+         let position = projector.project(lon_lat(waypoint.point.lon, waypoint.point.lat)).to_pos2();
+         if !visible.contains(position)
+         {
+            continue;
+         }
+         match clusters.iter_mut().find(|(first, _, _)| first.distance(position) < CLUSTER_RADIUS)
+         {
+            | Some((_, sum, members)) =>
+            {
+               *sum += position.to_vec2();
+               members.push(i);
+            }
+            | None => clusters.push((position, position.to_vec2(), vec![i])),
+         }
+      }
+      let painter = ui.painter_at(response.rect);
+      let hover = response.hover_pos();
+      for (_, sum, members) in clusters
+      {
+         let center = (sum / members.len() as f32).to_pos2();
+         if let &[index] = members.as_slice()
+         {
+            let tip = center;
+            let head = tip - egui::vec2(0.0, 14.0);
+            painter.line_segment([tip, head], egui::Stroke::new(2.0, self.color));
+            painter.circle(head, 6.0, self.color, egui::Stroke::new(1.5, egui::Color32::WHITE));
+            let name = &self.waypoints[index].name;
+            if !name.is_empty()
+            {
+               painter.text(head + egui::vec2(9.0, 0.0), egui::Align2::LEFT_CENTER, name, egui::FontId::proportional(12.0),
+                            egui::Color32::BLACK);
+            }
+         }
+         else
+         {
+            let radius = 11.0 + (members.len() as f32).log10() * 4.0;
+            painter.circle(center, radius, self.color, egui::Stroke::new(2.0, egui::Color32::WHITE));
+            painter.text(center, egui::Align2::CENTER_CENTER, members.len().to_string(), egui::FontId::proportional(13.0),
+                         egui::Color32::WHITE);
+            if hover.is_some_and(|pos| pos.distance(center) <= radius)
+            {
+               let names: Vec<&str> = members.iter().map(|&i| self.waypoints[i].name.as_str()).filter(|n| !n.is_empty()).take(10).collect();
+               if !names.is_empty()
+               {
+                  let more = if members.len() > names.len() { format!("\n+{} more, zoom in to see them", members.len() - names.len()) }
+                             else { String::new() };
+                  response.clone().on_hover_text(format!("{}{more}", names.join("\n")));
+               }
+            }
+         }
+      }
+   }
+}
@@ -0,0 +1,91 @@
+use std::{backtrace::Backtrace, fs, panic::PanicHookInfo, path::{Path, PathBuf}};
+
+use chrono::Local;
+
+use crate::SETTINGS;
+
+/// Holds the path of the report written by the last crash until it has been shown on the next launch.
+const PENDING_FILE: &str = "crash-pending";
+const REPORT_PREFIX: &str = "crash-report-";
+/// Older reports are deleted when a new one is written.
+const MAX_REPORTS: usize = 5;
+
+/// Install a panic hook which, after the default message, writes a crash report (backtrace, system and the settings
+/// without secrets) to `directory`, normally the config directory, for `take_pending` to offer on the next launch.
+pub fn install(directory: PathBuf)
+//--------------------------------
+{
+   let previous = std::panic::take_hook();
+   std::panic::set_hook(Box::new(move |info|
+   {
+      previous(info);
+      let report = crash_report(info);
+      match write_report(&directory, &report)
+      {
+         | Ok(path) =>
+         {
+            log_error!("GPXAssist panicked: {}, crash report written to {}", panic_message(info), path.display());
+            eprintln!("Crash report written to {}", path.display());
+         }
+         | Err(e) => eprintln!("Could not write the crash report to {}: {}", directory.display(), e),
+      }
+   }));
+}
+
+/// The report written by a crash since the last launch, if any. Each report is only returned once.
+pub fn take_pending(directory: &Path) -> Option<PathBuf>
+//------------------------------------------------------
+{
+   let pending = directory.join(PENDING_FILE);
+   let report = PathBuf::from(fs::read_to_string(&pending).ok()?.trim());
+   let _ = fs::remove_file(&pending);
+   report.is_file().then_some(report)
+}
+
+fn panic_message(info: &PanicHookInfo) -> String
+//----------------------------------------------
+{
+   let payload = info.payload();
+   payload.downcast_ref::<&str>().map(|s| s.to_string())
+          .or_else(|| payload.downcast_ref::<String>().cloned())
+          .unwrap_or_else(|| "(no message)".to_string())
+}
+
+fn crash_report(info: &PanicHookInfo) -> String
+//----------------------------------------------
+{
+   let thread = std::thread::current();
+   let location = info.location().map_or("unknown".to_string(), |l| format!("{}:{}:{}", l.file(), l.line(), l.column()));
+   // The crashed thread may hold the settings lock, so do not wait for it
+   let settings = SETTINGS.get().and_then(|settings| settings.try_lock().map(|settings| settings.redacted_toml()))
+                          .unwrap_or_else(|| "(unavailable, the settings were in use when GPXAssist crashed)\n".to_string());
+   format!("GPXAssist {} crash report, {}\n\
+            System:   {} {} ({})\n\
+            Thread:   {}\n\
+            Panic:    {}\n\
+            Location: {}\n\n\
+            Backtrace:\n{}\n\n\
+            Settings (secrets removed):\n{}",
+           env!("CARGO_PKG_VERSION"), Local::now().format("%Y-%m-%d %H:%M:%S %z"),
+           std::env::consts::OS, std::env::consts::ARCH, std::env::consts::FAMILY,
+           thread.name().unwrap_or("unnamed"), panic_message(info), location, Backtrace::force_capture(), settings)
+}
+
+/// Write `report` to a new timestamped file in `directory`, mark it pending and delete the oldest reports.
+fn write_report(directory: &Path, report: &str) -> std::io::Result<PathBuf>
+//------------------------------------------------------------------------
+{
+   fs::create_dir_all(directory)?;
+   let path = directory.join(format!("{REPORT_PREFIX}{}.txt", Local::now().format("%Y%m%d-%H%M%S")));
+   fs::write(&path, report)?;
+   fs::write(directory.join(PENDING_FILE), path.to_string_lossy().as_bytes())?;
+   let mut reports: Vec<PathBuf> = fs::read_dir(directory)?.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                                                           .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().starts_with(REPORT_PREFIX)))
+                                                           .collect();
+   reports.sort();
+   for old in reports.iter().rev().skip(MAX_REPORTS)
+   {
+      let _ = fs::remove_file(old);
+   }
+   Ok(path)
+}
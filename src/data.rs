@@ -1,6 +1,11 @@
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
+/// A rider entry from the broadcast file. Missing fields take their default and unknown fields are ignored, so a
+/// game update adding, renaming or dropping a field doesn't stop the broadcast from being read.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RiderDataJSON 
 {
     pub name: String,
@@ -154,10 +159,16 @@ pub fn parse_rider_json(json_str: &str) -> Result<RiderDataJSON, String> { Rider
 pub struct RiderData
 {    
     pub distance: i32,
+    pub speed: i32, // millimetres per second
+    pub power: i32,
+    pub heartrate: i32,
+    pub cadence: i32,
     pub wind_angle: i32,
     pub wind_speed: i32,
     pub slope: i32,
     pub height: i32,
+    pub tss: i32,
+    pub event_laps_done: i32,
     pub latitude: f64,
     pub longitude: f64, 
     pub altitude: f64
@@ -170,10 +181,16 @@ impl From<RiderDataJSON> for RiderData
         Self
         {
             distance: rider.distance,
+            speed: rider.speed,
+            power: rider.power,
+            heartrate: rider.heartrate,
+            cadence: rider.cadence,
             wind_angle: rider.wind_angle,
             wind_speed: rider.wind_speed,
             slope: rider.slope,
             height: rider.height,
+            tss: rider.tss,
+            event_laps_done: rider.event_laps_done,
             latitude: rider.latitude,
             longitude: rider.longitude,
             altitude: rider.altitude,
@@ -188,10 +205,16 @@ impl From<&RiderDataJSON> for RiderData
         Self
         {
             distance: rider.distance,
+            speed: rider.speed,
+            power: rider.power,
+            heartrate: rider.heartrate,
+            cadence: rider.cadence,
             wind_angle: rider.wind_angle,
             wind_speed: rider.wind_speed,
             slope: rider.slope,
             height: rider.height,
+            tss: rider.tss,
+            event_laps_done: rider.event_laps_done,
             latitude: rider.latitude,
             longitude: rider.longitude,
             altitude: rider.altitude,
@@ -206,13 +229,76 @@ impl Default for RiderData
         Self
         {
             distance: 0,
+            speed: 0,
+            power: 0,
+            heartrate: 0,
+            cadence: 0,
             wind_angle: 0,
             wind_speed: 0,
             slope: 0,
             height: 0,
+            tss: 0,
+            event_laps_done: 0,
             latitude: 0.0,
             longitude: 0.0,
             altitude: 0.0,
         }
     }
 }   
+
+impl RiderData
+{
+    /// Get the rider's current speed in km/h (converts from mm/s -> km/h)
+    pub fn speed_kmh(&self) -> f64 { self.speed as f64 / 1000.0 * 3.6 }
+}
+
+/// The rider's name, team and country, which `RiderData` leaves out to stay Copy.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RiderIdentity
+{
+    pub name: String,
+    pub team: String,
+    pub country: String,
+}
+
+impl From<&RiderDataJSON> for RiderIdentity
+{
+    fn from(rider: &RiderDataJSON) -> Self
+    {
+        let team = if rider.team.trim().is_empty() { rider.team_code.trim() } else { rider.team.trim() };
+        Self { name: rider.name.trim().to_string(), team: team.to_string(), country: rider.country.trim().to_string() }
+    }
+}
+
+impl RiderIdentity
+{
+    pub fn is_empty(&self) -> bool { self.name.is_empty() }
+
+    /// "Name (Team, Country)", leaving out whatever the broadcast didn't include.
+    pub fn describe(&self) -> String
+    //------------------------------
+    {
+        let details: Vec<&str> = [self.team.as_str(), self.country.as_str()].into_iter().filter(|s| !s.is_empty()).collect();
+        if details.is_empty() { self.name.clone() } else { format!("{} ({})", self.name, details.join(", ")) }
+    }
+}
+
+/// The latest `RiderIdentity`, replaced by the broadcast thread when it changes and read by the UI every frame. Readers
+/// get a shared snapshot so the lock is only held long enough to clone an `Arc`.
+#[derive(Clone, Default)]
+pub struct SharedIdentity(Arc<parking_lot::Mutex<Arc<RiderIdentity>>>);
+
+impl SharedIdentity
+{
+    pub fn load(&self) -> Arc<RiderIdentity> { self.0.lock().clone() }
+
+    pub fn store(&self, identity: RiderIdentity)
+    //------------------------------------------
+    {
+        let mut current = self.0.lock();
+        if **current != identity
+        {
+            *current = Arc::new(identity);
+        }
+    }
+}
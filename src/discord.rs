@@ -0,0 +1,172 @@
+use std::{io::BufReader, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use crossbeam::channel::{Receiver, Sender};
+use discord_rich_presence::{DiscordIpc, DiscordIpcClient, activity};
+
+use crate::{gpx::course_name, settings::DiscordSettings, units::Units};
+
+/// Discord ignores presence updates sent more often than every 15 seconds.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(15);
+/// Delay before trying again when Discord is not running or the connection was lost.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What is shown on the user's Discord profile.
+#[derive(Debug, Clone)]
+struct Presence
+{
+   details: String,      // course name
+   state:   String,      // distance completed
+   start:   Option<i64>, // unix time the ride started, shown by Discord as the elapsed time
+}
+
+/// Publishes the course being ridden, the distance completed and the elapsed time as Discord Rich Presence. The
+/// Discord connection is made on a background thread, which is stopped (clearing the presence) when disabled.
+#[derive(Default)]
+pub struct DiscordPresence
+//========================
+{
+   config:    DiscordSettings,
+   sender:    Option<Sender<Presence>>,
+   course:    Option<(PathBuf, String)>, // course file and its name
+   started:   Option<i64>,
+   last_sent: Option<Instant>,
+}
+
+impl DiscordPresence
+{
+   pub fn new(config: DiscordSettings) -> Self
+   //-----------------------------------------
+   {
+      let mut presence = Self::default();
+      presence.configure(config);
+      presence
+   }
+
+   /// Start or stop publishing, reconnecting when the application id changes.
+   pub fn configure(&mut self, config: DiscordSettings)
+   //--------------------------------------------------
+   {
+      if config == self.config
+      {
+         return;
+      }
+      self.sender = None; // the thread clears the presence and exits once its channel is closed
+      self.last_sent = None;
+      if config.is_enabled && !config.application_id.trim().is_empty()
+      {
+         let (sender, receiver) = crossbeam::channel::unbounded();
+         let application_id = config.application_id.trim().to_string();
+         std::thread::spawn(move || publish(&application_id, receiver));
+         self.sender = Some(sender);
+      }
+      self.config = config;
+   }
+
+   /// Restart the elapsed time, e.g. when a new track is opened.
+   pub fn reset(&mut self)
+   //---------------------
+   {
+      self.started = None;
+      self.last_sent = None;
+   }
+
+   /// Publish the ride at `distance` along `course`, at most every UPDATE_INTERVAL.
+   pub fn update(&mut self, distance: f64, course: &Path, total_distance: f64, units: Units)
+   //---------------------------------------------------------------------------------------
+   {
+      let Some(sender) = &self.sender else { return; };
+      if self.course.as_ref().is_none_or(|(file, _)| file != course)
+      {
+         self.course = Some((course.to_path_buf(), read_course_name(course)));
+      }
+      if distance > 0.0 && self.started.is_none()
+      {
+         self.started = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).ok().map(|d| d.as_secs() as i64);
+      }
+      if self.last_sent.is_some_and(|time| time.elapsed() < UPDATE_INTERVAL)
+      {
+         return;
+      }
+      let state = if distance > 0.0
+      {
+         format!("{} of {}", units.format_distance(distance, 1), units.format_distance(total_distance, 1))
+      }
+      else
+      {
+         format!("Ready to ride {}", units.format_distance(total_distance, 1))
+      };
+      let presence = Presence { details: self.course.as_ref().map(|(_, name)| name.clone()).unwrap_or_default(), state,
+                                start: self.started };
+      if sender.send(presence).is_ok()
+      {
+         self.last_sent = Some(Instant::now());
+      }
+   }
+}
+
+fn read_course_name(course: &Path) -> String
+//------------------------------------------
+{
+   std::fs::File::open(course).ok()
+                              .and_then(|file| gpx::read(BufReader::new(file)).ok())
+                              .and_then(|gpx| course_name(&gpx))
+                              .unwrap_or_else(|| course.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default())
+}
+
+fn connect(application_id: &str) -> Result<DiscordIpcClient, String>
+//-------------------------------------------------------------------
+{
+   let mut client = DiscordIpcClient::new(application_id).map_err(|e| e.to_string())?;
+   client.connect().map_err(|e| e.to_string())?;
+   Ok(client)
+}
+
+/// Background thread sending each presence received to the Discord client, connecting when Discord is (re)started.
+fn publish(application_id: &str, receiver: Receiver<Presence>)
+//------------------------------------------------------------
+{
+   let mut client: Option<DiscordIpcClient> = None;
+   let mut retry_at = Instant::now();
+   let mut is_error_logged = false;
+   while let Ok(presence) = receiver.recv()
+   {
+      if client.is_none() && Instant::now() >= retry_at
+      {
+         match connect(application_id)
+         {
+            | Ok(connected) =>
+            {
+               log_info!("Connected to Discord for Rich Presence");
+               client = Some(connected);
+               is_error_logged = false;
+            }
+            | Err(e) =>
+            {
+               if !is_error_logged
+               {
+                  log_warn!("Could not connect to Discord (is it running?): {}", e);
+                  is_error_logged = true;
+               }
+               retry_at = Instant::now() + RETRY_INTERVAL;
+            }
+         }
+      }
+      let Some(connected) = client.as_mut() else { continue; };
+      let mut activity = activity::Activity::new().details(&presence.details).state(&presence.state);
+      if let Some(start) = presence.start
+      {
+         activity = activity.timestamps(activity::Timestamps::new().start(start));
+      }
+      if let Err(e) = connected.set_activity(activity)
+      {
+         log_warn!("Lost the Discord connection: {}", e);
+         client = None;
+         retry_at = Instant::now() + RETRY_INTERVAL;
+      }
+   }
+   if let Some(mut connected) = client
+   {
+      let _ = connected.clear_activity();
+      let _ = connected.close();
+   }
+}
@@ -0,0 +1,135 @@
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+use crate::{data::RiderData, http, settings::{FanControl, FanMetric}};
+
+/// Sets a smart fan (or anything else behind a webhook or Home Assistant service) from the ride: the fan level of
+/// the highest rule threshold reached by the power, speed or gradient is POSTed once it has been wanted for the hold
+/// time. Requests are sent directly rather than through the proxy as the fan is normally on the local network.
+#[derive(Default)]
+pub struct FanController
+//=======================
+{
+   config:     FanControl,
+   token:      String, // decrypted
+   sent:       Arc<parking_lot::Mutex<Option<u32>>>, // level the fan was last set to
+   pending:    Option<(u32, Instant)>, // level wanted and since when
+   is_sending: Arc<AtomicBool>,
+}
+
+impl FanController
+{
+   pub fn new(config: FanControl) -> Self
+   //------------------------------------
+   {
+      let mut controller = Self::default();
+      controller.configure(config);
+      controller
+   }
+
+   /// Use new settings, turning the fan off when fan control is disabled.
+   pub fn configure(&mut self, config: FanControl)
+   //---------------------------------------------
+   {
+      if config == self.config
+      {
+         return;
+      }
+      if self.config.is_enabled && !config.is_enabled && self.sent.lock().is_some_and(|level| level > 0)
+      {
+         self.send(0);
+      }
+      self.token = config.decrypted_token().unwrap_or_else(|e|
+      {
+         log_error!("{e}");
+         String::new()
+      });
+      self.config = config;
+      self.pending = None;
+      if self.config.is_enabled
+      {  // The URL or body may have changed so set the fan again
+         *self.sent.lock() = None;
+      }
+   }
+
+   /// Update the fan level from the latest rider data and the course gradient (percent) at the rider position.
+   pub fn update(&mut self, rider: &RiderData, gradient: f64)
+   //--------------------------------------------------------
+   {
+      if !self.config.is_enabled || self.config.url.trim().is_empty() || self.is_sending.load(Ordering::Relaxed)
+      {
+         return;
+      }
+      let value = match self.config.metric
+      {
+         | FanMetric::Power => rider.power as f64,
+         | FanMetric::Speed => rider.speed as f64 * 0.0036, // mm/s to km/h
+         | FanMetric::Gradient => gradient,
+      };
+      let level = self.config.level(value);
+      let sent = *self.sent.lock();
+      if sent == Some(level)
+      {
+         self.pending = None;
+         return;
+      }
+      let hold = Duration::from_secs(self.config.hold_seconds);
+      match self.pending
+      {
+         | Some((pending, since)) if pending == level =>
+         {
+            if since.elapsed() < hold
+            {
+               return;
+            }
+         }
+         | _ =>
+         {
+            self.pending = Some((level, Instant::now()));
+            if sent.is_some()
+            {  // The first level is sent straight away, changes once held
+               return;
+            }
+         }
+      }
+      // Retried after another hold period if the request fails
+      self.pending = Some((level, Instant::now()));
+      self.send(level);
+   }
+
+   fn send(&self, level: u32)
+   //------------------------
+   {
+      let (url, body, token) = (self.config.url.trim().to_string(), self.config.body_for(level), self.token.clone());
+      let (sent, is_sending) = (self.sent.clone(), self.is_sending.clone());
+      is_sending.store(true, Ordering::Relaxed);
+      http::spawn(async move
+      {
+         match post(&url, body, &token).await
+         {
+            | Ok(_) =>
+            {
+               log_info!("Fan set to {level}%");
+               *sent.lock() = Some(level);
+            }
+            | Err(e) => log_warn!("Fan control request failed: {e}"),
+         }
+         is_sending.store(false, Ordering::Relaxed);
+      });
+   }
+}
+
+async fn post(url: &str, body: String, token: &str) -> Result<(), String>
+//-----------------------------------------------------------------------
+{
+   let mut request = http::local_client()?.post(url).header(reqwest::header::CONTENT_TYPE, "application/json").body(body);
+   if !token.is_empty()
+   {
+      request = request.bearer_auth(token);
+   }
+   let response = request.send().await.map_err(|e| format!("Could not reach {url}: {e}"))?;
+   if !response.status().is_success()
+   {
+      return Err(format!("HTTP error {} from {url}", response.status()));
+   }
+   Ok(())
+}
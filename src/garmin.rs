@@ -0,0 +1,103 @@
+use std::{fs::File, io::BufWriter, path::{Path, PathBuf}};
+
+use crate::{http, import::read_fit, ut::safe_file_name};
+
+const GARMIN_API_URL: &str = "https://connectapi.garmin.com/course-service";
+/// Environment variable holding the Garmin Connect access token when it is not given on the command line.
+pub const GARMIN_TOKEN_VARIABLE: &str = "GARMIN_CONNECT_TOKEN";
+
+/// A course saved in a Garmin Connect account.
+#[derive(Debug, Clone)]
+pub struct GarminCourse
+{
+   pub id:       u64,
+   pub name:     String,
+   pub distance: f64, // metres
+   pub ascent:   f64, // metres
+}
+
+/// Client for the Garmin Connect course service. Garmin only offers its Courses API to approved partners, so this
+/// uses the OAuth2 access token of a signed in Garmin Connect session (e.g. as obtained with the garth Python tool).
+pub struct GarminConnect
+{
+   client: reqwest::Client,
+   token:  String,
+}
+
+impl GarminConnect
+{
+   pub fn new(token: &str) -> Result<Self, String>
+   //---------------------------------------------
+   {
+      let token = token.trim().trim_start_matches("Bearer ").to_string();
+      if token.is_empty()
+      {
+         return Err(format!("A Garmin Connect access token is needed (--token or the {GARMIN_TOKEN_VARIABLE} environment variable)"));
+      }
+      Ok(Self { client: http::client()?, token })
+   }
+
+   /// GET `url`, waiting for the response body.
+   fn get(&self, url: &str) -> Result<Vec<u8>, String>
+   //-------------------------------------------------
+   {
+      http::block_on(async
+      {
+         let response = self.client.get(url)
+                                   .bearer_auth(&self.token)
+                                   .header("DI-Backend", "connectapi.garmin.com")
+                                   .send()
+                                   .await
+                                   .map_err(|e| format!("Could not reach Garmin Connect: {}", e))?;
+         match response.status().as_u16()
+         {
+            | 200..=299 => response.bytes().await.map(|bytes| bytes.to_vec()).map_err(|e| format!("Failed to read the Garmin Connect response: {}", e)),
+            | 401 | 403 => Err("Garmin Connect rejected the access token; it may have expired".to_string()),
+            | 404 => Err("Course not found on Garmin Connect".to_string()),
+            | status => Err(format!("HTTP error {status} from Garmin Connect")),
+         }
+      })
+   }
+
+   /// The courses in the account, most recently updated first.
+   pub fn list_courses(&self) -> Result<Vec<GarminCourse>, String>
+   //-------------------------------------------------------------
+   {
+      let bytes = self.get(&format!("{GARMIN_API_URL}/course")).map_err(|e| format!("Failed to read course list: {}", e))?;
+      let json: serde_json::Value = serde_json::from_slice(&bytes).map_err(|e| format!("Unexpected course list from Garmin Connect: {}", e))?;
+      // The list is either an array or wrapped in an object depending on the API version
+      let courses = json.as_array().or_else(|| json["coursesForUser"].as_array()).ok_or("Unexpected course list from Garmin Connect")?;
+      Ok(courses.iter()
+                .filter_map(|course| Some(GarminCourse
+                {
+                   id: course["courseId"].as_u64()?,
+                   name: course["courseName"].as_str().unwrap_or("Garmin course").to_string(),
+                   distance: course["distanceInMeters"].as_f64().unwrap_or(0.0),
+                   ascent: course["elevationGainInMeters"].as_f64().unwrap_or(0.0),
+                }))
+                .collect())
+   }
+
+   /// Download a course as FIT and convert it to a GPX file in `directory`, named after the course. Returns None when
+   /// the file exists and `overwrite` is false.
+   pub fn import_course(&self, course: &GarminCourse, directory: &Path, overwrite: bool) -> Result<Option<PathBuf>, String>
+   //----------------------------------------------------------------------------------------------------------------------
+   {
+      let path = directory.join(format!("{}.gpx", safe_file_name(&course.name)));
+      if path.exists() && !overwrite
+      {
+         return Ok(None);
+      }
+      let bytes = self.get(&format!("{GARMIN_API_URL}/course/fit/{}/0?elevation=true", course.id))
+                      .map_err(|e| format!("Failed to download course {}: {}", course.id, e))?;
+      let mut gpx = read_fit(&bytes)?;
+      if let Some(metadata) = &mut gpx.metadata
+         && metadata.name.is_none()
+      {
+         metadata.name = Some(course.name.clone());
+      }
+      let writer = BufWriter::new(File::create(&path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?);
+      gpx::write(&gpx, writer).map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+      Ok(Some(path))
+   }
+}
@@ -0,0 +1,91 @@
+use gpx::Gpx;
+use serde_json::{Value, json};
+
+use crate::{gpx::{TrackPoint, elevation_gain_loss, find_closest_point}, import::course_to_gpx};
+
+/// Read a GeoJSON route as GPX: the first LineString (or the lines of the first MultiLineString, joined) in a
+/// geometry, Feature or FeatureCollection. Positions are [longitude, latitude] with an optional elevation; the route
+/// name is taken from a `name` or `title` property.
+pub fn read_geojson(text: &str) -> Result<Gpx, String>
+//----------------------------------------------------
+{
+   let json: Value = serde_json::from_str(text).map_err(|e| format!("Invalid GeoJSON file: {e}"))?;
+   let (name, coordinates) = find_line(&json, None).ok_or("The GeoJSON file contains no LineString")?;
+   let points = coordinates.iter()
+                           .filter_map(|position|
+                           {
+                              let position = position.as_array()?;
+                              Some((position.get(1)?.as_f64()?, position.first()?.as_f64()?, position.get(2).and_then(Value::as_f64)))
+                           })
+                           .collect();
+   course_to_gpx(name, points)
+}
+
+/// The name and positions of the first line in a GeoJSON object.
+fn find_line(json: &Value, name: Option<String>) -> Option<(Option<String>, Vec<Value>)>
+//--------------------------------------------------------------------------------------
+{
+   match json["type"].as_str()?
+   {
+      | "FeatureCollection" => json["features"].as_array()?.iter().find_map(|feature| find_line(feature, name.clone())),
+      | "Feature" =>
+      {
+         let properties = &json["properties"];
+         let name = properties["name"].as_str().or_else(|| properties["title"].as_str()).map(str::to_string).or(name);
+         find_line(&json["geometry"], name)
+      }
+      | "GeometryCollection" => json["geometries"].as_array()?.iter().find_map(|geometry| find_line(geometry, name.clone())),
+      | "LineString" => Some((name, json["coordinates"].as_array()?.clone())),
+      | "MultiLineString" =>
+      {
+         let lines = json["coordinates"].as_array()?;
+         Some((name, lines.iter().filter_map(Value::as_array).flatten().cloned().collect()))
+      }
+      | _ => None,
+   }
+}
+
+/// GeoJSON position of a track point, rounded to about 1cm.
+fn position(point: &TrackPoint) -> Value
+//--------------------------------------
+{
+   let round = |value: f64, scale: f64| (value * scale).round() / scale;
+   json!([round(point.point.lon, 1e7), round(point.point.lat, 1e7), round(point.altitude, 10.0)])
+}
+
+/// The track as a GeoJSON FeatureCollection for web maps: a LineString feature for the whole course with its distance
+/// and elevation totals, followed by one LineString per `segment_length` metres with its gradient so the course can
+/// be styled by gradient.
+pub fn track_to_geojson(name: &str, track: &[TrackPoint], segment_length: f64) -> Value
+//--------------------------------------------------------------------------------------
+{
+   let (ascent, descent) = elevation_gain_loss(track, 2.0);
+   let distance = track.last().map_or(0.0, |p| p.distance);
+   let mut features = vec![json!(
+   {
+      "type": "Feature",
+      "properties": { "name": name, "distance": distance.round(), "ascent": ascent.round(), "descent": descent.round() },
+      "geometry": { "type": "LineString", "coordinates": track.iter().map(position).collect::<Vec<_>>() },
+   })];
+
+   let mut start = 0.0;
+   while start < distance && segment_length > 0.0
+   {
+      let end = (start + segment_length).min(distance);
+      let (Some(first), Some(last)) = (find_closest_point(track, start).0, find_closest_point(track, end).0) else { break; };
+      let mut coordinates = vec![position(&first)];
+      coordinates.extend(track.iter().filter(|p| p.distance > first.distance && p.distance < last.distance).map(position));
+      coordinates.push(position(&last));
+      let length = last.distance - first.distance; // the closest track points, which may not be exactly at start and end
+      let gradient = if length > 0.0 { (last.altitude - first.altitude) / length * 100.0 } else { 0.0 };
+      features.push(json!(
+      {
+         "type": "Feature",
+         "properties": { "start": start.round(), "end": end.round(), "gradient": (gradient * 10.0).round() / 10.0,
+                         "altitude_start": first.altitude.round(), "altitude_end": last.altitude.round() },
+         "geometry": { "type": "LineString", "coordinates": coordinates },
+      }));
+      start = end;
+   }
+   json!({ "type": "FeatureCollection", "features": features })
+}
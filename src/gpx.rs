@@ -1,11 +1,14 @@
 #![allow(non_snake_case)]
 use std::{cmp::Ordering,
           fs::{self, File},
-          io::BufReader,
+          io::{self, BufReader, Read},
+          ops::RangeInclusive,
           path::Path};
 
 use gpx::{Gpx, read};
 
+use crate::source::CancelToken;
+
 // Earth's radius in meters.
 const EARTH_RADIUS_METERS: f64 = 6_371_000.0;
 
@@ -43,6 +46,14 @@ impl Default for TrackPoint
    }
 }
 
+/// A named point of interest (GPX wpt) on or near the course.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint
+{
+   pub point: Point,
+   pub name:  String,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct ECEFCoord
 {
@@ -51,22 +62,30 @@ struct ECEFCoord
    z: f64,
 }
 
-// fn haversine_distance(p1: Point, p2: Point) -> f64
-// //------------------------------------------------
-// {
-//    let lat1_rad = p1.lat.to_radians();
-//    let lon1_rad = p1.lon.to_radians();
-//    let lat2_rad = p2.lat.to_radians();
-//    let lon2_rad = p2.lon.to_radians();
+/// Great circle distance in metres between two points on a spherical Earth.
+pub fn haversine_distance(p1: Point, p2: Point) -> f64
+//------------------------------------------------
+{
+   let lat1_rad = p1.lat.to_radians();
+   let lon1_rad = p1.lon.to_radians();
+   let lat2_rad = p2.lat.to_radians();
+   let lon2_rad = p2.lon.to_radians();
+
+   let d_lat = lat2_rad - lat1_rad;
+   let d_lon = lon2_rad - lon1_rad;
 
-//    let d_lat = lat2_rad - lat1_rad;
-//    let d_lon = lon2_rad - lon1_rad;
+   let a = (d_lat / 2.0).sin().powi(2) + lat1_rad.cos() * lat2_rad.cos() * (d_lon / 2.0).sin().powi(2);
+   let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
 
-//    let a = (d_lat / 2.0).sin().powi(2) + lat1_rad.cos() * lat2_rad.cos() * (d_lon / 2.0).sin().powi(2);
-//    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+   EARTH_RADIUS_METERS * c
+}
 
-//    EARTH_RADIUS_METERS * c
-// }
+/// Course length in metres using Haversine distances, for comparison with the ECEF distances used for the track.
+pub fn haversine_length(track_data: &[TrackPoint]) -> f64
+//-------------------------------------------------------
+{
+   track_data.windows(2).map(|w| haversine_distance(w[0].point, w[1].point)).sum()
+}
 
 fn geodetic_to_ecef(p: Point) -> ECEFCoord
 //----------------------------------------
@@ -102,7 +121,67 @@ pub fn build_track_data(path: &Path) -> Result<Vec<TrackPoint>, Box<dyn std::err
    let file = File::open(path)?;
    let reader = BufReader::new(file);
    let gpx: Gpx = read(reader)?;
+   track_data_from_gpx(&gpx)
+}
+
+/// Same as `build_track_data` for the GUI, where large files take a while, also returning the waypoints: `progress` is
+/// called with the fraction (0 to 1) of the file parsed so far and parsing stops with an error once `cancel` is cancelled.
+pub fn load_track(path: &Path, progress: &dyn Fn(f32), cancel: &CancelToken)
+                  -> Result<(Vec<TrackPoint>, Vec<Waypoint>), Box<dyn std::error::Error>>
+//-----------------------------------------------------------------------------------------
+{
+   let file = File::open(path)?;
+   let total = file.metadata()?.len().max(1);
+   let reader = ProgressReader { inner: file, read: 0, total, percent: 0, progress, cancel };
+   let gpx = read(BufReader::new(reader));
+   if cancel.is_cancelled()
+   {
+      return Err("Cancelled".into());
+   }
+   let gpx = gpx?;
+   let track = track_data_from_gpx(&gpx)?;
+   progress(1.0);
+   let total_dist = track.last().map_or(0.0, |p| p.distance);
+   log_info!("Processed {} points from {}, total track distance: {:.2} meters.", track.len(), path.display(), total_dist);
+   Ok((track, waypoints_from_gpx(&gpx)))
+}
+
+/// Reports how much of the file has been read, in whole percent to limit the number of calls, and fails the read once
+/// cancelled.
+struct ProgressReader<'a, R>
+{
+   inner:    R,
+   read:     u64,
+   total:    u64,
+   percent:  u64,
+   progress: &'a dyn Fn(f32),
+   cancel:   &'a CancelToken,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R>
+{
+   fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+   {
+      if self.cancel.is_cancelled()
+      {
+         return Err(io::Error::other("Cancelled"));
+      }
+      let count = self.inner.read(buf)?;
+      self.read += count as u64;
+      let percent = (self.read * 100 / self.total).min(100);
+      if percent != self.percent
+      {
+         self.percent = percent;
+         (self.progress)(percent as f32 / 100.0);
+      }
+      Ok(count)
+   }
+}
 
+/// Build the cumulative distance/heading track from the first track segment of a parsed GPX file.
+pub fn track_data_from_gpx(gpx: &Gpx) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>>
+//-----------------------------------------------------------------------------------------
+{
    let track_segment = gpx.tracks.first()
                           .and_then(|track| track.segments.first())
                           .ok_or("GPX file does not contain a track segment.")?;
@@ -136,6 +215,52 @@ pub fn build_track_data(path: &Path) -> Result<Vec<TrackPoint>, Box<dyn std::err
    Ok(track_data)
 }
 
+/// The waypoints of a parsed GPX file, named after their name, description or symbol when present.
+pub fn waypoints_from_gpx(gpx: &Gpx) -> Vec<Waypoint>
+//---------------------------------------------------
+{
+   gpx.waypoints.iter().map(|waypoint| Waypoint
+   {
+      point: Point { lat: waypoint.point().y(), lon: waypoint.point().x() },
+      name:  waypoint.name.clone().or_else(|| waypoint.description.clone()).or_else(|| waypoint.symbol.clone()).unwrap_or_default(),
+   }).collect()
+}
+
+/// The course name from the GPX metadata or first track, if present.
+pub fn course_name(gpx: &Gpx) -> Option<String>
+//---------------------------------------------
+{
+   gpx.metadata.as_ref().and_then(|m| m.name.clone())
+      .or_else(|| gpx.tracks.first().and_then(|t| t.name.clone()))
+      .filter(|name| !name.trim().is_empty())
+}
+
+/// Total elevation gain and loss (both positive, in metres). Changes smaller than `threshold` metres are accumulated
+/// until they exceed it, to avoid counting GPS/DEM noise as climbing.
+pub fn elevation_gain_loss(track_data: &[TrackPoint], threshold: f64) -> (f64, f64)
+//----------------------------------------------------------------------------------
+{
+   altitude_gain_loss(track_data.iter().map(|p| p.altitude), threshold)
+}
+
+fn altitude_gain_loss(mut altitudes: impl Iterator<Item = f64>, threshold: f64) -> (f64, f64)
+//------------------------------------------------------------------------------------------
+{
+   let mut gain = 0.0;
+   let mut loss = 0.0;
+   let Some(mut reference) = altitudes.next() else { return (0.0, 0.0); };
+   for altitude in altitudes
+   {
+      let delta = altitude - reference;
+      if delta.abs() >= threshold
+      {
+         if delta > 0.0 { gain += delta; } else { loss -= delta; }
+         reference = altitude;
+      }
+   }
+   (gain, loss)
+}
+
 pub fn process_gpx(file_path: &str) -> Result<Vec<TrackPoint>, Box<dyn std::error::Error>>
 //-------------------------------------------------------
 {
@@ -150,16 +275,15 @@ pub fn process_gpx(file_path: &str) -> Result<Vec<TrackPoint>, Box<dyn std::erro
    };
    if !metadata.is_file()
    {
-      eprintln!("The path {} is not a valid file.", file_path);
+      log_error!("The path {} is not a valid file.", file_path);
       return Err(format!("Not a file {}.", file_path).into());
    }
    let track = match build_track_data(gpx_file_path)
    {
       | Ok(data) =>
       {
-         println!("Successfully processed {} points.", data.len());
          let total_dist = data.last().map_or(0.0, |p| p.distance);
-         println!("Total track distance: {:.2} meters.", total_dist);
+         log_info!("Processed {} points from {}, total track distance: {:.2} meters.", data.len(), file_path, total_dist);
          data
       }
       | Err(e) =>
@@ -209,6 +333,476 @@ pub fn find_closest_point(track_data: &[TrackPoint], target_distance: f64) -> (O
    }
 }
 
+/// A track stored as separate contiguous columns instead of an array of `TrackPoint`s. The distance searches made for
+/// every position update run over a dense array of distances, and the gradient and climb scans only touch the
+/// distances and altitudes instead of pulling the coordinates and headings through the cache with them. `point` and
+/// `iter` rebuild `TrackPoint`s for code that wants whole points.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track
+{
+   distances: Vec<f64>, // cumulative, in metres
+   lats:      Vec<f64>,
+   lons:      Vec<f64>,
+   altitudes: Vec<f64>,
+   headings:  Vec<f64>,
+}
+
+impl From<&[TrackPoint]> for Track
+{
+   fn from(points: &[TrackPoint]) -> Self
+   {
+      Track
+      {
+         distances: points.iter().map(|p| p.distance).collect(),
+         lats:      points.iter().map(|p| p.point.lat).collect(),
+         lons:      points.iter().map(|p| p.point.lon).collect(),
+         altitudes: points.iter().map(|p| p.altitude).collect(),
+         headings:  points.iter().map(|p| p.heading).collect(),
+      }
+   }
+}
+
+impl From<Vec<TrackPoint>> for Track
+{
+   fn from(points: Vec<TrackPoint>) -> Self { Track::from(points.as_slice()) }
+}
+
+impl Track
+{
+   pub fn len(&self) -> usize { self.distances.len() }
+
+   pub fn is_empty(&self) -> bool { self.distances.is_empty() }
+
+   /// Distance in metres to the last point, 0 for an empty track.
+   pub fn total_distance(&self) -> f64 { self.distances.last().copied().unwrap_or(0.0) }
+
+   pub fn distances(&self) -> &[f64] { &self.distances }
+
+   /// The point at `index`, which must be less than `len`.
+   pub fn point(&self, index: usize) -> TrackPoint
+   //---------------------------------------------
+   {
+      TrackPoint
+      {
+         distance: self.distances[index],
+         point:    Point { lat: self.lats[index], lon: self.lons[index] },
+         heading:  self.headings[index],
+         altitude: self.altitudes[index],
+      }
+   }
+
+   pub fn iter(&self) -> impl ExactSizeIterator<Item = TrackPoint> + '_ { (0..self.len()).map(|i| self.point(i)) }
+
+   /// The points in `range` of indices, e.g. to draw part of the profile.
+   pub fn points(&self, range: RangeInclusive<usize>) -> Vec<TrackPoint> { range.map(|i| self.point(i)).collect() }
+
+   /// The part of the track from `start` to `end` metres (inclusive) as a track of its own.
+   pub fn section(&self, start: f64, end: f64) -> Track
+   //--------------------------------------------------
+   {
+      let first = self.distances.partition_point(|d| *d < start);
+      let last = self.distances.partition_point(|d| *d <= end).max(first);
+      Track
+      {
+         distances: self.distances[first..last].to_vec(),
+         lats:      self.lats[first..last].to_vec(),
+         lons:      self.lons[first..last].to_vec(),
+         altitudes: self.altitudes[first..last].to_vec(),
+         headings:  self.headings[first..last].to_vec(),
+      }
+   }
+
+   /// Index of the point closest to `target_distance`, None when the track is empty.
+   pub fn closest_index(&self, target_distance: f64) -> Option<usize>
+   //-----------------------------------------------------------------
+   {
+      if self.is_empty()
+      {
+         return None;
+      }
+      let index = self.distances.partition_point(|d| *d < target_distance);
+      Some(if index == 0
+      {
+         0
+      }
+      else if index >= self.len()
+      {
+         self.len() - 1
+      }
+      else if (target_distance - self.distances[index - 1]) <= (self.distances[index] - target_distance)
+      {
+         index - 1
+      }
+      else
+      {
+         index
+      })
+   }
+
+   /// Same as `find_closest_point` for the slice of points.
+   pub fn find_closest(&self, target_distance: f64) -> (Option<TrackPoint>, i64)
+   //---------------------------------------------------------------------------
+   {
+      match self.closest_index(target_distance)
+      {
+         | Some(index) => (Some(self.point(index)), index as i64),
+         | None => (None, -1),
+      }
+   }
+
+   /// Same as `gradient_at` for the slice of points.
+   pub fn gradient_at(&self, distance: f64, window: f64) -> f64
+   //-----------------------------------------------------------
+   {
+      let half = (window / 2.0).max(1.0);
+      match (self.closest_index((distance - half).max(0.0)), self.closest_index(distance + half))
+      {
+         | (Some(i), Some(j)) if j > i && (self.distances[j] - self.distances[i]) > 0.1 =>
+         {
+            (self.altitudes[j] - self.altitudes[i]) / (self.distances[j] - self.distances[i]) * 100.0
+         }
+         | _ => 0.0,
+      }
+   }
+
+   /// Altitude at `distance` metres, interpolated between the points either side (clamped to the ends of the track).
+   pub fn altitude_at(&self, distance: f64) -> Option<f64> { self.interpolate(&self.altitudes, distance) }
+
+   /// Position at `distance` metres, interpolated as for `altitude_at`.
+   pub fn position_at(&self, distance: f64) -> Option<Point>
+   //-------------------------------------------------------
+   {
+      Some(Point { lat: self.interpolate(&self.lats, distance)?, lon: self.interpolate(&self.lons, distance)? })
+   }
+
+   /// The value of the `values` column at `distance` metres.
+   fn interpolate(&self, values: &[f64], distance: f64) -> Option<f64>
+   //-----------------------------------------------------------------
+   {
+      let index = self.distances.partition_point(|d| *d < distance);
+      match index
+      {
+         | _ if self.is_empty() => None,
+         | 0 => Some(values[0]),
+         | i if i >= self.len() => values.last().copied(),
+         | i =>
+         {
+            let span = self.distances[i] - self.distances[i - 1];
+            let t = if span > 0.0 { (distance - self.distances[i - 1]) / span } else { 0.0 };
+            Some(values[i - 1] + t * (values[i] - values[i - 1]))
+         }
+      }
+   }
+
+   /// Distance and climbing in each gradient band between `edges` (percent, ascending), measured over `step` metres
+   /// at a time. The first and last bands are open ended.
+   pub fn gradient_distribution(&self, edges: &[f64], step: f64) -> Vec<GradientBand>
+   //---------------------------------------------------------------------------------
+   {
+      let mut bands: Vec<GradientBand> = (0..=edges.len()).map(|i| GradientBand
+      {
+         min:      if i == 0 { f64::NEG_INFINITY } else { edges[i - 1] },
+         max:      edges.get(i).copied().unwrap_or(f64::INFINITY),
+         distance: 0.0,
+         gain:     0.0,
+      }).collect();
+      let total = self.total_distance();
+      let mut start = 0.0;
+      while start < total
+      {
+         let end = (start + step).min(total);
+         if let (Some(a), Some(b)) = (self.altitude_at(start), self.altitude_at(end))
+            && end > start
+         {
+            let gradient = (b - a) / (end - start) * 100.0;
+            let band = &mut bands[edges.partition_point(|edge| *edge <= gradient)];
+            band.distance += end - start;
+            band.gain += (b - a).max(0.0);
+         }
+         start = end;
+      }
+      bands
+   }
+
+   /// Start and average gradient of the steepest `length` metres of the track, found by sliding a window along it
+   /// every `step` metres. None when the track is shorter than `length`.
+   pub fn steepest_window(&self, length: f64, step: f64) -> Option<(f64, f64)>
+   //-------------------------------------------------------------------------
+   {
+      let mut steepest: Option<(f64, f64)> = None;
+      let mut start = 0.0;
+      while start + length <= self.total_distance()
+      {
+         let gradient = (self.altitude_at(start + length)? - self.altitude_at(start)?) / length * 100.0;
+         if steepest.is_none_or(|(_, best)| gradient > best)
+         {
+            steepest = Some((start, gradient));
+         }
+         start += step;
+      }
+      steepest
+   }
+
+   /// The first change of direction of at least `threshold` degrees in the `look_ahead` metres after `distance`. The
+   /// heading is compared over TURN_SPAN metres either side of each point so GPS jitter and gentle bends are ignored.
+   pub fn next_turn(&self, distance: f64, look_ahead: f64, threshold: f64) -> Option<Turn>
+   //-------------------------------------------------------------------------------------
+   {
+      let end = (distance + look_ahead).min(self.total_distance() - TURN_SPAN);
+      let bearing = |from: f64, to: f64| -> Option<f64>
+      {
+         let (a, b) = (self.position_at(from)?, self.position_at(to)?);
+         Some(calculate_bearing(a.lat, a.lon, b.lat, b.lon))
+      };
+      let turn_at = |at: f64| -> Option<f64>
+      {
+         let change = bearing(at, at + TURN_SPAN)? - bearing((at - TURN_SPAN).max(0.0), at)?;
+         Some((change + 540.0) % 360.0 - 180.0)
+      };
+      let mut at = distance.max(TURN_SPAN) + TURN_STEP;
+      while at <= end
+      {
+         if let Some(angle) = turn_at(at) && angle.abs() >= threshold
+         {  // Find where the turn is sharpest before the road straightens again
+            let mut turn = Turn { distance: at, angle };
+            let mut next = at + TURN_STEP;
+            while next <= (at + TURN_SPAN).min(end) && let Some(angle) = turn_at(next)
+            {
+               if angle.abs() > turn.angle.abs() { turn = Turn { distance: next, angle }; }
+               next += TURN_STEP;
+            }
+            return Some(turn);
+         }
+         at += TURN_STEP;
+      }
+      None
+   }
+
+   /// Same as `elevation_gain_loss` for the slice of points.
+   pub fn elevation_gain_loss(&self, threshold: f64) -> (f64, f64)
+   //-------------------------------------------------------------
+   {
+      altitude_gain_loss(self.altitudes.iter().copied(), threshold)
+   }
+
+   /// Elevation gain (as `elevation_gain_loss`) from the start to each point, e.g. to find the ascent remaining.
+   pub fn cumulative_ascent(&self, threshold: f64) -> Vec<f64>
+   //----------------------------------------------------------
+   {
+      let mut gains = Vec::with_capacity(self.len());
+      let (mut gain, mut reference) = (0.0, self.altitudes.first().copied().unwrap_or(0.0));
+      for &altitude in &self.altitudes
+      {
+         let delta = altitude - reference;
+         if delta.abs() >= threshold
+         {
+            gain += delta.max(0.0);
+            reference = altitude;
+         }
+         gains.push(gain);
+      }
+      gains
+   }
+
+   /// Same as `find_climbs` for the slice of points.
+   pub fn climbs(&self, min_gain: f64, min_gradient: f64) -> Vec<Climb>
+   //-------------------------------------------------------------------
+   {
+      let mut climbs = Vec::new();
+      if self.len() < 2
+      {
+         return climbs;
+      }
+      let (distances, altitudes) = (&self.distances, &self.altitudes);
+      let mut push_climb = |start: usize, high: usize|
+      {
+         let length = distances[high] - distances[start];
+         let gain = altitudes[high] - altitudes[start];
+         if length <= 0.0 || gain < min_gain
+         {
+            return;
+         }
+         let average_gradient = gain / length * 100.0;
+         if average_gradient < min_gradient
+         {
+            return;
+         }
+         let mut max_gradient = average_gradient;
+         let mut distance = distances[start];
+         while distance < distances[high]
+         {
+            max_gradient = max_gradient.max(self.gradient_at(distance + 50.0, 100.0));
+            distance += 50.0;
+         }
+         climbs.push(Climb { start: distances[start], end: distances[high], gain, average_gradient, max_gradient });
+      };
+      let mut start = 0;
+      let mut high = 0;
+      for (i, altitude) in altitudes.iter().copied().enumerate().skip(1)
+      {
+         if altitude > altitudes[high]
+         {
+            high = i;
+         }
+         else if high == start && altitude <= altitudes[start]
+         {  // Still descending to the foot of the next climb
+            start = i;
+            high = i;
+         }
+         else if altitudes[high] - altitude > CLIMB_DROP_TOLERANCE
+         {
+            push_climb(start, high);
+            start = i;
+            high = i;
+         }
+      }
+      push_climb(start, high);
+      climbs
+   }
+}
+
+/// Remembers where the last closest point lookup ended so the next lookup for a nearby distance searches outward from
+/// there, which is O(1) for a rider moving along the track. Jumps further than a few points fall back to the binary
+/// search of `Track::find_closest`, which gives the same results.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrackCursor
+{
+   index: usize,
+}
+
+impl TrackCursor
+{
+   /// Points walked before giving up and binary searching.
+   const MAX_STEPS: usize = 32;
+
+   /// Same as `Track::find_closest` but starting from the previous lookup.
+   pub fn find(&mut self, track: &Track, target_distance: f64) -> (Option<TrackPoint>, i64)
+   //--------------------------------------------------------------------------------------
+   {
+      if track.is_empty()
+      {
+         return (None, -1);
+      }
+      let distances = track.distances();
+      let len = distances.len();
+      let mut i = self.index.min(len - 1);
+      let mut steps = 0;
+      // Move to the last point at or before the target
+      while i + 1 < len && distances[i + 1] <= target_distance
+      {
+         i += 1;
+         steps += 1;
+         if steps > Self::MAX_STEPS
+         {
+            return self.search(track, target_distance);
+         }
+      }
+      while i > 0 && distances[i] > target_distance
+      {
+         i -= 1;
+         steps += 1;
+         if steps > Self::MAX_STEPS
+         {
+            return self.search(track, target_distance);
+         }
+      }
+      if i + 1 < len && distances[i] < target_distance
+         && (distances[i + 1] - target_distance) < (target_distance - distances[i])
+      {
+         i += 1;
+      }
+      self.index = i;
+      (Some(track.point(i)), i as i64)
+   }
+
+   fn search(&mut self, track: &Track, target_distance: f64) -> (Option<TrackPoint>, i64)
+   //-------------------------------------------------------------------------------------
+   {
+      let (point, index) = track.find_closest(target_distance);
+      self.index = index.max(0) as usize;
+      (point, index)
+   }
+}
+
+/// Gradient (percent) of the track around `distance`, measured over `window` metres centred on the distance.
+pub fn gradient_at(track_data: &[TrackPoint], distance: f64, window: f64) -> f64
+//--------------------------------------------------------------------------
+{
+   let half = (window / 2.0).max(1.0);
+   let (start, i) = find_closest_point(track_data, (distance - half).max(0.0));
+   let (end, j) = find_closest_point(track_data, distance + half);
+   match (start, end)
+   {
+      | (Some(p1), Some(p2)) if j > i && (p2.distance - p1.distance) > 0.1 =>
+      {
+         (p2.altitude - p1.altitude) / (p2.distance - p1.distance) * 100.0
+      }
+      | _ => 0.0,
+   }
+}
+
+/// Headings are measured over this distance before and after a point to find turns, sampled every TURN_STEP.
+const TURN_SPAN: f64 = 30.0; // metres
+const TURN_STEP: f64 = 10.0; // metres
+
+/// A change of direction ahead on the track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turn
+{
+   pub distance: f64, // metres along the track
+   pub angle:    f64, // degrees, positive to the right
+}
+
+/// Part of the track in a range of gradients, from `Track::gradient_distribution`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientBand
+{
+   pub min:      f64, // percent, -infinity for the steepest descents
+   pub max:      f64, // percent, infinity for the steepest climbs
+   pub distance: f64, // metres
+   pub gain:     f64, // metres climbed
+}
+
+/// Minimum length (metres) × average gradient (percent) for each climb category, hardest first.
+const CLIMB_CATEGORIES: [(f64, &str); 5] = [(80000.0, "HC"), (64000.0, "Cat 1"), (32000.0, "Cat 2"), (16000.0, "Cat 3"), (8000.0, "Cat 4")];
+
+/// A sustained climb on the track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Climb
+{
+   pub start:            f64, // distance in metres
+   pub end:              f64,
+   pub gain:             f64, // metres
+   pub average_gradient: f64, // percent
+   pub max_gradient:     f64, // percent, measured over 100m
+}
+
+impl Climb
+{
+   pub fn length(&self) -> f64 { self.end - self.start }
+
+   /// Road race style category from the length times the average gradient ("HC" and "Cat 1" to "Cat 4"), None for
+   /// climbs too small to categorise.
+   pub fn category(&self) -> Option<&'static str>
+   //--------------------------------------------
+   {
+      let score = self.length() * self.average_gradient;
+      CLIMB_CATEGORIES.iter().find(|(minimum, _)| score >= *minimum).map(|(_, category)| *category)
+   }
+}
+
+/// Descent (in metres) allowed within a climb before it is considered finished.
+const CLIMB_DROP_TOLERANCE: f64 = 10.0;
+
+/// Find climbs gaining at least `min_gain` metres at an average of at least `min_gradient` percent. A climb runs
+/// from a low point to the following high point and ends once the road drops more than `CLIMB_DROP_TOLERANCE`
+/// metres below that high point.
+pub fn find_climbs(track_data: &[TrackPoint], min_gain: f64, min_gradient: f64) -> Vec<Climb>
+//-------------------------------------------------------------------------------------------
+{
+   Track::from(track_data).climbs(min_gain, min_gradient)
+}
+
 fn calculate_bearing(from_latitude: f64, from_longitude: f64, to_latitude: f64, to_longitude: f64) -> f64
 //-------------------------------------------------------------
 {
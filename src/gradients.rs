@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use eframe::egui::{self, Color32};
+
+use crate::{gpx::{GradientBand, Track}, ui::Theme, units::Units};
+
+/// Boundaries of the gradient bands in percent, the bands either end being open.
+pub const GRADIENT_EDGES: [f64; 10] = [-9.0, -6.0, -3.0, -1.0, 1.0, 3.0, 6.0, 9.0, 12.0, 15.0];
+/// Gradients are measured over this distance at a time, which smooths out elevation noise.
+pub const GRADIENT_STEP: f64 = 50.0; // metres
+/// Length of the steepest stretch reported, slid along the course STEEPEST_STEP at a time.
+pub const STEEPEST_LENGTH: f64 = 500.0; // metres
+pub const STEEPEST_STEP: f64 = 10.0;    // metres
+const BAR_WIDTH: f32 = 220.0;
+
+/// e.g. "6 to 9%", "< -9%" or "≥ 15%".
+pub fn band_label(band: &GradientBand) -> String
+//-----------------------------------------------
+{
+   if band.min == f64::NEG_INFINITY { format!("< {}%", band.max) }
+   else if band.max == f64::INFINITY { format!("≥ {}%", band.min) }
+   else { format!("{} to {}%", band.min, band.max) }
+}
+
+/// Colour of a band in the histogram, blue descending, green flat and yellow through red to black climbing as on
+/// the gradient profile.
+fn band_color(band: &GradientBand) -> Color32
+//-------------------------------------------
+{
+   let middle = if band.min.is_finite() && band.max.is_finite() { (band.min + band.max) / 2.0 }
+                else if band.min.is_finite() { band.min } else { band.max };
+   match middle
+   {
+      | g if g < -1.0 => Color32::from_rgb(0, (216.0 * (1.0 + g / 12.0).clamp(0.0, 1.0)) as u8, 255),
+      | g if g <= 1.0 => Color32::from_rgb(40, 190, 60),
+      | g if g >= 15.0 => Color32::BLACK,
+      | g =>
+      {
+         let t = ((g - 1.0) / 14.0).clamp(0.0, 1.0);
+         Color32::from_rgb(255, (255.0 * (1.0 - t)) as u8, 0)
+      }
+   }
+}
+
+/// Window with a histogram of the course distance in each gradient band, the climbing in each and the steepest
+/// STEEPEST_LENGTH metres, to help choose gearing before a ride.
+#[derive(Default)]
+pub struct GradientStatistics
+//===========================
+{
+   pub is_open: bool,
+   track:       Option<Arc<Track>>, // the statistics below are for
+   bands:       Vec<GradientBand>,
+   steepest:    Option<(f64, f64)>, // start distance and average gradient
+}
+
+impl GradientStatistics
+{
+   pub fn show(&mut self, ctx: &egui::Context, track: &Arc<Track>, theme: &Theme, units: Units)
+   //-----------------------------------------------------------------------------------------
+   {
+      if !self.is_open
+      {
+         return;
+      }
+      if !self.track.as_ref().is_some_and(|t| Arc::ptr_eq(t, track))
+      {
+         self.bands = track.gradient_distribution(&GRADIENT_EDGES, GRADIENT_STEP);
+         self.steepest = track.steepest_window(STEEPEST_LENGTH, STEEPEST_STEP);
+         self.track = Some(track.clone());
+      }
+      let total = track.total_distance();
+      let mut is_open = self.is_open;
+      egui::Window::new("Gradient Statistics")
+         .open(&mut is_open)
+         .resizable(false)
+         .show(ctx, |ui|
+         {
+            if total <= 0.0
+            {
+               ui.label("Open a course to see its gradients.");
+               return;
+            }
+            let largest = self.bands.iter().map(|b| b.distance).fold(0.0, f64::max).max(1.0);
+            egui::Grid::new("gradient_statistics_grid").num_columns(5).striped(true).spacing([12.0, 4.0]).show(ui, |ui|
+            {
+               for title in ["Gradient", "", "Course", "Distance", "Climbing"]
+               {
+                  ui.label(egui::RichText::new(title).strong());
+               }
+               ui.end_row();
+               for band in self.bands.iter().rev()
+               {
+                  ui.label(band_label(band));
+                  let (rect, _) = ui.allocate_exact_size(egui::vec2(BAR_WIDTH, 14.0), egui::Sense::hover());
+                  let bar = egui::Rect::from_min_size(rect.min, egui::vec2(BAR_WIDTH * (band.distance / largest) as f32, rect.height()));
+                  ui.painter().rect_filled(bar, 2.0, band_color(band));
+                  ui.painter().rect_stroke(bar, 2.0, egui::Stroke::new(1.0, theme.gradient_label), egui::StrokeKind::Inside);
+                  ui.label(format!("{:.1}%", band.distance / total * 100.0));
+                  ui.label(units.format_distance(band.distance, 2));
+                  ui.label(if band.gain > 0.0 { units.format_length(band.gain) } else { "–".to_string() });
+                  ui.end_row();
+               }
+            });
+            ui.separator();
+            match self.steepest
+            {
+               | Some((start, gradient)) =>
+               {
+                  ui.label(format!("Steepest {}: {gradient:.1}% from {} to {}", units.format_length(STEEPEST_LENGTH),
+                                   units.format_distance(start, 2), units.format_distance(start + STEEPEST_LENGTH, 2)));
+               }
+               | None => { ui.label(format!("The course is shorter than {}.", units.format_length(STEEPEST_LENGTH))); }
+            }
+         });
+      self.is_open = is_open;
+   }
+}
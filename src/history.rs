@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+
+/// Samples are kept at full rate (normally one a second) for this long, then combined into older samples.
+const RECENT_SPAN: f64 = 600.0; // seconds
+/// Initial interval between the older samples, doubled whenever there would be more than MAX_OLDER of them.
+const OLDER_INTERVAL: f64 = 10.0; // seconds
+const MAX_OLDER: usize = 4320; // 12 hours at OLDER_INTERVAL
+
+/// A sample that can be stored in a `RideHistory`.
+pub trait Downsample: Copy
+{
+   /// Seconds since the start of the ride.
+   fn time(&self) -> f64;
+
+   /// One sample standing for `samples` (consecutive and never empty), at the time of the last of them.
+   fn combine(samples: &[Self]) -> Self;
+}
+
+/// Ride telemetry history with bounded memory for rides of any length: the last RECENT_SPAN seconds are kept as
+/// recorded and older samples are combined into one every OLDER_INTERVAL seconds, with the older interval doubling
+/// when there are too many of them.
+#[derive(Debug, Clone)]
+pub struct RideHistory<T: Downsample>
+//===================================
+{
+   older:          Vec<T>,
+   older_interval: f64,
+   pending:        Vec<T>, // expired from recent, waiting to be combined into the next older sample
+   recent:         VecDeque<T>,
+}
+
+impl<T: Downsample> Default for RideHistory<T>
+{
+   fn default() -> Self { Self { older: Vec::new(), older_interval: OLDER_INTERVAL, pending: Vec::new(), recent: VecDeque::new() } }
+}
+
+impl<T: Downsample> RideHistory<T>
+{
+   pub fn first(&self) -> Option<&T> { self.older.first().or(self.pending.first()).or(self.recent.front()) }
+
+   pub fn last(&self) -> Option<&T> { self.recent.back().or(self.pending.last()).or(self.older.last()) }
+
+   /// The samples in time order, the older ones downsampled.
+   pub fn iter(&self) -> impl Iterator<Item = &T> + Clone
+   //----------------------------------------------------
+   {
+      self.older.iter().chain(self.pending.iter()).chain(self.recent.iter())
+   }
+
+   /// Add the latest `sample`, downsampling the samples that are no longer recent.
+   pub fn push(&mut self, sample: T)
+   //-------------------------------
+   {
+      let now = sample.time();
+      self.recent.push_back(sample);
+      while self.recent.front().is_some_and(|s| now - s.time() > RECENT_SPAN)
+      {
+         let Some(expired) = self.recent.pop_front() else { break; };
+         self.pending.push(expired);
+         let start = self.older.last().map_or(self.pending[0].time(), T::time);
+         if expired.time() - start >= self.older_interval
+         {
+            self.older.push(T::combine(&self.pending));
+            self.pending.clear();
+         }
+      }
+      if self.older.len() > MAX_OLDER
+      {
+         self.older = self.older.chunks(2).map(T::combine).collect();
+         self.older_interval *= 2.0;
+      }
+   }
+}
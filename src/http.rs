@@ -0,0 +1,192 @@
+use std::{fmt, future::Future, sync::OnceLock, time::Duration};
+
+use reqwest::StatusCode;
+
+use crate::{SETTINGS, settings::{ProxySettings, Settings}, source::CancelToken};
+
+// GitHub rejects requests without a user agent
+const USER_AGENT: &str = concat!("GPXAssist/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Default for the whole request, individual requests may use a shorter one.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Transient failures are retried this many times, waiting RETRY_BACKOFF and then twice as long each time.
+const RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+static CLIENT: parking_lot::Mutex<Option<(ProxySettings, reqwest::Client)>> = parking_lot::Mutex::new(None);
+static LOCAL_CLIENT: OnceLock<Result<reqwest::Client, String>> = OnceLock::new();
+
+/// The runtime all HTTP requests run on, started on first use.
+pub fn runtime() -> &'static tokio::runtime::Runtime
+//--------------------------------------------------
+{
+   RUNTIME.get_or_init(||
+   {
+      tokio::runtime::Builder::new_multi_thread().worker_threads(2)
+                                                 .thread_name("http")
+                                                 .enable_all()
+                                                 .build()
+                                                 .expect("Failed to start the HTTP runtime")
+   })
+}
+
+/// Run `future` on the HTTP runtime and wait for the result. Must be called from outside the runtime, e.g. the UI
+/// thread, a worker thread or the command line.
+pub fn block_on<F: Future>(future: F) -> F::Output { runtime().block_on(future) }
+
+/// Run `future` in the background on the HTTP runtime.
+pub fn spawn<F: Future<Output = ()> + Send + 'static>(future: F) { runtime().spawn(future); }
+
+/// A client builder with the GPXAssist user agent, timeouts and `proxy`.
+pub fn builder(proxy: &ProxySettings) -> Result<reqwest::ClientBuilder, String>
+//----------------------------------------------------------------------------
+{
+   let builder = reqwest::Client::builder().user_agent(USER_AGENT)
+                                           .connect_timeout(CONNECT_TIMEOUT)
+                                           .timeout(REQUEST_TIMEOUT)
+                                           .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+   Ok(match proxy.url()?
+   {
+      | Some(url) => builder.proxy(reqwest::Proxy::all(url).map_err(|e| format!("Invalid proxy: {}", e))?),
+      | None => builder,
+   })
+}
+
+/// The shared client for internet services (Street View, weather, update checks), which pools connections between
+/// requests. It uses the proxy from the settings and is rebuilt when the proxy is changed.
+pub fn client() -> Result<reqwest::Client, String>
+//------------------------------------------------
+{
+   let proxy = SETTINGS.get_or_init(|| std::sync::Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())))
+                       .lock().proxy.clone();
+   let mut shared = CLIENT.lock();
+   if let Some((configured, client)) = shared.as_ref()
+      && *configured == proxy
+   {
+      return Ok(client.clone());
+   }
+   let client = builder(&proxy)?.build().map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+   *shared = Some((proxy, client.clone()));
+   Ok(client)
+}
+
+/// The shared client for devices on the local network (such as the smart fan webhook), which never uses the proxy.
+pub fn local_client() -> Result<reqwest::Client, String>
+//------------------------------------------------------
+{
+   LOCAL_CLIENT.get_or_init(||
+   {
+      reqwest::Client::builder().user_agent(USER_AGENT)
+                                .no_proxy()
+                                .connect_timeout(Duration::from_secs(5))
+                                .timeout(Duration::from_secs(5))
+                                .build()
+                                .map_err(|e| format!("Failed to create HTTP client: {}", e))
+   }).clone()
+}
+
+/// Run `future` until it completes or `cancel` is cancelled, in which case the request in progress is dropped.
+pub async fn cancellable<T>(cancel: &CancelToken, future: impl Future<Output = Result<T, String>>) -> Result<T, String>
+//--------------------------------------------------------------------------------------------------------------------
+{
+   let cancelled = async
+   {
+      while !cancel.is_cancelled()
+      {
+         tokio::time::sleep(Duration::from_millis(100)).await;
+      }
+   };
+   tokio::select!
+   {
+      result = future => result,
+      _ = cancelled => Err("Cancelled".to_string()),
+   }
+}
+
+/// Why a request failed, which decides whether it is retried and how it is reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpError
+{
+   Transient(String), // network failure, timeout or server error that may succeed if tried again
+   Quota(String),     // rate limited or over the API quota, so trying again now fails too
+   Permanent(String), // rejected, e.g. an invalid key, a bad request or nothing at the URL
+}
+
+impl fmt::Display for HttpError
+{
+   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+   {
+      match self
+      {
+         | HttpError::Transient(message) | HttpError::Quota(message) | HttpError::Permanent(message) => f.write_str(message),
+      }
+   }
+}
+
+impl From<HttpError> for String
+{
+   fn from(error: HttpError) -> Self { error.to_string() }
+}
+
+impl HttpError
+{
+   /// Classify a failure to send a request or read the response.
+   pub fn from_request(error: &reqwest::Error, context: &str) -> Self
+   //-----------------------------------------------------------------
+   {
+      match error.status()
+      {
+         | Some(status) => Self::from_status(status, "", context),
+         | None if error.is_builder() || error.is_redirect() => HttpError::Permanent(format!("{context}: {error}")),
+         | None => HttpError::Transient(format!("{context}: {error}")),
+      }
+   }
+
+   /// Classify an unsuccessful response `status`, using the response `body` to recognise quota errors (Google reports
+   /// them as 403 Forbidden).
+   pub fn from_status(status: StatusCode, body: &str, context: &str) -> Self
+   //-----------------------------------------------------------------------
+   {
+      let message = format!("{context}: HTTP {status}");
+      let body = body.to_lowercase();
+      if status == StatusCode::TOO_MANY_REQUESTS
+         || (status == StatusCode::FORBIDDEN && (body.contains("quota") || body.contains("limit")))
+      {
+         HttpError::Quota(message)
+      }
+      else if status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT
+      {
+         HttpError::Transient(message)
+      }
+      else
+      {
+         HttpError::Permanent(message)
+      }
+   }
+}
+
+/// Run `request` (named `what` in the log), retrying transient failures with an exponential backoff. Quota and
+/// permanent failures are returned straight away.
+pub async fn with_retry<T, F, Fut>(what: &str, mut request: F) -> Result<T, HttpError>
+   where F: FnMut() -> Fut, Fut: Future<Output = Result<T, HttpError>>
+//----------------------------------------------------------------------------------------
+{
+   let mut backoff = RETRY_BACKOFF;
+   let mut retries = 0;
+   loop
+   {
+      match request().await
+      {
+         | Err(HttpError::Transient(e)) if retries < RETRIES =>
+         {
+            retries += 1;
+            log_warn!("{what} failed ({e}), retrying in {} ms", backoff.as_millis());
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+         }
+         | result => return result,
+      }
+   }
+}
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, fs::{self, File}, io::BufReader, path::Path};
+
+use gpx::{Gpx, GpxVersion, Metadata, Track, TrackSegment, Waypoint};
+
+use crate::geojson::read_geojson;
+
+/// FIT message numbers and the fields read from them.
+const FIT_COURSE: u16 = 31;
+const FIT_COURSE_NAME: u8 = 5;
+const FIT_RECORD: u16 = 20;
+const FIT_LATITUDE: u8 = 0;
+const FIT_LONGITUDE: u8 = 1;
+const FIT_ALTITUDE: u8 = 2;
+const FIT_ENHANCED_ALTITUDE: u8 = 78;
+
+/// A course point read from a TCX or FIT file: latitude and longitude in degrees and elevation in metres.
+type CoursePoint = (f64, f64, Option<f64>);
+
+/// Read a GPX, TCX, FIT or GeoJSON course file (chosen by extension) as GPX.
+pub fn read_course_file(path: &Path) -> Result<Gpx, String>
+//----------------------------------------------------------
+{
+   let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+   match extension.as_str()
+   {
+      | "gpx" =>
+      {
+         let file = File::open(path).map_err(|e| e.to_string())?;
+         gpx::read(BufReader::new(file)).map_err(|e| format!("Invalid GPX file: {e}"))
+      }
+      | "tcx" => read_tcx(&fs::read_to_string(path).map_err(|e| e.to_string())?),
+      | "fit" => read_fit(&fs::read(path).map_err(|e| e.to_string())?),
+      | "geojson" | "json" => read_geojson(&fs::read_to_string(path).map_err(|e| e.to_string())?),
+      | _ => Err(format!("Unsupported file type '{extension}', expected GPX, TCX, FIT or GeoJSON")),
+   }
+}
+
+/// Convert a Garmin Training Center (TCX) course or activity to GPX.
+pub fn read_tcx(text: &str) -> Result<Gpx, String>
+//------------------------------------------------
+{
+   let document = roxmltree::Document::parse(text).map_err(|e| format!("Invalid TCX file: {e}"))?;
+   let child_text = |node: roxmltree::Node, name: &str| -> Option<String>
+   {
+      node.children().find(|c| c.has_tag_name(name)).and_then(|c| c.text()).map(|t| t.trim().to_string())
+   };
+   let name = document.descendants().find(|n| n.has_tag_name("Course")).and_then(|course| child_text(course, "Name"));
+   let mut points = Vec::new();
+   for trackpoint in document.descendants().filter(|n| n.has_tag_name("Trackpoint"))
+   {
+      let Some(position) = trackpoint.children().find(|c| c.has_tag_name("Position"))
+      else
+      {
+         continue; // e.g. paused or indoor samples
+      };
+      let latitude = child_text(position, "LatitudeDegrees").and_then(|t| t.parse::<f64>().ok());
+      let longitude = child_text(position, "LongitudeDegrees").and_then(|t| t.parse::<f64>().ok());
+      if let (Some(latitude), Some(longitude)) = (latitude, longitude)
+      {
+         let elevation = child_text(trackpoint, "AltitudeMeters").and_then(|t| t.parse::<f64>().ok());
+         points.push((latitude, longitude, elevation));
+      }
+   }
+   course_to_gpx(name, points)
+}
+
+/// Field layout of a FIT message type from its definition message.
+struct FitDefinition
+{
+   global:      u16,
+   big_endian:  bool,
+   fields:      Vec<(u8, usize)>, // field number and size in bytes
+   size:        usize,            // of a data message including developer fields
+}
+
+impl FitDefinition
+{
+   /// The bytes of field `number` within a data message.
+   fn field<'a>(&self, data: &'a [u8], number: u8) -> Option<&'a [u8]>
+   //------------------------------------------------------------------
+   {
+      let mut offset = 0;
+      for (field, size) in &self.fields
+      {
+         if *field == number
+         {
+            return data.get(offset .. offset + size);
+         }
+         offset += size;
+      }
+      None
+   }
+
+   /// An unsigned field value, None when missing or set to the FIT invalid value (all bits set).
+   fn unsigned(&self, data: &[u8], number: u8) -> Option<u64>
+   //---------------------------------------------------------
+   {
+      let bytes = self.field(data, number)?;
+      if bytes.is_empty() || bytes.len() > 8
+      {
+         return None;
+      }
+      let value = if self.big_endian
+      {
+         bytes.iter().fold(0u64, |v, b| (v << 8) | *b as u64)
+      }
+      else
+      {
+         bytes.iter().rev().fold(0u64, |v, b| (v << 8) | *b as u64)
+      };
+      let invalid = if bytes.len() == 8 { u64::MAX } else { (1u64 << (bytes.len() * 8)) - 1 };
+      (value != invalid).then_some(value)
+   }
+
+   /// A sint32 semicircle position in degrees.
+   fn degrees(&self, data: &[u8], number: u8) -> Option<f64>
+   //--------------------------------------------------------
+   {
+      let bytes = self.field(data, number)?;
+      let bytes: [u8; 4] = bytes.try_into().ok()?;
+      let value = if self.big_endian { i32::from_be_bytes(bytes) } else { i32::from_le_bytes(bytes) };
+      (value != i32::MAX).then(|| value as f64 * 180.0 / 2f64.powi(31))
+   }
+}
+
+/// Convert a FIT course (or activity) file to GPX, reading the record positions and altitudes and the course name.
+pub fn read_fit(bytes: &[u8]) -> Result<Gpx, String>
+//--------------------------------------------------
+{
+   if bytes.len() < 12 || &bytes[8 .. 12] != b".FIT"
+   {
+      return Err("Not a FIT file".to_string());
+   }
+   let header_size = bytes[0] as usize;
+   let data_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+   let end = (header_size + data_size).min(bytes.len());
+   let truncated = || "The FIT file is truncated".to_string();
+
+   let mut definitions: HashMap<u8, FitDefinition> = HashMap::new();
+   let mut name = None;
+   let mut points = Vec::new();
+   let mut pos = header_size;
+   while pos < end
+   {
+      let header = bytes[pos];
+      pos += 1;
+      let is_compressed_timestamp = header & 0x80 != 0;
+      let local = if is_compressed_timestamp { (header >> 5) & 0x03 } else { header & 0x0F };
+      if !is_compressed_timestamp && header & 0x40 != 0
+      {  // Definition message
+         let fixed = bytes.get(pos .. pos + 5).ok_or_else(truncated)?;
+         let big_endian = fixed[1] == 1;
+         let global = if big_endian { u16::from_be_bytes([fixed[2], fixed[3]]) } else { u16::from_le_bytes([fixed[2], fixed[3]]) };
+         let count = fixed[4] as usize;
+         pos += 5;
+         let field_bytes = bytes.get(pos .. pos + count * 3).ok_or_else(truncated)?;
+         let fields: Vec<(u8, usize)> = field_bytes.chunks_exact(3).map(|f| (f[0], f[1] as usize)).collect();
+         pos += count * 3;
+         let mut size: usize = fields.iter().map(|(_, size)| size).sum();
+         if header & 0x20 != 0
+         {  // Developer fields are skipped
+            let count = *bytes.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1;
+            let developer = bytes.get(pos .. pos + count * 3).ok_or_else(truncated)?;
+            size += developer.chunks_exact(3).map(|f| f[1] as usize).sum::<usize>();
+            pos += count * 3;
+         }
+         definitions.insert(local, FitDefinition { global, big_endian, fields, size });
+      }
+      else
+      {
+         let definition = definitions.get(&local).ok_or("The FIT file has data without a definition")?;
+         let data = bytes.get(pos .. pos + definition.size).ok_or_else(truncated)?;
+         pos += definition.size;
+         match definition.global
+         {
+            | FIT_RECORD =>
+            {
+               if let (Some(latitude), Some(longitude)) = (definition.degrees(data, FIT_LATITUDE), definition.degrees(data, FIT_LONGITUDE))
+               {
+                  let elevation = definition.unsigned(data, FIT_ENHANCED_ALTITUDE)
+                                            .or_else(|| definition.unsigned(data, FIT_ALTITUDE))
+                                            .map(|v| v as f64 / 5.0 - 500.0);
+                  points.push((latitude, longitude, elevation));
+               }
+            }
+            | FIT_COURSE if name.is_none() =>
+            {
+               name = definition.field(data, FIT_COURSE_NAME)
+                                .map(|b| String::from_utf8_lossy(b.split(|c| *c == 0).next().unwrap_or_default()).trim().to_string())
+                                .filter(|n| !n.is_empty());
+            }
+            | _ => {}
+         }
+      }
+   }
+   course_to_gpx(name, points)
+}
+
+pub(crate) fn course_to_gpx(name: Option<String>, points: Vec<CoursePoint>) -> Result<Gpx, String>
+//------------------------------------------------------------------------------------
+{
+   if points.len() < 2
+   {
+      return Err("The file contains no course positions".to_string());
+   }
+   let mut segment = TrackSegment::new();
+   segment.points = points.into_iter()
+                          .map(|(latitude, longitude, elevation)|
+                          {
+                             let mut waypoint = Waypoint::new(geo_types::Point::new(longitude, latitude));
+                             waypoint.elevation = elevation;
+                             waypoint
+                          })
+                          .collect();
+   let mut track = Track::new();
+   track.name = name.clone();
+   track.segments.push(segment);
+   Ok(Gpx
+   {
+      version: GpxVersion::Gpx11,
+      creator: Some("GPXAssist".to_string()),
+      metadata: Some(Metadata { name, ..Default::default() }),
+      tracks: vec![track],
+      ..Default::default()
+   })
+}
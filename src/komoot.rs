@@ -0,0 +1,182 @@
+use std::{fs::{self, File},
+          io::{BufRead, BufReader, BufWriter, Write},
+          net::TcpListener,
+          path::{Path, PathBuf},
+          time::SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::{http, import::course_to_gpx, settings::KomootSettings, ut::safe_file_name};
+
+const AUTHORIZE_URL: &str = "https://auth-api.main.komoot.net/oauth/authorize";
+const TOKEN_URL: &str = "https://auth-api.main.komoot.net/oauth/token";
+const API_URL: &str = "https://external-api.komoot.de/v007";
+/// Local port receiving the OAuth redirect; register http://127.0.0.1:8687/callback as the client redirect URI.
+pub const REDIRECT_PORT: u16 = 8687;
+/// Subdirectory of the course library that opened tours are saved in.
+const KOMOOT_DIRECTORY: &str = "komoot";
+
+/// A tour planned in Komoot.
+#[derive(Debug, Clone)]
+pub struct KomootTour
+{
+   pub id:       u64,
+   pub name:     String,
+   pub sport:    String,
+   pub distance: f64, // metres
+   pub ascent:   f64, // metres
+}
+
+/// POST a token request, returning the parsed JSON response.
+fn token_request(client: &reqwest::Client, client_id: &str, client_secret: &str, form: &[(&str, &str)])
+   -> Result<serde_json::Value, String>
+//-----------------------------------------------------------------------------------------------------
+{
+   let (status, text) = http::block_on(async
+   {
+      let response = client.post(TOKEN_URL)
+                           .basic_auth(client_id, Some(client_secret))
+                           .form(form)
+                           .send()
+                           .await
+                           .map_err(|e| format!("Could not reach Komoot: {}", e))?;
+      let status = response.status();
+      response.text().await.map(|text| (status, text)).map_err(|e| format!("Failed to read Komoot response: {}", e))
+   })?;
+   if !status.is_success()
+   {
+      return Err(format!("Komoot refused the authorisation ({status}): {text}"));
+   }
+   serde_json::from_str(&text).map_err(|e| format!("Unexpected response from Komoot: {}", e))
+}
+
+/// Authorise GPXAssist to read the user's Komoot tours with the OAuth authorisation code flow: the user opens the
+/// printed address in a browser and signs in, and Komoot redirects back to a listener on REDIRECT_PORT. Komoot only
+/// issues API clients to partners, so the client id and secret are the user's own.
+pub fn login(client_id: &str, client_secret: &str) -> Result<KomootSettings, String>
+//---------------------------------------------------------------------------------
+{
+   let listener = TcpListener::bind(("127.0.0.1", REDIRECT_PORT))
+      .map_err(|e| format!("Could not listen for the Komoot redirect on port {REDIRECT_PORT}: {e}"))?;
+   let redirect_uri = format!("http://127.0.0.1:{REDIRECT_PORT}/callback");
+   let seed = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+   let state = hex::encode(&Sha256::digest(format!("{seed}{}", std::process::id()).as_bytes())[..16]);
+   let url = reqwest::Url::parse_with_params(AUTHORIZE_URL, &[("client_id", client_id), ("response_type", "code"),
+                                                              ("scope", "profile"), ("redirect_uri", &redirect_uri),
+                                                              ("state", &state)])
+      .map_err(|e| e.to_string())?;
+   println!("Open this address in a browser and sign in to Komoot:\n\n{url}\n\nWaiting for the authorisation...");
+
+   let (stream, _) = listener.accept().map_err(|e| format!("Failed to receive the Komoot redirect: {e}"))?;
+   let mut request_line = String::new();
+   BufReader::new(&stream).read_line(&mut request_line).map_err(|e| e.to_string())?;
+   let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+   let callback = reqwest::Url::parse(&format!("http://127.0.0.1{target}")).map_err(|e| e.to_string())?;
+   let parameter = |name: &str| callback.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.to_string());
+   let message = if parameter("code").is_some() { "GPXAssist is now connected to Komoot. You can close this window." }
+                 else { "Komoot authorisation failed. You can close this window." };
+   let _ = write!(&stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+                  message.len());
+   if parameter("state").as_deref() != Some(state.as_str())
+   {
+      return Err("The Komoot redirect did not match the request".to_string());
+   }
+   let code = parameter("code").ok_or_else(|| format!("Komoot authorisation failed: {}", parameter("error").unwrap_or_default()))?;
+
+   let client = http::client()?;
+   let json = token_request(&client, client_id, client_secret, &[("grant_type", "authorization_code"), ("code", &code),
+                                                                  ("redirect_uri", &redirect_uri)])?;
+   let refresh_token = json["refresh_token"].as_str().ok_or("Komoot did not return a refresh token")?;
+   let username = json["username"].as_str().ok_or("Komoot did not return the user id")?;
+   let mut settings = KomootSettings::default();
+   settings.set_account(client_id, client_secret, username, refresh_token)?;
+   Ok(settings)
+}
+
+/// Client for the Komoot API signed in with the account in the settings.
+pub struct Komoot
+{
+   client:       reqwest::Client,
+   access_token: String,
+   username:     String,
+}
+
+impl Komoot
+{
+   /// Exchange the stored refresh token for an access token.
+   pub fn connect(settings: &KomootSettings) -> Result<Self, String>
+   //---------------------------------------------------------------
+   {
+      if !settings.is_connected()
+      {
+         return Err("Not signed in to Komoot, use the komoot login command first".to_string());
+      }
+      let client = http::client()?;
+      let refresh_token = settings.decrypted_refresh_token()?;
+      let json = token_request(&client, &settings.client_id, &settings.decrypted_secret()?,
+                               &[("grant_type", "refresh_token"), ("refresh_token", &refresh_token)])?;
+      let access_token = json["access_token"].as_str().ok_or("Komoot did not return an access token")?.to_string();
+      Ok(Self { client, access_token, username: settings.username.clone() })
+   }
+
+   fn get_json(&self, url: &str) -> Result<serde_json::Value, String>
+   //-----------------------------------------------------------------
+   {
+      let text = http::block_on(async
+      {
+         let response = self.client.get(url).bearer_auth(&self.access_token).send().await
+                                   .map_err(|e| format!("Could not reach Komoot: {}", e))?;
+         if !response.status().is_success()
+         {
+            return Err(format!("HTTP error {} from Komoot", response.status()));
+         }
+         response.text().await.map_err(|e| format!("Failed to read Komoot response: {}", e))
+      })?;
+      serde_json::from_str(&text).map_err(|e| format!("Unexpected response from Komoot: {}", e))
+   }
+
+   /// The planned tours in the account, following the paged list.
+   pub fn list_tours(&self) -> Result<Vec<KomootTour>, String>
+   //---------------------------------------------------------
+   {
+      let mut tours = Vec::new();
+      let mut url = Some(format!("{API_URL}/users/{}/tours/?type=tour_planned&limit=100", self.username));
+      while let Some(page) = url.take()
+      {
+         let json = self.get_json(&page)?;
+         for tour in json["_embedded"]["tours"].as_array().into_iter().flatten()
+         {
+            let Some(id) = tour["id"].as_u64() else { continue; };
+            tours.push(KomootTour
+            {
+               id,
+               name: tour["name"].as_str().unwrap_or("Komoot tour").to_string(),
+               sport: tour["sport"].as_str().unwrap_or_default().to_string(),
+               distance: tour["distance"].as_f64().unwrap_or(0.0),
+               ascent: tour["elevation_up"].as_f64().unwrap_or(0.0),
+            });
+         }
+         url = json["_links"]["next"]["href"].as_str().map(str::to_string);
+      }
+      Ok(tours)
+   }
+
+   /// Download a tour and save it as a GPX file in the komoot folder of `courses_directory`, returning its path.
+   pub fn save_tour(&self, tour: &KomootTour, courses_directory: &Path) -> Result<PathBuf, String>
+   //--------------------------------------------------------------------------------------------
+   {
+      let json = self.get_json(&format!("{API_URL}/tours/{}/coordinates", tour.id))?;
+      let points = json["items"].as_array()
+                                .ok_or("Komoot tour has no coordinates")?
+                                .iter()
+                                .filter_map(|item| Some((item["lat"].as_f64()?, item["lng"].as_f64()?, item["alt"].as_f64())))
+                                .collect();
+      let gpx = course_to_gpx(Some(tour.name.clone()), points)?;
+      let directory = courses_directory.join(KOMOOT_DIRECTORY);
+      fs::create_dir_all(&directory).map_err(|e| format!("Error creating {}: {}", directory.display(), e))?;
+      let path = directory.join(format!("{}-{}.gpx", safe_file_name(&tour.name), tour.id));
+      let writer = BufWriter::new(File::create(&path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?);
+      gpx::write(&gpx, writer).map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+      Ok(path)
+   }
+}
@@ -0,0 +1,415 @@
+use std::{collections::HashMap,
+          fs::{self, File},
+          io::{BufReader, Write},
+          path::{Path, PathBuf},
+          sync::{Arc, mpsc::{Receiver, Sender, channel}},
+          time::UNIX_EPOCH};
+
+use eframe::egui::{self, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{SETTINGS, gpx::{course_name, elevation_gain_loss, track_data_from_gpx}, komoot::{Komoot, KomootTour}, settings::Settings,
+            units::Units};
+
+pub(crate) const CACHE_FILE: &str = "course_cache.json";
+
+/// Summary of a course file in the library. Cached (keyed by path, invalidated by modification time and size) so
+/// large libraries don't have to be re-parsed every time the library is opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CourseInfo
+{
+   pub path:           PathBuf,
+   pub name:           String,
+   pub distance:       f64, // metres
+   pub elevation_gain: f64, // metres
+   pub points:         usize,
+   #[serde(default)]
+   pub start:          Option<(f64, f64)>, // (latitude, longitude) of the first point, to match the course to a ride
+   modified:           u64,
+   size:               u64,
+}
+
+/// Results of Komoot requests made on a background thread.
+enum KomootMessage
+{
+   Tours(Result<Vec<KomootTour>, String>),
+   Saved(Result<PathBuf, String>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum CourseSort
+{
+   Name,
+   Distance,
+   Gain,
+}
+
+/// Scan `dir` (recursively) for GPX files, using and updating the summary cache.
+pub fn scan_courses(dir: &Path) -> Result<Vec<CourseInfo>, String>
+//----------------------------------------------------------------
+{
+   if !dir.is_dir()
+   {
+      return Err(format!("Courses directory {:?} does not exist or is not a directory.", dir));
+   }
+   let cache = read_cache();
+   let mut courses = Vec::new();
+   let mut pending = vec![dir.to_path_buf()];
+   while let Some(current) = pending.pop()
+   {
+      let entries = match fs::read_dir(&current)
+      {
+         | Ok(entries) => entries,
+         | Err(e) =>
+         {
+            log_warn!("Could not read directory {:?}: {}", current, e);
+            continue;
+         }
+      };
+      for entry in entries.flatten()
+      {
+         let path = entry.path();
+         if path.is_dir()
+         {
+            pending.push(path);
+            continue;
+         }
+         if !path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpx"))
+         {
+            continue;
+         }
+         let (modified, size) = match entry.metadata()
+         {
+            | Ok(meta) => (meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_secs()), meta.len()),
+            | Err(_) => (0, 0),
+         };
+         match cache.get(&path)
+         {
+            | Some(info) if info.modified == modified && info.size == size && info.start.is_some() => courses.push(info.clone()),
+            | _ => match summarize_course(&path, modified, size)
+            {
+               | Ok(info) => courses.push(info),
+               | Err(e) => log_warn!("Skipping course {:?}: {}", path, e),
+            },
+         }
+      }
+   }
+   write_cache(&courses);
+   Ok(courses)
+}
+
+fn summarize_course(path: &Path, modified: u64, size: u64) -> Result<CourseInfo, String>
+//--------------------------------------------------------------------------------------
+{
+   let file = File::open(path).map_err(|e| format!("Failed to open: {}", e))?;
+   let gpx = gpx::read(BufReader::new(file)).map_err(|e| format!("Failed to parse: {}", e))?;
+   let track = track_data_from_gpx(&gpx).map_err(|e| e.to_string())?;
+   let (gain, _) = elevation_gain_loss(&track, 2.0);
+   let name = course_name(&gpx)
+      .unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default());
+   Ok(CourseInfo { path: path.to_path_buf(),
+                   name,
+                   distance: track.last().map_or(0.0, |p| p.distance),
+                   elevation_gain: gain,
+                   points: track.len(),
+                   start: track.first().map(|p| (p.point.lat, p.point.lon)),
+                   modified,
+                   size })
+}
+
+fn cache_path() -> Option<PathBuf>
+{
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   Some(settings.lock().cache_path(CACHE_FILE))
+}
+
+fn read_cache() -> HashMap<PathBuf, CourseInfo>
+//---------------------------------------------
+{
+   let Some(path) = cache_path() else { return HashMap::new(); };
+   let Ok(file) = File::open(&path) else { return HashMap::new(); };
+   match serde_json::from_reader::<_, Vec<CourseInfo>>(BufReader::new(file))
+   {
+      | Ok(courses) => courses.into_iter().map(|c| (c.path.clone(), c)).collect(),
+      | Err(e) =>
+      {
+         log_warn!("Ignoring invalid course cache {:?}: {}", path, e);
+         HashMap::new()
+      }
+   }
+}
+
+fn write_cache(courses: &[CourseInfo])
+//------------------------------------
+{
+   let Some(path) = cache_path() else { return; };
+   let result = serde_json::to_string(courses).map_err(|e| e.to_string())
+      .and_then(|json| File::create(&path).and_then(|mut f| f.write_all(json.as_bytes())).map_err(|e| e.to_string()));
+   if let Err(e) = result
+   {
+      log_warn!("Failed to write course cache {:?}: {}", path, e);
+   }
+}
+
+/// The course library window.
+pub struct CourseLibrary
+//======================
+{
+   pub is_open:  bool,
+   courses:      Vec<CourseInfo>,
+   is_scanning:  bool,
+   error:        Option<String>,
+   filter:       String,
+   sort:         CourseSort,
+   is_ascending: bool,
+   channel:      (Sender<Result<Vec<CourseInfo>, String>>, Receiver<Result<Vec<CourseInfo>, String>>),
+   komoot_tours:   Vec<KomootTour>,
+   komoot_error:   Option<String>,
+   is_komoot_busy: bool,
+   komoot_channel: (Sender<KomootMessage>, Receiver<KomootMessage>),
+}
+
+impl Default for CourseLibrary
+{
+   fn default() -> Self
+   {
+      Self { is_open: false, courses: Vec::new(), is_scanning: false, error: None, filter: String::new(), sort: CourseSort::Name,
+             is_ascending: true, channel: channel(), komoot_tours: Vec::new(), komoot_error: None, is_komoot_busy: false,
+             komoot_channel: channel() }
+   }
+}
+
+impl CourseLibrary
+{
+   pub fn courses_directory() -> PathBuf
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      settings.lock().courses_directory.clone()
+   }
+
+   pub fn open(&mut self, ctx: &Context)
+   {
+      self.is_open = true;
+      self.rescan(ctx);
+   }
+
+   /// Scan the courses directory on a background thread.
+   pub fn rescan(&mut self, ctx: &Context)
+   //-------------------------------------
+   {
+      if self.is_scanning
+      {
+         return;
+      }
+      self.is_scanning = true;
+      let dir = CourseLibrary::courses_directory();
+      let sender = self.channel.0.clone();
+      let ctxx = ctx.clone();
+      std::thread::spawn(move ||
+      {
+         let _ = sender.send(scan_courses(&dir));
+         ctxx.request_repaint();
+      });
+   }
+
+   /// Run a Komoot request on a background thread, signed in with the account from the settings.
+   fn komoot_request<F>(&mut self, ctx: &Context, request: F)
+      where F: FnOnce(Komoot) -> KomootMessage + Send + 'static
+   //---------------------------------------------------------
+   {
+      if self.is_komoot_busy
+      {
+         return;
+      }
+      self.is_komoot_busy = true;
+      self.komoot_error = None;
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let account = settings.lock().komoot.clone();
+      let sender = self.komoot_channel.0.clone();
+      let ctxx = ctx.clone();
+      std::thread::spawn(move ||
+      {
+         let message = match Komoot::connect(&account)
+         {
+            | Ok(komoot) => request(komoot),
+            | Err(e) => KomootMessage::Tours(Err(e)),
+         };
+         let _ = sender.send(message);
+         ctxx.request_repaint();
+      });
+   }
+
+   /// Planned Komoot tours with buttons to download and open them, when a Komoot account is connected.
+   fn show_komoot_tours(&mut self, ui: &mut egui::Ui, units: Units)
+   //--------------------------------------------------------------
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      if !settings.lock().komoot.is_connected()
+      {
+         return;
+      }
+      egui::CollapsingHeader::new("Komoot planned tours").show(ui, |ui|
+      {
+         ui.horizontal(|ui|
+         {
+            if ui.button("⟳ Load tours").clicked()
+            {
+               self.komoot_request(ui.ctx(), |komoot| KomootMessage::Tours(komoot.list_tours()));
+            }
+            if self.is_komoot_busy
+            {
+               ui.spinner();
+            }
+         });
+         if let Some(e) = &self.komoot_error
+         {
+            ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+         }
+         let mut opened = None;
+         egui::ScrollArea::vertical().id_salt("komoot_tours").max_height(200.0).show(ui, |ui|
+         {
+            egui::Grid::new("komoot_tours_grid").num_columns(5).striped(true).spacing([20.0, 6.0]).show(ui, |ui|
+            {
+               for tour in &self.komoot_tours
+               {
+                  ui.label(&tour.name);
+                  ui.label(&tour.sport);
+                  ui.label(units.format_distance(tour.distance, 1));
+                  ui.label(units.format_length(tour.ascent));
+                  if ui.add_enabled(!self.is_komoot_busy, egui::Button::new("Open"))
+                       .on_hover_text("Download the tour to the komoot folder of the course library and open it")
+                       .clicked()
+                  {
+                     opened = Some(tour.clone());
+                  }
+                  ui.end_row();
+               }
+            });
+         });
+         if let Some(tour) = opened
+         {
+            let directory = CourseLibrary::courses_directory();
+            self.komoot_request(ui.ctx(), move |komoot| KomootMessage::Saved(komoot.save_tour(&tour, &directory)));
+         }
+      });
+   }
+
+   fn sort_courses(&mut self)
+   {
+      let sort = self.sort;
+      self.courses.sort_by(|a, b|
+      {
+         let ordering = match sort
+         {
+            | CourseSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            | CourseSort::Distance => a.distance.total_cmp(&b.distance),
+            | CourseSort::Gain => a.elevation_gain.total_cmp(&b.elevation_gain),
+         };
+         if self.is_ascending { ordering } else { ordering.reverse() }
+      });
+   }
+
+   /// Show the library window, returning the path of a course the user chose to open.
+   pub fn show(&mut self, ctx: &Context, units: Units) -> Option<PathBuf>
+   //--------------------------------------------------------------------
+   {
+      if let Ok(result) = self.channel.1.try_recv()
+      {
+         self.is_scanning = false;
+         match result
+         {
+            | Ok(courses) =>
+            {
+               self.courses = courses;
+               self.error = None;
+               self.sort_courses();
+            }
+            | Err(e) => self.error = Some(e),
+         }
+      }
+      let mut selected = None;
+      if let Ok(message) = self.komoot_channel.1.try_recv()
+      {
+         self.is_komoot_busy = false;
+         match message
+         {
+            | KomootMessage::Tours(Ok(tours)) => self.komoot_tours = tours,
+            | KomootMessage::Saved(Ok(path)) => selected = Some(path),
+            | KomootMessage::Tours(Err(e)) | KomootMessage::Saved(Err(e)) => self.komoot_error = Some(e),
+         }
+      }
+      if !self.is_open || selected.is_some()
+      {
+         self.is_open = self.is_open && selected.is_none();
+         return selected;
+      }
+      let mut is_open = self.is_open;
+      egui::Window::new("Course Library")
+         .open(&mut is_open)
+         .resizable(true)
+         .default_width(700.0)
+         .default_height(500.0)
+         .show(ctx, |ui|
+         {
+            ui.horizontal(|ui|
+            {
+               ui.label(format!("Folder: {}", CourseLibrary::courses_directory().display()));
+               if ui.button("⟳ Rescan").clicked()
+               {
+                  self.rescan(ui.ctx());
+               }
+               if self.is_scanning
+               {
+                  ui.spinner();
+               }
+            });
+            ui.horizontal(|ui|
+            {
+               ui.label("🔎");
+               ui.text_edit_singleline(&mut self.filter);
+            });
+            if let Some(e) = &self.error
+            {
+               ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+            }
+            self.show_komoot_tours(ui, units);
+            ui.separator();
+            let filter = self.filter.to_lowercase();
+            let mut new_sort = None;
+            egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui|
+            {
+               egui::Grid::new("course_library_grid").num_columns(4).striped(true).spacing([20.0, 6.0]).show(ui, |ui|
+               {
+                  for (sort, title) in [(CourseSort::Name, "Name"), (CourseSort::Distance, "Distance"), (CourseSort::Gain, "Elevation Gain")]
+                  {
+                     let arrow = if self.sort == sort { if self.is_ascending { " ⏶" } else { " ⏷" } } else { "" };
+                     if ui.add(egui::Button::new(egui::RichText::new(format!("{title}{arrow}")).strong()).frame(false)).clicked()
+                     {
+                        new_sort = Some(sort);
+                     }
+                  }
+                  ui.label("");
+                  ui.end_row();
+                  for course in self.courses.iter().filter(|c| filter.is_empty() || c.name.to_lowercase().contains(&filter))
+                  {
+                     ui.label(&course.name).on_hover_text(format!("{}\n{} points", course.path.display(), course.points));
+                     ui.label(units.format_distance(course.distance, 1));
+                     ui.label(units.format_length(course.elevation_gain));
+                     if ui.button("Open").clicked()
+                     {
+                        selected = Some(course.path.clone());
+                     }
+                     ui.end_row();
+                  }
+               });
+            });
+            if let Some(sort) = new_sort
+            {
+               if self.sort == sort { self.is_ascending = !self.is_ascending; } else { self.sort = sort; self.is_ascending = true; }
+               self.sort_courses();
+            }
+         });
+      self.is_open = is_open && selected.is_none();
+      selected
+   }
+}
@@ -0,0 +1,215 @@
+use std::{collections::VecDeque, fmt::{self, Write}, path::Path};
+
+use chrono::{DateTime, Local};
+use eframe::egui::{self, Color32};
+use tracing::{Level, field::{Field, Visit}};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{EnvFilter, Layer, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt};
+
+use crate::components::Labelled;
+
+const MAX_LOG_ENTRIES: usize = 500;
+/// Daily log files kept in the log directory.
+const MAX_LOG_FILES: usize = 7;
+/// Used when RUST_LOG is not set: GPXAssist messages from info up and only warnings from the libraries. Per update
+/// messages (distances sent, Street View requests) are logged at debug, e.g. RUST_LOG=GPXAssist=debug.
+const DEFAULT_FILTER: &str = "warn,GPXAssist=info";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogLevel
+{
+   Info,
+   Warning,
+   Error,
+}
+
+impl LogLevel
+{
+   pub fn label(&self) -> &'static str
+   {
+      match self
+      {
+         | LogLevel::Info => "INFO",
+         | LogLevel::Warning => "WARN",
+         | LogLevel::Error => "ERROR",
+      }
+   }
+
+   fn color(&self) -> Color32
+   {
+      match self
+      {
+         | LogLevel::Info => Color32::LIGHT_GRAY,
+         | LogLevel::Warning => Color32::from_rgb(255, 165, 0),
+         | LogLevel::Error => Color32::from_rgb(220, 53, 69),
+      }
+   }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry
+{
+   pub time:    DateTime<Local>,
+   pub level:   LogLevel,
+   pub message: String,
+}
+
+impl LogEntry
+{
+   pub fn to_line(&self) -> String { format!("{} [{}] {}", self.time.format("%Y-%m-%d %H:%M:%S"), self.level.label(), self.message) }
+}
+
+/// Ring buffer of recent log messages shown in the log console.
+static LOG_BUFFER: parking_lot::Mutex<VecDeque<LogEntry>> = parking_lot::Mutex::new(VecDeque::new());
+
+/// Send log messages (including those of libraries using the `log` crate) to stderr, to a daily rolling log file in
+/// `directory` when given and to the log console.
+pub fn init(directory: Option<&Path>)
+//-----------------------------------
+{
+   let filter = || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+   let file = directory.and_then(|directory|
+   {
+      RollingFileAppender::builder().rotation(Rotation::DAILY)
+                                    .filename_prefix("gpxassist")
+                                    .filename_suffix("log")
+                                    .max_log_files(MAX_LOG_FILES)
+                                    .build(directory)
+                                    .inspect_err(|e| eprintln!("Could not create a log file in {}: {}", directory.display(), e))
+                                    .ok()
+   });
+   let has_file = file.is_some();
+   let result = tracing_subscriber::registry()
+      .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_filter(filter()))
+      .with(file.map(|file| tracing_subscriber::fmt::layer().with_writer(file).with_ansi(false).with_filter(filter())))
+      .with(ConsoleLayer.with_filter(filter()))
+      .try_init();
+   match (result, directory)
+   {
+      | (Err(e), _) => eprintln!("Could not start logging: {e}"),
+      | (Ok(()), Some(directory)) if has_file => tracing::info!("Logging to {}", directory.display()),
+      | _ => (),
+   }
+}
+
+/// Copies events from info up into the log console buffer, prefixed with the names of the spans they occurred in.
+struct ConsoleLayer;
+
+impl<S> Layer<S> for ConsoleLayer
+   where S: tracing::Subscriber + for<'a> LookupSpan<'a>
+{
+   fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>)
+   {
+      let level = match *event.metadata().level()
+      {
+         | Level::ERROR => LogLevel::Error,
+         | Level::WARN => LogLevel::Warning,
+         | Level::INFO => LogLevel::Info,
+         | _ => return,
+      };
+      let mut message = String::new();
+      if let Some(scope) = ctx.event_scope(event)
+      {
+         for span in scope.from_root()
+         {
+            let _ = write!(message, "{}: ", span.name());
+         }
+      }
+      event.record(&mut MessageVisitor(&mut message));
+      record(level, message);
+   }
+}
+
+/// Formats the message of an event followed by any other fields as name=value.
+struct MessageVisitor<'a>(&'a mut String);
+
+impl Visit for MessageVisitor<'_>
+{
+   fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug)
+   {
+      let _ = if field.name() == "message" { write!(self.0, "{value:?}") } else { write!(self.0, " {}={value:?}", field.name()) };
+   }
+}
+
+/// Record a message in the log console buffer.
+fn record(level: LogLevel, message: String)
+//-----------------------------------------
+{
+   let mut buffer = LOG_BUFFER.lock();
+   if buffer.len() >= MAX_LOG_ENTRIES
+   {
+      buffer.pop_front();
+   }
+   buffer.push_back(LogEntry { time: Local::now(), level, message });
+}
+
+pub fn entries() -> Vec<LogEntry> { LOG_BUFFER.lock().iter().cloned().collect() }
+
+pub fn clear() { LOG_BUFFER.lock().clear(); }
+
+/// Number of warnings and errors in the buffer.
+pub fn problem_count() -> usize { LOG_BUFFER.lock().iter().filter(|e| e.level != LogLevel::Info).count() }
+
+macro_rules! log_error
+{
+   ($($arg:tt)*) => { tracing::error!($($arg)*) };
+}
+
+macro_rules! log_warn
+{
+   ($($arg:tt)*) => { tracing::warn!($($arg)*) };
+}
+
+macro_rules! log_info
+{
+   ($($arg:tt)*) => { tracing::info!($($arg)*) };
+}
+
+/// For messages logged on every update, which are only wanted when diagnosing a problem.
+macro_rules! log_debug
+{
+   ($($arg:tt)*) => { tracing::debug!($($arg)*) };
+}
+
+/// Collapsible bottom panel listing the buffered log messages with copy and clear buttons.
+pub fn show_log_console(ctx: &egui::Context, is_open: &mut bool)
+//-------------------------------------------------------------
+{
+   if ! *is_open
+   {
+      return;
+   }
+   egui::TopBottomPanel::bottom("log_console").resizable(true).default_height(180.0).show(ctx, |ui|
+   {
+      let entries = entries();
+      ui.horizontal(|ui|
+      {
+         ui.label(egui::RichText::new("Log").strong());
+         ui.separator();
+         if ui.button("📋 Copy").on_hover_text("Copy the log to the clipboard").clicked()
+         {
+            let text = entries.iter().map(|e| e.to_line()).collect::<Vec<_>>().join("\n");
+            ui.ctx().copy_text(text);
+         }
+         if ui.button("Clear").clicked()
+         {
+            clear();
+         }
+         ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui|
+         {
+            if ui.button("✖").labelled("Close").on_hover_text("Close the log console").clicked()
+            {
+               *is_open = false;
+            }
+         });
+      });
+      ui.separator();
+      egui::ScrollArea::vertical().auto_shrink([false, false]).stick_to_bottom(true).show(ui, |ui|
+      {
+         for entry in entries.iter()
+         {
+            ui.label(egui::RichText::new(entry.to_line()).monospace().size(13.0).color(entry.level.color()));
+         }
+      });
+   });
+}
@@ -7,36 +7,114 @@ use eframe::egui;
 use lazy_static::lazy_static;
 
 
+#[macro_use]
+mod logging;
 mod settings;
 mod components;
 mod gpx;
 pub mod ui;
 mod ut;
+mod update;
+mod library;
+mod simulation;
+mod source;
+mod units;
+mod cli;
+mod validate;
+mod import;
+mod precache;
+mod summary;
+mod history;
+mod recorder;
+mod server;
+mod weather;
+mod milestones;
+mod discord;
+mod fan;
+mod trainer;
+mod video;
+mod http;
+mod speech;
+mod slope;
+mod garmin;
+mod komoot;
+mod geojson;
+mod crash;
+mod automatch;
+mod compare;
+mod surface;
+mod gradients;
+mod climbs;
+mod pacing;
 pub mod data;
 
-use crate::{gpx::TrackPoint, ui::GPXAssistUI};
+use crate::{gpx::TrackPoint, ui::{GPXAssistUI, ViewMode}};
 use settings::Settings;
 
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args
 {
    /// Select gpx distance calculation method h = Haversine, e = ECEF
    #[arg(short = 'm', long = "method", default_value = "e")]
    method: char,
 
-   #[arg(short = 'p', long = "password", default_value = "", help = "Encrypt new password and write to config file")]
-   password: String,
+   /// Override a setting for this run only, e.g. --set gradient_length=5000 --set units=Imperial
+   /// (nested settings use dotted keys such as climb_alerts.threshold=10)
+   #[arg(long = "set", value_name = "KEY=VALUE")]
+   set: Vec<String>,
+
+   /// Start simulating movement along the GPX file straight away, optionally at SPEED (km/h, or mph with imperial
+   /// units) instead of the default speed
+   #[arg(long, value_name = "SPEED", num_args = 0..=1)]
+   simulate: Option<Option<f64>>,
+
+   /// Write a JSON ride summary when the course is completed or GPXAssist exits, to PATH (a file or directory) or
+   /// beside the GPX file
+   #[arg(long, value_name = "PATH", num_args = 0..=1)]
+   ride_summary: Option<Option<std::path::PathBuf>>,
+
+   /// Record rides from the broadcast file to DIR (default the current directory) without the user interface. The
+   /// optional GPX file supplies positions and altitudes
+   #[arg(long, value_name = "DIR", num_args = 0..=1, conflicts_with_all = ["simulate", "view", "ride_summary"])]
+   headless_log: Option<Option<std::path::PathBuf>>,
+
+   /// Recording formats for --headless-log
+   #[arg(long, value_enum, value_delimiter = ',', default_values_t = [recorder::RecordFormat::Csv, recorder::RecordFormat::Gpx])]
+   record_format: Vec<recorder::RecordFormat>,
+
+   /// Seconds without broadcast updates after which --headless-log ends the recording
+   #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+   idle_timeout: u64,
+
+   /// View to open the course in, instead of choosing one on the toolbar
+   #[arg(long, value_enum, value_name = "VIEW")]
+   view: Option<StartView>,
 
    /// Optional GPX file path
    #[arg()]
    file_path: Option<String>,
+
+   #[command(subcommand)]
+   command: Option<cli::Command>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum StartView
+{
+   Map,
+   Street,
+   Gradient,
+   Video,
 }
 
 struct StartupParameters
 {
-   file_path: Option<String>,
+   file_path:      Option<String>,
+   simulate_speed: Option<Option<f64>>, // km/h, inner None for the default speed
+   view:           Option<ViewMode>,
+   ride_summary:   Option<Option<std::path::PathBuf>>, // inner None to write beside the GPX file
 }
 
 static STARTUP_PARAMS: parking_lot::Mutex<RefCell<Option<StartupParameters>>> = parking_lot::Mutex::new(RefCell::new(None));
@@ -50,13 +128,16 @@ lazy_static!
 
 fn main()
 {
-   env_logger::init();
+   let config_directory = Settings::new().get_config_path().ok();
+   logging::init(config_directory.as_ref().map(|directory| directory.join("logs")).as_deref());
+   if let Some(directory) = config_directory
+   {
+      crash::install(directory);
+   }
    {
       let cmdline_opts = STARTUP_PARAMS.lock();
       let args = Args::parse();
 
-      let update_password = args.password.trim();
-
       let mut file_path: Option<String> = None;
       if let Some(filepath) = args.file_path
       {
@@ -77,28 +158,97 @@ fn main()
          }
          file_path = Some(filepath.clone());
       }
-      if !update_password.is_empty()
+      if !args.set.is_empty()
       {
          let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-         match settings.lock().set_streetview_api_key(&update_password)
+         if let Err(e) = settings.lock().apply_overrides(&args.set)
          {
-            | Ok(_) =>
-            {
-               println!("Password encrypted and saved to settings file");
-               return
-            },
-            | Err(e) =>
-            {
-               eprintln!("Error saving settings with new password: {}", e);
-               return
-            }
+            eprintln!("Invalid --set option: {}", e);
+            return
          }
       }
 
-      cmdline_opts.replace(Some(StartupParameters { file_path }));
+      if let Some(command) = args.command
+      {
+         // A copy, as commands making HTTP requests read the proxy from SETTINGS
+         let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())))
+                                .lock().clone();
+         if let Err(e) = cli::run(command, &settings)
+         {
+            eprintln!("{e}");
+            std::process::exit(1);
+         }
+         return
+      }
+
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      if let Err(e) = settings.lock().proxy.export_env()
+      {
+         log_warn!("Map tiles will not use the proxy: {}", e);
+      }
+
+      if let Some(directory) = args.headless_log
+      {
+         let directory = directory.unwrap_or_else(|| std::path::PathBuf::from("."));
+         let course = file_path.as_ref().map(std::path::Path::new);
+         let settings = settings.lock().clone();
+         if let Err(e) = recorder::log_broadcast(&directory, &args.record_format, course,
+                                                 std::time::Duration::from_secs(args.idle_timeout.max(10)), &settings)
+         {
+            eprintln!("{e}");
+            std::process::exit(1);
+         }
+         return
+      }
+
+      let mut simulate_speed = args.simulate;
+      if let Some(speed) = simulate_speed
+      {
+         if file_path.is_none()
+         {
+            eprintln!("--simulate needs a GPX file.");
+            return
+         }
+         let speed = speed.map(|s| settings.lock().units.from_speed(s));
+         if speed.is_some_and(|s| !(1.0..=200.0).contains(&s))
+         {
+            eprintln!("The --simulate speed must be between 1 and 200 km/h.");
+            return
+         }
+         simulate_speed = Some(speed);
+      }
+
+      let view = args.view.map(|view| match view
+      {
+         | StartView::Map => ViewMode::Map,
+         | StartView::Street => ViewMode::StreetView,
+         | StartView::Gradient => ViewMode::Gradient,
+         | StartView::Video => ViewMode::Video,
+      });
+
+      if let Some(Some(path)) = &args.ride_summary
+         && !path.is_dir()
+         && path.parent().is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.is_dir())
+      {
+         eprintln!("The --ride-summary directory {} does not exist.", path.parent().unwrap_or(path).display());
+         return
+      }
+
+      cmdline_opts.replace(Some(StartupParameters { file_path, simulate_speed, view, ride_summary: args.ride_summary }));
+   }
+   let (window_size, window_position, is_transparent) =
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let settings_lock = settings.lock();
+      (settings_lock.window_size, settings_lock.window_position, settings_lock.overlay_transparent)
+   };
+   let mut viewport = egui::ViewportBuilder::default().with_inner_size(window_size.unwrap_or([1024.0, 1024.0]))
+                                                      .with_transparent(is_transparent);
+   if let Some(position) = window_position
+   {
+      viewport = viewport.with_position(position);
    }
-   let options = eframe::NativeOptions { viewport: egui::ViewportBuilder::default().with_inner_size([1024.0, 1024.0]),
-                                         ..Default::default() };
+   let options = eframe::NativeOptions { viewport, ..Default::default() };
    let ret = eframe::run_native("GPXAssist",
                                 options,
                                 Box::new(|cc| {
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::Arc, time::Instant};
+
+use sha2::{Digest, Sha256};
+
+use crate::{SETTINGS, gpx::{Climb, Track}, settings::{Notifications, Settings}, units::Units};
+
+/// Best climb times, kept with the settings rather than in the cache so clearing the cache does not lose them.
+const RECORDS_FILE: &str = "climb_records.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Milestone
+{
+   Halfway,
+   FinalStretch,
+   Summit(usize), // index into the course climbs
+}
+
+/// Sends native desktop notifications for ride milestones (halfway, the final kilometre or mile, reaching the top of
+/// a climb and personal bests on climbs), so they are seen when GPXAssist is minimised or on another monitor.
+#[derive(Default)]
+pub struct MilestoneNotifier
+//==========================
+{
+   config:        Notifications,
+   climbs:        Option<Vec<Climb>>, // on the current track, found on first use
+   course_key:    Option<String>, // hash of the GPX file contents identifying the course in the climb records
+   reached:       Vec<Milestone>,
+   climb_start:   Option<(usize, Instant)>, // climb being ridden and when it was started
+   last_distance: f64,
+}
+
+impl MilestoneNotifier
+{
+   pub fn new(config: Notifications) -> Self { Self { config, ..Default::default() } }
+
+   pub fn configure(&mut self, config: Notifications) { self.config = config; }
+
+   /// Forget the course, e.g. when a new track is opened.
+   pub fn reset(&mut self) { *self = Self::new(self.config); }
+
+   /// Check the current distance for milestones. Climb times are not recorded while simulating.
+   pub fn update(&mut self, distance: f64, course: &Path, track: &Track, is_simulating: bool, units: Units)
+   //-----------------------------------------------------------------------------------------------------
+   {
+      let total_distance = track.total_distance();
+      if !self.config.is_enabled || distance <= 0.0 || total_distance <= 0.0
+      {
+         return;
+      }
+      let climbs = self.climbs.get_or_insert_with(|| track.climbs(20.0, 2.0));
+      if distance < self.last_distance
+      {  // Moved backwards (restart or scrub) so re-arm the milestones ahead of the new position
+         self.reached.retain(|milestone| match milestone
+         {
+            | Milestone::Halfway => distance >= total_distance / 2.0,
+            | Milestone::FinalStretch => distance >= total_distance - units.from_distance(1.0),
+            | Milestone::Summit(i) => distance >= climbs[*i].end,
+         });
+         self.climb_start = None;
+      }
+      let last_distance = std::mem::replace(&mut self.last_distance, distance);
+      if last_distance <= 0.0
+      {  // First update (or joined part way through): only notify for milestones passed from here on
+         self.reached.extend(climbs.iter().enumerate().filter(|(_, c)| distance >= c.end).map(|(i, _)| Milestone::Summit(i)));
+         if distance >= total_distance / 2.0 { self.reached.push(Milestone::Halfway); }
+         if distance >= total_distance - units.from_distance(1.0) { self.reached.push(Milestone::FinalStretch); }
+         return;
+      }
+
+      if self.config.is_halfway && distance >= total_distance / 2.0 && !self.reached.contains(&Milestone::Halfway)
+      {
+         self.reached.push(Milestone::Halfway);
+         notify("Halfway", &format!("{} to go", units.format_distance(total_distance - distance, 1)));
+      }
+      let final_stretch = units.from_distance(1.0);
+      if self.config.is_final_stretch && distance >= total_distance - final_stretch && distance < total_distance
+         && !self.reached.contains(&Milestone::FinalStretch)
+      {
+         self.reached.push(Milestone::FinalStretch);
+         notify(if units == Units::Metric { "Final kilometre" } else { "Final mile" }, "Nearly there!");
+      }
+
+      if let Some(i) = climbs.iter().position(|c| last_distance < c.start && distance >= c.start)
+      {
+         self.climb_start = Some((i, Instant::now()));
+      }
+      let Some(i) = climbs.iter().position(|c| last_distance < c.end && distance >= c.end) else { return; };
+      if self.reached.contains(&Milestone::Summit(i))
+      {
+         return;
+      }
+      self.reached.push(Milestone::Summit(i));
+      let climb = climbs[i];
+      let time = match self.climb_start.take()
+      {
+         | Some((started, start_time)) if started == i && !is_simulating => Some(start_time.elapsed().as_secs_f64()),
+         | _ => None,
+      };
+      let description = format!("{} at {:.1}%, +{}", units.format_distance(climb.length(), 1), climb.average_gradient,
+                                units.format_length(climb.gain));
+      let personal_best = time.and_then(|time|
+      {
+         let key = self.course_key.get_or_insert_with(|| course_key(course));
+         record_climb_time(key, climb.start, time)
+      });
+      match (time, personal_best)
+      {
+         | (Some(time), Some(previous)) if self.config.is_personal_best =>
+         {
+            notify("New personal best", &format!("{} in {} (previous best {})", description, format_time(time), format_time(previous)));
+         }
+         | (time, _) if self.config.is_summit =>
+         {
+            let time = time.map_or(String::new(), |t| format!(" in {}", format_time(t)));
+            notify("Summit reached", &format!("{description}{time}"));
+         }
+         | _ => (),
+      }
+   }
+}
+
+/// Show a desktop notification without blocking the UI.
+fn notify(summary: &str, body: &str)
+//----------------------------------
+{
+   let (summary, body) = (summary.to_string(), body.to_string());
+   std::thread::spawn(move ||
+   {
+      if let Err(e) = notify_rust::Notification::new().appname("GPXAssist").summary(&summary).body(&body).show()
+      {
+         log_warn!("Could not show desktop notification: {}", e);
+      }
+   });
+}
+
+fn format_time(seconds: f64) -> String
+//------------------------------------
+{
+   let seconds = seconds.round() as u64;
+   if seconds >= 3600
+   {
+      format!("{}:{:02}:{:02}", seconds / 3600, (seconds % 3600) / 60, seconds % 60)
+   }
+   else
+   {
+      format!("{}:{:02}", seconds / 60, seconds % 60)
+   }
+}
+
+fn course_key(course: &Path) -> String
+//------------------------------------
+{
+   match fs::read(course)
+   {
+      | Ok(contents) => hex::encode(Sha256::digest(&contents)),
+      | Err(_) => course.display().to_string(),
+   }
+}
+
+fn records_path() -> Option<PathBuf>
+//----------------------------------
+{
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   let path = settings.lock().get_config_path().ok()?;
+   Some(path.join(RECORDS_FILE))
+}
+
+/// Record the time for the climb starting at `start` metres on the course, returning the previous best when it was
+/// beaten. The first time a climb is ridden is recorded but is not a personal best.
+fn record_climb_time(course_key: &str, start: f64, time: f64) -> Option<f64>
+//--------------------------------------------------------------------------
+{
+   let path = records_path()?;
+   // course hash -> climb start distance (whole metres) -> best time in seconds
+   let mut records: HashMap<String, HashMap<String, f64>> = fs::read_to_string(&path).ok()
+                                                                .and_then(|json| serde_json::from_str(&json).ok())
+                                                                .unwrap_or_default();
+   let best = records.entry(course_key.to_string()).or_default().entry(format!("{:.0}", start));
+   let previous = match best
+   {
+      | std::collections::hash_map::Entry::Occupied(mut entry) if time < *entry.get() => Some(entry.insert(time)),
+      | std::collections::hash_map::Entry::Occupied(_) => return None,
+      | std::collections::hash_map::Entry::Vacant(entry) =>
+      {
+         entry.insert(time);
+         None
+      }
+   };
+   let result = serde_json::to_string_pretty(&records).map_err(|e| e.to_string())
+                                                      .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+   if let Err(e) = result
+   {
+      log_warn!("Could not save climb records to {}: {}", path.display(), e);
+   }
+   previous
+}
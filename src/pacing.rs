@@ -0,0 +1,404 @@
+use std::{sync::Arc, time::Instant};
+
+use eframe::egui::{self, Color32};
+
+use crate::{SETTINGS, data::RiderData, gpx::Track, settings::{PacingSettings, Settings}, simulation::PhysicsModel, ui::Theme,
+            units::Units};
+
+/// Added to the rider's weight for the speeds the plan times are estimated from.
+const BIKE_MASS: f64 = 8.0; // kg
+/// The course is classified this far at a time ...
+const STEP: f64 = 100.0; // metres
+/// ... into sections no shorter than MIN_SECTION, except possibly the last.
+const MIN_SECTION: f64 = 500.0; // metres
+/// Steeper than this is a climb, and steeper downhill a descent (percent).
+const CLIMB_GRADIENT: f64 = 2.0;
+/// Power on a climb is raised by CLIMB_FACTOR per percent up to MAX_CLIMB_FACTOR times the base power, and lowered on
+/// a descent by DESCENT_FACTOR per percent down to MIN_DESCENT_FACTOR times, where speed gains little for the effort.
+const CLIMB_FACTOR: f64 = 0.025;
+const MAX_CLIMB_FACTOR: f64 = 1.2;
+const DESCENT_FACTOR: f64 = 0.08;
+const MIN_DESCENT_FACTOR: f64 = 0.3;
+/// Range of intensities (fraction of FTP) searched for a target time.
+const MIN_INTENSITY: f64 = 0.3;
+const MAX_INTENSITY: f64 = 1.1;
+const MIN_SPEED: f64 = 1.0; // m/s, so very steep climbs still take a finite time
+/// Actual power is smoothed over about this many seconds before comparing it with the target ...
+const POWER_SMOOTHING: f64 = 10.0;
+/// ... and is on target within this fraction of it.
+const ON_TARGET: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terrain
+{
+   Climb,
+   Flat,
+   Descent,
+}
+
+impl Terrain
+{
+   fn from_gradient(gradient: f64) -> Self
+   //-------------------------------------
+   {
+      if gradient >= CLIMB_GRADIENT { Terrain::Climb } else if gradient <= -CLIMB_GRADIENT { Terrain::Descent } else { Terrain::Flat }
+   }
+
+   pub fn label(self) -> &'static str
+   //--------------------------------
+   {
+      match self
+      {
+         | Terrain::Climb => "Climb",
+         | Terrain::Flat => "Flat",
+         | Terrain::Descent => "Descent",
+      }
+   }
+
+   /// Power relative to the base power of the plan on a `gradient` (percent) of this terrain.
+   fn power_factor(self, gradient: f64) -> f64
+   //-----------------------------------------
+   {
+      match self
+      {
+         | Terrain::Climb => (1.0 + CLIMB_FACTOR * gradient).min(MAX_CLIMB_FACTOR),
+         | Terrain::Flat => 1.0,
+         | Terrain::Descent => (1.0 + DESCENT_FACTOR * gradient).max(MIN_DESCENT_FACTOR),
+      }
+   }
+}
+
+/// A stretch of the course ridden at one power target.
+#[derive(Debug, Clone, Copy)]
+pub struct PacingSection
+{
+   pub start:    f64, // metres
+   pub end:      f64, // metres
+   pub terrain:  Terrain,
+   pub gradient: f64, // average, percent
+   pub power:    f64, // W
+   pub time:     f64, // estimated seconds
+}
+
+#[derive(Debug, Clone)]
+pub struct PacingPlan
+{
+   pub sections:      Vec<PacingSection>,
+   pub intensity:     f64, // fraction of FTP on the flat
+   pub time:          f64, // estimated seconds for the course
+   pub average_power: f64, // W
+}
+
+impl PacingPlan
+{
+   pub fn section_at(&self, distance: f64) -> Option<usize>
+   //-------------------------------------------------------
+   {
+      self.sections.iter().position(|s| distance < s.end).or_else(|| self.sections.len().checked_sub(1))
+   }
+}
+
+/// Split `track` into climbs, flats and descents, each (start, end, average gradient).
+fn classify(track: &Track) -> Vec<(f64, f64, Terrain, f64)>
+//---------------------------------------------------------
+{
+   let total = track.total_distance();
+   let mut sections: Vec<(f64, f64, Terrain, f64)> = Vec::new();
+   let mut start = 0.0;
+   while start < total
+   {
+      let end = (start + STEP).min(total);
+      let gradient = track.gradient_at((start + end) / 2.0, end - start);
+      let terrain = Terrain::from_gradient(gradient);
+      match sections.last_mut()
+      {
+         | Some(last) if last.2 == terrain => last.1 = end,
+         | _ => sections.push((start, end, terrain, 0.0)),
+      }
+      start = end;
+   }
+   // Fold short sections into the one before (or after, at the start) so targets don't change every few hundred metres
+   let mut merged: Vec<(f64, f64, Terrain, f64)> = Vec::new();
+   for section in sections
+   {
+      match merged.last_mut()
+      {
+         | Some(last) if section.1 - section.0 < MIN_SECTION || last.2 == section.2 => last.1 = section.1,
+         | Some(last) if last.1 - last.0 < MIN_SECTION => { last.1 = section.1; last.2 = section.2; }
+         | _ => merged.push(section),
+      }
+   }
+   for section in &mut merged
+   {
+      let (start, end) = (track.altitude_at(section.0).unwrap_or(0.0), track.altitude_at(section.1).unwrap_or(0.0));
+      section.3 = if section.1 > section.0 { (end - start) / (section.1 - section.0) * 100.0 } else { 0.0 };
+   }
+   merged
+}
+
+/// Power targets for `sections` of `track` at `intensity` and the estimated time riding them with `model`.
+fn plan_at(track: &Track, sections: &[(f64, f64, Terrain, f64)], ftp: f64, intensity: f64, model: &PhysicsModel) -> PacingPlan
+//--------------------------------------------------------------------------------------------------------------------------
+{
+   let base = ftp * intensity;
+   let mut plan = PacingPlan { sections: Vec::with_capacity(sections.len()), intensity, time: 0.0, average_power: 0.0 };
+   let mut work = 0.0;
+   for &(start, end, terrain, gradient) in sections
+   {
+      let power = base * terrain.power_factor(gradient);
+      let mut time = 0.0;
+      let mut distance = start;
+      while distance < end
+      {
+         let step = (end - distance).min(STEP);
+         let speed = model.speed_for_power(power, track.gradient_at(distance + step / 2.0, step)).max(MIN_SPEED);
+         time += step / speed;
+         distance += step;
+      }
+      plan.sections.push(PacingSection { start, end, terrain, gradient, power, time });
+      plan.time += time;
+      work += power * time;
+   }
+   plan.average_power = if plan.time > 0.0 { work / plan.time } else { 0.0 };
+   plan
+}
+
+/// Suggested power per section of `track` for `settings`, either at its intensity or at the intensity which rides
+/// the course in its target time, with speeds estimated by the CdA and rolling resistance of `model`.
+pub fn generate(track: &Track, settings: &PacingSettings, model: &PhysicsModel) -> Result<PacingPlan, String>
+//-----------------------------------------------------------------------------------------------------------
+{
+   if track.total_distance() <= 0.0
+   {
+      return Err("Open a course to plan its pacing.".to_string());
+   }
+   if settings.ftp <= 0.0 || settings.weight <= 0.0
+   {
+      return Err("Enter an FTP and weight.".to_string());
+   }
+   let model = PhysicsModel { mass: settings.weight + BIKE_MASS, ..*model };
+   let sections = classify(track);
+   if !settings.is_time_target
+   {
+      return Ok(plan_at(track, &sections, settings.ftp, settings.intensity / 100.0, &model));
+   }
+   let target = settings.target_minutes * 60.0;
+   let fastest = plan_at(track, &sections, settings.ftp, MAX_INTENSITY, &model);
+   if fastest.time > target
+   {
+      return Err(format!("{} at {:.0}% of FTP is the fastest expected, over the target time.", format_time(fastest.time),
+                         MAX_INTENSITY * 100.0));
+   }
+   // Time only decreases with intensity so bisect for it
+   let (mut low, mut high) = (MIN_INTENSITY, MAX_INTENSITY);
+   for _ in 0 .. 20
+   {
+      let middle = (low + high) / 2.0;
+      if plan_at(track, &sections, settings.ftp, middle, &model).time > target { low = middle; } else { high = middle; }
+   }
+   Ok(plan_at(track, &sections, settings.ftp, high, &model))
+}
+
+fn format_time(seconds: f64) -> String
+//------------------------------------
+{
+   let seconds = seconds.round() as u64;
+   format!("{}:{:02}:{:02}", seconds / 3600, (seconds % 3600) / 60, seconds % 60)
+}
+
+/// Window generating a pacing plan from the FTP, weight and a target intensity or time, and the current target
+/// against the actual power shown over the view while riding the plan.
+pub struct PacingPlanner
+//======================
+{
+   pub is_open: bool,
+   settings:    PacingSettings,
+   plan:        Option<(Arc<Track>, PacingPlan)>, // and the track it is for
+   error:       Option<String>,
+   power:       Option<(f64, Instant)>, // smoothed actual power and when it was last updated
+}
+
+impl PacingPlanner
+{
+   pub fn new(settings: PacingSettings) -> Self { Self { is_open: false, settings, plan: None, error: None, power: None } }
+
+   /// Forget the plan, e.g. when a new course is opened.
+   pub fn reset(&mut self)
+   //---------------------
+   {
+      self.plan = None;
+      self.error = None;
+      self.power = None;
+   }
+
+   fn plan_for(&self, track: &Arc<Track>) -> Option<&PacingPlan>
+   //------------------------------------------------------------
+   {
+      self.plan.as_ref().filter(|(t, _)| Arc::ptr_eq(t, track)).map(|(_, plan)| plan)
+   }
+
+   /// Show the window for `track` with the section at `distance` (the rider) highlighted.
+   pub fn show(&mut self, ctx: &egui::Context, track: &Arc<Track>, distance: f64, model: &PhysicsModel, units: Units)
+   //----------------------------------------------------------------------------------------------------------------
+   {
+      if !self.is_open
+      {
+         return;
+      }
+      let mut is_open = self.is_open;
+      egui::Window::new("Pacing Plan")
+         .open(&mut is_open)
+         .resizable(true)
+         .default_height(450.0)
+         .show(ctx, |ui|
+         {
+            egui::Grid::new("pacing_inputs").num_columns(2).spacing([12.0, 4.0]).show(ui, |ui|
+            {
+               ui.label("FTP:");
+               ui.add(egui::DragValue::new(&mut self.settings.ftp).range(50.0 ..= 600.0).speed(1.0).suffix(" W"));
+               ui.end_row();
+               ui.label("Weight:");
+               ui.add(egui::DragValue::new(&mut self.settings.weight).range(30.0 ..= 200.0).speed(0.5).suffix(" kg"))
+                 .on_hover_text("Rider only, the bike is taken as 8 kg");
+               ui.end_row();
+               ui.label("Target:");
+               ui.horizontal(|ui|
+               {
+                  ui.radio_value(&mut self.settings.is_time_target, false, "Intensity");
+                  ui.radio_value(&mut self.settings.is_time_target, true, "Time");
+                  if self.settings.is_time_target
+                  {
+                     let mut hours = (self.settings.target_minutes / 60.0).floor();
+                     let mut minutes = self.settings.target_minutes - hours * 60.0;
+                     let is_changed = ui.add(egui::DragValue::new(&mut hours).range(0.0 ..= 24.0).suffix(" h")).changed()
+                                      | ui.add(egui::DragValue::new(&mut minutes).range(0.0 ..= 59.0).suffix(" min")).changed();
+                     if is_changed
+                     {
+                        self.settings.target_minutes = (hours * 60.0 + minutes).max(1.0);
+                     }
+                  }
+                  else
+                  {
+                     ui.add(egui::DragValue::new(&mut self.settings.intensity).range(30.0 ..= 110.0).speed(0.5).suffix("% of FTP"));
+                  }
+               });
+               ui.end_row();
+            });
+            ui.horizontal(|ui|
+            {
+               if ui.button("Generate").on_hover_text("Plan power targets for the open course").clicked()
+               {
+                  match generate(track, &self.settings, model)
+                  {
+                     | Ok(plan) =>
+                     {
+                        log_info!("Pacing plan at {:.0}% of FTP, {} sections, {}", plan.intensity * 100.0, plan.sections.len(),
+                                  format_time(plan.time));
+                        self.plan = Some((track.clone(), plan));
+                        self.error = None;
+                     }
+                     | Err(e) => self.error = Some(e),
+                  }
+                  let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+                  settings.lock().set_pacing(self.settings);
+               }
+               if ui.checkbox(&mut self.settings.is_shown, "Show target over the view").changed()
+               {
+                  let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+                  settings.lock().set_pacing(self.settings);
+               }
+            });
+            if let Some(e) = &self.error
+            {
+               ui.label(egui::RichText::new(e).color(Color32::RED));
+            }
+            let Some(plan) = self.plan_for(track) else { return; };
+            ui.separator();
+            ui.label(format!("{:.0}% of FTP on the flat, {:.0} W average ({:.2} W/kg), about {}", plan.intensity * 100.0,
+                             plan.average_power, plan.average_power / self.settings.weight, format_time(plan.time)));
+            let current = plan.section_at(distance);
+            egui::ScrollArea::vertical().auto_shrink([true, false]).show(ui, |ui|
+            {
+               egui::Grid::new("pacing_plan_grid").num_columns(6).striped(true).spacing([16.0, 4.0]).show(ui, |ui|
+               {
+                  for title in ["From", "To", "Terrain", "Gradient", "Power", "Time"]
+                  {
+                     ui.label(egui::RichText::new(title).strong());
+                  }
+                  ui.end_row();
+                  for (i, section) in plan.sections.iter().enumerate()
+                  {
+                     let text = |text: String| if current == Some(i) { egui::RichText::new(text).strong().color(Color32::from_rgb(40, 190, 60)) }
+                                               else if section.end < distance { egui::RichText::new(text).weak() }
+                                               else { egui::RichText::new(text) };
+                     ui.label(text(units.format_distance(section.start, 2)));
+                     ui.label(text(units.format_distance(section.end, 2)));
+                     ui.label(text(section.terrain.label().to_string()));
+                     ui.label(text(format!("{:.1}%", section.gradient)));
+                     ui.label(text(format!("{:.0} W", section.power)));
+                     ui.label(text(format_time(section.time)));
+                     ui.end_row();
+                  }
+               });
+            });
+         });
+      self.is_open = is_open;
+   }
+
+   /// Show the target power at `distance` along `track` against the actual (smoothed) power of `rider` in the bottom
+   /// right corner of the view `rect`, with the next target.
+   pub fn show_target(&mut self, ctx: &egui::Context, rect: egui::Rect, distance: f64, track: &Arc<Track>, rider: &RiderData,
+                      theme: &Theme, units: Units)
+   //-----------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.settings.is_shown
+      {
+         return;
+      }
+      let Some(plan) = self.plan_for(track) else { return; };
+      let Some(index) = plan.section_at(distance) else { return; };
+      let section = plan.sections[index];
+      let next = plan.sections.get(index + 1).copied();
+      let now = Instant::now();
+      let power = match self.power
+      {
+         | Some((smoothed, time)) =>
+         {
+            let weight = (now.duration_since(time).as_secs_f64() / POWER_SMOOTHING).min(1.0);
+            smoothed + (rider.power as f64 - smoothed) * weight
+         }
+         | None => rider.power as f64,
+      };
+      self.power = Some((power, now));
+      let color = if (power - section.power).abs() <= section.power * ON_TARGET { Color32::from_rgb(40, 190, 60) }
+                  else if power < section.power { Color32::from_rgb(66, 133, 244) }
+                  else { Color32::from_rgb(234, 67, 53) };
+      egui::Area::new(egui::Id::new("pacing_target"))
+         .order(egui::Order::Foreground)
+         .pivot(egui::Align2::RIGHT_BOTTOM)
+         .fixed_pos(rect.right_bottom() + egui::vec2(-12.0, -12.0))
+         .interactable(false)
+         .show(ctx, |ui|
+         {
+            egui::Frame::new().fill(Color32::from_black_alpha(160)).corner_radius(8.0).inner_margin(8.0).show(ui, |ui|
+            {
+               egui::Grid::new("pacing_target_grid").num_columns(2).spacing([10.0, 2.0]).show(ui, |ui|
+               {
+                  ui.label(egui::RichText::new(format!("{} target", section.terrain.label())).color(theme.gradient_label).size(14.0));
+                  ui.label(egui::RichText::new(format!("{:.0} W", section.power)).color(Color32::WHITE).strong().size(16.0));
+                  ui.end_row();
+                  ui.label(egui::RichText::new("Actual").color(theme.gradient_label).size(14.0));
+                  ui.label(egui::RichText::new(format!("{power:.0} W")).color(color).strong().size(16.0));
+                  ui.end_row();
+                  if let Some(next) = next
+                  {
+                     ui.label(egui::RichText::new(format!("In {}", units.format_distance((next.start - distance).max(0.0), 1)))
+                                 .color(theme.gradient_label).size(14.0));
+                     ui.label(egui::RichText::new(format!("{:.0} W", next.power)).color(Color32::WHITE).size(14.0));
+                     ui.end_row();
+                  }
+               });
+            });
+         });
+   }
+}
@@ -0,0 +1,240 @@
+use std::{collections::BTreeSet, fs::{self, File}, io::BufReader, ops::RangeInclusive, path::{Path, PathBuf}, sync::Arc,
+          time::Duration};
+
+use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, HttpCacheOptions};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{SETTINGS, gpx::TrackPoint, http::{self, HttpError}, settings::{ProxySettings, STREETVIEW_CACHE, Settings}};
+
+const PRECACHE_DIRECTORY: &str = "precache";
+const INDEX_FILE: &str = "index.json";
+
+/// A Street View image downloaded ahead of a ride by the `precache streetview` command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrecachedImage
+{
+   pub distance: f64, // metres along the course
+   pub file:     String, // relative to the course precache directory
+}
+
+/// Street View images downloaded along a course at a fixed interval. Stored per course (keyed by a hash of the GPX
+/// file contents) so the images are found however the file is opened, and used by the Street View in place of a
+/// request when the rider is within half an interval of one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreetViewPrecache
+{
+   pub interval: f64, // metres
+   pub images:   Vec<PrecachedImage>, // in distance order
+   #[serde(skip)]
+   directory:    PathBuf,
+}
+
+impl StreetViewPrecache
+{
+   /// An empty precache for the GPX file at `gpx_path`, removing any existing one.
+   pub fn new(gpx_path: &Path, interval: f64, settings: &Settings) -> Result<Self, String>
+   //-------------------------------------------------------------------------------------
+   {
+      let directory = Self::directory(gpx_path, settings)?;
+      if directory.exists()
+      {
+         fs::remove_dir_all(&directory).map_err(|e| format!("Error removing {}: {}", directory.display(), e))?;
+      }
+      Ok(Self { interval, images: Vec::new(), directory })
+   }
+
+   /// The precache for the GPX file at `gpx_path`, if the course has been precached.
+   pub fn load(gpx_path: &Path) -> Option<Self>
+   //-------------------------------------------
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let directory = Self::directory(gpx_path, &settings.lock()).ok()?;
+      let file = File::open(directory.join(INDEX_FILE)).ok()?;
+      match serde_json::from_reader::<_, Self>(BufReader::new(file))
+      {
+         | Ok(mut precache) =>
+         {
+            log_info!("Using {} precached Street View images for {}", precache.images.len(), gpx_path.display());
+            precache.directory = directory;
+            Some(precache)
+         }
+         | Err(e) =>
+         {
+            log_warn!("Ignoring unreadable Street View precache index in {}: {}", directory.display(), e);
+            None
+         }
+      }
+   }
+
+   fn directory(gpx_path: &Path, settings: &Settings) -> Result<PathBuf, String>
+   //---------------------------------------------------------------------------
+   {
+      let contents = fs::read(gpx_path).map_err(|e| format!("Error reading {}: {}", gpx_path.display(), e))?;
+      let key = hex::encode(Sha256::digest(&contents));
+      Ok(settings.cache_path(STREETVIEW_CACHE).join(PRECACHE_DIRECTORY).join(key))
+   }
+
+   /// Full path of a file in the course precache directory.
+   fn path(&self, name: &str) -> PathBuf { self.directory.join(name) }
+
+   /// Save the image bytes for `distance` and add it to the index.
+   pub fn add(&mut self, distance: f64, bytes: &[u8]) -> Result<(), String>
+   //-----------------------------------------------------------------------
+   {
+      let name = format!("{:05}.jpg", self.images.len());
+      let path = self.path(&name);
+      fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, bytes))
+         .map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+      self.images.push(PrecachedImage { distance, file: name });
+      Ok(())
+   }
+
+   pub fn save(&self) -> Result<(), String>
+   //--------------------------------------
+   {
+      let path = self.path(INDEX_FILE);
+      let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+      fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, json))
+         .map_err(|e| format!("Error writing {}: {}", path.display(), e))
+   }
+
+   /// The precached image nearest `distance`, if it is within half an interval.
+   pub fn image_near(&self, distance: f64) -> Option<PathBuf>
+   //--------------------------------------------------------
+   {
+      let i = self.images.partition_point(|image| image.distance < distance);
+      [i.checked_sub(1), Some(i)].into_iter()
+                                 .flatten()
+                                 .filter_map(|i| self.images.get(i))
+                                 .min_by(|a, b| (a.distance - distance).abs().total_cmp(&(b.distance - distance).abs()))
+                                 .filter(|image| (image.distance - distance).abs() <= self.interval / 2.0)
+                                 .map(|image| self.path(&image.file))
+   }
+}
+
+/// The OpenStreetMap tile server used by the Map view.
+const OSM_TILE_URL: &str = "https://tile.openstreetmap.org";
+/// The OpenStreetMap tile usage policy forbids downloading more than this many tiles at zoom 13 or above for later use.
+pub const OSM_OFFLINE_TILE_LIMIT: usize = 250;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tile
+{
+   pub zoom: u8,
+   pub x:    u32,
+   pub y:    u32,
+}
+
+impl Tile
+{
+   /// The Web Mercator tile containing a position.
+   fn at(lat: f64, lon: f64, zoom: u8) -> Self
+   //-----------------------------------------
+   {
+      let n = 2f64.powi(zoom as i32);
+      let lat = lat.clamp(-85.0511, 85.0511).to_radians();
+      let x = ((lon + 180.0) / 360.0 * n).floor().clamp(0.0, n - 1.0) as u32;
+      let y = ((1.0 - lat.tan().asinh() / std::f64::consts::PI) / 2.0 * n).floor().clamp(0.0, n - 1.0) as u32;
+      Self { zoom, x, y }
+   }
+
+   fn url(&self) -> String { format!("{}/{}/{}/{}.png", OSM_TILE_URL, self.zoom, self.x, self.y) }
+}
+
+/// The tiles within `corridor` metres of the track for each zoom level in `zooms`, in zoom order.
+pub fn corridor_tiles(track: &[TrackPoint], zooms: RangeInclusive<u8>, corridor: f64) -> Vec<Tile>
+//-------------------------------------------------------------------------------------------------
+{
+   // Positions along the track no further apart than the corridor, so no tile is skipped between distant points
+   let step = corridor.max(10.0);
+   let mut positions = Vec::new();
+   for pair in track.windows(2)
+   {
+      let steps = ((pair[1].distance - pair[0].distance) / step).ceil().max(1.0) as usize;
+      for i in 0 .. steps
+      {
+         let t = i as f64 / steps as f64;
+         positions.push((pair[0].point.lat + (pair[1].point.lat - pair[0].point.lat) * t,
+                         pair[0].point.lon + (pair[1].point.lon - pair[0].point.lon) * t));
+      }
+   }
+   positions.extend(track.last().map(|p| (p.point.lat, p.point.lon)));
+
+   let mut tiles = BTreeSet::new();
+   for zoom in zooms
+   {
+      for (lat, lon) in &positions
+      {
+         let dlat = corridor / 111_320.0;
+         let dlon = corridor / (111_320.0 * lat.to_radians().cos().max(0.01));
+         let top_left = Tile::at(lat + dlat, lon - dlon, zoom);
+         let bottom_right = Tile::at(lat - dlat, lon + dlon, zoom);
+         for x in top_left.x ..= bottom_right.x
+         {
+            for y in top_left.y ..= bottom_right.y
+            {
+               tiles.insert(Tile { zoom, x, y });
+            }
+         }
+      }
+   }
+   tiles.into_iter().collect()
+}
+
+/// Download tiles one at a time into the Map view tile cache (`cache`), through the same HTTP cache the map uses so
+/// the tiles are found when riding without a connection. `progress` is called with the number of tiles done; returns
+/// the tiles that could not be downloaded with the reason.
+pub fn download_tiles(tiles: &[Tile], cache: PathBuf, proxy: &ProxySettings, mut progress: impl FnMut(usize))
+   -> Result<Vec<(Tile, String)>, String>
+//-----------------------------------------------------------------------------------------------------------------
+{
+   let client = http::builder(proxy)?.timeout(Duration::from_secs(20))
+                                     .build()
+                                     .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+   let client = reqwest_middleware::ClientBuilder::new(client)
+      .with(Cache(HttpCache
+      {
+         mode:    CacheMode::Default,
+         manager: CACacheManager::new(cache, true),
+         options: HttpCacheOptions::default(),
+      }))
+      .build();
+   let mut failed = Vec::new();
+   let client = &client;
+   http::block_on(async
+   {
+      for (i, tile) in tiles.iter().enumerate()
+      {
+         let url = tile.url();
+         let url = url.as_str();
+         let result = http::with_retry("Tile download", move || async move
+         {
+            let response = client.get(url).send().await.map_err(|e| match e
+            {
+               | reqwest_middleware::Error::Reqwest(e) => HttpError::from_request(&e, "Download failed"),
+               | e => HttpError::Transient(format!("Download failed: {e}")),
+            })?;
+            let status = response.status();
+            if !status.is_success()
+            {
+               return Err(HttpError::from_status(status, &response.text().await.unwrap_or_default(), "Download failed"));
+            }
+            response.bytes().await.map(|_| ()).map_err(|e| HttpError::from_request(&e, "Download failed"))
+         }).await;
+         match result
+         {
+            | Ok(()) => (),
+            | Err(HttpError::Quota(e)) =>
+            {  // The tile server is refusing requests so the remaining tiles would fail too
+               failed.extend(tiles[i ..].iter().map(|tile| (*tile, e.clone())));
+               progress(tiles.len());
+               break;
+            }
+            | Err(e) => failed.push((*tile, e.to_string())),
+         }
+         progress(i + 1);
+      }
+   });
+   Ok(failed)
+}
@@ -0,0 +1,191 @@
+use std::{fmt::Write as _,
+          fs::{self, File},
+          io::{BufWriter, Write},
+          path::{Path, PathBuf},
+          time::{Duration, Instant}};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::{data::RiderDataJSON,
+            gpx::{TrackPoint, find_closest_point, process_gpx},
+            settings::Settings,
+            ui::frame::{get_broadcast_file, read_rider_data}};
+
+/// How often the GPX file is rewritten while recording, as it can only be written complete.
+const GPX_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Recording file formats written by the headless logger.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat
+{
+   /// CSV log, replayable by the simulator
+   Csv,
+   /// GPX track with timestamps (needs a course file for positions)
+   Gpx,
+}
+
+struct RecordSample
+{
+   time:     DateTime<Utc>,
+   elapsed:  f64, // seconds
+   rider:    RiderDataJSON,
+   position: Option<TrackPoint>,
+}
+
+/// Writes one ride from the broadcast data to CSV and/or GPX files named after the start time.
+struct RideRecorder
+{
+   started:   Instant,
+   csv:       Option<BufWriter<File>>,
+   gpx_path:  Option<PathBuf>,
+   gpx_saved: Instant,
+   samples:   Vec<RecordSample>,
+}
+
+impl RideRecorder
+{
+   fn new(directory: &Path, formats: &[RecordFormat], has_course: bool) -> Result<Self, String>
+   //-----------------------------------------------------------------------------------------
+   {
+      let name = format!("ride-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+      let csv = if formats.contains(&RecordFormat::Csv)
+      {
+         let path = directory.join(format!("{name}.csv"));
+         let mut writer = BufWriter::new(File::create(&path).map_err(|e| format!("Error creating {}: {}", path.display(), e))?);
+         writeln!(writer, "time,distance,speed,power,heartrate,cadence,latitude,longitude,altitude")
+            .map_err(|e| format!("Error writing {}: {}", path.display(), e))?;
+         log_info!("Recording to {}", path.display());
+         Some(writer)
+      }
+      else
+      {
+         None
+      };
+      let gpx_path = (formats.contains(&RecordFormat::Gpx) && has_course).then(|| directory.join(format!("{name}.gpx")));
+      if let Some(path) = &gpx_path
+      {
+         log_info!("Recording to {}", path.display());
+      }
+      Ok(Self { started: Instant::now(), csv, gpx_path, gpx_saved: Instant::now(), samples: Vec::new() })
+   }
+
+   fn record(&mut self, rider: RiderDataJSON, position: Option<TrackPoint>) -> Result<(), String>
+   //---------------------------------------------------------------------------------------------
+   {
+      let sample = RecordSample { time: Utc::now(), elapsed: self.started.elapsed().as_secs_f64(), rider, position };
+      if let Some(csv) = &mut self.csv
+      {
+         let (latitude, longitude, altitude) = sample.position.map_or((String::new(), String::new(), String::new()),
+            |p| (format!("{:.7}", p.point.lat), format!("{:.7}", p.point.lon), format!("{:.1}", p.altitude)));
+         writeln!(csv, "{:.1},{},{:.2},{},{},{},{},{},{}", sample.elapsed, sample.rider.distance, sample.rider.speed_kmh(),
+                  sample.rider.power, sample.rider.heartrate, sample.rider.cadence, latitude, longitude, altitude)
+            .and_then(|_| csv.flush())
+            .map_err(|e| format!("Error writing CSV recording: {}", e))?;
+      }
+      if self.gpx_path.is_some()
+      {
+         self.samples.push(sample);
+         if self.gpx_saved.elapsed() >= GPX_SAVE_INTERVAL
+         {
+            self.save_gpx()?;
+         }
+      }
+      Ok(())
+   }
+
+   /// Rewrite the GPX file with all the samples so far, using Garmin track point extensions for power, heart rate
+   /// and cadence.
+   fn save_gpx(&mut self) -> Result<(), String>
+   //------------------------------------------
+   {
+      let Some(path) = &self.gpx_path else { return Ok(()); };
+      self.gpx_saved = Instant::now();
+      let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"GPXAssist\" xmlns=\"http://www.topografix.com/GPX/1/1\" \
+         xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">\n<trk>\n<name>GPXAssist ride</name>\n<trkseg>\n");
+      for sample in &self.samples
+      {
+         let Some(position) = sample.position else { continue; };
+         let _ = write!(xml, "<trkpt lat=\"{:.7}\" lon=\"{:.7}\"><ele>{:.1}</ele><time>{}</time>\
+                              <extensions><power>{}</power><gpxtpx:TrackPointExtension><gpxtpx:hr>{}</gpxtpx:hr>\
+                              <gpxtpx:cad>{}</gpxtpx:cad></gpxtpx:TrackPointExtension></extensions></trkpt>\n",
+                        position.point.lat, position.point.lon, position.altitude,
+                        sample.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                        sample.rider.power.max(0), sample.rider.heartrate.max(0), sample.rider.cadence.max(0));
+      }
+      xml.push_str("</trkseg>\n</trk>\n</gpx>\n");
+      let temp = path.with_extension("gpx.tmp");
+      fs::write(&temp, xml).and_then(|_| fs::rename(&temp, path)).map_err(|e| format!("Error writing {}: {}", path.display(), e))
+   }
+
+   fn finish(mut self) -> Result<(), String>
+   //---------------------------------------
+   {
+      if let Some(csv) = &mut self.csv
+      {
+         csv.flush().map_err(|e| format!("Error writing CSV recording: {}", e))?;
+      }
+      self.save_gpx()?;
+      log_info!("Recorded {:.0} minutes", self.started.elapsed().as_secs_f64() / 60.0);
+      Ok(())
+   }
+}
+
+/// Headless logger: read the broadcast file and record each ride to `directory` without starting the user
+/// interface. Positions and altitudes come from `course` when given. A ride ends when the broadcast data stops
+/// changing for `idle` or the distance goes backwards (a new ride); runs until killed.
+pub fn log_broadcast(directory: &Path, formats: &[RecordFormat], course: Option<&Path>, idle: Duration, settings: &Settings)
+   -> Result<(), String>
+//-------------------------------------------------------------------------------------------------------------------
+{
+   fs::create_dir_all(directory).map_err(|e| format!("Error creating {}: {}", directory.display(), e))?;
+   let track = match course
+   {
+      | Some(path) => process_gpx(&path.to_string_lossy()).map_err(|e| format!("Error reading {}: {}", path.display(), e))?,
+      | None => Vec::new(),
+   };
+   if track.is_empty() && formats.contains(&RecordFormat::Gpx)
+   {
+      log_warn!("GPX recordings need a course file for positions; only other formats will be written.");
+   }
+   let broadcast_file = get_broadcast_file().ok_or("No broadcast directory is set")?;
+   log_info!("Waiting for broadcast data in {}", broadcast_file.display());
+
+   let polling = settings.broadcast_polling;
+   let mut recorder: Option<RideRecorder> = None;
+   let mut last: Option<(i32, i32)> = None; // (time, distance) of the last recorded data
+   let mut last_change = Instant::now();
+   loop
+   {
+      if let Some(rider) = read_rider_data(3, polling.retry())
+         && last.is_none_or(|(time, _)| time != rider.time)
+      {
+         if last.is_some_and(|(_, distance)| rider.distance < distance)
+            && let Some(finished) = recorder.take()
+         {  // Distance went backwards so a new ride has started
+            finished.finish()?;
+         }
+         last = Some((rider.time, rider.distance));
+         last_change = Instant::now();
+         if rider.distance > 0
+         {
+            if recorder.is_none()
+            {
+               recorder = Some(RideRecorder::new(directory, formats, !track.is_empty())?);
+            }
+            let position = find_closest_point(&track, rider.distance as f64).0;
+            if let Some(recorder) = &mut recorder
+            {
+               recorder.record(rider, position)?;
+            }
+         }
+      }
+      if last_change.elapsed() >= idle
+         && let Some(finished) = recorder.take()
+      {
+         log_info!("No broadcast data for {} seconds, ending the recording", idle.as_secs());
+         finished.finish()?;
+      }
+      std::thread::sleep(polling.interval());
+   }
+}
@@ -0,0 +1,425 @@
+use std::{io::{BufRead, BufReader, Read, Write},
+          net::{IpAddr, Shutdown, TcpListener, TcpStream, UdpSocket},
+          path::Path,
+          sync::{Arc, mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError, sync_channel}},
+          time::Duration};
+
+use base64::Engine;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{data::RiderData, gpx::{Track, course_name}, source::CancelToken, units::Units};
+
+/// Overlay page for OBS browser sources, served at `/overlay`.
+const OVERLAY_HTML: &str = include_str!("../assets/overlay.html");
+/// Mobile second display page, served at `/companion`.
+const COMPANION_HTML: &str = include_str!("../assets/companion.html");
+/// Spacing of the elevation profile served at `/api/profile`.
+const PROFILE_SPACING: f64 = 25.0; // metres
+
+/// The course loaded in GPXAssist.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CourseState
+{
+   pub name:     String,
+   pub file:     String, // file name only, so the local directory layout isn't served
+   pub distance: f64, // metres
+   pub ascent:   f64, // metres
+   pub points:   usize,
+   pub climbs:   Vec<ClimbState>,
+   #[serde(skip)]
+   pub profile:  Vec<[f64; 2]>, // [distance, altitude] in metres, served separately as it is large
+}
+
+/// A climb on the course.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct ClimbState
+{
+   pub start:            f64, // metres
+   pub end:              f64, // metres
+   pub gain:             f64, // metres
+   pub average_gradient: f64, // percent
+}
+
+/// Rider position on the course.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct PositionState
+{
+   pub distance:  f64, // metres along the course
+   pub remaining: f64, // metres to the end of the course
+   pub latitude:  f64,
+   pub longitude: f64,
+   pub altitude:  f64, // metres
+   pub heading:   f64, // degrees
+}
+
+/// Telemetry from the broadcast file or simulator.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct TelemetryState
+{
+   pub speed:      f64, // km/h
+   pub power:      i32, // watts
+   pub heartrate:  i32,
+   pub cadence:    i32,
+   pub wind_speed: f64, // km/h
+   pub wind_angle: i32, // degrees
+}
+
+impl From<&RiderData> for TelemetryState
+{
+   fn from(rider: &RiderData) -> Self
+   {
+      Self
+      {
+         speed: rider.speed as f64 * 0.0036,
+         power: rider.power,
+         heartrate: rider.heartrate,
+         cadence: rider.cadence,
+         wind_speed: rider.wind_speed as f64 * 0.0036,
+         wind_angle: rider.wind_angle,
+      }
+   }
+}
+
+/// Everything served by the live state server.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct LiveState
+{
+   pub course:    Option<CourseState>,
+   pub position:  Option<PositionState>,
+   pub gradient:  f64, // percent over 100m
+   pub telemetry: TelemetryState,
+   pub units:     Units, // display units selected in GPXAssist
+}
+
+impl CourseState
+{
+   pub fn new(path: &Path, track: &Track) -> Self
+   //---------------------------------------------
+   {
+      let name = std::fs::File::open(path).ok()
+                                          .and_then(|file| gpx::read(BufReader::new(file)).ok())
+                                          .and_then(|gpx| course_name(&gpx))
+                                          .unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default());
+      Self
+      {
+         name,
+         file: path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default(),
+         distance: track.total_distance(),
+         ascent: track.elevation_gain_loss(2.0).0,
+         points: track.len(),
+         climbs: track.climbs(20.0, 2.0).into_iter()
+                                              .map(|c| ClimbState { start: c.start, end: c.end, gain: c.gain, average_gradient: c.average_gradient })
+                                              .collect(),
+         profile: Self::profile(track),
+      }
+   }
+
+   /// The track thinned to a point every PROFILE_SPACING metres, always keeping the last point.
+   fn profile(track: &Track) -> Vec<[f64; 2]>
+   //-----------------------------------------
+   {
+      let mut profile: Vec<[f64; 2]> = Vec::new();
+      for (i, p) in track.iter().enumerate()
+      {
+         if i == track.len() - 1 || profile.last().is_none_or(|last| p.distance - last[0] >= PROFILE_SPACING)
+         {
+            profile.push([p.distance, p.altitude]);
+         }
+      }
+      profile
+   }
+}
+
+/// GUID appended to the client key in the WebSocket handshake (RFC 6455).
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Messages queued for a WebSocket client before it is considered too slow and dropped.
+const WEBSOCKET_QUEUE: usize = 64;
+
+type Subscribers = Arc<parking_lot::Mutex<Vec<SyncSender<Arc<str>>>>>;
+
+/// Local HTTP server publishing the live state as JSON for OBS scripts, home automation and dashboards. Only listens
+/// on the loopback interface unless `is_lan`, when phones and tablets on the local network can open the `/companion`
+/// page as a second display. Endpoints: `/api/state` (everything), `/api/course`, `/api/position`, `/api/gradient`
+/// and `/api/telemetry`, the course elevation profile at `/api/profile`, plus a WebSocket at `/ws` which sends the
+/// full state on connecting and on every distance update. `/overlay` serves an HTML overlay (gradient strip, next
+/// climb and wind) built on the WebSocket for use as an OBS browser source.
+pub struct LiveServer
+{
+   pub port:    u16,
+   pub is_lan:  bool,
+   state:       Arc<parking_lot::Mutex<LiveState>>,
+   subscribers: Subscribers,
+   cancel:      CancelToken,
+}
+
+impl LiveServer
+{
+   pub fn start(port: u16, is_lan: bool) -> Result<Self, String>
+   //-----------------------------------------------------------
+   {
+      let address = if is_lan { "0.0.0.0" } else { "127.0.0.1" };
+      // A server being restarted with new settings may not have released the port yet
+      let mut attempts = 0;
+      let listener = loop
+      {
+         match TcpListener::bind((address, port))
+         {
+            | Ok(listener) => break listener,
+            | Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && attempts < 10 =>
+            {
+               attempts += 1;
+               std::thread::sleep(Duration::from_millis(50));
+            }
+            | Err(e) => return Err(format!("Could not start the live state server on port {port}: {e}")),
+         }
+      };
+      listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+      let state = Arc::new(parking_lot::Mutex::new(LiveState::default()));
+      let subscribers: Subscribers = Arc::default();
+      let cancel = CancelToken::default();
+      let (thread_state, thread_subscribers, thread_cancel) = (state.clone(), subscribers.clone(), cancel.clone());
+      std::thread::spawn(move ||
+      {
+         while !thread_cancel.is_cancelled()
+         {
+            match listener.accept()
+            {
+               | Ok((stream, _)) =>
+               {
+                  let (state, subscribers, cancel) = (thread_state.clone(), thread_subscribers.clone(), thread_cancel.clone());
+                  std::thread::spawn(move || if let Err(e) = handle_request(stream, &state, &subscribers, &cancel)
+                  {
+                     log_warn!("Live state server request failed: {e}");
+                  });
+               }
+               | Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => { thread_cancel.sleep(Duration::from_millis(50)); }
+               | Err(e) => log_warn!("Live state server: {e}"),
+            }
+         }
+      });
+      log_info!("Live state server listening on http://127.0.0.1:{port}/api/state");
+      if is_lan
+      {
+         match lan_address()
+         {
+            | Some(ip) => log_info!("Companion display available on the local network at http://{ip}:{port}/companion"),
+            | None => log_warn!("Could not find the local network address for the companion display"),
+         }
+      }
+      Ok(Self { port, is_lan, state, subscribers, cancel })
+   }
+
+   pub fn set_course(&self, course: Option<CourseState>) { self.state.lock().course = course; }
+
+   /// Publish the rider position and telemetry, pushing the state to WebSocket clients when the distance changed.
+   pub fn update(&self, position: Option<PositionState>, gradient: f64, telemetry: TelemetryState, units: Units)
+   //------------------------------------------------------------------------------------------------------------
+   {
+      let message =
+      {
+         let mut state = self.state.lock();
+         let is_moved = state.position.map(|p| p.distance) != position.map(|p| p.distance);
+         state.position = position;
+         state.gradient = gradient;
+         state.telemetry = telemetry;
+         state.units = units;
+         if !is_moved || self.subscribers.lock().is_empty()
+         {
+            return;
+         }
+         match serde_json::to_string(&*state)
+         {
+            | Ok(json) => Arc::<str>::from(json),
+            | Err(_) => return,
+         }
+      };
+      // Clients which have disconnected or stopped reading are dropped
+      self.subscribers.lock().retain(|subscriber| !matches!(subscriber.try_send(message.clone()),
+                                                            Err(TrySendError::Disconnected(_) | TrySendError::Full(_))));
+   }
+}
+
+/// The address of this computer on the local network, found from the interface that would route to the internet (no
+/// packets are sent).
+pub fn lan_address() -> Option<IpAddr>
+//------------------------------------
+{
+   let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+   socket.connect(("8.8.8.8", 80)).ok()?;
+   socket.local_addr().ok().map(|address| address.ip()).filter(|ip| !ip.is_loopback() && !ip.is_unspecified())
+}
+
+impl Drop for LiveServer
+{
+   fn drop(&mut self) { self.cancel.cancel(); }
+}
+
+fn handle_request(mut stream: TcpStream, state: &parking_lot::Mutex<LiveState>, subscribers: &Subscribers,
+                  cancel: &CancelToken) -> Result<(), String>
+//------------------------------------------------------------------------------------------------------------
+{
+   stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+   stream.set_read_timeout(Some(Duration::from_secs(5))).map_err(|e| e.to_string())?;
+   let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+   let mut request_line = String::new();
+   reader.read_line(&mut request_line).map_err(|e| e.to_string())?;
+   let (mut websocket_key, mut origin, mut host) = (None, None, None);
+   let mut line = String::new();
+   while reader.read_line(&mut line).map_err(|e| e.to_string())? > 2
+   {
+      if let Some((name, value)) = line.split_once(':')
+      {
+         let (name, value) = (name.trim(), Some(value.trim().to_string()));
+         if name.eq_ignore_ascii_case("sec-websocket-key") { websocket_key = value; }
+         else if name.eq_ignore_ascii_case("origin") { origin = value; }
+         else if name.eq_ignore_ascii_case("host") { host = value; }
+      }
+      line.clear();
+   }
+
+   let mut parts = request_line.split_whitespace();
+   let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+   let path = path.split('?').next().unwrap_or_default().trim_end_matches('/');
+   if path == "/ws"
+      && method == "GET"
+      && let Some(key) = websocket_key
+   {
+      // Browsers send the page's origin, so a WebSocket opened by any other web site is refused. Other clients (such
+      // as OBS scripts) don't send one.
+      if let Some(origin) = &origin
+         && !is_same_origin(origin, host.as_deref())
+      {
+         log_warn!("Refused a live server WebSocket from {origin}");
+         return write!(stream, "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").map_err(|e| e.to_string());
+      }
+      let (sender, receiver) = sync_channel(WEBSOCKET_QUEUE);
+      let json = serde_json::to_string(&*state.lock()).map_err(|e| e.to_string())?;
+      subscribers.lock().push(sender);
+      return serve_websocket(stream, reader, &key, &json, receiver, cancel);
+   }
+   let page = match path
+   {
+      | "/overlay" => Some(OVERLAY_HTML),
+      | "/companion" => Some(COMPANION_HTML),
+      | _ => None,
+   };
+   if let Some(html) = page
+      && method == "GET"
+   {
+      return write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+                             Cache-Control: no-store\r\nConnection: close\r\n\r\n{html}", html.len())
+         .map_err(|e| e.to_string());
+   }
+   let body = if method != "GET"
+   {
+      Err((405, "Method Not Allowed"))
+   }
+   else
+   {
+      let state = state.lock().clone();
+      match path
+      {
+         | "/api/state" => serde_json::to_string(&state).map_err(|_| (500, "Internal Server Error")),
+         | "/api/course" => serde_json::to_string(&state.course).map_err(|_| (500, "Internal Server Error")),
+         | "/api/position" => serde_json::to_string(&state.position).map_err(|_| (500, "Internal Server Error")),
+         | "/api/gradient" => Ok(serde_json::json!({ "gradient": state.gradient }).to_string()),
+         | "/api/telemetry" => serde_json::to_string(&state.telemetry).map_err(|_| (500, "Internal Server Error")),
+         | "/api/profile" => serde_json::to_string(&state.course.map(|c| c.profile).unwrap_or_default())
+                                .map_err(|_| (500, "Internal Server Error")),
+         | "" | "/api" => Ok(serde_json::json!({ "endpoints": ["/api/state", "/api/course", "/api/position", "/api/gradient",
+                                                             "/api/telemetry", "/api/profile", "/ws", "/overlay",
+                                                             "/companion"] }).to_string()),
+         | _ => Err((404, "Not Found")),
+      }
+   };
+   let (status, body) = match body
+   {
+      | Ok(json) => ("200 OK".to_string(), json),
+      | Err((code, reason)) => (format!("{code} {reason}"), serde_json::json!({ "error": reason }).to_string()),
+   };
+   // No Access-Control-Allow-Origin, so other web sites open in the rider's browser can't read the position
+   write!(stream, "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                   Cache-Control: no-store\r\nConnection: close\r\n\r\n{body}", body.len())
+      .map_err(|e| e.to_string())
+}
+
+/// Whether a request's `origin` (e.g. http://192.168.1.10:8686) is the server's own `host` (from the Host header).
+fn is_same_origin(origin: &str, host: Option<&str>) -> bool
+//----------------------------------------------------------
+{
+   let origin_host = origin.split_once("://").map_or(origin, |(_, rest)| rest).trim_end_matches('/');
+   host.is_some_and(|host| origin_host.eq_ignore_ascii_case(host))
+}
+
+/// Complete the WebSocket handshake, send `initial` and then every message from `receiver` as a text frame until the
+/// client closes the connection or the server stops. Client frames are read only to notice a close.
+fn serve_websocket(mut stream: TcpStream, mut reader: BufReader<TcpStream>, key: &str, initial: &str, receiver: Receiver<Arc<str>>,
+                   cancel: &CancelToken) -> Result<(), String>
+//---------------------------------------------------------------------------------------------------------------------------
+{
+   let accept = base64::engine::general_purpose::STANDARD.encode(Sha1::digest(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+   write!(stream, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                   Sec-WebSocket-Accept: {accept}\r\n\r\n").map_err(|e| e.to_string())?;
+   stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+   let closer = stream.try_clone().map_err(|e| e.to_string())?;
+   std::thread::spawn(move ||
+   {
+      let mut header = [0u8; 2];
+      while reader.read_exact(&mut header).is_ok()
+      {
+         let opcode = header[0] & 0x0F;
+         let length = match header[1] & 0x7F
+         {
+            | 126 => { let mut b = [0u8; 2]; if reader.read_exact(&mut b).is_err() { break; } u16::from_be_bytes(b) as u64 }
+            | 127 => { let mut b = [0u8; 8]; if reader.read_exact(&mut b).is_err() { break; } u64::from_be_bytes(b) }
+            | n => n as u64,
+         };
+         let masked = if header[1] & 0x80 != 0 { 4 } else { 0 };
+         if opcode == 0x8 || std::io::copy(&mut (&mut reader).take(length + masked), &mut std::io::sink()).is_err()
+         {
+            break;
+         }
+      }
+      let _ = closer.shutdown(Shutdown::Both);
+   });
+
+   send_text_frame(&mut stream, initial)?;
+   while !cancel.is_cancelled()
+   {
+      match receiver.recv_timeout(Duration::from_millis(500))
+      {
+         | Ok(message) => if send_text_frame(&mut stream, &message).is_err() { break; },
+         | Err(RecvTimeoutError::Timeout) => (),
+         | Err(RecvTimeoutError::Disconnected) => break,
+      }
+   }
+   let _ = stream.write_all(&[0x88, 0]); // close
+   let _ = stream.shutdown(Shutdown::Both);
+   Ok(())
+}
+
+/// Write an unmasked (server to client) WebSocket text frame.
+fn send_text_frame(stream: &mut TcpStream, text: &str) -> Result<(), String>
+//---------------------------------------------------------------------------
+{
+   let payload = text.as_bytes();
+   let mut frame = Vec::with_capacity(payload.len() + 10);
+   frame.push(0x81);
+   match payload.len()
+   {
+      | n if n < 126 => frame.push(n as u8),
+      | n if n <= u16::MAX as usize =>
+      {
+         frame.push(126);
+         frame.extend_from_slice(&(n as u16).to_be_bytes());
+      }
+      | n =>
+      {
+         frame.push(127);
+         frame.extend_from_slice(&(n as u64).to_be_bytes());
+      }
+   }
+   frame.extend_from_slice(payload);
+   stream.write_all(&frame).map_err(|e| e.to_string())
+}
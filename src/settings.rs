@@ -2,14 +2,125 @@
 use std::{fs::File};
 use std::io::Write;
 use std::env;
-use std::path::PathBuf;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
 use eframe::egui::{self, Color32, Context, Vec2};
 
-use crate::ui::get_broadcast_directory_or_default;
-use crate::{ ui::{self, GPXAssistUI}, ut };
+use crate::ui::{Theme, ThemeKind, ToolbarItem, frame::{length_drag_value, test_streetview_api_key}, get_broadcast_directory_or_default};
+use crate::{ components::Labelled, simulation::{PhysicsModel, SpeedVariation}, units::Units, ui::{self, GPXAssistUI}, ut, http };
 
 const PROGRAM: &str = "GPXAssist";
+const SETTINGS_FILE: &str = "settings.toml";
+const LEGACY_SETTINGS_FILE: &str = "settings.json"; // read once and converted to SETTINGS_FILE
+/// Settings changed while riding (last directory, window geometry, toolbar) are written once they have been left
+/// alone this long, so a burst of changes is a single write.
+const SETTINGS_WRITE_DELAY: Duration = Duration::from_secs(2);
+
+pub(crate) const TILE_CACHE: &str = "tiles";
+pub(crate) const STREETVIEW_CACHE: &str = "streetview";
+pub(crate) const SURFACE_CACHE: &str = "surfaces";
+/// Everything GPXAssist writes to the cache directory.
+const CACHE_ENTRIES: [&str; 4] = [TILE_CACHE, STREETVIEW_CACHE, SURFACE_CACHE, crate::library::CACHE_FILE];
+
+/// Settings holding keys, tokens or passwords, which are left out of crash reports.
+const SECRET_SETTINGS: [&str; 5] = ["streetview_api_key", "token", "client_secret", "refresh_token", "password"];
+
+/// First line of a settings file encrypted with `encrypt_settings` using the key in the system keyring, followed by
+/// the hex encoded encrypted TOML ...
+const KEYRING_HEADER: &str = "# GPXAssist encrypted settings (keyring)\n";
+/// ... or using the machine key when there is no keyring.
+const ENCRYPTED_HEADER: &str = "# GPXAssist encrypted settings\n";
+
+const SETTINGS_HEADER: &str = "\
+# GPXAssist settings. Most of these can be changed in the settings dialog (gear icon); the file may also be edited
+# by hand while GPXAssist is closed. Distances are in metres and speeds in km/h regardless of the display units.
+";
+
+/// Descriptions written above each setting in the settings file.
+const SETTING_DOCS: &[(&str, &str)] =
+&[
+   ("last_directory", "Directory last used to open a GPX file."),
+   ("broadcast_directory", "Directory TrainingPeaks Virtual writes its broadcast (Save to Local File) data to."),
+   ("gradient_length", "Length of the course shown in the gradient view (metres, 500-10000)."),
+   ("gradient_offset", "Position of the rider within the gradient view (metres from the left, 100-2000)."),
+   ("flat_gradient_percentage", "Gradients within +/- this percentage are drawn as flat."),
+   ("extreme_gradient_percentage", "Gradients at or above this percentage are drawn in the extreme colour."),
+   ("gradient_patterns", "Hatch the gradient profile as well as colouring it (lines downhill, vertical lines climbing, a grid \
+                          when steep) so it can be read without telling the colours apart."),
+   ("vertical_exaggeration", "Vertical scale of the gradient profile relative to the horizontal (1-50)."),
+   ("streetview_api_key", "Google Street View API key, encrypted for this machine and user. Set it in the settings dialog or \
+                           with the key set command rather than editing it here."),
+   ("streetview_delta", "Distance in metres to travel before requesting a new Street View image, 0 to use the toolbar Refresh \
+                         distance."),
+   ("streetview_size", "Largest Street View image requested (max_size pixels on the longest side, 640 on the standard API \
+                        plan). With is_upscaled a smaller image of scale_percent of the panel size is requested and \
+                        stretched to fit, using less bandwidth and quota."),
+   ("window_size", "Main window size and position, restored on startup."),
+   ("theme", "Colour theme: Dark, Light, HighContrast or Custom."),
+   ("custom_theme", "Colours used by the Custom theme."),
+   ("overlay_background", "Background colour of the streaming overlay mode (F9) as #RRGGBB or #RRGGBBAA."),
+   ("overlay_transparent", "Use a transparent window for the overlay mode instead of the background colour (needs a restart)."),
+   ("check_for_updates", "Check for a newer GPXAssist release on startup."),
+   ("encrypt_settings", "Encrypt the whole settings file (directories, tokens and everything else) with a random key kept in \
+                         the system keyring (Keychain, Credential Manager or Secret Service). Without a keyring, e.g. on a \
+                         headless Linux, a key derived from this machine and user is used instead, which only stops the \
+                         file being read on another machine. Either way the file can only be read by GPXAssist here."),
+   ("courses_directory", "Directory scanned by the course library."),
+   ("rider_marker", "Rider marker on the map and gradient profile: style Arrow, Dot or Avatar (the PNG image in avatar, \
+                     scaled to a small square), in color as #RRGGBB."),
+   ("course_stats", "Show the percent complete, ascent remaining and current altitude over the view in every view mode."),
+   ("turn_indicator", "Show an arrow and countdown for the next turn over the Map and Street View modes."),
+   ("surface_overlay", "Look up the road surface under the course in OpenStreetMap to colour the route and profile and warn before gravel or cobbles."),
+   ("screenshot_directory", "Directory the camera button saves screenshots of the current view to."),
+   ("cache_directory", "Directory for downloaded map tiles, Street View images and course library data."),
+   ("touch_mode", "Larger controls and touch gestures."),
+   ("toolbar_items", "Toolbar items in display order with their visibility."),
+   ("toolbar_collapsed", "Whether the toolbar is collapsed."),
+   ("units", "Display units: Metric or Imperial."),
+   ("climb_alerts", "Warnings shown before steep climbs (threshold in percent, distance in metres)."),
+   ("simulation_physics", "Physics based simulation speed: mass (kg), cda (m²), rolling_resistance and power (W). The mass, \
+                           cda and rolling_resistance are also used for the grade-adjusted speed in the status bar."),
+   ("simulation_variation", "Random variation of the simulated speed: amount in percent and occasional stops."),
+   ("broadcast_polling", "How often the broadcast file is read (interval_ms) when it cannot be watched for changes, and \
+                          how soon a partly written file is re-read (retry_ms), in milliseconds. A watched file is read \
+                          as soon as it changes."),
+   ("telemetry_calibration", "Corrections applied to the broadcast data when the game course length does not match the GPX \
+                              file: the distance is multiplied by distance_scale and distance_offset (metres) is added, and \
+                              altitude_offset (metres) is added to the altitude."),
+   ("live_server", "Local HTTP server publishing the position, gradient, telemetry and course as JSON on \
+                    http://127.0.0.1:port/api/state (and /api/course, /api/position, /api/gradient, /api/telemetry). With is_lan \
+                    it also listens on the local network so a phone can open http://<computer address>:port/companion as a \
+                    second display."),
+   ("weather", "Real weather at the rider position from Open-Meteo shown in the status bar, refreshed every refresh_minutes \
+                (or after 10 km), optionally with the in-game wind from the broadcast for comparison. is_panel_shown shows a \
+                panel over the view with the in-game wind relative to the direction of travel (and the real \
+                temperature and rain when enabled)."),
+   ("pacing", "Pacing plan inputs, set in the Pacing Plan window: ftp (W), weight (kg, rider only) and either intensity \
+               (percent of FTP) or, with is_time_target, target_minutes for the whole course. is_shown shows the target and actual \
+               power over the view."),
+   ("notifications", "Desktop notifications at halfway, the final kilometre (or mile), the top of each climb and for \
+                      personal best climb times."),
+   ("speech", "Spoken announcements using the system text-to-speech voice: climbs ahead (warning_distance metres before \
+               the start), the summit 200 m before the top (is_summits), halfway and the final kilometre or mile \
+               (is_progress)."),
+   ("fan_control", "Smart fan control: when the power (W), speed (km/h) or gradient (%) reaches a rule threshold the \
+                    body is POSTed to url with {level} replaced by the rule fan level (0-100), e.g. to the Home Assistant \
+                    fan.set_percentage service. A new level is only sent after hold_seconds. The token (such as a Home \
+                    Assistant long-lived access token) is encrypted; set it in the settings dialog."),
+   ("trainer", "Bluetooth FTMS smart trainer control: the course gradient, scaled by difficulty (percent), is sent to the \
+                first trainer found whose name contains device_name (any trainer when empty). While simulating, the \
+                trainer power drives the rider along the course so GPXAssist can be ridden without the game."),
+   ("discord", "Discord Rich Presence showing the course, distance completed and elapsed time on your Discord profile. \
+                application_id is the id of an application created at https://discord.com/developers/applications, whose \
+                name Discord shows as the activity."),
+   ("komoot", "Komoot account used to open planned tours from the course library, set with the `komoot login` \
+               command. The client secret and refresh token are encrypted."),
+   ("proxy", "HTTP proxy for Street View, map tiles and update checks. The password is encrypted; set it in the settings \
+              dialog. When disabled the HTTP_PROXY and HTTPS_PROXY environment variables are used if set."),
+];
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Settings
@@ -24,434 +135,2798 @@ pub struct Settings
    pub(crate) flat_gradient_percentage: f64,
    pub(crate) extreme_gradient_percentage: f64,
    pub(crate) vertical_exaggeration: f64,
+   #[serde(default)]
+   pub(crate) gradient_patterns: bool,
    streetview_api_key: String,
+   #[serde(default)]
+   pub(crate) window_size: Option<[f32; 2]>,
+   #[serde(default)]
+   pub(crate) window_position: Option<[f32; 2]>,
+   #[serde(default)]
+   pub(crate) theme: ThemeKind,
+   #[serde(default)]
+   pub(crate) custom_theme: Theme,
+   #[serde(default = "Settings::default_overlay_background", with = "crate::ui::theme::hex_color")]
+   pub(crate) overlay_background: Color32,
+   #[serde(default)]
+   pub(crate) overlay_transparent: bool,
+   #[serde(default)]
+   pub(crate) check_for_updates: bool,
+   #[serde(default)]
+   pub(crate) encrypt_settings: bool,
+   #[serde(default = "Settings::default_courses_directory")]
+   pub(crate) courses_directory: PathBuf,
+   #[serde(default = "Settings::default_screenshot_directory")]
+   pub(crate) screenshot_directory: PathBuf,
+   #[serde(default = "Settings::default_course_stats")]
+   pub(crate) course_stats: bool,
+   #[serde(default = "Settings::default_turn_indicator")]
+   pub(crate) turn_indicator: bool,
+   #[serde(default)]
+   pub(crate) surface_overlay: bool,
+   #[serde(default)]
+   pub(crate) rider_marker: RiderMarker,
+   #[serde(default = "Settings::default_cache_directory")]
+   pub(crate) cache_directory: PathBuf,
+   #[serde(default)]
+   pub(crate) touch_mode: bool,
+   #[serde(default = "ToolbarItem::defaults")]
+   pub(crate) toolbar_items: Vec<(ToolbarItem, bool)>,
+   #[serde(default)]
+   pub(crate) toolbar_collapsed: bool,
+   #[serde(default)]
+   pub(crate) climb_alerts: ClimbAlerts,
+   #[serde(default)]
+   pub(crate) simulation_physics: PhysicsModel,
+   #[serde(default)]
+   pub(crate) simulation_variation: SpeedVariation,
+   #[serde(default)]
+   pub(crate) units: Units,
+   #[serde(default)]
+   pub(crate) proxy: ProxySettings,
+   #[serde(default)]
+   pub(crate) broadcast_polling: BroadcastPolling,
+   #[serde(default)]
+   pub(crate) telemetry_calibration: TelemetryCalibration,
+   #[serde(default)]
+   pub(crate) streetview_size: StreetViewSize,
+   #[serde(default)]
+   pub(crate) streetview_delta: f64, // metres, 0 to use the toolbar refresh distance
+   #[serde(default)]
+   pub(crate) live_server: LiveServerSettings,
+   #[serde(default)]
+   pub(crate) weather: WeatherSettings,
+   #[serde(default)]
+   pub(crate) pacing: PacingSettings,
+   #[serde(default)]
+   pub(crate) notifications: Notifications,
+   #[serde(default)]
+   pub(crate) speech: SpeechSettings,
+   #[serde(default)]
+   pub(crate) fan_control: FanControl,
+   #[serde(default)]
+   pub(crate) trainer: TrainerSettings,
+   #[serde(default)]
+   pub(crate) discord: DiscordSettings,
+   #[serde(default)]
+   pub(crate) komoot: KomootSettings,
 
+   /// `--set` command line overrides as (dotted key, value, value before the override). Overridden settings are
+   /// written back with their previous value unless changed in the UI, so the overrides only last for this run.
+   #[serde(skip)] overrides:                 Vec<(String, toml::Value, Option<toml::Value>)>,
+   #[serde(skip)] pending_write:             Option<Instant>, // when last changed by `write_later`, None once written
+   #[serde(skip)] settings_tab:              SettingsTab,
+   #[serde(skip)] invalid_fields:            Vec<(SettingsField, String)>, // from the last failed Save
    #[serde(skip)] show_api_key:              bool,
    #[serde(skip)] temp_api_key:              String,
+   /// Result of the "Test" button, None inside while the request is running.
+   #[serde(skip)] api_key_test:              Option<Arc<parking_lot::Mutex<Option<Result<String, String>>>>>,
    #[serde(skip)] temp_broadcast_dir:        PathBuf,
    #[serde(skip)] temp_gradient_length:      f64,
    #[serde(skip)] temp_gradient_offset:      f64,
    #[serde(skip)] temp_flat_gradient:        f64,
    #[serde(skip)] temp_extreme_gradient:     f64,
-   #[serde(skip)] temp_vertical_exaggeration: f64
+   #[serde(skip)] temp_vertical_exaggeration: f64,
+   #[serde(skip)] temp_gradient_patterns:    bool,
+   #[serde(skip)] temp_theme:                ThemeKind,
+   #[serde(skip)] temp_custom_theme:         Theme,
+   #[serde(skip)] temp_overlay_background:   Color32,
+   #[serde(skip)] temp_overlay_transparent:  bool,
+   #[serde(skip)] temp_check_for_updates:    bool,
+   #[serde(skip)] temp_encrypt_settings:     bool,
+   #[serde(skip)] temp_courses_dir:          PathBuf,
+   #[serde(skip)] temp_screenshot_dir:       PathBuf,
+   #[serde(skip)] temp_course_stats:         bool,
+   #[serde(skip)] temp_turn_indicator:       bool,
+   #[serde(skip)] temp_surface_overlay:      bool,
+   #[serde(skip)] temp_rider_marker:         RiderMarker,
+   #[serde(skip)] temp_cache_dir:            PathBuf,
+   #[serde(skip)] cache_size:                Option<u64>, // bytes, measured when the dialog opens
+   #[serde(skip)] temp_touch_mode:           bool,
+   #[serde(skip)] temp_climb_alerts:         ClimbAlerts,
+   #[serde(skip)] temp_simulation_physics:   PhysicsModel,
+   #[serde(skip)] temp_simulation_variation: SpeedVariation,
+   #[serde(skip)] temp_units:                Units,
+   #[serde(skip)] temp_proxy:                ProxySettings,
+   #[serde(skip)] temp_broadcast_polling:    BroadcastPolling,
+   #[serde(skip)] temp_calibration:          TelemetryCalibration,
+   #[serde(skip)] temp_streetview_size:      StreetViewSize,
+   #[serde(skip)] temp_streetview_delta:     f64,
+   #[serde(skip)] temp_live_server:          LiveServerSettings,
+   #[serde(skip)] temp_weather:              WeatherSettings,
+   #[serde(skip)] temp_notifications:        Notifications,
+   #[serde(skip)] temp_speech:               SpeechSettings,
+   #[serde(skip)] temp_fan_control:          FanControl,
+   #[serde(skip)] temp_fan_token:            String, // plain text while editing, encrypted into temp_fan_control on save
+   #[serde(skip)] temp_trainer:              TrainerSettings,
+   #[serde(skip)] temp_discord:              DiscordSettings,
+   #[serde(skip)] temp_proxy_password:       String // plain text while editing, encrypted into temp_proxy on save
 }
 
-impl Default for Settings
+/// Replace the non-empty SECRET_SETTINGS in `table` and the tables within it.
+fn redact_secrets(table: &mut toml::Table)
+//----------------------------------------
 {
-   fn default() -> Self
-   //------------------
+   for (key, value) in table.iter_mut()
    {
-      let default_open_dir = match dirs::home_dir()
-      {
-         Some(h) => h,
-         None => env::temp_dir(),
-      };
-      Self
+      match value
       {
-         last_directory: default_open_dir,
-         broadcast_directory: ui::get_broadcast_directory_or_default(),
-         gradient_length: 3000.0,
-         gradient_offset: 500.0,
-         flat_gradient_percentage: 0.5,
-         extreme_gradient_percentage: 16.0,
-         vertical_exaggeration: 10.0,
-         streetview_api_key: String::new(),
-
-         show_api_key: false,
-         temp_api_key: String::new(),
-         temp_broadcast_dir: PathBuf::new(),
-         temp_gradient_length: 3000.0,
-         temp_gradient_offset: 500.0,
-         temp_flat_gradient: 0.5,
-         temp_extreme_gradient: 16.0,
-         temp_vertical_exaggeration: 10.0
+         | toml::Value::Table(inner) => redact_secrets(inner),
+         | toml::Value::Array(items) => items.iter_mut().filter_map(|item| item.as_table_mut()).for_each(redact_secrets),
+         | toml::Value::String(text) if !text.is_empty() && SECRET_SETTINGS.contains(&key.as_str()) => *text = "<removed>".to_string(),
+         | _ => (),
       }
    }
 }
 
-impl Settings
-//===========
+/// The value of a dotted settings key such as `climb_alerts.threshold`.
+fn get_setting<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value>
+//-------------------------------------------------------------------------------
 {
-   pub fn new() -> Self
+   let (parents, name) = key.rsplit_once('.').map_or((None, key), |(p, n)| (Some(p), n));
+   let table = match parents
    {
-      Settings::default()
+      | Some(parents) => parents.split('.').try_fold(table, |t, part| t.get(part)?.as_table())?,
+      | None => table,
+   };
+   table.get(name)
+}
+
+/// Set (or with None remove) the value of a dotted settings key, returning the previous value.
+fn set_setting(table: &mut toml::Table, key: &str, value: Option<toml::Value>) -> Result<Option<toml::Value>, String>
+//------------------------------------------------------------------------------------------------------------------
+{
+   let (parents, name) = key.rsplit_once('.').map_or((None, key), |(p, n)| (Some(p), n));
+   let mut table = table;
+   for part in parents.into_iter().flat_map(|p| p.split('.'))
+   {
+      table = table.get_mut(part).and_then(|v| v.as_table_mut()).ok_or_else(|| format!("Unknown setting '{key}'"))?;
    }
+   Ok(match value
+   {
+      | Some(value) => table.insert(name.to_string(), value),
+      | None => table.remove(name),
+   })
+}
 
-   pub fn get_settings(&self) -> Result<Settings, String>
-   //-------------------------------------------
+/// First line of the settings file when encrypted with the key available here.
+fn encryption_header() -> &'static str { if ut::keyring_key().is_some() { KEYRING_HEADER } else { ENCRYPTED_HEADER } }
+
+fn is_encrypted(text: &str) -> bool { text.starts_with(KEYRING_HEADER) || text.starts_with(ENCRYPTED_HEADER) }
+
+/// Encrypt the settings file `text` for `encrypt_settings`, with the keyring key or else the machine key.
+fn encode_settings(text: &str) -> Result<String, std::io::Error>
+//--------------------------------------------------------------
+{
+   let encrypted = match ut::keyring_key()
    {
-      let _settings_dir = match self.get_settings_path()
-      {
-         Ok(pb) => pb,
-         Err(e) =>
-         {
-            let errmsg = format!("Error getting settings path: {}", e);
-            eprintln!("{errmsg}");
-            return Err(errmsg);
-         }
-      };
-      let mut settings_path = match self.get_settings_path()
-      {
-         Ok(p) => p,
-         Err(_e) =>
-         {
-            match self.write_default_settings()
-            {
-               Ok(pp) => pp,
-               Err(e) =>
-               {
-                  let errmsg = format!("Error creating default settings: {}", e);
-                  return Err(errmsg);
-               }
-            }
-         }
-      };
+      | Some(key) => ut::encrypt_with(key, text),
+      | None => ut::encrypt(text),
+   };
+   let encrypted = encrypted.map_err(|e| std::io::Error::other(format!("Error encrypting settings: {e:?}")))?;
+   Ok(format!("{}{}\n", encryption_header(), hex::encode(encrypted)))
+}
 
-      if ! settings_path.exists()
-      {
-         settings_path = match self.write_default_settings()
-         {
-            Ok(pp) => pp,
-            Err(e) =>
-            {
-               eprintln!("Error creating default settings: {}", e);
-               PathBuf::new()
-            }
-         };
-      }
-      Ok(self.read_settings())
+/// The TOML from a settings file, decrypting it if it was written with `encrypt_settings`.
+fn decode_settings(text: &str) -> Result<String, String>
+//------------------------------------------------------
+{
+   if let Some(encoded) = text.strip_prefix(KEYRING_HEADER)
+   {
+      let key = ut::keyring_key().ok_or("its key is in a system keyring which is not available")?;
+      let encrypted = hex::decode(encoded.trim()).map_err(|e| e.to_string())?;
+      return ut::decrypt_with(key, &encrypted).map_err(|_| "the key in the system keyring does not match it".to_string());
    }
+   let Some(encoded) = text.strip_prefix(ENCRYPTED_HEADER) else { return Ok(text.to_string()); };
+   let encrypted = hex::decode(encoded.trim()).map_err(|e| e.to_string())?;
+   ut::decrypt(&encrypted).map_err(|_| "it may have been encrypted on another machine or by another user".to_string())
+}
 
-   pub fn get_settings_or_default(&self) -> Settings
-   //-------------------------------------------
+/// Pages of the settings dialog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum SettingsTab
+{
+   #[default]
+   General,
+   StreetView,
+   Gradient,
+   Broadcast,
+   Integrations,
+   Advanced,
+}
+
+impl SettingsTab
+{
+   const ALL: [SettingsTab; 6] = [SettingsTab::General, SettingsTab::StreetView, SettingsTab::Gradient, SettingsTab::Broadcast,
+                                  SettingsTab::Integrations, SettingsTab::Advanced];
+
+   fn label(self) -> &'static str
+   //----------------------------
    {
-      match self.get_settings()
+      match self
       {
-         Ok(s) => s,
-         Err(_) => Settings::default(),
+         | SettingsTab::General => "General",
+         | SettingsTab::StreetView => "Street View",
+         | SettingsTab::Gradient => "Gradient",
+         | SettingsTab::Broadcast => "Broadcast",
+         | SettingsTab::Integrations => "Integrations",
+         | SettingsTab::Advanced => "Advanced",
       }
    }
+}
 
-   pub(crate) fn write_settings(&self) -> Result<PathBuf, std::io::Error>
-   //-----------------------------------------------------------------------
-   {
-      let mut config_file = self.get_config_path()?;
-      config_file.push("settings.json");
-      let mut file = File::create(&config_file)?;
-      let json = serde_json::to_string(&self)?;
-      file.write_all(json.as_bytes())?;
-      // let file = File::create(&config_file)?;
-      // let mut writer = BufWriter::new(file);
-      // serde_json::to_writer(&mut writer, &settings)?;
-      println!("Wrote settings {} to {}", json, config_file.display());
-      Ok(config_file)
-   }
+/// Settings dialog fields checked by `Settings::validate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SettingsField
+{
+   ApiKey,
+   BroadcastDir,
+   CoursesDir,
+   CacheDir,
+   GradientLength,
+   GradientOffset,
+   FlatGradient,
+   ExtremeGradient,
+   VerticalExaggeration,
+   ClimbAlerts,
+   Simulation,
+   Proxy,
+   BroadcastPolling,
+   TelemetryCalibration,
+   StreetViewSize,
+   StreetViewDelta,
+   LiveServer,
+   Weather,
+   Speech,
+   RiderMarker,
+   FanControl,
+   Trainer,
+   Discord,
+}
 
-   pub fn get_streetview_api_key(&self) -> Result<String, String>
-   //--------------------------------------
+impl SettingsField
+{
+   /// The dialog tab the field is on.
+   fn tab(self) -> SettingsTab
+   //-------------------------
    {
-      let encrypted_bytes = match hex::decode(&self.streetview_api_key)
-      {
-         Ok(bytes) => bytes,
-         Err(e) =>
-         {
-            let errmsg = format!("Failed to hex decode encrypted password: {}", e);
-            // self.toast_manager.error(errmsg);
-            return Err(errmsg);
-         }
-      };
-      if encrypted_bytes.is_empty()
-      {
-         return Err("Street View API key is not set.".to_string());
-      }
+      match self
       {
-         match ut::decrypt(&encrypted_bytes)
-         {
-            | Ok(decrypted_key) =>
-            {
-               Ok(decrypted_key)
-            }
-            | Err(e) =>
-            {
-               let errmsg = format!("Failed to decrypt Street View API key: {}", e);
-               eprintln!("{errmsg}");
-               // self.toast_manager.error(errmsg);
-               Err(errmsg)
-            }
-         }
+         | SettingsField::ApiKey | SettingsField::StreetViewSize | SettingsField::StreetViewDelta => SettingsTab::StreetView,
+         | SettingsField::BroadcastDir | SettingsField::BroadcastPolling | SettingsField::TelemetryCalibration
+         | SettingsField::LiveServer => SettingsTab::Broadcast,
+         | SettingsField::CoursesDir | SettingsField::Weather | SettingsField::Speech | SettingsField::RiderMarker => SettingsTab::General,
+         | SettingsField::FanControl | SettingsField::Trainer | SettingsField::Discord => SettingsTab::Integrations,
+         | SettingsField::GradientLength | SettingsField::GradientOffset | SettingsField::FlatGradient
+         | SettingsField::ExtremeGradient | SettingsField::VerticalExaggeration | SettingsField::ClimbAlerts => SettingsTab::Gradient,
+         | SettingsField::CacheDir | SettingsField::Simulation | SettingsField::Proxy => SettingsTab::Advanced,
       }
    }
+}
 
-   fn set_streetview_api_key_from_tmp(&mut self) -> Result<(), String>
-   //----------------------------------------------------------------
+/// Small button after a settings dialog field that resets it to its default value.
+fn reset_button(ui: &mut egui::Ui) -> bool
+//----------------------------------------
+{
+   ui.small_button("⟲").labelled("Reset to default").on_hover_text("Reset to default").clicked()
+}
+
+/// Warnings shown ahead of steep climbs.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ClimbAlerts
+{
+   pub is_enabled: bool,
+   pub threshold:  f64, // percent
+   pub distance:   f64, // metres before the start of the climb
+   pub is_sound:   bool,
+}
+
+impl Default for ClimbAlerts
+{
+   fn default() -> Self { Self { is_enabled: false, threshold: 8.0, distance: 300.0, is_sound: false } }
+}
+
+/// How often the broadcast file is read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct BroadcastPolling
+{
+   pub interval_ms: u64, // between reads of the broadcast file
+   pub retry_ms:    u64, // before re-reading a file that could not be parsed (usually caught mid-write)
+}
+
+impl Default for BroadcastPolling
+{
+   fn default() -> Self { Self { interval_ms: 1000, retry_ms: 300 } }
+}
+
+impl BroadcastPolling
+{
+   /// Allowed intervals in milliseconds, also enforced on values from a hand edited settings file so the reader never
+   /// spins.
+   pub const INTERVAL_RANGE: RangeInclusive<u64> = 100..=5000;
+   pub const RETRY_RANGE: RangeInclusive<u64> = 50..=2000;
+
+   pub fn interval(self) -> Duration
+   //-------------------------------
    {
-      match ut::encrypt(&self.temp_api_key)
-      {
-         | Ok(encrypted_data) =>
-         {
-            self.streetview_api_key = hex::encode(encrypted_data);
-            match self.write_settings()
-            {
-               | Ok(_) => (),
-               | Err(e) =>
-               {
-                  let errmsg = format!("Failed to write settings file: {}", e);
-                  eprintln!("{errmsg}");
-                  return Err(errmsg);
-               }
-            }
-            Ok(())
-         }
-         | Err(e) =>
-         {
-            let errmsg = format!("Failed to encrypt Street View API key: {}", e);
-            eprintln!("{errmsg}");
-            // self.toast_manager.error(errmsg);
-            Err(errmsg)
-         }
-      }
+      Duration::from_millis(self.interval_ms.clamp(*Self::INTERVAL_RANGE.start(), *Self::INTERVAL_RANGE.end()))
    }
 
-   pub fn set_streetview_api_key(&mut self, api_key: &str) -> Result<(), String>
-   //-------------------------------------------------------
+   pub fn retry(self) -> Duration { Duration::from_millis(self.retry_ms.clamp(*Self::RETRY_RANGE.start(), *Self::RETRY_RANGE.end())) }
+}
+
+/// Corrections to the broadcast data for courses whose length in the game differs slightly from the GPX file, which
+/// otherwise makes the position drift ahead of or behind the landmarks in the views.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TelemetryCalibration
+{
+   pub distance_scale:  f64, // game distance to GPX distance
+   pub distance_offset: f64, // metres added after scaling
+   pub altitude_offset: f64, // metres
+}
+
+impl Default for TelemetryCalibration
+{
+   fn default() -> Self { Self { distance_scale: 1.0, distance_offset: 0.0, altitude_offset: 0.0 } }
+}
+
+impl TelemetryCalibration
+{
+   /// The GPX distance for the broadcast `distance` (both in metres).
+   pub fn distance(self, distance: f64) -> f64 { distance * self.distance_scale + self.distance_offset }
+
+   /// The corrected broadcast `altitude` (metres).
+   pub fn altitude(self, altitude: i32) -> i32 { altitude + self.altitude_offset.round() as i32 }
+}
+
+/// Local HTTP server publishing the live state (see `server::LiveServer`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LiveServerSettings
+{
+   pub is_enabled: bool,
+   pub port:       u16,
+   pub is_lan:     bool, // listen on all interfaces for the companion display instead of only this computer
+}
+
+impl Default for LiveServerSettings
+{
+   fn default() -> Self { Self { is_enabled: false, port: 8686, is_lan: false } }
+}
+
+/// Desktop notifications for ride milestones (see `milestones::MilestoneNotifier`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Notifications
+{
+   pub is_enabled:       bool,
+   pub is_halfway:       bool,
+   pub is_final_stretch: bool, // last kilometre or mile
+   pub is_summit:        bool,
+   pub is_personal_best: bool,
+}
+
+impl Default for Notifications
+{
+   fn default() -> Self
    {
-      match ut::encrypt(api_key)
-      {
-         | Ok(encrypted_data) =>
-         {
-            self.streetview_api_key = hex::encode(encrypted_data);
-            match self.write_settings()
-            {
-               | Ok(_) => (),
-               | Err(e) =>
-               {
-                  let errmsg = format!("Failed to write settings file: {}", e);
-                  eprintln!("{errmsg}");
-                  return Err(errmsg);
-               }
-            }
-            Ok(())
-         }
-         | Err(e) =>
-         {
-            let errmsg = format!("Failed to encrypt Street View API key: {}", e);
-            eprintln!("{errmsg}");
-            // self.toast_manager.error(errmsg);
-            Err(errmsg)
-         }
-      }
+      Self { is_enabled: false, is_halfway: true, is_final_stretch: true, is_summit: true, is_personal_best: true }
    }
+}
 
-   pub fn set_last_directory(&mut self, path: &str) -> bool
-   //-------------------------------------------
+/// Spoken climb, summit and progress announcements (see `speech::SpeechCues`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SpeechSettings
+{
+   pub is_enabled:       bool,
+   pub is_climbs:        bool,
+   pub is_summits:       bool,
+   pub is_progress:      bool, // halfway and the final kilometre or mile
+   pub warning_distance: u32,  // metres before a climb it is announced
+}
+
+impl Default for SpeechSettings
+{
+   fn default() -> Self
    {
-      let path = PathBuf::from(path);
-      if path.is_dir()
-      {
-         self.last_directory = path;
-         match self.write_settings()
-         {
-            | Ok(_) => (),
-            | Err(e) =>
-            {
-               eprintln!("Failed to write settings file: {}", e);
-               return false;
-            }
-         }
-         return true;
-      }
-      eprintln!("{} is not a directory", path.display());
-      false
+      Self { is_enabled: false, is_climbs: true, is_summits: true, is_progress: true, warning_distance: 500 }
    }
+}
 
-   pub fn set_last_directorybuf(&mut self, path: &PathBuf) -> bool
-   //-------------------------------------------
+/// Shape of the rider marker on the map and gradient profile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MarkerStyle
+{
+   #[default]
+   Arrow,
+   Dot,
+   Avatar, // a small PNG chosen by the user
+}
+
+impl MarkerStyle
+{
+   pub const ALL: [MarkerStyle; 3] = [MarkerStyle::Arrow, MarkerStyle::Dot, MarkerStyle::Avatar];
+
+   pub fn label(self) -> &'static str
    {
-      if path.is_dir()
+      match self
       {
-         self.last_directory = path.clone();
-         match self.write_settings()
-         {
-            | Ok(_) => (),
+         | MarkerStyle::Arrow => "Arrow",
+         | MarkerStyle::Dot => "Dot",
+         | MarkerStyle::Avatar => "Avatar",
+      }
+   }
+}
+
+/// Rider position marker (see `components::draw_rider_marker`).
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RiderMarker
+{
+   pub style:  MarkerStyle,
+   #[serde(with = "crate::ui::theme::hex_color")]
+   pub color:  Color32,
+   pub avatar: PathBuf, // PNG image for the Avatar style
+}
+
+impl Default for RiderMarker
+{
+   fn default() -> Self { Self { style: MarkerStyle::Arrow, color: Color32::from_rgb(255, 100, 100), avatar: PathBuf::new() } }
+}
+
+/// Real weather widget (see `weather::WeatherMonitor`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WeatherSettings
+{
+   pub is_enabled:       bool,
+   pub refresh_minutes:  u64,
+   pub is_wind_compared: bool, // also show the in-game wind from the broadcast
+   pub is_panel_shown:   bool, // wind (and real weather) panel over the view
+}
+
+impl Default for WeatherSettings
+{
+   fn default() -> Self { Self { is_enabled: false, refresh_minutes: 15, is_wind_compared: true, is_panel_shown: true } }
+}
+
+impl WeatherSettings
+{
+   pub fn refresh_interval(self) -> Duration { Duration::from_secs(self.refresh_minutes * 60) }
+}
+
+/// Inputs of the pacing plan (see `pacing::PacingPlanner`).
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PacingSettings
+{
+   pub ftp:            f64, // W
+   pub weight:         f64, // kg, rider only
+   pub is_time_target: bool, // pace for target_minutes rather than intensity
+   pub intensity:      f64, // percent of FTP
+   pub target_minutes: f64,
+   pub is_shown:       bool, // target and actual power over the view
+}
+
+impl Default for PacingSettings
+{
+   fn default() -> Self
+   {
+      Self { ftp: 250.0, weight: 75.0, is_time_target: false, intensity: 75.0, target_minutes: 120.0, is_shown: true }
+   }
+}
+
+/// Ride value compared with the smart fan control rule thresholds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FanMetric
+{
+   #[default]
+   Power,    // W
+   Speed,    // km/h
+   Gradient, // percent
+}
+
+impl FanMetric
+{
+   pub const ALL: [FanMetric; 3] = [FanMetric::Power, FanMetric::Speed, FanMetric::Gradient];
+
+   pub fn label(self) -> &'static str
+   //--------------------------------
+   {
+      match self
+      {
+         | FanMetric::Power => "Power",
+         | FanMetric::Speed => "Speed",
+         | FanMetric::Gradient => "Gradient",
+      }
+   }
+
+   pub fn unit(self) -> &'static str
+   //-------------------------------
+   {
+      match self
+      {
+         | FanMetric::Power => "W",
+         | FanMetric::Speed => "km/h",
+         | FanMetric::Gradient => "%",
+      }
+   }
+}
+
+/// Fan level (percent) used from `threshold` upwards.
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FanRule
+{
+   pub threshold: f64,
+   pub level:     u32,
+}
+
+/// Smart fan or Home Assistant webhook driven by the ride (see `fan::FanController`).
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FanControl
+{
+   pub is_enabled:   bool,
+   pub url:          String,
+   pub token:        String, // bearer token, encrypted and hex encoded like the Street View API key
+   pub body:         String, // JSON POSTed with {level} replaced by the fan level
+   pub metric:       FanMetric,
+   pub rules:        Vec<FanRule>,
+   pub hold_seconds: u64, // a new level must be wanted this long before it is sent
+}
+
+impl Default for FanControl
+{
+   fn default() -> Self
+   {
+      Self
+      {
+         is_enabled: false,
+         url: "http://homeassistant.local:8123/api/services/fan/set_percentage".to_string(),
+         token: String::new(),
+         body: r#"{"entity_id": "fan.trainer_fan", "percentage": {level}}"#.to_string(),
+         metric: FanMetric::Power,
+         rules: vec![FanRule { threshold: 0.0, level: 0 }, FanRule { threshold: 100.0, level: 33 },
+                     FanRule { threshold: 200.0, level: 66 }, FanRule { threshold: 300.0, level: 100 }],
+         hold_seconds: 10,
+      }
+   }
+}
+
+impl FanControl
+{
+   /// The decrypted token, empty when not set.
+   pub fn decrypted_token(&self) -> Result<String, String>
+   //-----------------------------------------------------
+   {
+      if self.token.is_empty()
+      {
+         return Ok(String::new());
+      }
+      let bytes = hex::decode(&self.token).map_err(|e| format!("Failed to hex decode fan control token: {}", e))?;
+      ut::decrypt(&bytes).map_err(|e| format!("Failed to decrypt fan control token: {}", e))
+   }
+
+   /// Encrypt `token` into the settings (an empty token clears it).
+   pub fn set_token(&mut self, token: &str) -> Result<(), String>
+   //-------------------------------------------------------------
+   {
+      if self.decrypted_token().is_ok_and(|current| current == token)
+      {  // Encrypting is not repeatable, so keep the stored token to leave the settings file unchanged
+         return Ok(());
+      }
+      self.token = if token.is_empty()
+      {
+         String::new()
+      }
+      else
+      {
+         hex::encode(ut::encrypt(token).map_err(|e| format!("Failed to encrypt fan control token: {}", e))?)
+      };
+      Ok(())
+   }
+
+   /// The fan level for `value`: that of the highest rule threshold reached, or 0 below them all.
+   pub fn level(&self, value: f64) -> u32
+   //------------------------------------
+   {
+      self.rules.iter()
+                .filter(|rule| value >= rule.threshold)
+                .max_by(|a, b| a.threshold.total_cmp(&b.threshold))
+                .map_or(0, |rule| rule.level)
+   }
+
+   /// The request body for `level`.
+   pub fn body_for(&self, level: u32) -> String { self.body.replace("{level}", &level.to_string()) }
+}
+
+/// Bluetooth FTMS smart trainer control (see `trainer::TrainerControl`).
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TrainerSettings
+{
+   pub is_enabled:  bool,
+   pub device_name: String, // part of the trainer's Bluetooth name, empty for the first FTMS trainer found
+   pub difficulty:  u32,    // percent of the course gradient sent to the trainer
+}
+
+impl Default for TrainerSettings
+{
+   fn default() -> Self { Self { is_enabled: false, device_name: String::new(), difficulty: 100 } }
+}
+
+/// Discord Rich Presence (see `discord::DiscordPresence`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DiscordSettings
+{
+   pub is_enabled:     bool,
+   pub application_id: String, // Discord developer portal application, shown as the activity name
+}
+
+/// Size of the requested Street View images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StreetViewSize
+{
+   pub max_size:      u32,  // pixels on the longest side
+   pub is_upscaled:   bool, // request a smaller image and stretch it to the panel
+   pub scale_percent: u32,  // of the panel size, when upscaling
+}
+
+impl Default for StreetViewSize
+{
+   fn default() -> Self { Self { max_size: 640, is_upscaled: false, scale_percent: 50 } }
+}
+
+impl StreetViewSize
+{
+   /// The image size to request for a `width` x `height` panel, keeping its aspect ratio.
+   pub fn request_size(self, width: f32, height: f32) -> (u32, u32)
+   //--------------------------------------------------------------
+   {
+      let scale = if self.is_upscaled { self.scale_percent as f32 / 100.0 } else { 1.0 };
+      let (width, height) = (width.max(1.0) * scale, height.max(1.0) * scale);
+      let fit = (self.max_size as f32 / width.max(height)).min(1.0);
+      ((width * fit).round().max(1.0) as u32, (height * fit).round().max(1.0) as u32)
+   }
+}
+
+/// Komoot API client registration and the account authorised with it (see `komoot::login`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct KomootSettings
+{
+   pub client_id:     String,
+   pub client_secret: String, // encrypted and hex encoded like the Street View API key
+   pub username:      String, // Komoot user id
+   pub refresh_token: String, // encrypted and hex encoded
+}
+
+impl KomootSettings
+{
+   pub fn is_connected(&self) -> bool { !self.username.is_empty() && !self.refresh_token.is_empty() }
+
+   fn decrypt(value: &str, name: &str) -> Result<String, String>
+   //-----------------------------------------------------------
+   {
+      let bytes = hex::decode(value).map_err(|e| format!("Failed to hex decode Komoot {name}: {}", e))?;
+      ut::decrypt(&bytes).map_err(|e| format!("Failed to decrypt Komoot {name}: {}", e))
+   }
+
+   fn encrypt(value: &str, name: &str) -> Result<String, String>
+   //-----------------------------------------------------------
+   {
+      Ok(hex::encode(ut::encrypt(value).map_err(|e| format!("Failed to encrypt Komoot {name}: {}", e))?))
+   }
+
+   pub fn decrypted_secret(&self) -> Result<String, String> { Self::decrypt(&self.client_secret, "client secret") }
+
+   pub fn decrypted_refresh_token(&self) -> Result<String, String> { Self::decrypt(&self.refresh_token, "refresh token") }
+
+   /// Store a newly authorised account, encrypting the secrets.
+   pub fn set_account(&mut self, client_id: &str, client_secret: &str, username: &str, refresh_token: &str) -> Result<(), String>
+   //---------------------------------------------------------------------------------------------------------------------------
+   {
+      self.client_id = client_id.to_string();
+      self.client_secret = Self::encrypt(client_secret, "client secret")?;
+      self.username = username.to_string();
+      self.refresh_token = Self::encrypt(refresh_token, "refresh token")?;
+      Ok(())
+   }
+}
+
+/// HTTP proxy for Street View, map tiles and update checks. When disabled reqwest falls back to the HTTP_PROXY and
+/// HTTPS_PROXY environment variables.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ProxySettings
+{
+   pub is_enabled: bool,
+   pub host:       String, // host name or URL such as socks5://host
+   pub port:       u16,
+   pub username:   String,
+   pub password:   String, // encrypted and hex encoded like the Street View API key
+}
+
+impl Default for ProxySettings
+{
+   fn default() -> Self
+   {
+      Self { is_enabled: false, host: String::new(), port: 8080, username: String::new(), password: String::new() }
+   }
+}
+
+impl ProxySettings
+{
+   /// The decrypted password, empty when not set.
+   pub fn decrypted_password(&self) -> Result<String, String>
+   //--------------------------------------------------------
+   {
+      if self.password.is_empty()
+      {
+         return Ok(String::new());
+      }
+      let bytes = hex::decode(&self.password).map_err(|e| format!("Failed to hex decode proxy password: {}", e))?;
+      ut::decrypt(&bytes).map_err(|e| format!("Failed to decrypt proxy password: {}", e))
+   }
+
+   /// Encrypt `password` into the settings (an empty password clears it).
+   pub fn set_password(&mut self, password: &str) -> Result<(), String>
+   //------------------------------------------------------------------
+   {
+      if self.decrypted_password().is_ok_and(|current| current == password)
+      {  // Encrypting is not repeatable, so keep the stored password to leave the settings file unchanged
+         return Ok(());
+      }
+      self.password = if password.is_empty()
+      {
+         String::new()
+      }
+      else
+      {
+         hex::encode(ut::encrypt(password).map_err(|e| format!("Failed to encrypt proxy password: {}", e))?)
+      };
+      Ok(())
+   }
+
+   /// The proxy as a URL including any credentials, or None when disabled.
+   pub fn url(&self) -> Result<Option<reqwest::Url>, String>
+   //-------------------------------------------------------
+   {
+      let host = self.host.trim();
+      if ! self.is_enabled || host.is_empty()
+      {
+         return Ok(None);
+      }
+      let invalid = || format!("Invalid proxy host '{host}'");
+      let address = if host.contains("://") { host.to_string() } else { format!("http://{host}") };
+      let mut url = reqwest::Url::parse(&address).map_err(|e| format!("{}: {}", invalid(), e))?;
+      if url.port().is_none()
+      {
+         url.set_port(Some(self.port)).map_err(|_| invalid())?;
+      }
+      if ! self.username.is_empty()
+      {
+         url.set_username(&self.username).map_err(|_| invalid())?;
+         url.set_password(Some(&self.decrypted_password()?)).map_err(|_| invalid())?;
+      }
+      Ok(Some(url))
+   }
+
+   /// Set HTTP_PROXY and HTTPS_PROXY for libraries that create their own HTTP client, such as the walkers tile
+   /// fetcher. Must be called at startup before any other threads are running.
+   pub fn export_env(&self) -> Result<(), String>
+   //--------------------------------------------
+   {
+      if let Some(url) = self.url()?
+      {
+         // SAFETY: called from main before the UI or any worker threads are started.
+         unsafe
+         {
+            env::set_var("HTTP_PROXY", url.as_str());
+            env::set_var("HTTPS_PROXY", url.as_str());
+         }
+      }
+      Ok(())
+   }
+}
+
+impl Default for Settings
+{
+   fn default() -> Self
+   //------------------
+   {
+      let default_open_dir = match dirs::home_dir()
+      {
+         Some(h) => h,
+         None => env::temp_dir(),
+      };
+      Self
+      {
+         last_directory: default_open_dir,
+         broadcast_directory: ui::get_broadcast_directory_or_default(),
+         gradient_length: 3000.0,
+         gradient_offset: 500.0,
+         flat_gradient_percentage: 0.5,
+         extreme_gradient_percentage: 16.0,
+         gradient_patterns: false,
+         vertical_exaggeration: 10.0,
+         streetview_api_key: String::new(),
+         window_size: None,
+         window_position: None,
+         theme: ThemeKind::Dark,
+         custom_theme: Theme::dark(),
+         overlay_background: Settings::default_overlay_background(),
+         overlay_transparent: false,
+         check_for_updates: false,
+         encrypt_settings: false,
+         courses_directory: Settings::default_courses_directory(),
+         screenshot_directory: Settings::default_screenshot_directory(),
+         course_stats: Settings::default_course_stats(),
+         turn_indicator: Settings::default_turn_indicator(),
+         surface_overlay: false,
+         rider_marker: RiderMarker::default(),
+         cache_directory: Settings::default_cache_directory(),
+         touch_mode: false,
+         toolbar_items: ToolbarItem::defaults(),
+         toolbar_collapsed: false,
+         climb_alerts: ClimbAlerts::default(),
+         simulation_physics: PhysicsModel::default(),
+         simulation_variation: SpeedVariation::default(),
+         units: Units::default(),
+         proxy: ProxySettings::default(),
+         broadcast_polling: BroadcastPolling::default(),
+         telemetry_calibration: TelemetryCalibration::default(),
+         streetview_size: StreetViewSize::default(),
+         streetview_delta: 0.0,
+         live_server: LiveServerSettings::default(),
+         weather: WeatherSettings::default(),
+         pacing: PacingSettings::default(),
+         notifications: Notifications::default(),
+         speech: SpeechSettings::default(),
+         fan_control: FanControl::default(),
+         trainer: TrainerSettings::default(),
+         discord: DiscordSettings::default(),
+         komoot: KomootSettings::default(),
+
+         show_api_key: false,
+         temp_api_key: String::new(),
+         api_key_test: None,
+         temp_broadcast_dir: PathBuf::new(),
+         temp_gradient_length: 3000.0,
+         temp_gradient_offset: 500.0,
+         temp_flat_gradient: 0.5,
+         temp_extreme_gradient: 16.0,
+         temp_gradient_patterns: false,
+         temp_vertical_exaggeration: 10.0,
+         temp_theme: ThemeKind::Dark,
+         temp_custom_theme: Theme::dark(),
+         temp_overlay_background: Settings::default_overlay_background(),
+         temp_overlay_transparent: false,
+         temp_check_for_updates: false,
+         temp_encrypt_settings: false,
+         temp_courses_dir: PathBuf::new(),
+         temp_screenshot_dir: PathBuf::new(),
+         temp_course_stats: Settings::default_course_stats(),
+         temp_turn_indicator: Settings::default_turn_indicator(),
+         temp_surface_overlay: false,
+         temp_rider_marker: RiderMarker::default(),
+         temp_cache_dir: PathBuf::new(),
+         cache_size: None,
+         temp_touch_mode: false,
+         temp_climb_alerts: ClimbAlerts::default(),
+         temp_simulation_physics: PhysicsModel::default(),
+         temp_simulation_variation: SpeedVariation::default(),
+         temp_units: Units::default(),
+         temp_proxy: ProxySettings::default(),
+         temp_broadcast_polling: BroadcastPolling::default(),
+         temp_calibration: TelemetryCalibration::default(),
+         temp_streetview_size: StreetViewSize::default(),
+         temp_streetview_delta: 0.0,
+         temp_live_server: LiveServerSettings::default(),
+         temp_weather: WeatherSettings::default(),
+         temp_notifications: Notifications::default(),
+         temp_speech: SpeechSettings::default(),
+         temp_fan_control: FanControl::default(),
+         temp_fan_token: String::new(),
+         temp_trainer: TrainerSettings::default(),
+         temp_discord: DiscordSettings::default(),
+         temp_proxy_password: String::new(),
+         overrides: Vec::new(),
+         pending_write: None,
+         settings_tab: SettingsTab::default(),
+         invalid_fields: Vec::new()
+      }
+   }
+}
+
+impl Settings
+//===========
+{
+   pub fn new() -> Self
+   {
+      Settings::default()
+   }
+
+   pub fn get_settings(&self) -> Result<Settings, String>
+   //-------------------------------------------
+   {
+      self.migrate_json_settings();
+      let _settings_dir = match self.get_settings_path()
+      {
+         Ok(pb) => pb,
+         Err(e) =>
+         {
+            let errmsg = format!("Error getting settings path: {}", e);
+            log_error!("{errmsg}");
+            return Err(errmsg);
+         }
+      };
+      let mut settings_path = match self.get_settings_path()
+      {
+         Ok(p) => p,
+         Err(_e) =>
+         {
+            match self.write_default_settings()
+            {
+               Ok(pp) => pp,
+               Err(e) =>
+               {
+                  let errmsg = format!("Error creating default settings: {}", e);
+                  return Err(errmsg);
+               }
+            }
+         }
+      };
+
+      if ! settings_path.exists()
+      {
+         settings_path = match self.write_default_settings()
+         {
+            Ok(pp) => pp,
+            Err(e) =>
+            {
+               log_error!("Error creating default settings: {}", e);
+               PathBuf::new()
+            }
+         };
+      }
+      let mut settings = self.read_settings();
+      settings.migrate_streetview_api_key();
+      Ok(settings)
+   }
+
+   /// Re-encrypt a Street View API key written with the old built-in key using the machine derived key.
+   fn migrate_streetview_api_key(&mut self)
+   //--------------------------------------
+   {
+      let Ok(encrypted_bytes) = hex::decode(&self.streetview_api_key) else { return; };
+      if encrypted_bytes.is_empty() || ut::decrypt(&encrypted_bytes).is_ok()
+      {
+         return;
+      }
+      match ut::decrypt_legacy(&encrypted_bytes)
+      {
+         | Ok(api_key) =>
+         {
+            match self.set_streetview_api_key(&api_key)
+            {
+               | Ok(_) => log_info!("Migrated the Street View API key to the machine specific encryption key."),
+               | Err(e) => log_error!("Failed to migrate the Street View API key: {e}"),
+            }
+         }
+         | Err(_) => log_warn!("The Street View API key could not be decrypted; it may have been encrypted on another machine \
+                                or by another user. Re-enter it in the settings."),
+      }
+   }
+
+   pub fn get_settings_or_default(&self) -> Settings
+   //-------------------------------------------
+   {
+      match self.get_settings()
+      {
+         Ok(s) => s,
+         Err(_) => Settings::default(),
+      }
+   }
+
+   /// Write the settings file, unless it already holds these settings.
+   pub(crate) fn write_settings(&self) -> Result<PathBuf, std::io::Error>
+   //-----------------------------------------------------------------------
+   {
+      let mut config_file = self.get_config_path()?;
+      config_file.push(SETTINGS_FILE);
+      let text = self.to_documented_toml()?;
+      // Compared decrypted as every encryption of the same settings differs
+      let is_same_format = |existing: &str| if self.encrypt_settings { existing.starts_with(encryption_header()) }
+                                            else { !is_encrypted(existing) };
+      if std::fs::read_to_string(&config_file).is_ok_and(|existing| is_same_format(&existing)
+                                                                      && decode_settings(&existing).is_ok_and(|e| e == text))
+      {
+         return Ok(config_file);
+      }
+      let text = if self.encrypt_settings { encode_settings(&text)? } else { text };
+      let mut file = File::create(&config_file)?;
+      file.write_all(text.as_bytes())?;
+      log_info!("Wrote settings to {}", config_file.display());
+      Ok(config_file)
+   }
+
+   /// Write the settings file after SETTINGS_WRITE_DELAY without further changes (see `flush`), for settings that
+   /// change often such as the last directory or window geometry.
+   fn write_later(&mut self) { self.pending_write = Some(Instant::now()); }
+
+   /// Write changes made with `write_later` once they have settled, or straight away when `is_forced` (e.g. on exit).
+   /// Returns the time left before the pending write, None when nothing is pending.
+   pub(crate) fn flush(&mut self, is_forced: bool) -> Option<Duration>
+   //-----------------------------------------------------------------
+   {
+      let changed = self.pending_write?;
+      if !is_forced && changed.elapsed() < SETTINGS_WRITE_DELAY
+      {
+         return Some(SETTINGS_WRITE_DELAY.saturating_sub(changed.elapsed()));
+      }
+      self.pending_write = None;
+      if let Err(e) = self.write_settings()
+      {
+         log_error!("Failed to write settings file: {}", e);
+      }
+      None
+   }
+
+   /// The settings as TOML without the secrets, for crash reports.
+   pub(crate) fn redacted_toml(&self) -> String
+   //------------------------------------------
+   {
+      let mut table = match toml::Table::try_from(self)
+      {
+         | Ok(table) => table,
+         | Err(e) => return format!("(could not be written: {e})\n"),
+      };
+      redact_secrets(&mut table);
+      toml::to_string_pretty(&table).unwrap_or_else(|e| format!("(could not be written: {e})\n"))
+   }
+
+   /// The settings as TOML with a comment describing each setting.
+   fn to_documented_toml(&self) -> Result<String, std::io::Error>
+   //------------------------------------------------------------
+   {
+      let mut table = toml::Table::try_from(self).map_err(std::io::Error::other)?;
+      for (key, value, original) in &self.overrides
+      {
+         if get_setting(&table, key) == Some(value)
+         {
+            set_setting(&mut table, key, original.clone()).map_err(std::io::Error::other)?;
+         }
+      }
+      let body = toml::to_string_pretty(&table).map_err(std::io::Error::other)?;
+      let mut text = String::from(SETTINGS_HEADER);
+      let mut is_in_table = false;
+      for line in body.lines()
+      {
+         let key = if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+         {
+            is_in_table = true;
+            Some(header)
+         }
+         else if !is_in_table
+         {
+            line.split_once(" = ").map(|(key, _)| key)
+         }
+         else
+         {
+            None
+         };
+         if let Some((_, doc)) = key.and_then(|key| SETTING_DOCS.iter().find(|(name, _)| *name == key))
+         {
+            text.push('\n');
+            for doc_line in doc.lines()
+            {
+               text.push_str("# ");
+               text.push_str(doc_line.trim());
+               text.push('\n');
+            }
+         }
+         text.push_str(line);
+         text.push('\n');
+      }
+      Ok(text)
+   }
+
+   pub fn get_streetview_api_key(&self) -> Result<String, String>
+   //--------------------------------------
+   {
+      let encrypted_bytes = match hex::decode(&self.streetview_api_key)
+      {
+         Ok(bytes) => bytes,
+         Err(e) =>
+         {
+            let errmsg = format!("Failed to hex decode encrypted password: {}", e);
+            // self.toast_manager.error(errmsg);
+            return Err(errmsg);
+         }
+      };
+      if encrypted_bytes.is_empty()
+      {
+         return Err("Street View API key is not set.".to_string());
+      }
+      {
+         match ut::decrypt(&encrypted_bytes)
+         {
+            | Ok(decrypted_key) =>
+            {
+               Ok(decrypted_key)
+            }
             | Err(e) =>
             {
-               eprintln!("Failed to write settings file: {}", e);
-               return false;
+               let errmsg = format!("Failed to decrypt Street View API key: {}", e);
+               log_error!("{errmsg}");
+               // self.toast_manager.error(errmsg);
+               Err(errmsg)
+            }
+         }
+      }
+   }
+
+   fn set_streetview_api_key_from_tmp(&mut self) -> Result<(), String>
+   //----------------------------------------------------------------
+   {
+      if self.get_streetview_api_key().is_ok_and(|api_key| api_key == self.temp_api_key)
+      {  // Unchanged, and encrypting it again would still change the settings file
+         return Ok(());
+      }
+      match ut::encrypt(&self.temp_api_key)
+      {
+         | Ok(encrypted_data) =>
+         {
+            // Written with the rest of the dialog settings
+            self.streetview_api_key = hex::encode(encrypted_data);
+            Ok(())
+         }
+         | Err(e) =>
+         {
+            let errmsg = format!("Failed to encrypt Street View API key: {}", e);
+            log_error!("{errmsg}");
+            // self.toast_manager.error(errmsg);
+            Err(errmsg)
+         }
+      }
+   }
+
+   pub fn set_streetview_api_key(&mut self, api_key: &str) -> Result<(), String>
+   //-------------------------------------------------------
+   {
+      match ut::encrypt(api_key)
+      {
+         | Ok(encrypted_data) =>
+         {
+            self.streetview_api_key = hex::encode(encrypted_data);
+            match self.write_settings()
+            {
+               | Ok(_) => (),
+               | Err(e) =>
+               {
+                  let errmsg = format!("Failed to write settings file: {}", e);
+                  log_error!("{errmsg}");
+                  return Err(errmsg);
+               }
+            }
+            Ok(())
+         }
+         | Err(e) =>
+         {
+            let errmsg = format!("Failed to encrypt Street View API key: {}", e);
+            log_error!("{errmsg}");
+            // self.toast_manager.error(errmsg);
+            Err(errmsg)
+         }
+      }
+   }
+
+   /// Remove the Street View API key and write the settings.
+   pub fn clear_streetview_api_key(&mut self) -> Result<(), String>
+   //--------------------------------------------------------------
+   {
+      self.streetview_api_key.clear();
+      self.write_settings().map_err(|e| format!("Failed to write settings file: {}", e))?;
+      Ok(())
+   }
+
+   pub fn set_last_directory(&mut self, path: &str) -> bool
+   //-------------------------------------------
+   {
+      let path = PathBuf::from(path);
+      if path.is_dir()
+      {
+         if self.last_directory != path
+         {
+            self.last_directory = path;
+            self.write_later();
+         }
+         return true;
+      }
+      log_error!("{} is not a directory", path.display());
+      false
+   }
+
+   pub fn set_last_directorybuf(&mut self, path: &PathBuf) -> bool
+   //-------------------------------------------
+   {
+      if path.is_dir()
+      {
+         if self.last_directory != *path
+         {
+            self.last_directory = path.clone();
+            self.write_later();
+         }
+         return true;
+      }
+      log_error!("{} is not a directory", path.display());
+      false
+   }
+
+   /// Chroma-key green used as the streaming overlay background.
+   pub fn default_overlay_background() -> Color32 { Color32::from_rgb(0, 255, 0) }
+
+   /// Default folder scanned by the course library (Documents/GPXAssist/Courses).
+   pub fn default_courses_directory() -> PathBuf
+   {
+      dirs::document_dir().unwrap_or_else(Settings::get_home_dir).join(PROGRAM).join("Courses")
+   }
+
+   /// Default folder for screenshots of the view (Pictures/GPXAssist).
+   pub fn default_screenshot_directory() -> PathBuf
+   {
+      dirs::picture_dir().unwrap_or_else(Settings::get_home_dir).join(PROGRAM)
+   }
+
+   pub fn default_course_stats() -> bool { true }
+
+   pub fn default_turn_indicator() -> bool { true }
+
+   pub fn default_cache_directory() -> PathBuf
+   {
+      dirs::cache_dir().unwrap_or_else(env::temp_dir).join(PROGRAM)
+   }
+
+   /// `name` (one of `CACHE_ENTRIES`) inside the cache directory, creating the cache directory if needed.
+   pub(crate) fn cache_path(&self, name: &str) -> PathBuf
+   //----------------------------------------------------
+   {
+      if let Err(e) = std::fs::create_dir_all(&self.cache_directory)
+      {
+         log_warn!("Could not create cache directory {}: {}", self.cache_directory.display(), e);
+      }
+      self.cache_directory.join(name)
+   }
+
+   /// Bytes used by the GPXAssist cache entries in `directory`.
+   fn cache_size(directory: &Path) -> u64
+   //------------------------------------
+   {
+      CACHE_ENTRIES.iter().map(|entry| ut::disk_usage(&directory.join(entry))).sum()
+   }
+
+   /// Delete the GPXAssist cache entries in `directory`. Only known entries are removed in case the cache directory
+   /// was set to a folder holding other files.
+   fn clear_cache(directory: &Path) -> Result<(), String>
+   //----------------------------------------------------
+   {
+      for entry in CACHE_ENTRIES
+      {
+         let path = directory.join(entry);
+         let result = if path.is_dir() { std::fs::remove_dir_all(&path) } else if path.exists() { std::fs::remove_file(&path) } else { Ok(()) };
+         result.map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+      }
+      Ok(())
+   }
+
+   /// The palette currently selected in the settings.
+   pub fn active_theme(&self) -> Theme { Theme::for_kind(self.theme, &self.custom_theme) }
+
+   /// Save the outer position and inner size of the main window so it can be restored on the next start.
+   pub fn set_window_geometry(&mut self, position: [f32; 2], size: [f32; 2]) -> bool
+   //-------------------------------------------------------------------------------
+   {
+      if size[0] < 100.0 || size[1] < 100.0
+      {
+         return false;
+      }
+      if self.window_position != Some(position) || self.window_size != Some(size)
+      {
+         self.window_position = Some(position);
+         self.window_size = Some(size);
+         self.write_later();
+      }
+      true
+   }
+
+   /// Save the pacing plan inputs.
+   pub fn set_pacing(&mut self, pacing: PacingSettings)
+   //--------------------------------------------------
+   {
+      if self.pacing != pacing
+      {
+         self.pacing = pacing;
+         self.write_later();
+      }
+   }
+
+   /// Save the toolbar layout and collapsed state.
+   pub fn set_toolbar(&mut self, items: &[(ToolbarItem, bool)], is_collapsed: bool) -> bool
+   //-------------------------------------------------------------------------------------
+   {
+      if self.toolbar_items != items || self.toolbar_collapsed != is_collapsed
+      {
+         self.toolbar_items = items.to_vec();
+         self.toolbar_collapsed = is_collapsed;
+         self.write_later();
+      }
+      true
+   }
+
+   pub fn get_last_directory(&self) -> String
+   //-------------------------------------------
+   {
+      self.last_directory.display().to_string()
+   }
+
+   pub fn get_last_directorybuf(&self) -> PathBuf
+   //-------------------------------------------
+   {
+      self.last_directory.clone()
+   }
+
+
+   /// Get OS specific path to the config directory for the program
+   pub fn get_config_path(&self) -> Result<PathBuf, std::io::Error>
+   //-----------------------------------------------------------------------------------------
+   {
+      match dirs::config_dir()
+      {
+         Some(p) =>
+         {
+            let pp = p.join(PROGRAM);
+            if ! pp.exists()
+            {
+               match std::fs::create_dir_all(pp.as_path())
+               {
+                  Ok(_) => (),
+                  Err(e) =>
+                  {
+                     return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create config directory {}: {}", pp.display(), e)));
+                  }
+               }
+            }
+            Ok(pp)
+         },
+         None =>
+         {
+            let mut config_path = Settings::get_home_dir();
+
+            if env::consts::OS == "windows"
+            {
+               config_path.push("Application Data/Local Settings/");
+            }
+            else if env::consts::OS == "macos" // No config dir ?
+            {
+               //config_path.push("Library/Application Support/");
+            }
+            else
+            {
+               config_path.push(".config/");
+            }
+            config_path.push(PROGRAM);
+            if config_path.exists() && ! config_path.is_dir()
+            {
+               return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Config path {} exists and is not a directory", config_path.display())));
+            }
+            if !config_path.exists()
+            {
+               std::fs::create_dir_all(config_path.as_path())?;
+            }
+            Ok(config_path)
+         }
+      }
+   }
+
+
+   /// Get the path to the settings file for the program.
+   pub fn get_settings_path(&self) -> Result<PathBuf, std::io::Error>
+   //-------------------------------------------------------------------
+   {
+      let mut config_path = match self.get_config_path()
+      {
+         Ok(p) => p,
+         Err(e) =>
+         {
+            log_error!("Error getting settings path: {}", e);
+            return Err(e);
+         }
+      };
+      config_path.push(SETTINGS_FILE);
+      Ok(config_path)
+   }
+
+   fn write_default_settings(&self) -> Result<PathBuf, std::io::Error>
+   //-----------------------------------------------------------------------
+   {
+      let settings = Settings::default();
+      let mut config_file = self.get_config_path()?;
+      config_file.push(SETTINGS_FILE);
+      let mut file = File::create(&config_file)?;
+      file.write_all(settings.to_documented_toml()?.as_bytes())?;
+      Ok(config_file)
+   }
+
+
+   fn read_settings(&self) -> Settings
+   //-----------------------------------------------------------------
+   {
+      let mut config_file = match self.get_config_path()
+      {
+         Ok(p) => p,
+         Err(e) =>
+         {
+            log_error!("Error getting settings path: {}", e);
+            return Settings::default();
+         }
+      };
+      config_file.push(SETTINGS_FILE);
+      if !config_file.exists()
+      {
+         return Settings::default();
+      }
+      match Settings::load_settings_file(&config_file)
+      {
+         Ok(s) => s,
+         Err(e) =>
+         {
+            log_error!("{e}");
+            // Keep the unreadable file (e.g. encrypted on another machine) as the defaults will be written over it
+            let backup = config_file.with_extension("toml.bak");
+            match std::fs::copy(&config_file, &backup)
+            {
+               | Ok(_) => log_warn!("Kept the unreadable settings file as {}", backup.display()),
+               | Err(e) => log_error!("Could not back up the settings file to {}: {}", backup.display(), e),
+            }
+            Settings::default()
+         }
+      }
+   }
+
+   fn load_settings_file(config_file: &Path) -> Result<Settings, String>
+   //-------------------------------------------------------------------
+   {
+      let text = std::fs::read_to_string(config_file).map_err(|e| format!("Error opening settings file {}: {}", config_file.display(), e))?;
+      let text = decode_settings(&text).map_err(|e| format!("Error decrypting settings {}: {}", config_file.display(), e))?;
+      toml::from_str(&text).map_err(|e| format!("Error reading settings {}: {}", config_file.display(), e))
+   }
+
+   /// Modification time of the settings file, used to notice edits made outside GPXAssist.
+   pub(crate) fn settings_modified(&self) -> Option<SystemTime>
+   //----------------------------------------------------------
+   {
+      self.get_settings_path().ok().and_then(|path| std::fs::metadata(path).ok()).and_then(|m| m.modified().ok())
+   }
+
+   /// Read the settings file again after an external edit. Returns None when the file matches these settings (e.g. it
+   /// was just written by GPXAssist itself), and an error rather than defaults when the edited file doesn't parse.
+   pub(crate) fn reload(&self) -> Result<Option<Settings>, String>
+   //-------------------------------------------------------------
+   {
+      let path = self.get_settings_path().map_err(|e| e.to_string())?;
+      let mut settings = Settings::load_settings_file(&path)?;
+      if settings.to_documented_toml().ok() == self.to_documented_toml().ok()
+      {
+         return Ok(None);
+      }
+      settings.migrate_streetview_api_key();
+      Ok(Some(settings))
+   }
+
+   /// Apply `KEY=VALUE` overrides from the command line for this run. Values are TOML (numbers, true/false, "strings"),
+   /// with unquoted text taken as a string; nested settings use dotted keys such as `climb_alerts.threshold=10`.
+   pub(crate) fn apply_overrides(&mut self, assignments: &[String]) -> Result<(), String>
+   //------------------------------------------------------------------------------------
+   {
+      let mut table = toml::Table::try_from(&*self).map_err(|e| e.to_string())?;
+      let mut overrides = Vec::new();
+      for assignment in assignments
+      {
+         let (key, value) = assignment.split_once('=').ok_or_else(|| format!("Expected KEY=VALUE but found '{assignment}'"))?;
+         let (key, value) = (key.trim(), value.trim());
+         let value = toml::from_str::<toml::Table>(&format!("value = {value}")).ok()
+                        .and_then(|mut t| t.remove("value"))
+                        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+         let original = set_setting(&mut table, key, Some(value.clone()))?;
+         overrides.push((key.to_string(), value, original));
+      }
+      let mut settings: Settings = table.clone().try_into().map_err(|e: toml::de::Error| e.message().to_string())?;
+      let check = toml::Table::try_from(&settings).map_err(|e| e.to_string())?;
+      for (key, value, _) in &mut overrides
+      {
+         // A whole number given for a float setting comes back as a float, which is kept so the override is
+         // recognised when the settings are written
+         if let toml::Value::Integer(whole) = *value
+            && let Some(toml::Value::Float(float)) = get_setting(&check, key)
+            && whole as f64 == *float
+         {
+            *value = toml::Value::Float(*float);
+         }
+         if get_setting(&check, key) != Some(&*value)
+         {
+            return Err(format!("Unknown setting or invalid value '{key}'"));
+         }
+         log_info!("Setting {key} overridden with {value} for this run");
+      }
+      settings.overrides = overrides;
+      *self = settings;
+      Ok(())
+   }
+
+   /// Convert a settings.json written by earlier versions to the TOML settings file, keeping the old file as a backup.
+   fn migrate_json_settings(&self)
+   //-----------------------------
+   {
+      let Ok(config_path) = self.get_config_path() else { return; };
+      let (json_file, toml_file) = (config_path.join(LEGACY_SETTINGS_FILE), config_path.join(SETTINGS_FILE));
+      if toml_file.exists() || !json_file.exists()
+      {
+         return;
+      }
+      let settings: Settings = match File::open(&json_file).map_err(|e| e.to_string())
+                                         .and_then(|file| serde_json::from_reader(file).map_err(|e| e.to_string()))
+      {
+         Ok(s) => s,
+         Err(e) =>
+         {
+            log_error!("Error reading old settings file {}: {}", json_file.display(), e);
+            return;
+         }
+      };
+      match settings.write_settings()
+      {
+         Ok(path) =>
+         {
+            log_info!("Converted {} to {}", json_file.display(), path.display());
+            if let Err(e) = std::fs::rename(&json_file, json_file.with_extension("json.bak"))
+            {
+               log_warn!("Could not rename {}: {}", json_file.display(), e);
+            }
+         }
+         Err(e) => log_error!("Error writing settings file {}: {}", toml_file.display(), e),
+      }
+   }
+
+   pub fn open_settings_dialog(&mut self, assist: &mut GPXAssistUI)
+   //---------------------------------
+   {
+      // let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      // let settings_lock = settings.lock();
+
+      // if let Ok(api_key) = settings_lock.get_streetview_api_key()
+      // {
+      //    settings_lock.temp_api_key = api_key;
+      // }
+      // else
+      // {
+      //    settings_lock.temp_api_key.clear();
+      // }
+      self.temp_api_key = match self.get_streetview_api_key()
+      {
+         Ok(k) => k,
+         Err(_) => String::new(),
+      };
+
+      self.load_temp_values();
+      self.show_api_key = false;
+      self.api_key_test = None;
+
+      // Show the dialog
+      assist.show_settings_dialog = true;
+   }
+
+   /// Copy the saved settings into the values edited by the dialog.
+   fn load_temp_values(&mut self)
+   //----------------------------
+   {
+      self.temp_broadcast_dir = self.broadcast_directory.clone();
+      self.temp_gradient_length = self.gradient_length;
+      self.temp_gradient_offset = self.gradient_offset;
+      self.temp_flat_gradient = self.flat_gradient_percentage;
+      self.temp_extreme_gradient = self.extreme_gradient_percentage;
+      self.temp_gradient_patterns = self.gradient_patterns;
+      self.temp_vertical_exaggeration = self.vertical_exaggeration;
+      self.temp_theme = self.theme;
+      self.temp_custom_theme = self.custom_theme;
+      self.temp_overlay_background = self.overlay_background;
+      self.temp_overlay_transparent = self.overlay_transparent;
+      self.temp_check_for_updates = self.check_for_updates;
+      self.temp_encrypt_settings = self.encrypt_settings;
+      self.temp_courses_dir = self.courses_directory.clone();
+      self.temp_screenshot_dir = self.screenshot_directory.clone();
+      self.temp_course_stats = self.course_stats;
+      self.temp_turn_indicator = self.turn_indicator;
+      self.temp_surface_overlay = self.surface_overlay;
+      self.temp_rider_marker = self.rider_marker.clone();
+      self.temp_cache_dir = self.cache_directory.clone();
+      self.cache_size = Some(Settings::cache_size(&self.cache_directory));
+      self.temp_touch_mode = self.touch_mode;
+      self.temp_climb_alerts = self.climb_alerts;
+      self.temp_simulation_physics = self.simulation_physics;
+      self.temp_simulation_variation = self.simulation_variation;
+      self.temp_units = self.units;
+      self.temp_broadcast_polling = self.broadcast_polling;
+      self.temp_calibration = self.telemetry_calibration;
+      self.temp_streetview_size = self.streetview_size;
+      self.temp_streetview_delta = self.streetview_delta;
+      self.temp_live_server = self.live_server;
+      self.temp_weather = self.weather;
+      self.temp_notifications = self.notifications;
+      self.temp_speech = self.speech;
+      self.temp_fan_control = self.fan_control.clone();
+      self.temp_fan_token = self.fan_control.decrypted_token().unwrap_or_default();
+      self.temp_trainer = self.trainer.clone();
+      self.temp_discord = self.discord.clone();
+      self.temp_proxy = self.proxy.clone();
+      self.temp_proxy_password = self.proxy.decrypted_password().unwrap_or_default();
+   }
+
+   fn general_tab(&mut self, ui: &mut egui::Ui)
+   //------------------------------------------
+   {
+      egui::Grid::new("settings_general_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
+         {
+            ui.label("Units:");
+            egui::ComboBox::from_id_salt("units_combo")
+               .selected_text(self.temp_units.label())
+               .show_ui(ui, |ui|
+               {
+                  for units in [Units::Metric, Units::Imperial]
+                  {
+                     ui.selectable_value(&mut self.temp_units, units, units.label());
+                  }
+               });
+            if reset_button(ui)
+            {
+               self.temp_units = Units::default();
+            }
+            ui.end_row();
+
+            ui.label("Theme:");
+            egui::ComboBox::from_id_salt("theme_combo")
+               .selected_text(self.temp_theme.label())
+               .show_ui(ui, |ui|
+               {
+                  for kind in ThemeKind::ALL
+                  {
+                     ui.selectable_value(&mut self.temp_theme, kind, kind.label());
+                  }
+               });
+            if reset_button(ui)
+            {
+               self.temp_theme = ThemeKind::Dark;
+               self.temp_custom_theme = Theme::dark();
+            }
+            ui.end_row();
+
+            ui.label("Overlay Background:");
+            ui.horizontal(|ui|
+            {
+               ui.color_edit_button_srgba(&mut self.temp_overlay_background)
+                 .on_hover_text("Solid background colour used in streaming overlay mode (F9) for chroma keying");
+               ui.checkbox(&mut self.temp_overlay_transparent, "Transparent window")
+                 .on_hover_text("Use a transparent window background instead where supported (requires restart)");
+            });
+            if reset_button(ui)
+            {
+               self.temp_overlay_background = Settings::default_overlay_background();
+               self.temp_overlay_transparent = false;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Rider Marker:", SettingsField::RiderMarker);
+            ui.horizontal(|ui|
+            {
+               let marker = &mut self.temp_rider_marker;
+               egui::ComboBox::from_id_salt("marker_style_combo")
+                  .selected_text(marker.style.label())
+                  .show_ui(ui, |ui|
+                  {
+                     for style in MarkerStyle::ALL
+                     {
+                        ui.selectable_value(&mut marker.style, style, style.label());
+                     }
+                  });
+               ui.color_edit_button_srgba(&mut marker.color).on_hover_text("Marker colour (the border of an avatar)");
+               if marker.style == MarkerStyle::Avatar
+               {
+                  let mut avatar_string = marker.avatar.display().to_string();
+                  if ui.add_sized(egui::Vec2::new(300.0, 30.0), egui::TextEdit::singleline(&mut avatar_string))
+                       .on_hover_text("A small PNG image, e.g. your profile picture, shown at the rider position")
+                       .changed()
+                  {
+                     marker.avatar = PathBuf::from(avatar_string);
+                  }
+                  if ui.button("  📂  ").labelled("Browse").on_hover_text("Browse").clicked()
+                     && let Some(file) = rfd::FileDialog::new().add_filter("PNG image", &["png"]).pick_file()
+                  {
+                     marker.avatar = file;
+                  }
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_rider_marker = RiderMarker::default();
+            }
+            ui.end_row();
+
+            ui.label("Course stats:");
+            ui.checkbox(&mut self.temp_course_stats, "Show progress over the view")
+              .on_hover_text("Percent complete, ascent remaining and current altitude in the corner of every view mode");
+            if reset_button(ui)
+            {
+               self.temp_course_stats = Settings::default_course_stats();
+            }
+            ui.end_row();
+
+            ui.label("Turns:");
+            ui.checkbox(&mut self.temp_turn_indicator, "Show the next turn")
+              .on_hover_text("Arrow and countdown distance for the next change of direction over the Map and Street View");
+            if reset_button(ui)
+            {
+               self.temp_turn_indicator = Settings::default_turn_indicator();
+            }
+            ui.end_row();
+
+            ui.label("Surfaces:");
+            ui.checkbox(&mut self.temp_surface_overlay, "Show gravel and cobbles from OpenStreetMap")
+              .on_hover_text("Colours the route on the map and under the gradient profile by road surface and warns before \
+                              gravel or cobbled sectors. The course is sent to the public Overpass API and the result cached.");
+            if reset_button(ui)
+            {
+               self.temp_surface_overlay = false;
+            }
+            ui.end_row();
+
+            ui.label("Touch:");
+            ui.checkbox(&mut self.temp_touch_mode, "Touch-friendly controls")
+              .on_hover_text("Larger buttons, swipe left/right to change view and pinch to zoom the gradient profile");
+            if reset_button(ui)
+            {
+               self.temp_touch_mode = false;
+            }
+            ui.end_row();
+
+            ui.label("Updates:");
+            ui.checkbox(&mut self.temp_check_for_updates, "Check for a newer release on startup")
+              .on_hover_text("Queries the GitHub releases page when GPXAssist starts");
+            if reset_button(ui)
+            {
+               self.temp_check_for_updates = false;
+            }
+            ui.end_row();
+
+            ui.label("Encryption:");
+            ui.checkbox(&mut self.temp_encrypt_settings, "Encrypt the settings file")
+              .on_hover_text("Encrypt all the settings, including directories and integration tokens, with a key kept in the \
+                              system keyring (or derived from this machine and user without one). The file can no longer be \
+                              edited by hand or copied to another computer");
+            if reset_button(ui)
+            {
+               self.temp_encrypt_settings = false;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Weather:", SettingsField::Weather);
+            ui.horizontal(|ui|
+            {
+               let weather = &mut self.temp_weather;
+               ui.checkbox(&mut weather.is_enabled, "Show real weather")
+                 .on_hover_text("Temperature, wind and rain at the rider position from Open-Meteo, shown in the status bar");
+               ui.add_enabled_ui(weather.is_enabled, |ui|
+               {
+                  ui.add(egui::DragValue::new(&mut weather.refresh_minutes).range(5..=120).prefix("every ").suffix(" min"));
+                  ui.checkbox(&mut weather.is_wind_compared, "Compare with game wind")
+                    .on_hover_text("Also show the wind from the broadcast data");
+               });
+               ui.checkbox(&mut weather.is_panel_shown, "Wind panel")
+                 .on_hover_text("Show the in-game headwind, tailwind or crosswind (and the real weather when shown) over the view");
+            });
+            if reset_button(ui)
+            {
+               self.temp_weather = WeatherSettings::default();
+            }
+            ui.end_row();
+
+            ui.label("Notifications:");
+            ui.horizontal(|ui|
+            {
+               let notifications = &mut self.temp_notifications;
+               ui.checkbox(&mut notifications.is_enabled, "Desktop notifications")
+                 .on_hover_text("Notify milestones with the system notifications, which show even when GPXAssist is minimised");
+               ui.add_enabled_ui(notifications.is_enabled, |ui|
+               {
+                  ui.checkbox(&mut notifications.is_halfway, "Halfway");
+                  ui.checkbox(&mut notifications.is_final_stretch, "Final km/mile");
+                  ui.checkbox(&mut notifications.is_summit, "Summits");
+                  ui.checkbox(&mut notifications.is_personal_best, "Climb PBs")
+                    .on_hover_text("Climb times are kept for each course; simulated rides are not timed");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_notifications = Notifications::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Spoken cues:", SettingsField::Speech);
+            ui.horizontal(|ui|
+            {
+               let speech = &mut self.temp_speech;
+               ui.checkbox(&mut speech.is_enabled, "Speak")
+                 .on_hover_text("Announce climbs and progress with the system text-to-speech voice, so there is no need to \
+                                 look at the screen during hard efforts");
+               ui.add_enabled_ui(speech.is_enabled, |ui|
+               {
+                  ui.checkbox(&mut speech.is_climbs, "Climbs");
+                  ui.add_enabled(speech.is_climbs, egui::DragValue::new(&mut speech.warning_distance).range(100..=2000)
+                                                                                                    .speed(10)
+                                                                                                    .prefix("at ")
+                                                                                                    .suffix(" m"))
+                    .on_hover_text("Distance before the start of a climb it is announced");
+                  ui.checkbox(&mut speech.is_summits, "Summits");
+                  ui.checkbox(&mut speech.is_progress, "Halfway/final km");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_speech = SpeechSettings::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Courses Dir:", SettingsField::CoursesDir);
+            ui.horizontal(|ui|
+            {
+               let mut courses_string = self.temp_courses_dir.display().to_string();
+               if ui.add_sized(egui::Vec2::new(400.0, 30.0), egui::TextEdit::singleline(&mut courses_string))
+                    .on_hover_text("Folder scanned for GPX files by the course library")
+                    .changed()
+               {
+                  self.temp_courses_dir = PathBuf::from(courses_string);
+               }
+               if ui.button("  📂  ").labelled("Browse").on_hover_text("Browse").clicked()
+                  && let Some(selected_dir) = rfd::FileDialog::new().set_directory(&self.temp_courses_dir).pick_folder()
+               {
+                  self.temp_courses_dir = selected_dir;
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_courses_dir = Settings::default_courses_directory();
+            }
+            ui.end_row();
+
+            ui.label("Screenshots:");
+            ui.horizontal(|ui|
+            {
+               let mut screenshot_string = self.temp_screenshot_dir.display().to_string();
+               if ui.add_sized(egui::Vec2::new(400.0, 30.0), egui::TextEdit::singleline(&mut screenshot_string))
+                    .on_hover_text("Folder the camera button saves screenshots of the view to (created when needed)")
+                    .changed()
+               {
+                  self.temp_screenshot_dir = PathBuf::from(screenshot_string);
+               }
+               if ui.button("  📂  ").labelled("Browse").on_hover_text("Browse").clicked()
+                  && let Some(selected_dir) = rfd::FileDialog::new().set_directory(&self.temp_screenshot_dir).pick_folder()
+               {
+                  self.temp_screenshot_dir = selected_dir;
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_screenshot_dir = Settings::default_screenshot_directory();
+            }
+            ui.end_row();
+         });
+
+      if self.temp_theme == ThemeKind::Custom
+      {
+         ui.collapsing("Custom Colours", |ui|
+         {
+            egui::Grid::new("custom_theme_grid").num_columns(4).spacing([10.0, 6.0]).show(ui, |ui|
+            {
+               let theme = &mut self.temp_custom_theme;
+               ui.checkbox(&mut theme.dark_mode, "Dark base");
+               ui.checkbox(&mut theme.high_contrast, "High contrast outlines");
+               ui.end_row();
+               let mut colors: [(&str, &mut Color32); 8] = [("Toolbar", &mut theme.top_panel_fill),
+                                                            ("Buttons", &mut theme.button_fill),
+                                                            ("Active button", &mut theme.button_active_fill),
+                                                            ("Window", &mut theme.window_fill),
+                                                            ("Labels", &mut theme.label_color),
+                                                            ("Mode labels", &mut theme.mode_label_color),
+                                                            ("Gradient background", &mut theme.gradient_background),
+                                                            ("Gradient labels", &mut theme.gradient_label)];
+               for (i, (name, color)) in colors.iter_mut().enumerate()
+               {
+                  ui.label(*name);
+                  ui.color_edit_button_srgba(color);
+                  if i % 2 == 1
+                  {
+                     ui.end_row();
+                  }
+               }
+            });
+         });
+      }
+   }
+
+   fn streetview_tab(&mut self, ui: &mut egui::Ui)
+   //---------------------------------------------
+   {
+      egui::Grid::new("settings_streetview_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
+         {
+            self.field_label(ui, "Street View API Key:", SettingsField::ApiKey);
+            ui.horizontal(|ui|
+            {
+               ui.add_sized(Vec2::new(400.0, 30.0),
+                   egui::TextEdit::singleline(&mut self.temp_api_key)
+                  .hint_text("Enter your Google API key")
+                  .password(!self.show_api_key)
+                  // .desired_width(300.0)
+               ).on_hover_text("Enter your Google API key");
+
+               // Toggle button to show/hide API key
+               let button_text = if self.show_api_key { "  🙈  " } else { "  👁  " };
+               if ui.button(button_text).clicked() {
+                  self.show_api_key = !self.show_api_key;
+               }
+
+               let is_testing = self.api_key_test.as_ref().is_some_and(|result| result.lock().is_none());
+               if ui.add_enabled(!is_testing && !self.temp_api_key.trim().is_empty(), egui::Button::new("Test"))
+                    .on_hover_text("Check the key with a Street View metadata request (not billed)")
+                    .clicked()
+               {
+                  let result = Arc::new(parking_lot::Mutex::new(None));
+                  self.api_key_test = Some(result.clone());
+                  let api_key = self.temp_api_key.clone();
+                  let ctx = ui.ctx().clone();
+                  http::spawn(async move
+                  {
+                     *result.lock() = Some(test_streetview_api_key(&api_key).await);
+                     ctx.request_repaint();
+                  });
+               }
+            });
+            ui.end_row();
+
+            if let Some(result) = &self.api_key_test
+            {
+               ui.label("");
+               match &*result.lock()
+               {
+                  | None => { ui.spinner(); }
+                  | Some(Ok(message)) => { ui.label(egui::RichText::new(message).color(Color32::GREEN)); }
+                  | Some(Err(message)) => { ui.label(egui::RichText::new(message).color(Color32::RED)); }
+               }
+               ui.end_row();
+            }
+
+            self.field_label(ui, "Image Size:", SettingsField::StreetViewSize);
+            ui.horizontal(|ui|
+            {
+               let size = &mut self.temp_streetview_size;
+               ui.label("at most");
+               ui.add(egui::DragValue::new(&mut size.max_size).range(100..=2048).speed(5.0).suffix(" px"))
+                 .on_hover_text("Largest image side requested. The standard Street View Static API plan allows up to 640");
+               ui.checkbox(&mut size.is_upscaled, "Request smaller and upscale")
+                 .on_hover_text("Trade sharpness for bandwidth and quota by requesting a smaller image and stretching it to fit");
+               ui.add_enabled(size.is_upscaled, egui::DragValue::new(&mut size.scale_percent).range(20..=90).speed(1.0).suffix("%"))
+                 .on_hover_text("Requested size as a percentage of the Street View panel");
+            });
+            if reset_button(ui)
+            {
+               self.temp_streetview_size = StreetViewSize::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Refresh:", SettingsField::StreetViewDelta);
+            ui.horizontal(|ui|
+            {
+               let units = self.temp_units;
+               let is_separate = self.temp_streetview_delta > 0.0;
+               let mut is_checked = is_separate;
+               ui.checkbox(&mut is_checked, "Separate distance")
+                 .on_hover_text("Request Street View images less often than the map updates to save quota. When off the \
+                                 toolbar Refresh distance is used");
+               if is_checked != is_separate
+               {
+                  self.temp_streetview_delta = if is_checked { 200.0 } else { 0.0 };
+               }
+               ui.add_enabled(is_checked, length_drag_value(&mut self.temp_streetview_delta, units).range(0.0..=5000.0).speed(10.0))
+                 .on_hover_text("The distance to travel before requesting a new Street View image");
+            });
+            if reset_button(ui)
+            {
+               self.temp_streetview_delta = 0.0;
+            }
+            ui.end_row();
+         });
+   }
+
+   fn gradient_tab(&mut self, ui: &mut egui::Ui)
+   //-------------------------------------------
+   {
+      egui::Grid::new("settings_gradient_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
+         {
+            self.field_label(ui, "Gradient Length:", SettingsField::GradientLength);
+            ui.add_sized(
+               egui::Vec2::new(100.0, 30.0),
+               length_drag_value(&mut self.temp_gradient_length, self.temp_units)
+               .range(500.0..=10000.0)
+               .speed(10.0))
+               .on_hover_text("The length of the gradient section to display");
+            if reset_button(ui)
+            {
+               self.temp_gradient_length = 3000.0;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Gradient Offset:", SettingsField::GradientOffset);
+            ui.add_sized(
+               egui::Vec2::new(100.0, 30.0),
+               length_drag_value(&mut self.temp_gradient_offset, self.temp_units)
+               .range(100.0..=2000.0)
+               .speed(10.0))
+               .on_hover_text("The position within the gradient section where the rider currently is positioned");
+            if reset_button(ui)
+            {
+               self.temp_gradient_offset = 500.0;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Flat Gradient (%):", SettingsField::FlatGradient);
+            ui.add_sized(
+               egui::Vec2::new(100.0, 30.0),
+               egui::DragValue::new(&mut self.temp_flat_gradient)
+               .range(0.1..=2.0)
+               .speed(0.1)
+               .max_decimals(1))
+               .on_hover_text("The gradient considered to be 'flat', e.g if 0.5 then -0.5 to 0.5 is flat");
+            if reset_button(ui)
+            {
+               self.temp_flat_gradient = 0.5;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Extreme Gradient (%):", SettingsField::ExtremeGradient);
+            ui.add_sized(
+               egui::Vec2::new(100.0, 30.0),
+               egui::DragValue::new(&mut self.temp_extreme_gradient)
+               .range(10.0..=25.0)
+               .speed(0.5)
+               .max_decimals(1))
+               .on_hover_text("The gradient considered to be 'extreme' (black), e.g if > 16 then gradient color is black");
+            if reset_button(ui)
+            {
+               self.temp_extreme_gradient = 16.0;
+            }
+            ui.end_row();
+
+            ui.label("Patterns:");
+            ui.checkbox(&mut self.temp_gradient_patterns, "Hatch the gradient bands")
+              .on_hover_text("Lines downhill, vertical lines climbing, a grid when steep and dense lines at the extreme gradient, \
+                              for colourblind riders");
+            if reset_button(ui)
+            {
+               self.temp_gradient_patterns = false;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Vertical Exaggeration:", SettingsField::VerticalExaggeration);
+            ui.add_sized(
+               egui::Vec2::new(100.0, 30.0),
+               egui::DragValue::new(&mut self.temp_vertical_exaggeration)
+               .range(1.0..=50.0)
+               .speed(0.5)
+               .max_decimals(1))
+               .on_hover_text("Vertical exaggeration factor for elevation plot (1.0 = true scale, 10.0 = default, higher = more vertical stretch)");
+            if reset_button(ui)
+            {
+               self.temp_vertical_exaggeration = 10.0;
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Climb Alerts:", SettingsField::ClimbAlerts);
+            ui.horizontal(|ui|
+            {
+               let units = self.temp_units;
+               let alerts = &mut self.temp_climb_alerts;
+               ui.checkbox(&mut alerts.is_enabled, "Warn")
+                 .on_hover_text("Show a notification before climbs with sections steeper than the threshold");
+               ui.add_enabled_ui(alerts.is_enabled, |ui|
+               {
+                  ui.add(length_drag_value(&mut alerts.distance, units).range(50.0..=5000.0).speed(10.0))
+                    .on_hover_text("How far before the climb to warn");
+                  ui.label("before climbs over");
+                  ui.add(egui::DragValue::new(&mut alerts.threshold).range(1.0..=30.0).speed(0.1).suffix("%"))
+                    .on_hover_text("Maximum gradient (measured over 100m) that triggers a warning");
+                  ui.checkbox(&mut alerts.is_sound, "Sound");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_climb_alerts = ClimbAlerts::default();
+            }
+            ui.end_row();
+         });
+   }
+
+   fn broadcast_tab(&mut self, ui: &mut egui::Ui)
+   //--------------------------------------------
+   {
+      let (dir, dir_color, _) = self.broadcast_dir_status();
+      let mut dir_string = dir.display().to_string();
+      egui::Grid::new("settings_broadcast_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
+         {
+            self.field_label(ui, "Broadcast Dir:", SettingsField::BroadcastDir);
+            ui.horizontal(|ui|
+            {
+               let text_color = if dir_color == Color32::RED || dir_color == Color32::YELLOW
+               {
+                  Color32::BLACK
+               }
+               else
+               {
+                  Color32::WHITE
+               };
+               ui.style_mut().visuals.override_text_color = Some(text_color);
+               if ui.add_sized( egui::Vec2::new(400.0, 30.0), egui::TextEdit::singleline(&mut dir_string).background_color(dir_color)).changed()
+               {
+                  self.temp_broadcast_dir = PathBuf::from(dir_string);
+               }
+               if ui.button("  📂  ").labelled("Browse").on_hover_text("Browse").clicked()
+               {
+                  // let dialog_future = rfd::AsyncFileDialog::new().set_directory(home).pick_file();
+                  if let Some(selected_dir) = rfd::FileDialog::new().set_directory(&dir).pick_folder()
+                  {
+                     self.temp_broadcast_dir = selected_dir;
+                  }
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_broadcast_dir = get_broadcast_directory_or_default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Polling:", SettingsField::BroadcastPolling);
+            ui.horizontal(|ui|
+            {
+               let polling = &mut self.temp_broadcast_polling;
+               ui.label("read every");
+               ui.add(egui::DragValue::new(&mut polling.interval_ms).range(BroadcastPolling::INTERVAL_RANGE).speed(10.0).suffix(" ms"))
+                 .on_hover_text("How often the broadcast file is read when it cannot be watched for changes (a watched file is read \
+                                 as soon as the game writes it). Shorter intervals update the views sooner but use more CPU");
+               ui.label("retry after");
+               ui.add(egui::DragValue::new(&mut polling.retry_ms).range(BroadcastPolling::RETRY_RANGE).speed(10.0).suffix(" ms"))
+                 .on_hover_text("Delay before re-reading the file when it was caught part way through being written");
+            });
+            if reset_button(ui)
+            {
+               self.temp_broadcast_polling = BroadcastPolling::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Calibration:", SettingsField::TelemetryCalibration);
+            ui.horizontal(|ui|
+            {
+               let calibration = &mut self.temp_calibration;
+               ui.label("distance ×");
+               ui.add(egui::DragValue::new(&mut calibration.distance_scale).range(0.9..=1.1).speed(0.0001).max_decimals(4))
+                 .on_hover_text("Multiplies the game distance, e.g. 1.01 when the GPX course is 1% longer than the game course \
+                                 and the position falls further behind the landmarks as the ride goes on");
+               ui.label("+");
+               ui.add(egui::DragValue::new(&mut calibration.distance_offset).range(-5000.0..=5000.0).speed(1.0).suffix(" m"))
+                 .on_hover_text("Added to the scaled distance when the position is a constant distance ahead or behind");
+               ui.label("altitude +");
+               ui.add(egui::DragValue::new(&mut calibration.altitude_offset).range(-1000.0..=1000.0).speed(1.0).suffix(" m"))
+                 .on_hover_text("Added to the altitude reported by the game");
+            });
+            if reset_button(ui)
+            {
+               self.temp_calibration = TelemetryCalibration::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Live server:", SettingsField::LiveServer);
+            ui.horizontal(|ui|
+            {
+               let server = &mut self.temp_live_server;
+               ui.checkbox(&mut server.is_enabled, "Enable")
+                 .on_hover_text("Serve the position, gradient, telemetry and course as JSON on this computer for OBS scripts, \
+                                 home automation and dashboards");
+               ui.add_enabled(server.is_enabled, egui::DragValue::new(&mut server.port).range(1024..=65535).prefix("port "));
+               ui.add_enabled(server.is_enabled, egui::Checkbox::new(&mut server.is_lan, "Local network"))
+                 .on_hover_text("Also accept connections from other devices on the network, so a phone on the handlebars can \
+                                 show the companion display. Anyone on the network can read the live state");
+               if server.is_enabled
+               {
+                  ui.hyperlink_to("open", format!("http://127.0.0.1:{}/api/state", server.port));
+                  if server.is_lan
+                     && let Some(ip) = crate::server::lan_address()
+                  {
+                     ui.hyperlink_to(format!("companion: http://{ip}:{}/companion", server.port),
+                                     format!("http://{ip}:{}/companion", server.port));
+                  }
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_live_server = LiveServerSettings::default();
+            }
+            ui.end_row();
+         });
+   }
+
+   fn integrations_tab(&mut self, ui: &mut egui::Ui)
+   //-----------------------------------------------
+   {
+      egui::Grid::new("settings_integrations_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
+         {
+            self.field_label(ui, "Fan control:", SettingsField::FanControl);
+            ui.horizontal(|ui|
+            {
+               let fan = &mut self.temp_fan_control;
+               ui.checkbox(&mut fan.is_enabled, "Enable")
+                 .on_hover_text("Set a smart fan speed from the ride with a webhook or Home Assistant REST call");
+               ui.add_enabled_ui(fan.is_enabled, |ui|
+               {
+                  ui.label("from");
+                  egui::ComboBox::from_id_salt("fan_metric_combo")
+                     .selected_text(fan.metric.label())
+                     .show_ui(ui, |ui|
+                     {
+                        for metric in FanMetric::ALL
+                        {
+                           ui.selectable_value(&mut fan.metric, metric, metric.label());
+                        }
+                     });
+                  ui.add(egui::DragValue::new(&mut fan.hold_seconds).range(1..=120).prefix("hold ").suffix(" s"))
+                    .on_hover_text("How long a new fan level must be wanted before it is sent, so short efforts do not \
+                                    keep changing the fan");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_fan_control = FanControl::default();
+               self.temp_fan_token.clear();
+            }
+            ui.end_row();
+
+            ui.label("");
+            ui.add_enabled_ui(self.temp_fan_control.is_enabled, |ui|
+            {
+               ui.vertical(|ui|
+               {
+                  let fan = &mut self.temp_fan_control;
+                  ui.horizontal(|ui|
+                  {
+                     ui.add(egui::TextEdit::singleline(&mut fan.url).hint_text("URL").desired_width(340.0))
+                       .on_hover_text("Address POSTed to, e.g. http://homeassistant.local:8123/api/services/fan/set_percentage \
+                                       or a webhook");
+                     ui.add(egui::TextEdit::singleline(&mut self.temp_fan_token).hint_text("token").password(true)
+                                                                                .desired_width(120.0))
+                       .on_hover_text("Bearer token sent with the request, e.g. a Home Assistant long-lived access token. \
+                                       Leave empty if not needed");
+                  });
+                  ui.add(egui::TextEdit::multiline(&mut fan.body).code_editor().desired_rows(2).desired_width(470.0))
+                    .on_hover_text("JSON request body, {level} is replaced by the fan level from 0 to 100");
+                  let unit = fan.metric.unit();
+                  let mut removed = None;
+                  for (i, rule) in fan.rules.iter_mut().enumerate()
+                  {
+                     ui.horizontal(|ui|
+                     {
+                        ui.label("from");
+                        ui.add(egui::DragValue::new(&mut rule.threshold).range(-30.0..=2000.0).speed(1.0).suffix(format!(" {unit}")));
+                        ui.label("fan");
+                        ui.add(egui::DragValue::new(&mut rule.level).range(0..=100).suffix("%"));
+                        if ui.small_button("🗑").labelled("Remove this level").on_hover_text("Remove this level").clicked()
+                        {
+                           removed = Some(i);
+                        }
+                     });
+                  }
+                  if let Some(i) = removed
+                  {
+                     fan.rules.remove(i);
+                  }
+                  if ui.small_button("➕ Level").clicked()
+                  {
+                     let threshold = fan.rules.iter().map(|rule| rule.threshold).fold(0.0, f64::max);
+                     fan.rules.push(FanRule { threshold: threshold + 50.0, level: 100 });
+                  }
+               });
+            });
+            ui.end_row();
+
+            self.field_label(ui, "Smart trainer:", SettingsField::Trainer);
+            ui.horizontal(|ui|
+            {
+               let trainer = &mut self.temp_trainer;
+               ui.checkbox(&mut trainer.is_enabled, "Bluetooth FTMS")
+                 .on_hover_text("Send the course gradient to a smart trainer. Start the simulation to ride the course with the \
+                                 trainer power when the game is not running");
+               ui.add_enabled_ui(trainer.is_enabled, |ui|
+               {
+                  ui.add(egui::TextEdit::singleline(&mut trainer.device_name).hint_text("any trainer").desired_width(140.0))
+                    .on_hover_text("Part of the trainer's Bluetooth name, leave empty to use the first trainer found");
+                  ui.add(egui::DragValue::new(&mut trainer.difficulty).range(0..=100).prefix("difficulty ").suffix("%"))
+                    .on_hover_text("Percentage of the course gradient the trainer simulates");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_trainer = TrainerSettings::default();
+            }
+            ui.end_row();
+
+            self.field_label(ui, "Discord:", SettingsField::Discord);
+            ui.horizontal(|ui|
+            {
+               let discord = &mut self.temp_discord;
+               ui.checkbox(&mut discord.is_enabled, "Rich Presence")
+                 .on_hover_text("Show the course, distance completed and elapsed time on your Discord profile");
+               ui.add_enabled_ui(discord.is_enabled, |ui|
+               {
+                  ui.label("Application id:");
+                  ui.add(egui::TextEdit::singleline(&mut discord.application_id).desired_width(180.0))
+                    .on_hover_text("Id of an application created in the Discord developer portal; its name is shown as the activity");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_discord = DiscordSettings::default();
             }
-         }
-         return true;
-      }
-      eprintln!("{} is not a directory", path.display());
-      false
-   }
-
-   pub fn get_last_directory(&self) -> String
-   //-------------------------------------------
-   {
-      self.last_directory.display().to_string()
+            ui.end_row();
+         });
    }
 
-   pub fn get_last_directorybuf(&self) -> PathBuf
+   fn advanced_tab(&mut self, ui: &mut egui::Ui)
    //-------------------------------------------
    {
-      self.last_directory.clone()
-   }
-
-
-   /// Get OS specific path to the config directory for the program
-   pub fn get_config_path(&self) -> Result<PathBuf, std::io::Error>
-   //-----------------------------------------------------------------------------------------
-   {
-      match dirs::config_dir()
-      {
-         Some(p) =>
+      egui::Grid::new("settings_advanced_grid")
+         .num_columns(3)
+         .spacing([10.0, 10.0])
+         .striped(true)
+         .show(ui, |ui|
          {
-            let pp = p.join(PROGRAM);
-            if ! pp.exists()
+            self.field_label(ui, "Cache Dir:", SettingsField::CacheDir);
+            ui.horizontal(|ui|
             {
-               match std::fs::create_dir_all(pp.as_path())
+               let mut cache_string = self.temp_cache_dir.display().to_string();
+               if ui.add_sized(egui::Vec2::new(400.0, 30.0), egui::TextEdit::singleline(&mut cache_string))
+                    .on_hover_text("Folder for downloaded map tiles, Street View images and course library data \
+                                    (map tiles use the new folder after a restart)")
+                    .changed()
                {
-                  Ok(_) => (),
-                  Err(e) =>
+                  self.temp_cache_dir = PathBuf::from(cache_string);
+               }
+               if ui.button("  📂  ").labelled("Browse").on_hover_text("Browse").clicked()
+                  && let Some(selected_dir) = rfd::FileDialog::new().set_directory(&self.temp_cache_dir).pick_folder()
+               {
+                  self.temp_cache_dir = selected_dir;
+               }
+            });
+            if reset_button(ui)
+            {
+               self.temp_cache_dir = Settings::default_cache_directory();
+            }
+            ui.end_row();
+
+            ui.label("");
+            ui.horizontal(|ui|
+            {
+               let size = self.cache_size.unwrap_or(0);
+               ui.label(format!("{:.1} MB used", size as f64 / (1024.0 * 1024.0)));
+               if ui.add_enabled(size > 0, egui::Button::new("Clear cache")).clicked()
+               {
+                  if let Err(e) = Settings::clear_cache(&self.cache_directory)
                   {
-                     return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to create config directory {}: {}", pp.display(), e)));
+                     log_error!("{e}");
                   }
+                  self.cache_size = Some(Settings::cache_size(&self.cache_directory));
                }
-            }
-            Ok(pp)
-         },
-         None =>
-         {
-            let mut config_path = Settings::get_home_dir();
+            });
+            ui.end_row();
 
-            if env::consts::OS == "windows"
+            self.field_label(ui, "Proxy:", SettingsField::Proxy);
+            ui.horizontal(|ui|
             {
-               config_path.push("Application Data/Local Settings/");
+               let proxy = &mut self.temp_proxy;
+               ui.checkbox(&mut proxy.is_enabled, "Use")
+                 .on_hover_text("Connect through an HTTP proxy (map tiles use the new proxy after a restart). When off the \
+                                 HTTP_PROXY and HTTPS_PROXY environment variables are used if set");
+               ui.add_enabled_ui(proxy.is_enabled, |ui|
+               {
+                  ui.add(egui::TextEdit::singleline(&mut proxy.host).hint_text("host").desired_width(160.0));
+                  ui.add(egui::DragValue::new(&mut proxy.port).range(1..=65535).prefix("port "));
+                  ui.add(egui::TextEdit::singleline(&mut proxy.username).hint_text("user").desired_width(80.0))
+                    .on_hover_text("Leave empty if the proxy does not need a login");
+                  ui.add(egui::TextEdit::singleline(&mut self.temp_proxy_password).hint_text("password").password(true)
+                                                                                   .desired_width(80.0));
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_proxy = ProxySettings::default();
+               self.temp_proxy_password.clear();
             }
-            else if env::consts::OS == "macos" // No config dir ?
+            ui.end_row();
+
+            self.field_label(ui, "Simulation:", SettingsField::Simulation);
+            ui.horizontal(|ui|
             {
-               //config_path.push("Library/Application Support/");
+               let physics = &mut self.temp_simulation_physics;
+               ui.checkbox(&mut physics.is_enabled, "Physics")
+                 .on_hover_text("Simulate speed from power, weight and drag so the rider slows on climbs and speeds up on descents \
+                                 instead of using the constant toolbar speed");
+               ui.add_enabled(physics.is_enabled, egui::DragValue::new(&mut physics.power).range(50.0..=600.0).speed(1.0).suffix("W"))
+                 .on_hover_text("Rider power");
+               // Also used for the grade-adjusted speed so editable with the simulation disabled
+               ui.add(egui::DragValue::new(&mut physics.mass).range(30.0..=200.0).speed(0.5).suffix("kg"))
+                 .on_hover_text("Rider + bike mass");
+               ui.add(egui::DragValue::new(&mut physics.cda).range(0.15..=0.6).speed(0.005).max_decimals(3).prefix("CdA "))
+                 .on_hover_text("Drag coefficient × frontal area (m²): about 0.25 on the drops, 0.4 upright");
+               ui.add(egui::DragValue::new(&mut physics.rolling_resistance).range(0.002..=0.02).speed(0.0005).max_decimals(4).prefix("Crr "))
+                 .on_hover_text("Rolling resistance coefficient: about 0.004 for good road tyres");
+            });
+            if reset_button(ui)
+            {
+               self.temp_simulation_physics = PhysicsModel::default();
             }
-            else
+            ui.end_row();
+
+            ui.label("");
+            ui.horizontal(|ui|
             {
-               config_path.push(".config/");
+               let variation = &mut self.temp_simulation_variation;
+               ui.checkbox(&mut variation.is_enabled, "Vary speed")
+                 .on_hover_text("Randomly vary the simulated speed with surges, slowing on climbs (without physics) and brief \
+                                 stops, to test view refreshes under realistic riding");
+               ui.add_enabled_ui(variation.is_enabled, |ui|
+               {
+                  ui.add(egui::DragValue::new(&mut variation.amount).range(0.0..=50.0).speed(0.5).prefix("±").suffix("%"))
+                    .on_hover_text("How far the pace wanders from the set speed or power");
+                  ui.checkbox(&mut variation.is_stops, "Stops")
+                    .on_hover_text("Occasionally stop for 5-30 seconds");
+               });
+            });
+            if reset_button(ui)
+            {
+               self.temp_simulation_variation = SpeedVariation::default();
             }
-            config_path.push(PROGRAM);
-            if config_path.exists() && ! config_path.is_dir()
+            ui.end_row();
+         });
+   }
+
+   /// Apply the dialog values and write the settings file.
+   fn save_dialog(&mut self, assist: &mut GPXAssistUI)
+   //-------------------------------------------------
+   {
+      // let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      // let mut settings_lock = settings.lock();
+
+      // Save API key
+      if !self.temp_api_key.is_empty()
+      {
+         match self.set_streetview_api_key_from_tmp()
+         {
+            | Ok(_) =>
             {
-               return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Config path {} exists and is not a directory", config_path.display())));
+               // toast_manager.success("Settings saved successfully", Some(Duration::from_secs(3)));
+               assist.encrypted_api_key = Some(self.temp_api_key.clone());
+               assist.settings_dialog_message = "Settings saved successfully".to_string();
             }
-            if !config_path.exists()
+            | Err(e) =>
             {
-               std::fs::create_dir_all(config_path.as_path())?;
+               assist.settings_dialog_message = format!("Failed to save API key: {}", e);
+               //toast_manager.error(&format!("Failed to save API key: {}", e), None);
             }
-            Ok(config_path)
          }
       }
-   }
 
+      self.broadcast_directory = self.temp_broadcast_dir.clone();
 
-   /// Get the path to the settings file for the program.
-   pub fn get_settings_path(&self) -> Result<PathBuf, std::io::Error>
-   //-------------------------------------------------------------------
-   {
-      let mut config_path = match self.get_config_path()
+      // Update gradient settings
+      self.gradient_length = self.temp_gradient_length;
+      self.gradient_offset = self.temp_gradient_offset;
+      self.flat_gradient_percentage = self.temp_flat_gradient;
+      self.extreme_gradient_percentage = self.temp_extreme_gradient;
+      self.gradient_patterns = self.temp_gradient_patterns;
+      assist.is_gradient_patterned = self.gradient_patterns;
+      self.vertical_exaggeration = self.temp_vertical_exaggeration;
+      self.theme = self.temp_theme;
+      self.custom_theme = self.temp_custom_theme;
+      assist.theme = self.active_theme();
+      self.overlay_background = self.temp_overlay_background;
+      self.overlay_transparent = self.temp_overlay_transparent;
+      assist.overlay_background = self.overlay_background;
+      self.check_for_updates = self.temp_check_for_updates;
+      self.encrypt_settings = self.temp_encrypt_settings;
+      self.courses_directory = self.temp_courses_dir.clone();
+      self.screenshot_directory = self.temp_screenshot_dir.clone();
+      self.course_stats = self.temp_course_stats;
+      assist.course_stats.set_enabled(self.course_stats);
+      self.turn_indicator = self.temp_turn_indicator;
+      assist.turn_indicator.set_enabled(self.turn_indicator);
+      self.surface_overlay = self.temp_surface_overlay;
+      assist.surface_overlay.set_enabled(self.surface_overlay);
+      self.rider_marker = self.temp_rider_marker.clone();
+      assist.rider_marker = self.rider_marker.clone();
+      self.cache_directory = self.temp_cache_dir.clone();
+      self.broadcast_polling = self.temp_broadcast_polling;
+      self.streetview_size = self.temp_streetview_size;
+      self.streetview_delta = self.temp_streetview_delta;
+      assist.streetview_delta = self.streetview_delta;
+      assist.broadcast_polling.store(self.broadcast_polling);
+      self.telemetry_calibration = self.temp_calibration;
+      assist.telemetry_calibration.store(self.telemetry_calibration);
+      self.live_server = self.temp_live_server;
+      assist.configure_live_server(self.live_server);
+      self.weather = self.temp_weather;
+      assist.weather.configure(self.weather);
+      self.notifications = self.temp_notifications;
+      assist.milestones.configure(self.notifications);
+      self.speech = self.temp_speech;
+      assist.speech.configure(self.speech);
+      match self.temp_fan_control.set_token(&self.temp_fan_token)
       {
-         Ok(p) => p,
-         Err(e) =>
+         | Ok(_) =>
          {
-            eprintln!("Error getting settings path: {}", e);
-            return Err(e);
+            self.fan_control = self.temp_fan_control.clone();
+            assist.fan_controller.configure(self.fan_control.clone());
          }
-      };
-      config_path.push("settings.json");
-      Ok(config_path)
-   }
+         | Err(e) => log_error!("{e}"),
+      }
+      self.trainer = self.temp_trainer.clone();
+      assist.trainer.configure(self.trainer.clone());
+      self.discord = self.temp_discord.clone();
+      assist.discord.configure(self.discord.clone());
+      match self.temp_proxy.set_password(&self.temp_proxy_password)
+      {
+         | Ok(_) => self.proxy = self.temp_proxy.clone(),
+         | Err(e) => log_error!("{e}"),
+      }
+      self.touch_mode = self.temp_touch_mode;
+      assist.is_touch_mode = self.touch_mode;
+      self.climb_alerts = self.temp_climb_alerts;
+      assist.climb_alerter.configure(self.climb_alerts);
+      self.simulation_physics = self.temp_simulation_physics;
+      assist.rider_model = self.simulation_physics;
+      self.simulation_variation = self.temp_simulation_variation;
+      self.units = self.temp_units;
+      assist.units = self.units;
+      assist.is_first_gradient_frame = true;
 
-   fn write_default_settings(&self) -> Result<PathBuf, std::io::Error>
-   //-----------------------------------------------------------------------
-   {
-      let settings = Settings::default();
-      let mut config_file = self.get_config_path()?;
-      config_file.push("settings.json");
-      let mut file = File::create(&config_file)?;
-      let json = serde_json::to_string(&settings)?;
-      file.write_all(json.as_bytes())?;
-      // let file = File::create(&config_file)?;
-      // let mut writer = BufWriter::new(file);
-      // serde_json::to_writer(&mut writer, &settings)?;
-      Ok(config_file)
-   }
+      // Write settings to file
+      match self.write_settings()
+      {
+         | Ok(_) =>
+         {
+            assist.show_settings_dialog_err = false;
+         },
+         | Err(e) =>
+         {
+            assist.settings_dialog_message = format!("Failed to write settings: {}", e);
+            assist.show_settings_dialog_err = true;
+            // toast_manager.error(&format!("Failed to write settings: {}", e), None);
+         }
+      }
 
+      // Close dialog
+      assist.show_settings_dialog = false;
+   }
 
-   fn read_settings(&self) -> Settings
-   //-----------------------------------------------------------------
+   /// Check the dialog values, returning each invalid field with the reason.
+   fn validate(&self) -> Vec<(SettingsField, String)>
+   //------------------------------------------------
    {
-      let mut config_file = match self.get_config_path()
+      let mut invalid = Vec::new();
+      let mut check_range = |field: SettingsField, name: &str, value: f64, range: RangeInclusive<f64>, format: &dyn Fn(f64) -> String|
       {
-         Ok(p) => p,
-         Err(e) =>
+         if ! range.contains(&value)
          {
-            eprintln!("Error getting settings path: {}", e);
-            return Settings::default();
+            invalid.push((field, format!("{name} must be between {} and {}.", format(*range.start()), format(*range.end()))));
          }
       };
-      config_file.push("settings.json");
-      if !config_file.exists()
-      {
-         return Settings::default();
-      }
-      let file = match  File::open(&config_file)
+
+      let units = self.temp_units;
+      let length = |v: f64| units.format_length(v);
+      let percent = |v: f64| format!("{v}%");
+      let plain = |v: f64| format!("{v}");
+      check_range(SettingsField::GradientLength, "Gradient length", self.temp_gradient_length, 500.0..=10000.0, &length);
+      check_range(SettingsField::GradientOffset, "Gradient offset", self.temp_gradient_offset, 100.0..=2000.0, &length);
+      check_range(SettingsField::FlatGradient, "Flat gradient", self.temp_flat_gradient, 0.1..=2.0, &percent);
+      check_range(SettingsField::ExtremeGradient, "Extreme gradient", self.temp_extreme_gradient, 10.0..=25.0, &percent);
+      check_range(SettingsField::VerticalExaggeration, "Vertical exaggeration", self.temp_vertical_exaggeration, 1.0..=50.0, &plain);
+      check_range(SettingsField::ClimbAlerts, "Climb alert distance", self.temp_climb_alerts.distance, 50.0..=5000.0, &length);
+      check_range(SettingsField::ClimbAlerts, "Climb alert gradient", self.temp_climb_alerts.threshold, 1.0..=30.0, &percent);
+      let physics = &self.temp_simulation_physics;
+      check_range(SettingsField::Simulation, "Simulation power", physics.power, 50.0..=600.0, &|v| format!("{v}W"));
+      check_range(SettingsField::Simulation, "Simulation mass", physics.mass, 30.0..=200.0, &|v| format!("{v}kg"));
+      check_range(SettingsField::Simulation, "Simulation CdA", physics.cda, 0.15..=0.6, &plain);
+      check_range(SettingsField::Simulation, "Simulation Crr", physics.rolling_resistance, 0.002..=0.02, &plain);
+      let size = self.temp_streetview_size;
+      check_range(SettingsField::StreetViewSize, "Street View image size", size.max_size as f64, 100.0..=2048.0, &|v| format!("{v} px"));
+      check_range(SettingsField::StreetViewSize, "Street View upscaling", size.scale_percent as f64, 20.0..=90.0, &percent);
+      check_range(SettingsField::StreetViewDelta, "Street View refresh", self.temp_streetview_delta, 0.0..=5000.0, &length);
+      let polling = self.temp_broadcast_polling;
+      let milliseconds = |range: RangeInclusive<u64>| *range.start() as f64..=*range.end() as f64;
+      check_range(SettingsField::BroadcastPolling, "Polling interval", polling.interval_ms as f64,
+                  milliseconds(BroadcastPolling::INTERVAL_RANGE), &|v| format!("{v} ms"));
+      check_range(SettingsField::BroadcastPolling, "Retry interval", polling.retry_ms as f64,
+                  milliseconds(BroadcastPolling::RETRY_RANGE), &|v| format!("{v} ms"));
+      let calibration = self.temp_calibration;
+      check_range(SettingsField::TelemetryCalibration, "Distance scale", calibration.distance_scale, 0.9..=1.1, &plain);
+      check_range(SettingsField::TelemetryCalibration, "Distance offset", calibration.distance_offset, -5000.0..=5000.0,
+                  &|v| format!("{v} m"));
+      check_range(SettingsField::TelemetryCalibration, "Altitude offset", calibration.altitude_offset, -1000.0..=1000.0,
+                  &|v| format!("{v} m"));
+      check_range(SettingsField::LiveServer, "Live server port", self.temp_live_server.port as f64, 1024.0..=65535.0, &plain);
+      check_range(SettingsField::Weather, "Weather refresh", self.temp_weather.refresh_minutes as f64, 5.0..=120.0, &|v| format!("{v} min"));
+      check_range(SettingsField::Speech, "Climb announcement distance", self.temp_speech.warning_distance as f64, 100.0..=2000.0,
+                  &|v| format!("{v} m"));
+      check_range(SettingsField::Simulation, "Speed variation", self.temp_simulation_variation.amount, 0.0..=50.0, &percent);
+      check_range(SettingsField::FanControl, "Fan hold time", self.temp_fan_control.hold_seconds as f64, 1.0..=120.0, &|v| format!("{v} s"));
+      let fan = &self.temp_fan_control;
+      if fan.is_enabled
       {
-         Ok(f) => f,
-         Err(e) =>
+         if !reqwest::Url::parse(fan.url.trim()).is_ok_and(|url| url.scheme() == "http" || url.scheme() == "https")
          {
-            eprintln!("Error opening settings file: {}", e);
-            return Settings::default();
+            invalid.push((SettingsField::FanControl, "The fan control URL must be an http or https address.".to_string()));
          }
-      };
-      let settings: Settings = match serde_json::from_reader(file)
-      {
-         Ok(s) => s,
-         Err(e) =>
+         if !fan.body.trim().is_empty() && serde_json::from_str::<serde_json::Value>(&fan.body_for(0)).is_err()
          {
-            eprintln!("Error reading settings: {}", e);
-            Settings::default()
+            invalid.push((SettingsField::FanControl, "The fan control request body must be JSON (with {level} for the fan level).".to_string()));
          }
-      };
-      settings.clone()
+         if fan.rules.is_empty()
+         {
+            invalid.push((SettingsField::FanControl, "Fan control needs at least one level rule.".to_string()));
+         }
+      }
+      check_range(SettingsField::Trainer, "Trainer difficulty", self.temp_trainer.difficulty as f64, 0.0..=100.0, &percent);
+      if self.temp_rider_marker.style == MarkerStyle::Avatar && !self.temp_rider_marker.avatar.is_file()
+      {
+         invalid.push((SettingsField::RiderMarker, "The rider avatar must be an existing PNG image.".to_string()));
+      }
+      let application_id = self.temp_discord.application_id.trim();
+      if self.temp_discord.is_enabled && (application_id.is_empty() || !application_id.chars().all(|c| c.is_ascii_digit()))
+      {
+         invalid.push((SettingsField::Discord, "Discord Rich Presence needs the numeric id of a Discord application.".to_string()));
+      }
+      let mut proxy = self.temp_proxy.clone();
+      proxy.password.clear(); // only the address is checked, the password being edited is in temp_proxy_password
+      if let Err(e) = proxy.url()
+      {
+         invalid.push((SettingsField::Proxy, e));
+      }
+      if self.temp_gradient_offset >= self.temp_gradient_length
+      {
+         invalid.push((SettingsField::GradientOffset, "Gradient offset must be less than the gradient length.".to_string()));
+      }
+
+      let key = self.temp_api_key.trim();
+      if ! key.is_empty() && (key.len() < 30 || ! key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+      {
+         invalid.push((SettingsField::ApiKey, "The API key is not a valid Google API key (letters, digits, '-' and '_').".to_string()));
+      }
+      if ! self.temp_broadcast_dir.as_os_str().is_empty() && ! self.temp_broadcast_dir.is_dir()
+      {
+         invalid.push((SettingsField::BroadcastDir, format!("Broadcast directory {:?} does not exist.", self.temp_broadcast_dir)));
+      }
+      if ! self.temp_courses_dir.as_os_str().is_empty() && ! self.temp_courses_dir.is_dir()
+      {
+         invalid.push((SettingsField::CoursesDir, format!("Courses directory {:?} does not exist.", self.temp_courses_dir)));
+      }
+      if self.temp_cache_dir.as_os_str().is_empty()
+      {
+         invalid.push((SettingsField::CacheDir, "The cache directory is not set.".to_string()));
+      }
+      else if self.temp_cache_dir.exists() && ! self.temp_cache_dir.is_dir()
+      {
+         invalid.push((SettingsField::CacheDir, format!("Cache directory {:?} is not a directory.", self.temp_cache_dir)));
+      }
+      invalid
    }
 
-   pub fn open_settings_dialog(&mut self, assist: &mut GPXAssistUI)
-   //---------------------------------
+   /// A grid row label, shown in red with the reason on hover when `field` failed validation.
+   fn field_label(&self, ui: &mut egui::Ui, text: &str, field: SettingsField)
+   //-------------------------------------------------------------------------
    {
-      // let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-      // let settings_lock = settings.lock();
-
-      // if let Ok(api_key) = settings_lock.get_streetview_api_key()
-      // {
-      //    settings_lock.temp_api_key = api_key;
-      // }
-      // else
-      // {
-      //    settings_lock.temp_api_key.clear();
-      // }
-      self.temp_api_key = match self.get_streetview_api_key()
+      match self.invalid_fields.iter().find(|(f, _)| *f == field)
       {
-         Ok(k) => k,
-         Err(_) => String::new(),
-      };
-
-      self.temp_broadcast_dir = self.broadcast_directory.clone();
-      self.temp_gradient_length = self.gradient_length;
-      self.temp_gradient_offset = self.gradient_offset;
-      self.temp_flat_gradient = self.flat_gradient_percentage;
-      self.temp_extreme_gradient = self.extreme_gradient_percentage;
-      self.temp_vertical_exaggeration = self.vertical_exaggeration;
-      self.show_api_key = false;
+         | Some((_, reason)) => { ui.label(egui::RichText::new(text).color(Color32::RED)).on_hover_text(reason); }
+         | None => { ui.label(text); }
+      }
+   }
 
-      // Show the dialog
-      assist.show_settings_dialog = true;
+   /// The broadcast directory to show, its field colour and a warning when it is unset, missing or has no
+   /// broadcast file.
+   fn broadcast_dir_status(&self) -> (PathBuf, Color32, String)
+   //----------------------------------------------------------
+   {
+      if self.temp_broadcast_dir.display().to_string().trim().is_empty()
+      {
+         (get_broadcast_directory_or_default(), Color32::YELLOW, "WARN: Broadcast directory is not set.".to_string())
+      }
+      else if ! self.temp_broadcast_dir.exists()
+      {
+         (self.temp_broadcast_dir.clone(), Color32::RED, format!("Directory {:?} does not exist.", self.temp_broadcast_dir))
+      }
+      else if ! self.temp_broadcast_dir.is_dir()
+      {
+         (self.temp_broadcast_dir.clone(), Color32::RED, format!("Directory {:?} is not a directory.", self.temp_broadcast_dir))
+      }
+      else
+      {
+         let file_path = self.temp_broadcast_dir.join("focus.json");
+         if ! file_path.exists() || ! file_path.is_file()
+         {
+            (self.temp_broadcast_dir.clone(), Color32::YELLOW, format!("WARN: Broadcast file {:?} not found.", file_path))
+         }
+         else
+         {
+            (self.temp_broadcast_dir.clone(), Color32::GREEN, String::new())
+         }
+      }
    }
 
    pub fn show_settings_dialog(&mut self, assist: &mut GPXAssistUI, ctx: &Context)
@@ -462,8 +2937,11 @@ impl Settings
          return;
       }
 
-      let mut status_message: String = String::default();
-      let mut status_color = Color32::GREEN;
+      if ! self.invalid_fields.is_empty()
+      {  // Recheck so fields stop being highlighted once corrected
+         self.invalid_fields = self.validate();
+      }
+      let (_, status_color, status_message) = self.broadcast_dir_status();
       egui::Window::new("Settings")
          .collapsible(false)
          .resizable(false)
@@ -471,160 +2949,35 @@ impl Settings
          .show(ctx, |ui| {
             ui.set_min_width(500.0);
 
-            egui::Grid::new("settings_grid")
-               .num_columns(2)
-               .spacing([10.0, 10.0])
-               .striped(true)
-               .show(ui, |ui|
+            ui.horizontal(|ui|
+            {
+               for tab in SettingsTab::ALL
                {
-                  ui.label("Street View API Key:");
-                  ui.horizontal(|ui|
-                  {
-                     ui.add_sized(Vec2::new(400.0, 30.0),
-                         egui::TextEdit::singleline(&mut self.temp_api_key)
-                        .hint_text("Enter your Google API key")
-                        .password(!self.show_api_key)
-                        // .desired_width(300.0)
-                     ).on_hover_text("Enter your Google API key");
-
-                     // Toggle button to show/hide API key
-                     let button_text = if self.show_api_key { "  🙈  " } else { "  👁  " };
-                     if ui.button(button_text).clicked() {
-                        self.show_api_key = !self.show_api_key;
-                     }
-                  });
-                  ui.end_row();
-
-                  let mut dir_color = Color32::GREEN;
-                  let mut dir =
-                  if self.temp_broadcast_dir.display().to_string().trim().is_empty()
-                  {
-                     dir_color = Color32::YELLOW;
-                     status_color = Color32::YELLOW;
-                     status_message = "WARN: Broadcast directory is not set.".to_string();
-                     // self.temp_broadcast_dir.clone()
-                     get_broadcast_directory_or_default()
-                  }
-                  else if ! self.temp_broadcast_dir.exists()
-                  {
-                     dir_color = Color32::RED;
-                     status_color = Color32::RED;
-                     status_message = format!("Directory {:?} does not exist.", self.temp_broadcast_dir);
-                     self.temp_broadcast_dir.clone()
-                     // get_broadcast_directory_or_default()
-                  }
-                  else
-                  {
-                     if ! self.temp_broadcast_dir.is_dir()
-                     {
-                        dir_color = Color32::RED;
-                        status_color = Color32::RED;
-                        status_message = format!("Directory {:?} is not a directory.", self.temp_broadcast_dir);
-                        // PathBuf::new()
-                        self.temp_broadcast_dir.clone()
-                     }
-                     else
-                     {
-                        let file_path = self.temp_broadcast_dir.join("focus.json");
-                        if ! file_path.exists() || ! file_path.is_file()
-                        {
-                           dir_color = Color32::YELLOW;
-                           status_color = Color32::YELLOW;
-                           status_message = format!("WARN: Broadcast file {:?} not found.", file_path);
-                           // PathBuf::new()
-                        }
-                        else
-                        {
-                           status_message = "".to_string();
-                           // self.temp_broadcast_dir.clone()
-                        }
-                        self.temp_broadcast_dir.clone()
-                     }
-                  };
-                  let mut dir_string = dir.display().to_string();
-
-                  ui.label("Broadcast Dir:");
-                  ui.horizontal(|ui|
-                  {
-                     let text_color = if dir_color == Color32::RED || dir_color == Color32::YELLOW
-                     {
-                        Color32::BLACK
-                     }
-                     else
-                     {
-                        Color32::WHITE
-                     };
-                     ui.style_mut().visuals.override_text_color = Some(text_color);
-                     ui.add_sized( egui::Vec2::new(400.0, 30.0), egui::TextEdit::singleline(&mut dir_string).background_color(dir_color));
-                     if ui.button("  📂  ").clicked()
-                     {
-                        // let dialog_future = rfd::AsyncFileDialog::new().set_directory(home).pick_file();
-                        if let Some(selected_dir) = rfd::FileDialog::new().set_directory(&dir).pick_folder()
-                        {
-                           self.temp_broadcast_dir = selected_dir;
-                        }
-                     }
-                  });
-                  ui.end_row();
-
-                  // if ! status_message.is_empty()
-                  // {
-                  //    ui.horizontal(|ui| { ui.label(egui::RichText::new(&status_message).color(dir_color).text_style(egui::TextStyle::Small)); });
-                  //    ui.label("");
-                  //    ui.end_row();
-                  // }
-
-                  ui.label("Gradient Length (m):");
-                  ui.add_sized(
-                     egui::Vec2::new(100.0, 30.0),
-                     egui::DragValue::new(&mut self.temp_gradient_length)
-                     .range(500.0..=10000.0)
-                     .speed(10.0))
-                     .on_hover_text("The length of the gradient section to display (metres)");
-                  ui.end_row();
-
-                  ui.label("Gradient Offset (m):");
-                  ui.add_sized(
-                     egui::Vec2::new(100.0, 30.0),
-                     egui::DragValue::new(&mut self.temp_gradient_offset)
-                     .range(100.0..=2000.0)
-                     .speed(10.0))
-                     .on_hover_text("The position within the gradient section where the rider currently is positioned (metres)");
-                  ui.end_row();
-
-                  ui.label("Flat Gradient (%):");
-                  ui.add_sized(
-                     egui::Vec2::new(100.0, 30.0),
-                     egui::DragValue::new(&mut self.temp_flat_gradient)
-                     .range(0.1..=2.0)
-                     .speed(0.1)
-                     .max_decimals(1))
-                     .on_hover_text("The gradient considered to be 'flat', e.g if 0.5 then -0.5 to 0.5 is flat");
-                  ui.end_row();
-
-                  ui.label("Extreme Gradient (%):");
-                  ui.add_sized(
-                     egui::Vec2::new(100.0, 30.0),
-                     egui::DragValue::new(&mut self.temp_extreme_gradient)
-                     .range(10.0..=25.0)
-                     .speed(0.5)
-                     .max_decimals(1))
-                     .on_hover_text("The gradient considered to be 'extreme' (black), e.g if > 16 then gradient color is black");
-                  ui.end_row();
-
-                  ui.label("Vertical Exaggeration:");
-                  ui.add_sized(
-                     egui::Vec2::new(100.0, 30.0),
-                     egui::DragValue::new(&mut self.temp_vertical_exaggeration)
-                     .range(1.0..=50.0)
-                     .speed(0.5)
-                     .max_decimals(1))
-                     .on_hover_text("Vertical exaggeration factor for elevation plot (1.0 = true scale, 10.0 = default, higher = more vertical stretch)");
-                  ui.end_row();
-               });
+                  ui.selectable_value(&mut self.settings_tab, tab, tab.label());
+               }
+            });
+            ui.separator();
+            match self.settings_tab
+            {
+               | SettingsTab::General => self.general_tab(ui),
+               | SettingsTab::StreetView => self.streetview_tab(ui),
+               | SettingsTab::Gradient => self.gradient_tab(ui),
+               | SettingsTab::Broadcast => self.broadcast_tab(ui),
+               | SettingsTab::Integrations => self.integrations_tab(ui),
+               | SettingsTab::Advanced => self.advanced_tab(ui),
+            }
 
             ui.separator();
 
+            if ! self.invalid_fields.is_empty()
+            {
+               for (_, reason) in &self.invalid_fields
+               {
+                  ui.label(egui::RichText::new(reason).color(Color32::RED).text_style(egui::TextStyle::Small));
+               }
+               ui.separator();
+            }
+
             if ! status_message.is_empty()
             {
                ui.horizontal(|ui| { ui.label(egui::RichText::new(&status_message).color(status_color).text_style(egui::TextStyle::Small)); });
@@ -634,63 +2987,20 @@ impl Settings
             ui.horizontal(|ui| {
                if ui.button("Save").clicked()
                {
-                  // let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-                  // let mut settings_lock = settings.lock();
-
-                  // Save API key
-                  if !self.temp_api_key.is_empty()
+                  self.invalid_fields = self.validate();
+                  match self.invalid_fields.first()
                   {
-                     match self.set_streetview_api_key_from_tmp()
-                     {
-                        | Ok(_) =>
-                        {
-                           // toast_manager.success("Settings saved successfully", Some(Duration::from_secs(3)));
-                           assist.encrypted_api_key = Some(self.temp_api_key.clone());
-                           assist.settings_dialog_message = "Settings saved successfully".to_string();
-                        }
-                        | Err(e) =>
-                        {
-                           assist.settings_dialog_message = format!("Failed to save API key: {}", e);
-                           //toast_manager.error(&format!("Failed to save API key: {}", e), None);
-                        }
-                     }
-                  }
-
-                  // Update gradient settings
-                  self.gradient_length = self.temp_gradient_length;
-                  self.gradient_offset = self.temp_gradient_offset;
-                  self.flat_gradient_percentage = self.temp_flat_gradient;
-                  self.extreme_gradient_percentage = self.temp_extreme_gradient;
-                  self.vertical_exaggeration = self.temp_vertical_exaggeration;
-
-                  // Write settings to file
-                  match self.write_settings()
-                  {
-                     | Ok(_) =>
-                     {
-                        assist.show_settings_dialog_err = false;
-                     },
-                     | Err(e) =>
-                     {
-                        assist.settings_dialog_message = format!("Failed to write settings: {}", e);
-                        assist.show_settings_dialog_err = true;
-                        // toast_manager.error(&format!("Failed to write settings: {}", e), None);
-                     }
+                     | Some((field, _)) => self.settings_tab = field.tab(),
+                     | None => self.save_dialog(assist),
                   }
-
-                  // Close dialog
-                  assist.show_settings_dialog = false;
                }
 
                if ui.button("Cancel").clicked()
                {
-                  // Reset temp values
+                  // Discard edits
                   self.temp_api_key.clear();
-                  self.temp_gradient_length = 3000.0;
-                  self.temp_gradient_offset = 500.0;
-                  self.temp_flat_gradient = 0.5;
-                  self.temp_extreme_gradient = 16.0;
-                  self.temp_vertical_exaggeration = 10.0;
+                  self.load_temp_values();
+                  self.invalid_fields.clear();
                   self.show_api_key = false;
 
                   // Close dialog
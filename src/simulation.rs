@@ -0,0 +1,343 @@
+use std::{fs::{self, File}, io::BufReader, path::Path, sync::Arc};
+
+use chrono::{DateTime, FixedOffset};
+use crossbeam::atomic::AtomicCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{gpx::{Track, track_data_from_gpx}, trainer::TrainerReading};
+
+const GRAVITY: f64 = 9.80665;   // m/s²
+const AIR_DENSITY: f64 = 1.225; // kg/m³ at sea level, 15°C
+const MIN_SPEED: f64 = 1.0;     // m/s, avoids dividing the power by zero when starting or on very steep climbs
+const TIME_STEP: f64 = 0.1;     // seconds per integration step
+
+/// Rider and bike parameters for the physics based simulation speed. When disabled the simulator moves at the
+/// constant speed set on the toolbar.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsModel
+{
+   pub is_enabled:         bool,
+   pub mass:               f64, // kg, rider + bike
+   pub cda:                f64, // m², drag coefficient × frontal area
+   pub rolling_resistance: f64, // Crr
+   pub power:              f64, // W
+}
+
+impl Default for PhysicsModel
+{
+   fn default() -> Self { Self { is_enabled: false, mass: 85.0, cda: 0.32, rolling_resistance: 0.005, power: 200.0 } }
+}
+
+impl PhysicsModel
+{
+   /// Resistive force in newtons at `speed` (m/s) on a `gradient` (percent).
+   fn resistance(&self, speed: f64, gradient: f64) -> f64
+   //-----------------------------------------------------
+   {
+      let angle = (gradient / 100.0).atan();
+      let climbing = self.mass * GRAVITY * angle.sin();
+      let rolling = self.mass * GRAVITY * angle.cos() * self.rolling_resistance;
+      let aero = 0.5 * AIR_DENSITY * self.cda * speed * speed;
+      climbing + rolling + aero
+   }
+
+   /// New speed (m/s) after riding for `elapsed` seconds from `speed` on a `gradient` (percent).
+   pub fn advance(&self, speed: f64, gradient: f64, elapsed: f64) -> f64
+   //--------------------------------------------------------------------
+   {
+      let mut speed = speed.max(MIN_SPEED);
+      let mut remaining = elapsed;
+      while remaining > 0.0
+      {
+         let dt = remaining.min(TIME_STEP);
+         let propulsion = self.power / speed;
+         let acceleration = (propulsion - self.resistance(speed, gradient)) / self.mass;
+         speed = (speed + acceleration * dt).max(MIN_SPEED);
+         remaining -= dt;
+      }
+      speed
+   }
+
+   /// Grade-adjusted speed: the speed (m/s) on the flat for the power needed to ride at `speed` (m/s) on a `gradient`
+   /// (percent), so efforts on climbs and descents can be compared with flat sections.
+   pub fn flat_speed(&self, speed: f64, gradient: f64) -> f64
+   //---------------------------------------------------------
+   {
+      let power = (self.resistance(speed, gradient) * speed).max(0.0);
+      self.speed_for_power(power, 0.0)
+   }
+
+   /// Steady speed (m/s) riding at `power` (W) on a `gradient` (percent).
+   pub fn speed_for_power(&self, power: f64, gradient: f64) -> f64
+   //--------------------------------------------------------------
+   {
+      // The power needed only increases with speed (above any speed a descent reaches on its own) so bisect for it
+      let (mut low, mut high) = (0.0, 50.0);
+      for _ in 0 .. 40
+      {
+         let middle = (low + high) / 2.0;
+         if self.resistance(middle, gradient) * middle < power { low = middle; } else { high = middle; }
+      }
+      (low + high) / 2.0
+   }
+
+   /// Advance `distance` along `track` by `elapsed` seconds, returning the new (distance, speed).
+   pub fn ride(&self, track: &Track, distance: f64, speed: f64, elapsed: f64) -> (f64, f64)
+   //--------------------------------------------------------------------------------------
+   {
+      let gradient = track.gradient_at(distance, 100.0);
+      let new_speed = self.advance(speed, gradient, elapsed);
+      (distance + (speed + new_speed) / 2.0 * elapsed, new_speed)
+   }
+}
+
+/// Random variation of the simulated speed so the views are exercised under riding-like conditions rather than at a
+/// perfectly steady pace.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpeedVariation
+{
+   pub is_enabled: bool,
+   pub amount:     f64, // percent of the speed the random wander and surges can add or remove
+   pub is_stops:   bool, // occasionally stop for a few seconds (junctions, traffic lights)
+}
+
+impl Default for SpeedVariation
+{
+   fn default() -> Self { Self { is_enabled: false, amount: 15.0, is_stops: true } }
+}
+
+const SURGES_PER_SECOND: f64 = 1.0 / 120.0;
+const STOPS_PER_SECOND: f64 = 1.0 / 600.0;
+const UPHILL_SLOWDOWN: f64 = 0.06; // fraction of speed lost per percent of gradient at constant speed
+
+/// Running state of a `SpeedVariation`.
+pub struct SpeedNoise
+{
+   variation:       SpeedVariation,
+   state:           u64,
+   wander:          f64, // -1..1, slowly drifting
+   surge_remaining: f64, // seconds
+   stop_remaining:  f64, // seconds
+}
+
+impl SpeedNoise
+{
+   pub fn new(variation: SpeedVariation) -> Self
+   //-------------------------------------------
+   {
+      let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+      SpeedNoise { variation, state: seed | 1, wander: 0.0, surge_remaining: 0.0, stop_remaining: 0.0 }
+   }
+
+   /// Cheap xorshift in 0..1; the variation only has to look random.
+   fn random(&mut self) -> f64
+   //--------------------------
+   {
+      self.state ^= self.state << 13;
+      self.state ^= self.state >> 7;
+      self.state ^= self.state << 17;
+      (self.state % 1_000_000) as f64 / 1_000_000.0
+   }
+
+   /// Speed multiplier for the next `elapsed` seconds on a `gradient` (percent). Pass a gradient of 0 when the speed
+   /// model already accounts for the terrain. Returns 0 while stopped.
+   pub fn factor(&mut self, gradient: f64, elapsed: f64) -> f64
+   //----------------------------------------------------------
+   {
+      if !self.variation.is_enabled
+      {
+         return 1.0;
+      }
+      if self.stop_remaining > 0.0
+      {
+         self.stop_remaining -= elapsed;
+         return 0.0;
+      }
+      if self.variation.is_stops && self.random() < STOPS_PER_SECOND * elapsed
+      {
+         self.stop_remaining = 5.0 + self.random() * 25.0;
+         log_info!("Simulating a {:.0}s stop", self.stop_remaining);
+         return 0.0;
+      }
+      if self.surge_remaining > 0.0
+      {
+         self.surge_remaining -= elapsed;
+      }
+      else if self.random() < SURGES_PER_SECOND * elapsed
+      {
+         self.surge_remaining = 10.0 + self.random() * 20.0;
+      }
+      // Mean reverting random walk so the pace drifts rather than jitters
+      let step = (self.random() * 2.0 - 1.0) * 0.3 * elapsed.sqrt();
+      self.wander = (self.wander * (1.0 - 0.05 * elapsed).max(0.0) + step).clamp(-1.0, 1.0);
+      let amount = self.variation.amount / 100.0;
+      let surge = if self.surge_remaining > 0.0 { amount * 1.5 } else { 0.0 };
+      let terrain = (1.0 - gradient * UPHILL_SLOWDOWN).clamp(0.3, 1.6);
+      ((1.0 + self.wander * amount + surge) * terrain).max(0.1)
+   }
+}
+
+/// One sample of a recorded ride.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReplaySample
+{
+   pub time:      f64, // seconds since the start of the recording
+   pub distance:  f64, // metres
+   pub speed:     f64, // m/s
+   pub power:     i32,
+   pub heartrate: i32,
+   pub cadence:   i32,
+}
+
+/// A previously recorded ride which can drive the simulator instead of a speed model.
+#[derive(Clone, Debug)]
+pub struct RideRecording
+{
+   pub name: String,
+   samples:  Vec<ReplaySample>,
+}
+
+impl RideRecording
+{
+   /// Load a GPX file with timestamps or a CSV log, chosen by the file extension.
+   pub fn load(path: &Path) -> Result<RideRecording, String>
+   //-------------------------------------------------------
+   {
+      let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+      let mut samples = if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+      {
+         RideRecording::samples_from_csv(path)?
+      }
+      else
+      {
+         RideRecording::samples_from_gpx(path)?
+      };
+      samples.sort_by(|a, b| a.time.total_cmp(&b.time));
+      if samples.len() < 2
+      {
+         return Err(format!("{} does not contain enough timed samples to replay.", name));
+      }
+      RideRecording::fill_speeds(&mut samples);
+      Ok(RideRecording { name, samples })
+   }
+
+   fn samples_from_gpx(path: &Path) -> Result<Vec<ReplaySample>, String>
+   //--------------------------------------------------------------------
+   {
+      let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+      let gpx = gpx::read(BufReader::new(file)).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?;
+      let track = track_data_from_gpx(&gpx).map_err(|e| e.to_string())?;
+      let points = &gpx.tracks[0].segments[0].points; // track_data_from_gpx succeeded so these exist
+      let mut start: Option<DateTime<FixedOffset>> = None;
+      let mut samples = Vec::with_capacity(points.len());
+      for (point, track_point) in points.iter().zip(track.iter())
+      {
+         let Some(time) = point.time.as_ref().and_then(|t| t.format().ok()).and_then(|t| DateTime::parse_from_rfc3339(&t).ok()) else { continue; };
+         let start = *start.get_or_insert(time);
+         samples.push(ReplaySample { time: (time - start).num_milliseconds() as f64 / 1000.0, distance: track_point.distance,
+                                     ..Default::default() });
+      }
+      if samples.is_empty()
+      {
+         return Err(format!("{:?} has no timestamps; only recorded rides can be replayed.", path));
+      }
+      Ok(samples)
+   }
+
+   /// Read a CSV log with a header row. Recognised (case insensitive) columns are `time` (seconds from the start),
+   /// `distance` (metres), `speed` (km/h), `power`, `heartrate` and `cadence`; only time and distance are required.
+   fn samples_from_csv(path: &Path) -> Result<Vec<ReplaySample>, String>
+   //--------------------------------------------------------------------
+   {
+      let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+      let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+      let header: Vec<String> = lines.next().ok_or("The CSV file is empty.")?
+                                     .split(',').map(|h| h.trim().to_lowercase()).collect();
+      let column = |name: &str| header.iter().position(|h| h == name);
+      let (Some(time_col), Some(distance_col)) = (column("time"), column("distance")) else
+      {
+         return Err("The CSV file needs time and distance columns.".to_string());
+      };
+      let (speed_col, power_col, heartrate_col, cadence_col) = (column("speed"), column("power"), column("heartrate"), column("cadence"));
+      let mut samples = Vec::new();
+      for (line_no, line) in lines.enumerate()
+      {
+         let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+         let number = |col: Option<usize>| col.and_then(|c| fields.get(c)).and_then(|f| f.parse::<f64>().ok());
+         let (Some(time), Some(distance)) = (number(Some(time_col)), number(Some(distance_col))) else
+         {
+            log_warn!("Skipping invalid line {} in {:?}", line_no + 2, path);
+            continue;
+         };
+         samples.push(ReplaySample { time,
+                                     distance,
+                                     speed: number(speed_col).map_or(0.0, |kmh| kmh / 3.6),
+                                     power: number(power_col).unwrap_or(0.0) as i32,
+                                     heartrate: number(heartrate_col).unwrap_or(0.0) as i32,
+                                     cadence: number(cadence_col).unwrap_or(0.0) as i32 });
+      }
+      Ok(samples)
+   }
+
+   /// Derive speeds from distance and time where the recording doesn't include them.
+   fn fill_speeds(samples: &mut [ReplaySample])
+   //-------------------------------------------
+   {
+      for i in 1..samples.len()
+      {
+         if samples[i].speed <= 0.0
+         {
+            let dt = samples[i].time - samples[i - 1].time;
+            if dt > 0.0
+            {
+               samples[i].speed = ((samples[i].distance - samples[i - 1].distance) / dt).max(0.0);
+            }
+         }
+      }
+      if samples.len() > 1 && samples[0].speed <= 0.0
+      {
+         samples[0].speed = samples[1].speed;
+      }
+   }
+
+   pub fn duration(&self) -> f64 { self.samples.last().map_or(0.0, |s| s.time) }
+
+   /// Time (seconds) at which the recording first reached `distance`.
+   pub fn time_at_distance(&self, distance: f64) -> f64
+   //--------------------------------------------------
+   {
+      self.samples.iter().find(|s| s.distance >= distance).map_or_else(|| self.duration(), |s| s.time)
+   }
+
+   /// The recording at `time` seconds, interpolating distance and speed between samples.
+   pub fn sample_at(&self, time: f64) -> ReplaySample
+   //-------------------------------------------------
+   {
+      let index = self.samples.partition_point(|s| s.time <= time);
+      if index == 0
+      {
+         return self.samples[0];
+      }
+      if index >= self.samples.len()
+      {
+         return self.samples[self.samples.len() - 1];
+      }
+      let (a, b) = (&self.samples[index - 1], &self.samples[index]);
+      let t = if b.time > a.time { (time - a.time) / (b.time - a.time) } else { 0.0 };
+      ReplaySample { time,
+                     distance: a.distance + (b.distance - a.distance) * t,
+                     speed: a.speed + (b.speed - a.speed) * t,
+                     ..*a }
+   }
+}
+
+/// What drives the simulated position, chosen when the simulation starts.
+#[derive(Clone, Debug)]
+pub struct SimulationOptions
+{
+   pub physics:     PhysicsModel,
+   pub variation:   SpeedVariation,
+   pub replay:      Option<Arc<RideRecording>>,
+   pub replay_rate: f64, // 1.0 = real time
+   pub trainer:     Option<Arc<AtomicCell<TrainerReading>>>, // a connected trainer's power replaces the simulated effort
+}
@@ -0,0 +1,89 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{components::ToastManager, data::RiderData, gpx::Track};
+
+/// The broadcast slope is in hundredths of a percent.
+const SLOPE_SCALE: f64 = 100.0;
+/// Samples are taken this far apart along the course and compared over the last WINDOW metres.
+const SAMPLE_SPACING: f64 = 25.0; // metres
+const WINDOW: f64 = 1000.0;       // metres
+const MIN_SAMPLES: usize = 20;
+/// Average difference between the game slope and the GPX gradient above which the wrong course is probably loaded.
+pub const DRIFT_WARNING: f64 = 2.5; // percent
+
+/// Compares the slope reported by the game with the gradient of the GPX course at the same distance, warning when
+/// they disagree over a stretch of the ride as happens when the wrong course (or the right one in the wrong
+/// direction) is loaded, or the start offset is far out.
+#[derive(Default)]
+pub struct SlopeMonitor
+//======================
+{
+   samples:   VecDeque<(f64, f64, f64)>, // (distance, game slope, GPX gradient) over the last WINDOW metres
+   drift:     Option<f64>,
+   is_warned: bool,
+}
+
+impl SlopeMonitor
+{
+   /// Forget the samples, e.g. when a new track is opened.
+   pub fn reset(&mut self) { *self = Self::default(); }
+
+   /// Average difference in percent between the game slope and the GPX gradient over the last kilometre, or None until
+   /// enough has been ridden (or when the game does not report the slope).
+   pub fn drift(&self) -> Option<f64> { self.drift }
+
+   /// Sample the broadcast `rider` at `distance` metres along `track`, raising a toast once when the slopes disagree.
+   pub fn update(&mut self, distance: f64, rider: &RiderData, track: &Track, toast_manager: &mut ToastManager)
+   //---------------------------------------------------------------------------------------------------------
+   {
+      if distance <= 0.0 || track.is_empty()
+      {
+         return;
+      }
+      if let Some(&(last, _, _)) = self.samples.back()
+      {
+         if distance < last
+         {  // Moved backwards (restart or scrub) so start again from here
+            self.samples.clear();
+            self.drift = None;
+         }
+         else if distance - last < SAMPLE_SPACING
+         {
+            return;
+         }
+      }
+      self.samples.push_back((distance, rider.slope as f64 / SLOPE_SCALE, track.gradient_at(distance, SAMPLE_SPACING * 2.0)));
+      while self.samples.front().is_some_and(|&(start, _, _)| distance - start > WINDOW)
+      {
+         self.samples.pop_front();
+      }
+      self.check(toast_manager);
+   }
+
+   fn check(&mut self, toast_manager: &mut ToastManager)
+   //---------------------------------------------------
+   {
+      self.drift = None;
+      if self.samples.len() < MIN_SAMPLES || self.samples.iter().all(|&(_, slope, _)| slope == 0.0)
+      {
+         return;
+      }
+      let count = self.samples.len() as f64;
+      let drift = self.samples.iter().map(|&(_, slope, gradient)| (slope - gradient).abs()).sum::<f64>() / count;
+      let reversed = self.samples.iter().map(|&(_, slope, gradient)| (slope + gradient).abs()).sum::<f64>() / count;
+      self.drift = Some(drift);
+      if drift < DRIFT_WARNING / 2.0
+      {  // Back in agreement so warn again if it drifts later
+         self.is_warned = false;
+      }
+      else if drift >= DRIFT_WARNING && !self.is_warned
+      {
+         self.is_warned = true;
+         let hint = if reversed < drift / 2.0 { "the course may be loaded in the wrong direction" }
+                    else { "check the right course is loaded and the start offset is correct" };
+         log_warn!("Game slope differs from the GPX gradient by {drift:.1}% on average (reversed {reversed:.1}%)");
+         toast_manager.warning(format!("The game gradient differs from the course by {drift:.1}% on average, {hint}."),
+                               Some(Duration::from_secs(10)));
+      }
+   }
+}
@@ -0,0 +1,204 @@
+use std::{path::Path, sync::{Arc, atomic::{AtomicBool, Ordering}}, thread::JoinHandle, time::{Duration, Instant}};
+
+use notify::Watcher;
+
+/// Where the rider position currently comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind
+{
+   Broadcast,  // the game's broadcast file
+   Simulation, // simulated movement along the course
+   Replay,     // a recorded ride driving the simulator
+}
+
+/// Cooperative cancellation for a source thread. Threads check `is_cancelled` each iteration and use `sleep` or `wait`
+/// instead of `std::thread::sleep` so a cancelled thread wakes and exits immediately. `notify` wakes a thread blocked
+/// in `wait` early, e.g. when the broadcast file changes or the simulation is paused or seeks.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<TokenState>);
+
+#[derive(Debug, Default)]
+struct TokenState
+{
+   is_cancelled: AtomicBool,
+   is_notified:  parking_lot::Mutex<bool>,
+   wake:         parking_lot::Condvar,
+}
+
+impl CancelToken
+{
+   pub fn cancel(&self)
+   //------------------
+   {
+      self.0.is_cancelled.store(true, Ordering::Relaxed);
+      let _is_notified = self.0.is_notified.lock(); // so a thread about to wait sees the cancel
+      self.0.wake.notify_all();
+   }
+
+   pub fn is_cancelled(&self) -> bool { self.0.is_cancelled.load(Ordering::Relaxed) }
+
+   /// Wake the thread blocked in `wait`, or make its next `wait` return straight away.
+   pub fn notify(&self)
+   //------------------
+   {
+      *self.0.is_notified.lock() = true;
+      self.0.wake.notify_all();
+   }
+
+   /// Wait for up to `timeout`, returning early when notified or cancelled. Returns false if cancelled.
+   pub fn wait(&self, timeout: Duration) -> bool
+   //-------------------------------------------
+   {
+      let deadline = Instant::now() + timeout;
+      let mut is_notified = self.0.is_notified.lock();
+      while !*is_notified && !self.is_cancelled()
+      {
+         if self.0.wake.wait_until(&mut is_notified, deadline).timed_out()
+         {
+            break;
+         }
+      }
+      *is_notified = false;
+      !self.is_cancelled()
+   }
+
+   /// Sleep for `duration` or until cancelled (notifications do not end the sleep), returning false if cancelled.
+   pub fn sleep(&self, duration: Duration) -> bool
+   //---------------------------------------------
+   {
+      let deadline = Instant::now() + duration;
+      let mut is_notified = self.0.is_notified.lock();
+      while !self.is_cancelled()
+      {
+         if self.0.wake.wait_until(&mut is_notified, deadline).timed_out()
+         {
+            return !self.is_cancelled();
+         }
+      }
+      false
+   }
+}
+
+/// Notify `token` whenever `file` is written or replaced. The directory is watched rather than the file as the file
+/// may be replaced or not exist yet. Watching stops when the watcher is dropped.
+pub fn watch_file(file: &Path, token: &CancelToken) -> Result<notify::RecommendedWatcher, String>
+//-----------------------------------------------------------------------------------------------
+{
+   let directory = file.parent().filter(|directory| !directory.as_os_str().is_empty()).unwrap_or(Path::new("."));
+   let (name, token) = (file.file_name().map(|name| name.to_os_string()), token.clone());
+   let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>|
+   {
+      if let Ok(event) = event
+         && !event.kind.is_access()
+         && event.paths.iter().any(|path| path.file_name() == name.as_deref())
+      {
+         token.notify();
+      }
+   }).map_err(|e| format!("Could not watch {}: {}", file.display(), e))?;
+   watcher.watch(directory, notify::RecursiveMode::NonRecursive)
+          .map_err(|e| format!("Could not watch {}: {}", directory.display(), e))?;
+   Ok(watcher)
+}
+
+/// How long `SourceManager::shutdown` waits for the source thread to exit before leaving it to finish by itself.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Owns the single thread producing rider data. Starting a source cancels the previous one, so switching between
+/// broadcast, simulation and replay or opening another course never leaves an old thread polling.
+#[derive(Default)]
+pub struct SourceManager
+{
+   active: Option<(SourceKind, CancelToken, JoinHandle<()>)>,
+}
+
+impl SourceManager
+{
+   /// Stop the current source (if any) and run `run` on a new thread as the `kind` source.
+   pub fn start<F>(&mut self, kind: SourceKind, run: F)
+      where F: FnOnce(CancelToken) + Send + 'static
+   //----------------------------------------------------
+   {
+      self.stop();
+      let token = CancelToken::default();
+      let thread_token = token.clone();
+      match std::thread::Builder::new().name(format!("{:?} source", kind).to_lowercase()).spawn(move ||
+      {
+         let _span = tracing::info_span!("source", ?kind).entered();
+         run(thread_token)
+      })
+      {
+         | Ok(handle) =>
+         {
+            log_info!("Started {:?} source", kind);
+            self.active = Some((kind, token, handle));
+         }
+         | Err(e) => log_error!("Failed to start the {:?} source thread: {e}", kind),
+      }
+   }
+
+   /// Cancel the current source. The thread is not joined as it may be waiting on a read; it exits at its next check.
+   /// Use `shutdown` to wait for it.
+   pub fn stop(&mut self)
+   //--------------------
+   {
+      if let Some((kind, token, _)) = self.active.take()
+      {
+         token.cancel();
+         log_info!("Stopped {:?} source", kind);
+      }
+   }
+
+   /// Cancel the current source and join its thread, waiting up to SHUTDOWN_TIMEOUT. Used when the app closes or the
+   /// course is replaced so no thread is left reading the broadcast file or moving along the old course.
+   pub fn shutdown(&mut self)
+   //------------------------
+   {
+      let Some((kind, token, handle)) = self.active.take() else { return; };
+      token.cancel();
+      let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+      while !handle.is_finished() && Instant::now() < deadline
+      {
+         std::thread::sleep(Duration::from_millis(10));
+      }
+      if !handle.is_finished()
+      {
+         log_warn!("The {:?} source did not stop within {} seconds", kind, SHUTDOWN_TIMEOUT.as_secs());
+         return;
+      }
+      match handle.join()
+      {
+         | Ok(()) => log_info!("Stopped {:?} source", kind),
+         | Err(_) => log_error!("The {:?} source thread panicked", kind),
+      }
+   }
+
+   /// Wake the running source thread, e.g. after changing its speed, pausing it or seeking, so the change applies
+   /// immediately rather than at its next update.
+   pub fn notify(&self)
+   //------------------
+   {
+      if let Some((_, token, _)) = &self.active
+      {
+         token.notify();
+      }
+   }
+
+   /// The running source, or None when no source is running or it has finished by itself (e.g. end of the course).
+   pub fn kind(&self) -> Option<SourceKind>
+   //--------------------------------------
+   {
+      self.active.as_ref().filter(|(_, _, handle)| !handle.is_finished()).map(|(kind, _, _)| *kind)
+   }
+
+   /// True when the last started source was `kind` and its thread has exited by itself.
+   pub fn has_finished(&self, kind: SourceKind) -> bool
+   //--------------------------------------------------
+   {
+      self.active.as_ref().is_some_and(|(k, _, handle)| *k == kind && handle.is_finished())
+   }
+}
+
+impl Drop for SourceManager
+{
+   fn drop(&mut self) { self.shutdown(); }
+}
@@ -0,0 +1,166 @@
+use crossbeam::channel::Sender;
+
+use crate::{gpx::{Climb, Track}, settings::SpeechSettings, units::Units};
+
+/// Distance before the top of a climb at which the summit is announced.
+const SUMMIT_WARNING: f64 = 200.0; // metres
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cue
+{
+   ClimbAhead(usize), // index into the course climbs
+   Summit(usize),
+   Halfway,
+   FinalStretch,
+}
+
+/// Spoken announcements of upcoming climbs, the summit and ride progress, so the rider does not have to look at the
+/// screen during hard efforts. Speech runs on its own thread using the system text-to-speech voice.
+#[derive(Default)]
+pub struct SpeechCues
+//====================
+{
+   config:        SpeechSettings,
+   speaker:       Option<Sender<String>>,
+   climbs:        Option<Vec<Climb>>, // on the current track, found on first use
+   spoken:        Vec<Cue>,
+   last_distance: f64,
+}
+
+impl SpeechCues
+{
+   pub fn new(config: SpeechSettings) -> Self
+   //----------------------------------------
+   {
+      let mut cues = Self::default();
+      cues.configure(config);
+      cues
+   }
+
+   /// Start the speech thread when enabled, or stop it when disabled.
+   pub fn configure(&mut self, config: SpeechSettings)
+   //-------------------------------------------------
+   {
+      if config.is_enabled && self.speaker.is_none()
+      {
+         let (sender, receiver) = crossbeam::channel::unbounded::<String>();
+         std::thread::spawn(move ||
+         {
+            let mut tts = match tts::Tts::default()
+            {
+               | Ok(tts) => tts,
+               | Err(e) =>
+               {
+                  log_warn!("Text-to-speech is not available: {}", e);
+                  return;
+               }
+            };
+            while let Ok(text) = receiver.recv()
+            {
+               if let Err(e) = tts.speak(&text, false)
+               {
+                  log_warn!("Could not speak \"{}\": {}", text, e);
+               }
+            }
+         });
+         self.speaker = Some(sender);
+      }
+      else if !config.is_enabled
+      {
+         self.speaker = None;
+      }
+      self.config = config;
+   }
+
+   /// Forget the course, e.g. when a new track is opened.
+   pub fn reset(&mut self)
+   //---------------------
+   {
+      self.climbs = None;
+      self.spoken.clear();
+      self.last_distance = 0.0;
+   }
+
+   /// Announce the cues reached at `distance` metres along `track`.
+   pub fn update(&mut self, distance: f64, track: &Track, units: Units)
+   //------------------------------------------------------------------
+   {
+      let total_distance = track.total_distance();
+      if self.speaker.is_none() || distance <= 0.0 || total_distance <= 0.0
+      {
+         return;
+      }
+      let warning = self.config.warning_distance as f64;
+      let final_stretch = total_distance - units.from_distance(1.0);
+      let climbs = self.climbs.get_or_insert_with(|| track.climbs(20.0, 2.0));
+      // Where each cue is due, in the order they are checked
+      let mut cues: Vec<(Cue, f64)> = Vec::new();
+      for (i, climb) in climbs.iter().enumerate()
+      {
+         cues.push((Cue::ClimbAhead(i), climb.start - warning));
+         cues.push((Cue::Summit(i), climb.end - SUMMIT_WARNING.min(climb.length() / 2.0)));
+      }
+      cues.push((Cue::Halfway, total_distance / 2.0));
+      cues.push((Cue::FinalStretch, final_stretch));
+
+      if distance < self.last_distance
+      {  // Moved backwards (restart or scrub) so re-arm the cues ahead of the new position
+         self.spoken.retain(|spoken| cues.iter().any(|(cue, at)| cue == spoken && distance >= *at));
+      }
+      let last_distance = std::mem::replace(&mut self.last_distance, distance);
+      if last_distance <= 0.0
+      {  // First update (or joined part way through): only announce what is still ahead
+         self.spoken.extend(cues.iter().filter(|(_, at)| distance > *at + warning).map(|(cue, _)| *cue));
+      }
+
+      let mut announcements = Vec::new();
+      for (cue, at) in cues
+      {
+         if distance < at || self.spoken.contains(&cue)
+         {
+            continue;
+         }
+         self.spoken.push(cue);
+         let text = match cue
+         {
+            | Cue::ClimbAhead(i) if self.config.is_climbs && distance < climbs[i].start =>
+            {
+               let climb = &climbs[i];
+               format!("{} to {:.0} percent climb, {} long", spoken_length(climb.start - distance, units),
+                       climb.average_gradient, spoken_length(climb.length(), units))
+            }
+            | Cue::Summit(i) if self.config.is_summits && distance < climbs[i].end =>
+            {
+               format!("Summit in {}", spoken_length(climbs[i].end - distance, units))
+            }
+            | Cue::Halfway if self.config.is_progress => "Halfway".to_string(),
+            | Cue::FinalStretch if self.config.is_progress && distance < total_distance =>
+            {
+               if units == Units::Metric { "Final kilometre".to_string() } else { "Final mile".to_string() }
+            }
+            | _ => continue,
+         };
+         announcements.push(text);
+      }
+      if let Some(speaker) = &self.speaker
+      {
+         for text in announcements
+         {
+            let _ = speaker.send(text);
+         }
+      }
+   }
+}
+
+/// A distance as it is best spoken: rounded metres or feet when short, otherwise kilometres or miles.
+fn spoken_length(metres: f64, units: Units) -> String
+//----------------------------------------------------
+{
+   match units
+   {
+      | Units::Metric if metres < 1000.0 => format!("{:.0} metres", (metres / 10.0).round() * 10.0),
+      | Units::Metric => format!("{:.1} kilometres", metres / 1000.0),
+      | Units::Imperial if metres < 400.0 => format!("{:.0} feet", (metres / 0.3048 / 10.0).round() * 10.0),
+      | Units::Imperial => format!("{:.1} miles", metres / 1609.344),
+   }
+}
@@ -0,0 +1,343 @@
+use std::{collections::VecDeque, fs, path::{Path, PathBuf}, time::Instant};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::{data::RiderData, gpx::Track, history::{Downsample, RideHistory}};
+
+/// Rides shorter than this are not summarised on exit.
+const MIN_SUMMARY_DISTANCE: f64 = 100.0; // metres
+/// Samples in the rolling average used for normalized power, one a second.
+const NORMALIZED_WINDOW: usize = 30;
+/// Distance from the end of a circuit within which moving back to the start is counted as a new lap, not a restart.
+const LAP_TOLERANCE: f64 = 50.0; // metres
+
+#[derive(Debug, Clone, Copy)]
+struct RideSample
+{
+   time:     f64, // seconds since the start of the ride
+   distance: f64, // metres
+   power:    f64, // watts
+}
+
+impl Downsample for RideSample
+{
+   fn time(&self) -> f64 { self.time }
+
+   fn combine(samples: &[Self]) -> Self
+   //----------------------------------
+   {
+      let last = samples[samples.len() - 1];
+      RideSample { power: RideLog::average_power(samples.iter()), ..last }
+   }
+}
+
+/// Machine readable summary of a ride, written as JSON when the course is completed or GPXAssist exits.
+#[derive(Debug, Serialize)]
+pub struct RideReport
+{
+   pub course:           String,
+   pub started:          String, // RFC 3339
+   #[serde(skip)]
+   start_time:           DateTime<Local>,
+   pub is_complete:      bool,
+   pub duration:         f64, // seconds
+   pub distance:         f64, // metres
+   pub ascent:           f64, // metres
+   pub average_speed:    f64, // km/h
+   pub average_power:    f64, // watts
+   pub normalized_power: f64, // watts, 30 second rolling average
+   pub tss:              Option<f64>, // as reported by TrainingPeaks Virtual, None when simulating
+   pub climbs:           Vec<ClimbSplit>,
+   pub laps:             Vec<LapSplit>, // empty for a single lap
+}
+
+/// Time taken on a climb the ride passed over completely.
+#[derive(Debug, Serialize)]
+pub struct ClimbSplit
+{
+   pub start:            f64, // metres
+   pub length:           f64, // metres
+   pub gain:             f64, // metres
+   pub average_gradient: f64, // percent
+   pub duration:         f64, // seconds
+   pub average_speed:    f64, // km/h
+   pub average_power:    f64, // watts
+}
+
+/// Time, power and ascent of a completed lap.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LapSplit
+{
+   pub lap:           usize,
+   pub duration:      f64, // seconds
+   pub distance:      f64, // metres
+   pub ascent:        f64, // metres
+   pub average_power: f64, // watts
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CurrentLap
+{
+   start:    f64, // metres along the course
+   end:      f64, // metres, the furthest reached
+   game_lap: i32, // laps done according to the game when the lap started
+   started:  Instant,
+   updated:  Instant,
+   energy:   f64, // joules
+}
+
+impl CurrentLap
+{
+   fn new(distance: f64, game_lap: i32) -> Self
+   //------------------------------------------
+   {
+      let now = Instant::now();
+      CurrentLap { start: distance, end: distance, game_lap, started: now, updated: now, energy: 0.0 }
+   }
+}
+
+/// Splits the ride into laps, either at the lap count from an event in the broadcast or when the rider goes from the
+/// end of a circuit back to its start (e.g. a looped simulation).
+#[derive(Default)]
+pub struct LapTimer
+//=================
+{
+   laps:    Vec<LapSplit>,
+   current: Option<CurrentLap>,
+}
+
+impl LapTimer
+{
+   pub fn reset(&mut self) { *self = Self::default(); }
+
+   /// The completed laps.
+   pub fn laps(&self) -> &[LapSplit] { &self.laps }
+
+   /// Record the latest rider data at `distance` metres along `track`. A jump backwards that is not the start of a new
+   /// lap (new ride or restart) starts again.
+   pub fn update(&mut self, distance: f64, rider: &RiderData, track: &Track)
+   //-----------------------------------------------------------------------
+   {
+      if distance <= 0.0 || track.is_empty()
+      {
+         return;
+      }
+      let Some(lap) = self.current.as_mut() else
+      {
+         self.current = Some(CurrentLap::new(distance, rider.event_laps_done));
+         return;
+      };
+      let total_distance = track.total_distance();
+      let is_wrapped = distance < lap.end - total_distance / 2.0 && lap.end >= total_distance - LAP_TOLERANCE;
+      if distance < lap.end - 1.0 && !is_wrapped
+      {
+         self.reset();
+         return;
+      }
+      let now = Instant::now();
+      lap.energy += rider.power.max(0) as f64 * (now - lap.updated).as_secs_f64();
+      lap.updated = now;
+      if is_wrapped
+      {
+         self.finish_lap(track);
+         self.current = Some(CurrentLap::new(distance, rider.event_laps_done));
+      }
+      else if rider.event_laps_done > lap.game_lap
+      {
+         lap.end = distance;
+         self.finish_lap(track);
+         self.current = Some(CurrentLap::new(distance, rider.event_laps_done));
+      }
+      else
+      {
+         lap.end = distance;
+      }
+   }
+
+   /// Close the lap in progress when the ride ends, if the ride had more than one lap.
+   pub fn finish(&mut self, track: &Track)
+   //-------------------------------------
+   {
+      if !self.laps.is_empty()
+      {
+         self.finish_lap(track);
+      }
+   }
+
+   fn finish_lap(&mut self, track: &Track)
+   //-------------------------------------
+   {
+      let Some(lap) = self.current.take() else { return; };
+      let duration = (lap.updated - lap.started).as_secs_f64();
+      let (ascent, _) = track.section(lap.start, lap.end).elevation_gain_loss(2.0);
+      self.laps.push(LapSplit
+      {
+         lap: self.laps.len() + 1,
+         duration,
+         distance: lap.end - lap.start,
+         ascent,
+         average_power: if duration > 0.0 { lap.energy / duration } else { 0.0 },
+      });
+   }
+}
+
+/// Samples the rider distance and power (about once a second) during a ride for the ride summary. Older samples are
+/// downsampled so long rides do not keep growing the log, and normalized power is accumulated as the ride goes.
+#[derive(Default)]
+pub struct RideLog
+//================
+{
+   started:    Option<(Instant, DateTime<Local>)>,
+   samples:    RideHistory<RideSample>,
+   rolling:    VecDeque<f64>, // the last NORMALIZED_WINDOW powers
+   normalized: (f64, usize), // sum of the fourth powers of the rolling averages and their count
+   tss:        i32,
+   is_written: bool,
+}
+
+impl RideLog
+{
+   pub fn reset(&mut self) { *self = Self::default(); }
+
+   /// Record the latest rider data. A jump backwards (new ride or restart) starts a new log.
+   pub fn update(&mut self, distance: f64, rider: &RiderData)
+   //--------------------------------------------------------
+   {
+      if distance <= 0.0
+      {
+         return;
+      }
+      if self.samples.last().is_some_and(|last| distance < last.distance - 1.0)
+      {
+         self.reset();
+      }
+      let (started, _) = *self.started.get_or_insert_with(|| (Instant::now(), Local::now()));
+      let time = started.elapsed().as_secs_f64();
+      if self.samples.last().is_none_or(|last| time - last.time >= 1.0)
+      {
+         let power = rider.power.max(0) as f64;
+         self.samples.push(RideSample { time, distance, power });
+         self.rolling.push_back(power);
+         if self.rolling.len() > NORMALIZED_WINDOW
+         {
+            self.rolling.pop_front();
+         }
+         if self.rolling.len() == NORMALIZED_WINDOW
+         {
+            self.normalized.0 += (self.rolling.iter().sum::<f64>() / NORMALIZED_WINDOW as f64).powi(4);
+            self.normalized.1 += 1;
+         }
+      }
+      self.tss = rider.tss;
+   }
+
+   /// Whether the ride has been summarised, so it is only written once.
+   pub fn is_written(&self) -> bool { self.is_written }
+
+   pub fn set_written(&mut self) { self.is_written = true; }
+
+   /// Seconds into the ride at which `distance` was reached, interpolating between samples.
+   fn time_at(&self, distance: f64) -> Option<f64>
+   //----------------------------------------------
+   {
+      let mut previous: Option<RideSample> = None;
+      let (a, b) = self.samples.iter().find_map(|&s|
+      {
+         let pair = (s.distance >= distance).then(|| (previous.unwrap_or(s), s));
+         previous = Some(s);
+         pair
+      })?;
+      let t = if b.distance > a.distance { (distance - a.distance) / (b.distance - a.distance) } else { 0.0 };
+      Some(a.time + (b.time - a.time) * t)
+   }
+
+   fn average_power<'a>(samples: impl Iterator<Item = &'a RideSample>) -> f64
+   //------------------------------------------------------------------------
+   {
+      let (total, count) = samples.fold((0.0, 0), |(total, count), s| (total + s.power, count + 1));
+      if count == 0 { 0.0 } else { total / count as f64 }
+   }
+
+   /// Normalized power: the fourth root of the mean fourth power of the 30 second rolling average power.
+   fn normalized_power(&self) -> f64
+   //-------------------------------
+   {
+      match self.normalized
+      {
+         | (_, 0) => Self::average_power(self.samples.iter()),
+         | (total, count) => (total / count as f64).powf(0.25),
+      }
+   }
+
+   /// The summary of the ride so far on `track` with the `laps` completed, None if the rider has not moved far enough
+   /// for one.
+   pub fn report(&self, course: &Path, track: &Track, laps: &[LapSplit], is_complete: bool) -> Option<RideReport>
+   //-----------------------------------------------------------------------------------------------------------
+   {
+      let (first, last) = (self.samples.first()?, self.samples.last()?);
+      let (_, started) = self.started?;
+      let distance = last.distance - first.distance;
+      if distance < MIN_SUMMARY_DISTANCE
+      {
+         return None;
+      }
+      let duration = last.time - first.time;
+      let (ascent, _) = track.section(first.distance, last.distance).elevation_gain_loss(2.0);
+      let climbs = track.climbs(20.0, 2.0).into_iter()
+         .filter(|c| c.start >= first.distance && c.end <= last.distance)
+         .filter_map(|c|
+         {
+            let (start, end) = (self.time_at(c.start)?, self.time_at(c.end)?);
+            let duration = end - start;
+            Some(ClimbSplit
+            {
+               start: c.start,
+               length: c.length(),
+               gain: c.gain,
+               average_gradient: c.average_gradient,
+               duration,
+               average_speed: if duration > 0.0 { c.length() / duration * 3.6 } else { 0.0 },
+               average_power: Self::average_power(self.samples.iter().filter(|s| s.time >= start && s.time <= end)),
+            })
+         })
+         .collect();
+      Some(RideReport
+      {
+         course: course.display().to_string(),
+         started: started.to_rfc3339(),
+         start_time: started,
+         is_complete,
+         duration,
+         distance,
+         ascent,
+         average_speed: if duration > 0.0 { distance / duration * 3.6 } else { 0.0 },
+         average_power: Self::average_power(self.samples.iter()),
+         normalized_power: self.normalized_power(),
+         tss: (self.tss > 0).then_some(self.tss as f64),
+         climbs,
+         laps: laps.to_vec(),
+      })
+   }
+}
+
+/// Write a ride report to `target`: a file, a directory to create a file named after the course and start time in,
+/// or None for the directory containing the course.
+pub fn write_report(report: &RideReport, target: Option<&Path>) -> Result<PathBuf, String>
+//----------------------------------------------------------------------------------------
+{
+   let course = Path::new(&report.course);
+   let path = match target
+   {
+      | Some(path) if !path.is_dir() => path.to_path_buf(),
+      | _ =>
+      {
+         let directory = target.or_else(|| course.parent()).unwrap_or(Path::new("."));
+         let name = course.file_stem().map_or("ride".into(), |s| s.to_string_lossy());
+         directory.join(format!("{}-{}.json", name, report.start_time.format("%Y%m%d-%H%M%S")))
+      }
+   };
+   let json = serde_json::to_string_pretty(report).map_err(|e| format!("Error writing ride summary: {}", e))?;
+   fs::write(&path, json).map_err(|e| format!("Error writing ride summary {}: {}", path.display(), e))?;
+   Ok(path)
+}
@@ -0,0 +1,347 @@
+use std::{fs, path::PathBuf, sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use walkers::{MapMemory, Plugin, Position, Projector, lon_lat};
+
+use crate::{SETTINGS, components::ToastManager, gpx::{Point, Track}, http::{self, HttpError}, settings::{SURFACE_CACHE, Settings},
+            source::CancelToken, units::Units};
+
+const OVERPASS_URL: &str = "https://overpass-api.de/api/interpreter";
+/// The course is sampled this far apart and each sample matched to the nearest OSM way within MATCH_RADIUS.
+const SAMPLE_SPACING: f64 = 50.0; // metres
+const MATCH_RADIUS: f64 = 20.0; // metres
+/// Samples per Overpass request, to keep each query small enough for the public servers.
+const SAMPLES_PER_QUERY: usize = 150;
+/// Riders are warned this far before a gravel or cobbled sector.
+const WARNING_DISTANCE: f64 = 500.0; // metres
+/// Metres per degree of latitude (and of longitude at the equator) for matching over short distances.
+const METRES_PER_DEGREE: f64 = 111_320.0;
+
+/// Road surface of a part of the course.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Surface
+{
+   Paved,
+   Gravel, // any unpaved surface: gravel, compacted, dirt, grass ...
+   Cobbles,
+   Unknown, // no OSM way near the course
+}
+
+impl Surface
+{
+   /// Classify an OSM way from its tags. Roads without a surface tag are taken to be paved and tracks, paths and
+   /// bridleways unpaved, as is usual in OSM.
+   fn from_tags(tags: &serde_json::Value) -> Self
+   //--------------------------------------------
+   {
+      match tags["surface"].as_str()
+      {
+         | Some("asphalt" | "concrete" | "concrete:plates" | "concrete:lanes" | "paved" | "paving_stones" | "chipseal" | "metal"
+                | "wood") => Surface::Paved,
+         | Some("sett" | "cobblestone" | "unhewn_cobblestone" | "cobblestone:flattened") => Surface::Cobbles,
+         | Some(_) => Surface::Gravel,
+         | None => match tags["highway"].as_str()
+         {
+            | Some("track" | "path" | "bridleway") => Surface::Gravel,
+            | Some(_) => Surface::Paved,
+            | None => Surface::Unknown,
+         },
+      }
+   }
+
+   pub fn label(self) -> &'static str
+   //--------------------------------
+   {
+      match self
+      {
+         | Surface::Paved => "Paved",
+         | Surface::Gravel => "Gravel",
+         | Surface::Cobbles => "Cobbles",
+         | Surface::Unknown => "Unknown",
+      }
+   }
+
+   /// Colour of the surface on the map and under the gradient profile.
+   pub fn color(self) -> egui::Color32
+   //---------------------------------
+   {
+      match self
+      {
+         | Surface::Paved => egui::Color32::from_rgb(70, 70, 80),
+         | Surface::Gravel => egui::Color32::from_rgb(205, 145, 60),
+         | Surface::Cobbles => egui::Color32::from_rgb(150, 80, 185),
+         | Surface::Unknown => egui::Color32::TRANSPARENT,
+      }
+   }
+
+   /// Gravel and cobbles, which riders want to know about in advance.
+   pub fn is_rough(self) -> bool { matches!(self, Surface::Gravel | Surface::Cobbles) }
+}
+
+/// A stretch of the course with one surface.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SurfaceSection
+{
+   pub start:   f64, // metres
+   pub end:     f64,
+   pub surface: Surface,
+}
+
+/// An OSM way under the course, as a line of points.
+struct Way
+{
+   surface: Surface,
+   points:  Vec<Point>,
+}
+
+/// Distance in metres from `point` to the line from `a` to `b`, in a flat projection which is accurate enough over
+/// the few metres that matter.
+fn distance_to_segment(point: Point, a: Point, b: Point) -> f64
+//-------------------------------------------------------------
+{
+   let scale = point.lat.to_radians().cos();
+   let to_xy = |p: Point| ((p.lon - point.lon) * scale * METRES_PER_DEGREE, (p.lat - point.lat) * METRES_PER_DEGREE);
+   let ((ax, ay), (bx, by)) = (to_xy(a), to_xy(b));
+   let (dx, dy) = (bx - ax, by - ay);
+   let length = dx * dx + dy * dy;
+   let t = if length > 0.0 { (-(ax * dx + ay * dy) / length).clamp(0.0, 1.0) } else { 0.0 };
+   (ax + t * dx).hypot(ay + t * dy)
+}
+
+/// The ways tagged `highway` within MATCH_RADIUS of `samples`, from one Overpass query.
+async fn query_ways(samples: &[Point]) -> Result<Vec<Way>, HttpError>
+//-------------------------------------------------------------------
+{
+   let line = samples.iter().map(|p| format!("{:.6},{:.6}", p.lat, p.lon)).collect::<Vec<_>>().join(",");
+   let query = format!("[out:json][timeout:90];way(around:{MATCH_RADIUS},{line})[highway];out tags geom;");
+   let client = http::client().map_err(HttpError::Permanent)?;
+   let response = client.post(OVERPASS_URL)
+                        .form(&[("data", query)])
+                        .timeout(Duration::from_secs(120))
+                        .send()
+                        .await
+                        .map_err(|e| HttpError::from_request(&e, "Surface query"))?;
+   let status = response.status();
+   let text = response.text().await.map_err(|e| HttpError::from_request(&e, "Surface query"))?;
+   if !status.is_success()
+   {
+      return Err(HttpError::from_status(status, &text, "Surface query"));
+   }
+   let json: serde_json::Value = serde_json::from_str(&text)
+      .map_err(|e| HttpError::Permanent(format!("Failed to parse the surface query response: {e}")))?;
+   Ok(json["elements"].as_array().into_iter().flatten().filter_map(|element|
+   {
+      let points: Vec<Point> = element["geometry"].as_array()?.iter()
+                                                  .filter_map(|p| Some(Point { lat: p["lat"].as_f64()?, lon: p["lon"].as_f64()? }))
+                                                  .collect();
+      (points.len() >= 2).then(|| Way { surface: Surface::from_tags(&element["tags"]), points })
+   }).collect())
+}
+
+/// Surface of the way nearest `point`, Unknown when none is within MATCH_RADIUS.
+fn surface_at(point: Point, ways: &[Way]) -> Surface
+//--------------------------------------------------
+{
+   ways.iter()
+       .flat_map(|way| way.points.windows(2).map(move |pair| (way.surface, distance_to_segment(point, pair[0], pair[1]))))
+       .filter(|(_, distance)| *distance <= MATCH_RADIUS)
+       .min_by(|a, b| a.1.total_cmp(&b.1))
+       .map_or(Surface::Unknown, |(surface, _)| surface)
+}
+
+/// Where the surfaces of the course sampled at `samples` (distance and position) are cached, named by a hash of the
+/// samples so an edited course is queried again.
+fn cache_file(samples: &[(f64, Point)]) -> Option<PathBuf>
+//--------------------------------------------------------
+{
+   let mut hasher = Sha256::new();
+   for (_, point) in samples
+   {
+      hasher.update(format!("{:.5},{:.5};", point.lat, point.lon).as_bytes());
+   }
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   let directory = settings.lock().cache_path(SURFACE_CACHE);
+   fs::create_dir_all(&directory).ok()?;
+   Some(directory.join(format!("{}.json", hex::encode(hasher.finalize()))))
+}
+
+/// Find the surface along `track` from the cache or else OpenStreetMap, merged into sections of the same surface.
+pub async fn fetch_surfaces(track: &Track, cancel: &CancelToken) -> Result<Vec<SurfaceSection>, String>
+//------------------------------------------------------------------------------------------------------
+{
+   let total = track.total_distance();
+   let count = (total / SAMPLE_SPACING).ceil() as usize;
+   let samples: Vec<(f64, Point)> = (0..=count).filter_map(|i|
+   {
+      let distance = (i as f64 * SAMPLE_SPACING).min(total);
+      track.position_at(distance).map(|point| (distance, point))
+   }).collect();
+   let cache = cache_file(&samples);
+   if let Some(sections) = cache.as_ref().and_then(|path| fs::read_to_string(path).ok())
+                                .and_then(|json| serde_json::from_str::<Vec<SurfaceSection>>(&json).ok())
+   {
+      return Ok(sections);
+   }
+   let mut sections: Vec<SurfaceSection> = Vec::new();
+   for chunk in samples.chunks(SAMPLES_PER_QUERY)
+   {
+      let points: Vec<Point> = chunk.iter().map(|(_, point)| *point).collect();
+      let ways = http::cancellable(cancel, async
+      {
+         http::with_retry("Surface query", || query_ways(&points)).await.map_err(String::from)
+      }).await?;
+      for &(distance, point) in chunk
+      {
+         let surface = surface_at(point, &ways);
+         let (start, end) = ((distance - SAMPLE_SPACING / 2.0).max(0.0), (distance + SAMPLE_SPACING / 2.0).min(total));
+         match sections.last_mut()
+         {
+            | Some(last) if last.surface == surface => last.end = end,
+            | _ => sections.push(SurfaceSection { start, end, surface }),
+         }
+      }
+   }
+   if let Some(path) = cache
+      && let Err(e) = serde_json::to_string(&sections).map_err(|e| e.to_string())
+                                                      .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()))
+   {
+      log_warn!("Could not cache the course surfaces in {}: {e}", path.display());
+   }
+   Ok(sections)
+}
+
+/// Surfaces found for the course, with the route of each section for the map.
+#[derive(Default)]
+struct Surfaces
+{
+   sections: Vec<SurfaceSection>,
+   routes:   Vec<(Surface, Vec<Position>)>,
+}
+
+/// Fetches the road surface under the course from OpenStreetMap in the background (cached per course) to colour the
+/// route on the map and under the gradient profile, and warns before gravel and cobbled sectors.
+#[derive(Default)]
+pub struct SurfaceOverlay
+//========================
+{
+   is_enabled:  bool,
+   surfaces:    Arc<parking_lot::Mutex<Arc<Surfaces>>>,
+   is_fetching: Arc<AtomicBool>,
+   is_fetched:  bool, // for the current course, successfully or not
+   cancel:      Option<CancelToken>,
+   warned:      Option<f64>, // start of the last rough section warned about
+}
+
+impl SurfaceOverlay
+{
+   pub fn new(is_enabled: bool) -> Self { Self { is_enabled, ..Default::default() } }
+
+   pub fn set_enabled(&mut self, is_enabled: bool) { self.is_enabled = is_enabled; }
+
+   /// Forget the surfaces of the previous course.
+   pub fn reset(&mut self)
+   //---------------------
+   {
+      if let Some(cancel) = self.cancel.take()
+      {
+         cancel.cancel();
+      }
+      *self.surfaces.lock() = Arc::default();
+      self.is_fetched = false;
+      self.warned = None;
+   }
+
+   /// The surface sections overlapping `start` to `end` metres, none when disabled or not fetched yet.
+   pub fn sections_between(&self, start: f64, end: f64) -> Vec<SurfaceSection>
+   //--------------------------------------------------------------------------
+   {
+      if !self.is_enabled
+      {
+         return Vec::new();
+      }
+      self.surfaces.lock().sections.iter().filter(|s| s.end >= start && s.start <= end).copied().collect()
+   }
+
+   /// Map plugin drawing the route coloured by surface, None when disabled or not fetched yet.
+   pub fn route(&self) -> Option<SurfaceRoute>
+   //-----------------------------------------
+   {
+      let surfaces = self.surfaces.lock().clone();
+      (self.is_enabled && !surfaces.routes.is_empty()).then_some(SurfaceRoute { surfaces })
+   }
+
+   /// Start fetching the surfaces of `track` when enabled and not fetched yet, and warn once before each rough sector
+   /// ahead of `distance` metres.
+   pub fn update(&mut self, ctx: &egui::Context, track: &Arc<Track>, distance: f64, toast_manager: &mut ToastManager, units: Units)
+   //------------------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.is_enabled || track.is_empty()
+      {
+         return;
+      }
+      if !self.is_fetched && !self.is_fetching.load(Ordering::Relaxed)
+      {
+         self.is_fetched = true;
+         self.is_fetching.store(true, Ordering::Relaxed);
+         let (surfaces, is_fetching, ctx, track) = (self.surfaces.clone(), self.is_fetching.clone(), ctx.clone(), track.clone());
+         let cancel = CancelToken::default();
+         self.cancel = Some(cancel.clone());
+         http::spawn(async move
+         {
+            match fetch_surfaces(&track, &cancel).await
+            {
+               | Ok(sections) if !cancel.is_cancelled() =>
+               {
+                  let routes = sections.iter().filter(|s| s.surface != Surface::Unknown).map(|s|
+                  {
+                     (s.surface, track.section(s.start, s.end).iter().map(|p| lon_lat(p.point.lon, p.point.lat)).collect())
+                  }).collect();
+                  log_info!("Found {} surface sections, {} rough", sections.len(), sections.iter().filter(|s| s.surface.is_rough()).count());
+                  *surfaces.lock() = Arc::new(Surfaces { sections, routes });
+               }
+               | Ok(_) => (),
+               | Err(_) if cancel.is_cancelled() => (),
+               | Err(e) => log_warn!("Could not find the course surfaces: {e}"),
+            }
+            is_fetching.store(false, Ordering::Relaxed);
+            ctx.request_repaint();
+         });
+      }
+      let surfaces = self.surfaces.lock().clone();
+      let next = surfaces.sections.iter().find(|s| s.surface.is_rough() && s.end > distance);
+      if let Some(section) = next
+         && section.start - distance <= WARNING_DISTANCE
+         && self.warned != Some(section.start)
+      {
+         self.warned = Some(section.start);
+         let length = units.format_distance(section.end - section.start, 1);
+         let message = if section.start <= distance { format!("{} sector, {length} long", section.surface.label()) }
+                       else { format!("{} sector in {}, {length} long", section.surface.label(), units.format_length(section.start - distance)) };
+         toast_manager.info(message, Some(Duration::from_secs(6)));
+      }
+   }
+}
+
+/// Walkers Plugin drawing the course coloured by surface under the rider marker.
+pub struct SurfaceRoute
+//=====================
+{
+   surfaces: Arc<Surfaces>,
+}
+
+impl Plugin for SurfaceRoute
+{
+   fn run(self: Box<Self>, ui: &mut egui::Ui, response: &egui::Response, projector: &Projector, _map_memory: &MapMemory)
+   //--------------------------------------------------------------------------------------------------------------------
+   {
+      let painter = ui.painter_at(response.rect);
+      for (surface, route) in &self.surfaces.routes
+      {
+         let width = if surface.is_rough() { 6.0 } else { 3.0 };
+         let points: Vec<egui::Pos2> = route.iter().map(|p| projector.project(*p).to_pos2()).collect();
+         painter.add(egui::Shape::line(points, egui::Stroke::new(width, surface.color())));
+      }
+   }
+}
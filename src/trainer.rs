@@ -0,0 +1,314 @@
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use btleplug::{api::{Central, Manager as _, Peripheral as _, ScanFilter, WriteType, bleuuid::uuid_from_u16},
+               platform::{Adapter, Manager, Peripheral}};
+use crossbeam::atomic::AtomicCell;
+use eframe::egui;
+use futures::StreamExt;
+use uuid::Uuid;
+
+use crate::{settings::TrainerSettings, source::CancelToken, ui::Theme};
+
+/// Bluetooth Fitness Machine Service and the characteristics used from it.
+const FTMS_SERVICE: Uuid = uuid_from_u16(0x1826);
+const INDOOR_BIKE_DATA: Uuid = uuid_from_u16(0x2AD2);
+const CONTROL_POINT: Uuid = uuid_from_u16(0x2AD9);
+/// Control point op codes.
+const REQUEST_CONTROL: u8 = 0x00;
+const START_OR_RESUME: u8 = 0x07;
+const SET_SIMULATION: u8 = 0x11;
+/// Rolling resistance (×0.0001) and wind resistance (kg/m ×0.01) sent with the gradient, for road tyres and a rider
+/// on the hoods.
+const CRR: u8 = 40;
+const CW: u8 = 51;
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+/// Trainers drop simulation mode if not updated, so the gradient is resent at least this often.
+const RESEND_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Latest values from the trainer's Indoor Bike Data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrainerReading
+{
+   pub is_connected: bool,
+   pub power:        i32, // W
+   pub speed:        f64, // km/h
+   pub cadence:      i32, // rpm
+   pub heartrate:    i32, // bpm, when the trainer relays a heart rate strap
+}
+
+/// Controls a smart trainer over Bluetooth FTMS: the course gradient at the rider position is sent in simulation mode
+/// so the resistance follows the course, and the trainer power and speed are read back to drive the simulation when
+/// the game is not running.
+#[derive(Default)]
+pub struct TrainerControl
+//========================
+{
+   config:   TrainerSettings,
+   gradient: Arc<AtomicCell<f64>>, // percent, after the difficulty scaling
+   reading:  Arc<AtomicCell<TrainerReading>>,
+   name:     Arc<parking_lot::Mutex<String>>, // of the connected trainer
+   cancel:   Option<CancelToken>,
+}
+
+impl TrainerControl
+{
+   pub fn new(config: TrainerSettings) -> Self
+   //-----------------------------------------
+   {
+      let mut trainer = Self::default();
+      trainer.configure(config);
+      trainer
+   }
+
+   /// Connect to the trainer when enabled, restarting the connection when the device changes.
+   pub fn configure(&mut self, config: TrainerSettings)
+   //--------------------------------------------------
+   {
+      let is_changed = config.is_enabled != self.config.is_enabled || config.device_name != self.config.device_name;
+      self.config = config;
+      if !is_changed
+      {
+         return;
+      }
+      if let Some(cancel) = self.cancel.take()
+      {
+         cancel.cancel();
+      }
+      self.reading.store(TrainerReading::default());
+      if self.config.is_enabled
+      {
+         let cancel = CancelToken::default();
+         let (device_name, gradient, reading, name, thread_cancel) =
+            (self.config.device_name.trim().to_lowercase(), self.gradient.clone(), self.reading.clone(), self.name.clone(), cancel.clone());
+         let result = std::thread::Builder::new().name("trainer".to_string()).spawn(move ||
+         {
+            let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build()
+            {
+               | Ok(runtime) => runtime,
+               | Err(e) =>
+               {
+                  log_error!("Failed to start the trainer connection: {e}");
+                  return;
+               }
+            };
+            runtime.block_on(run(&device_name, gradient, reading, name, thread_cancel));
+         });
+         match result
+         {
+            | Ok(_) => self.cancel = Some(cancel),
+            | Err(e) => log_error!("Failed to start the trainer thread: {e}"),
+         }
+      }
+   }
+
+   /// Set the gradient (percent) the trainer should simulate.
+   pub fn update(&self, gradient: f64)
+   //---------------------------------
+   {
+      if self.config.is_enabled
+      {
+         self.gradient.store(gradient * self.config.difficulty as f64 / 100.0);
+      }
+   }
+
+   /// Shared trainer readings, e.g. for the simulation thread.
+   pub fn readings(&self) -> Arc<AtomicCell<TrainerReading>> { self.reading.clone() }
+
+   /// Status bar item showing whether the trainer is connected.
+   pub fn show(&self, ui: &mut egui::Ui, theme: &Theme)
+   //--------------------------------------------------
+   {
+      if !self.config.is_enabled
+      {
+         return;
+      }
+      ui.separator();
+      ui.label(egui::RichText::new("Trainer:").color(theme.label_color).size(16.0));
+      if self.reading.load().is_connected
+      {
+         ui.label(egui::RichText::new(format!("{:.1}%", self.gradient.load())).strong().size(16.0))
+           .on_hover_text(format!("Gradient sent to {}", self.name.lock()));
+      }
+      else
+      {
+         ui.label(egui::RichText::new("searching").size(16.0)).on_hover_text("Looking for a Bluetooth FTMS smart trainer");
+      }
+   }
+}
+
+impl Drop for TrainerControl
+{
+   fn drop(&mut self)
+   {
+      if let Some(cancel) = self.cancel.take()
+      {
+         cancel.cancel();
+      }
+   }
+}
+
+/// Keep connecting to the trainer until cancelled.
+async fn run(device_name: &str, gradient: Arc<AtomicCell<f64>>, reading: Arc<AtomicCell<TrainerReading>>,
+             name: Arc<parking_lot::Mutex<String>>, cancel: CancelToken)
+//-------------------------------------------------------------------------------------------------------
+{
+   let mut last_error = String::new();
+   while !cancel.is_cancelled()
+   {
+      if let Err(e) = connect_and_control(device_name, &gradient, &reading, &name, &cancel).await
+         && e != last_error
+      {  // Only log each problem once while it keeps happening, e.g. the trainer switched off
+         log_warn!("Trainer: {e}");
+         last_error = e;
+      }
+      reading.store(TrainerReading::default());
+      let start = Instant::now();
+      while !cancel.is_cancelled() && start.elapsed() < RECONNECT_INTERVAL
+      {
+         tokio::time::sleep(Duration::from_millis(250)).await;
+      }
+   }
+}
+
+/// Find the trainer, take control of it and send the gradient until disconnected or cancelled.
+async fn connect_and_control(device_name: &str, gradient: &AtomicCell<f64>, reading: &AtomicCell<TrainerReading>,
+                             name: &parking_lot::Mutex<String>, cancel: &CancelToken) -> Result<(), String>
+//--------------------------------------------------------------------------------------------------------------
+{
+   let manager = Manager::new().await.map_err(|e| format!("Bluetooth is not available: {e}"))?;
+   let adapter = manager.adapters().await.map_err(|e| e.to_string())?.into_iter().next().ok_or("No Bluetooth adapter found")?;
+   adapter.start_scan(ScanFilter { services: vec![FTMS_SERVICE] }).await.map_err(|e| format!("Bluetooth scan failed: {e}"))?;
+   let trainer = find_trainer(&adapter, device_name, cancel).await;
+   let _ = adapter.stop_scan().await;
+   let Some((trainer, trainer_name)) = trainer? else { return Ok(()); };
+
+   trainer.connect().await.map_err(|e| format!("Could not connect to {trainer_name}: {e}"))?;
+   trainer.discover_services().await.map_err(|e| e.to_string())?;
+   let characteristics = trainer.characteristics();
+   let control_point = characteristics.iter().find(|c| c.uuid == CONTROL_POINT)
+                                      .ok_or_else(|| format!("{trainer_name} does not support FTMS control"))?;
+   // The control point answers with indications, which some trainers require to be enabled before accepting commands
+   trainer.subscribe(control_point).await.map_err(|e| e.to_string())?;
+   if let Some(bike_data) = characteristics.iter().find(|c| c.uuid == INDOOR_BIKE_DATA)
+   {
+      trainer.subscribe(bike_data).await.map_err(|e| e.to_string())?;
+   }
+   let mut notifications = trainer.notifications().await.map_err(|e| e.to_string())?;
+   for op_code in [REQUEST_CONTROL, START_OR_RESUME]
+   {
+      trainer.write(control_point, &[op_code], WriteType::WithResponse).await.map_err(|e| format!("{trainer_name} refused control: {e}"))?;
+   }
+   log_info!("Connected to trainer {trainer_name}");
+   *name.lock() = trainer_name.clone();
+   reading.store(TrainerReading { is_connected: true, ..Default::default() });
+
+   let mut sent: Option<(f64, Instant)> = None;
+   let mut interval = tokio::time::interval(Duration::from_millis(500));
+   let result = loop
+   {
+      tokio::select!
+      {
+         notification = notifications.next() =>
+         {
+            match notification
+            {
+               | Some(notification) if notification.uuid == INDOOR_BIKE_DATA =>
+               {
+                  if let Some(data) = parse_indoor_bike_data(&notification.value)
+                  {
+                     reading.store(data);
+                  }
+               }
+               | Some(_) => (),
+               | None => break Err(format!("Lost the connection to {trainer_name}")),
+            }
+         }
+         _ = interval.tick() =>
+         {
+            if cancel.is_cancelled()
+            {
+               break Ok(());
+            }
+            if !trainer.is_connected().await.unwrap_or(false)
+            {
+               break Err(format!("Lost the connection to {trainer_name}"));
+            }
+            let grade = (gradient.load().clamp(-40.0, 40.0) * 100.0).round() / 100.0;
+            let is_due = sent.is_none_or(|(last, time)| (grade - last).abs() >= 0.1 || time.elapsed() >= RESEND_INTERVAL);
+            if is_due
+            {
+               if let Err(e) = trainer.write(control_point, &simulation_command(grade), WriteType::WithResponse).await
+               {
+                  break Err(format!("Could not send the gradient to {trainer_name}: {e}"));
+               }
+               sent = Some((grade, Instant::now()));
+            }
+         }
+      }
+   };
+   let _ = trainer.disconnect().await;
+   result
+}
+
+/// Wait for an FTMS trainer whose name contains `device_name` (any trainer when empty) to be seen.
+async fn find_trainer(adapter: &Adapter, device_name: &str, cancel: &CancelToken) -> Result<Option<(Peripheral, String)>, String>
+//-----------------------------------------------------------------------------------------------------------------------------
+{
+   while !cancel.is_cancelled()
+   {
+      for peripheral in adapter.peripherals().await.map_err(|e| e.to_string())?
+      {
+         let Ok(Some(properties)) = peripheral.properties().await else { continue; };
+         let name = properties.local_name.unwrap_or_else(|| peripheral.address().to_string());
+         if properties.services.contains(&FTMS_SERVICE) && (device_name.is_empty() || name.to_lowercase().contains(device_name))
+         {
+            return Ok(Some((peripheral, name)));
+         }
+      }
+      tokio::time::sleep(Duration::from_secs(1)).await;
+   }
+   Ok(None)
+}
+
+/// Set Indoor Bike Simulation Parameters: wind speed (0.001 m/s), grade (0.01%), rolling and wind resistance.
+fn simulation_command(grade: f64) -> Vec<u8>
+//------------------------------------------
+{
+   let mut command = vec![SET_SIMULATION];
+   command.extend_from_slice(&0i16.to_le_bytes());
+   command.extend_from_slice(&((grade * 100.0) as i16).to_le_bytes());
+   command.extend_from_slice(&[CRR, CW]);
+   command
+}
+
+/// Speed, cadence, power and heart rate from an Indoor Bike Data notification, whose flags say which of the optional
+/// fields follow in order.
+fn parse_indoor_bike_data(data: &[u8]) -> Option<TrainerReading>
+//--------------------------------------------------------------
+{
+   fn take<'a>(data: &'a [u8], offset: &mut usize, size: usize) -> Option<&'a [u8]>
+   {
+      let bytes = data.get(*offset..*offset + size)?;
+      *offset += size;
+      Some(bytes)
+   }
+   let flags = u16::from_le_bytes([*data.first()?, *data.get(1)?]);
+   let mut offset = 2;
+   let mut field = |size: usize| take(data, &mut offset, size);
+   let u16_at = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]);
+   let mut reading = TrainerReading { is_connected: true, ..Default::default() };
+   if flags & 0x0001 == 0
+   {  // "More data" clear means the instantaneous speed is present
+      reading.speed = u16_at(field(2)?) as f64 / 100.0;
+   }
+   if flags & 0x0002 != 0 { field(2)?; }                                  // average speed
+   if flags & 0x0004 != 0 { reading.cadence = (u16_at(field(2)?) / 2) as i32; }
+   if flags & 0x0008 != 0 { field(2)?; }                                  // average cadence
+   if flags & 0x0010 != 0 { field(3)?; }                                  // total distance
+   if flags & 0x0020 != 0 { field(2)?; }                                  // resistance level
+   if flags & 0x0040 != 0 { reading.power = u16_at(field(2)?) as i16 as i32; }
+   if flags & 0x0080 != 0 { field(2)?; }                                  // average power
+   if flags & 0x0100 != 0 { field(5)?; }                                  // expended energy
+   if flags & 0x0200 != 0 { reading.heartrate = *field(1)?.first()? as i32; }
+   Some(reading)
+}
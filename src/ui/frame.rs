@@ -1,15 +1,28 @@
-use std::{future::Future, path::PathBuf, sync::{Arc, atomic::Ordering, mpsc::Sender}, time::Duration};
+use std::{collections::HashMap, future::Future, path::{Path, PathBuf}, sync::{Arc, atomic::Ordering, mpsc::Sender}, time::Duration};
 
-use eframe::egui::{self, Color32, ColorImage, Context, Frame, Image, Vec2};
+use eframe::egui::{self, Color32, ColorImage, Context, Frame, Image, TextureHandle, Vec2};
 use walkers::{lon_lat, Map};
-use tiny_skia::{Pixmap, Paint, PathBuilder, Stroke, Transform, FillRule};
+use tiny_skia::{Pixmap, PixmapPaint, Paint, PathBuilder, Stroke, Transform, FillRule};
+use rayon::prelude::*;
 
-use crate::{components::DirectionalArrow, data::{RiderData, RiderDataJSON}, gpx::{TrackPoint, find_closest_point, process_gpx}};
+use crate::{components::{DirectionalArrow, Labelled, Toast, ToastLevel, WaypointMarkers, draw_rider_marker, draw_wind_arrow, splits_grid}, data::{RiderData, RiderDataJSON}, gpx::{Track, TrackPoint, load_track}};
 use eframe::emath::Numeric;
 use crate::SETTINGS;
-use crate::settings::Settings;
-
-use super::ui::{GPXAssistUI, ViewMode};
+use crate::simulation::RideRecording;
+use crate::precache::StreetViewPrecache;
+use crate::compare::ComparedCourse;
+use crate::surface::{Surface, SurfaceSection};
+use crate::climbs::ClimbAction;
+use crate::gpx::Climb;
+use crate::server::CourseState;
+use crate::source::{CancelToken, SourceKind};
+use crate::http::{self, HttpError};
+use crate::units::Units;
+use crate::slope::DRIFT_WARNING;
+use crate::settings::{MarkerStyle, RiderMarker, STREETVIEW_CACHE, Settings};
+use sha2::{Digest, Sha256};
+
+use super::{theme::Theme, ui::{BROADCAST_CHECK_INTERVAL, GPXAssistUI, GpxLoading, LoadedCourse, StreetViewFetch, ToolbarItem, ViewMode}};
 
 impl eframe::App for GPXAssistUI
 //==============================
@@ -18,47 +31,70 @@ impl eframe::App for GPXAssistUI
    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame)
    //------------------------------------------------------------------
    {
-      set_style(ctx);
-      egui::TopBottomPanel::top("top_panel").resizable(true).min_height(36.0)
-      .frame(Frame::new().fill(egui::Color32::from_rgb(169, 157, 133)))
-      .show(ctx, |ui|
+      set_style(ctx, &self.theme, self.is_touch_mode);
+      handle_dropped_files(self, ctx);
+      handle_window_shortcuts(self, ctx);
+      self.check_settings_reload();
+      self.flush_settings(ctx);
+      self.update_title(ctx);
+      if self.source_manager.has_finished(SourceKind::Simulation) || self.source_manager.has_finished(SourceKind::Replay)
+      {  // The simulation reached the end of the course (or recording)
+         self.start_broadcast_source(ctx);
+      }
+      if let Ok(release) = self.update_channel.1.try_recv()
+      {
+         self.toast_manager.add(Toast::new(format!("GPXAssist {} is available (you have {}).", release.version, env!("CARGO_PKG_VERSION")),
+                                           ToastLevel::Info).indefinite().with_link("Open the release page", release.url));
+      }
+      let is_overlay_mode = self.is_overlay_mode;
+      let is_toolbar_collapsed = self.is_toolbar_collapsed;
+      egui::TopBottomPanel::top("top_panel").resizable(!is_toolbar_collapsed).min_height(if is_toolbar_collapsed { 14.0 } else { 36.0 })
+      .frame(Frame::new().fill(self.theme.top_panel_fill))
+      .show_animated(ctx, !is_overlay_mode, |ui|
       {
          if let Ok(tt) = self.open_dialog_channel.1.try_recv() // new GPX file opened
          {
             if !tt.0.is_empty()
             {
-               let (trackdata, filepath) = tt;
+               let (trackdata, waypoints, filepath) = tt;
+               // Wait for the thread reading the previous course before replacing it
+               self.source_manager.shutdown();
                self.gpx_file = Some(PathBuf::from(&filepath));
                self.total_distance = trackdata.last().map_or(0.0, |p| p.distance);
                self.current_distance = 0.0;
                self.updated_distance.store(0.0);
+               self.start_offset.store(0.0);
+               self.sim_start_distance = 0.0;
+               self.ride_progress.reset();
+               self.course_stats.reset();
+               self.ride_completion.reset();
+               self.ride_log.reset();
+               self.lap_timer.reset();
+               self.climb_alerter.reset();
+               self.slope_monitor.reset();
+               self.weather.reset();
+               self.surface_overlay.reset();
+               self.climb_preview = None;
+               self.pacing_planner.reset();
+               cancel_streetview(self);
+               self.milestones.reset();
+               self.speech.reset();
+               self.discord.reset();
+               self.video.open_for_course(Path::new(&filepath));
                self.is_first_map_frame = true;
                // self.first_map_count = 3;
                self.is_first_street_frame = true;
                self.current_position = trackdata.first().copied(); //.map(|p| *p);
                self.previous_position = self.current_position;
-               self.gpx_track = Arc::new(trackdata);
-               self.current_mode = Arc::new(crossbeam::atomic::AtomicCell::new(ViewMode::Map));
-               self.is_simulating.store(false, Ordering::Relaxed);
-               match PathBuf::from(&filepath).file_name()
+               self.gpx_track = Arc::new(Track::from(trackdata));
+               self.waypoints = Arc::new(waypoints);
+               self.streetview_precache = StreetViewPrecache::load(Path::new(&filepath));
+               if let Some(server) = &self.live_server
                {
-                  | Some(name) =>
-                  {
-                     let title = "GPXAssist: ".to_string() + &name.to_string_lossy();
-                     ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
-                  },
-                  | None => ()
+                  server.set_course(Some(CourseState::new(Path::new(&filepath), &self.gpx_track)));
                }
-               self.is_running.store(true, Ordering::Relaxed);
-               let current_mode = self.current_mode.clone();
-               let updated_distance = self.updated_distance.clone();
-               let requested_delta = self.requested_delta.clone();
-               let gradient_delta = self.gradient_delta.clone();
-               let rider_data = self.rider_data.clone();
-               let total_distance = self.total_distance;
-               let is_running = self.is_running.clone();
-               let track = self.gpx_track.clone();
-               let ctxx = ctx.clone();
+               self.current_mode = Arc::new(crossbeam::atomic::AtomicCell::new(self.startup_view.take().unwrap_or(ViewMode::Map)));
+               self.is_simulating.store(false, Ordering::Relaxed);
                self.is_first_map_frame = false;
                self.is_first_street_frame = false;
                self.is_first_gradient_frame = false;
@@ -86,10 +122,7 @@ impl eframe::App for GPXAssistUI
                   if vertical_exaggeration < 1.0 || vertical_exaggeration > 50.0 { vertical_exaggeration = 10.0; }
                   self.vertical_scale.store(vertical_exaggeration);
                }
-               std::thread::spawn(move ||
-               {
-                  GPXAssistUI::update_distance_thread(ctxx, updated_distance, track, requested_delta, gradient_delta, rider_data, total_distance, current_mode, is_running);
-               });
+               self.start_broadcast_source(ctx);
             }
             else
             {
@@ -97,13 +130,34 @@ impl eframe::App for GPXAssistUI
             }
          }
 
+         if is_toolbar_collapsed
+         {
+            if ui.add(egui::Button::new(egui::RichText::new("⏷").size(12.0)).frame(false))
+                 .labelled("Show the toolbar")
+                 .on_hover_text("Show the toolbar")
+                 .clicked()
+            {
+               self.is_toolbar_collapsed = false;
+               save_toolbar(self);
+            }
+            return;
+         }
+
          ui.horizontal(|ui|
          {
+            if ui.add(egui::Button::new(egui::RichText::new("⏶").size(16.0)).frame(false))
+                 .labelled("Collapse the toolbar")
+                 .on_hover_text("Collapse the toolbar to a thin strip")
+                 .clicked()
+            {
+               self.is_toolbar_collapsed = true;
+               save_toolbar(self);
+            }
             if let Some((texture, size)) = self.textures.get("settings")
                && ui.add(egui::Button::image(egui::Image::new(texture)
                      .alt_text("Settings")
-                     .bg_fill(egui::Color32::from_rgb(232, 227, 209))
-                     .fit_to_exact_size((*size).into()))).clicked()
+                     .bg_fill(self.theme.button_fill)
+                     .fit_to_exact_size((*size).into()))).labelled("Settings").on_hover_text("Settings").clicked()
             {
                let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
                let mut settings_lock = settings.lock();
@@ -117,140 +171,158 @@ impl eframe::App for GPXAssistUI
             if let Some((texture, size)) = self.textures.get("open")
                && ui.add(egui::Button::image(egui::Image::new(texture)
                      .alt_text("Open")
-                     .bg_fill(egui::Color32::from_rgb(232, 227, 209))
-                     .fit_to_exact_size((*size).into()))).clicked()
+                     .bg_fill(self.theme.button_fill)
+                     .fit_to_exact_size((*size).into()))).labelled("Open a GPX file").on_hover_text("Open a GPX file").clicked()
             {
                let sender = self.open_dialog_channel.0.clone();
-               open_file_dialog(ui.ctx(), sender);
+               open_file_dialog(ui.ctx(), sender, self.gpx_loading.clone());
             }
-
-            if self.gpx_file.is_some() && self.total_distance > 0.0
+            if ui.add(egui::Button::new(egui::RichText::new("📚").size(24.0)).selected(self.course_library.is_open))
+                 .labelled("Course library")
+                 .on_hover_text("Open the course library")
+                 .clicked()
             {
-               let mut dist: f64 = self.requested_delta.load();
-               ui.label(egui::RichText::new("Refresh:").color(egui::Color32::YELLOW).strong());
-               let distance_response = ui.add_sized(
-                  egui::Vec2::new(80.0, 30.0), // Fixed size: width = 80, height = 30
-                  egui::DragValue::new(&mut dist)
-                     .suffix("m")
-                     .range(0.0..=1000.0)
-                     .min_decimals(0)
-                     .max_decimals(0)
-                     .speed(1.0)
-                     .clamp_existing_to_range(true))
-               .on_hover_text("The distance in metres to travel before updating the current view. Drag with mouse or enter a value.");
-               if distance_response.dragged() || distance_response.changed()
-               {
-                  self.requested_delta.store(dist);
-                  println!("Requested Distance Delta set to {:.2} meters", dist);
-               }
-               ui.separator();
-
-               let mut current_mode = self.current_mode.load();
-               let before_mode = self.current_mode.load();
-               ui.selectable_value(&mut current_mode, ViewMode::Map,
-                  egui::RichText::new("Map").color(egui::Color32::LIGHT_YELLOW));
-               ui.selectable_value(&mut current_mode, ViewMode::StreetView,
-                  egui::RichText::new("StreetView").color(egui::Color32::LIGHT_YELLOW));
-               ui.selectable_value(&mut current_mode, ViewMode::Gradient,
-                  egui::RichText::new("Gradient").color(egui::Color32::LIGHT_YELLOW));
-               if before_mode != current_mode
+               if self.course_library.is_open { self.course_library.is_open = false; } else { self.course_library.open(ctx); }
+            }
+            if ui.add(egui::Button::new(egui::RichText::new("⛰").size(24.0)).selected(self.profile_comparison.is_open))
+                 .labelled("Compare profiles")
+                 .on_hover_text("Compare the elevation profiles of two GPX files")
+                 .clicked()
+            {
+               if self.profile_comparison.is_open { self.profile_comparison.is_open = false; }
+               else
                {
-                  self.current_mode.store(current_mode);
-                  if before_mode == ViewMode::Map
-                  {
-                     self.is_first_map_frame = false;
-                  }
-                  if before_mode == ViewMode::StreetView
-                  {
-                     self.is_first_street_frame = false;
-                  }
-                  if before_mode == ViewMode::Gradient
-                  {
-                     self.is_first_gradient_frame = false;
-                  }
-                  if current_mode == ViewMode::Map
-                  {
-                     self.is_first_map_frame = true;
-                  }
-                  if current_mode == ViewMode::StreetView
-                  {
-                     self.is_first_street_frame = true;
-                  }
-                  if current_mode == ViewMode::Gradient
+                  let current = self.gpx_file.as_ref().filter(|_| !self.gpx_track.is_empty()).map(|path|
                   {
-                     self.is_first_gradient_frame = true;
-                  }
-               }
-               ui.separator();
-               ui.add_space(100.0);
-
-               let mut speed: f64 = self.simulated_speed.load();
-               ui.label(egui::RichText::new("Speed:").color(egui::Color32::YELLOW).strong());
-               let speed_response = ui.add_sized(
-                  egui::Vec2::new(60.0, 30.0), // Fixed size: width = 60, height = 30
-                  egui::DragValue::new(&mut speed)
-                     .range(0.0..=200.0)
-                     .min_decimals(0)
-                     .max_decimals(0)
-                     .speed(1.0)
-                     .clamp_existing_to_range(true))
-               .on_hover_text("The speed in km/h when simulating. Drag with mouse or enter a value.");
-               if speed_response.dragged() || speed_response.changed()
-               {
-                  self.simulated_speed.store(speed);
-                  println!("Simulated speed set to {:.2} meters", speed);
+                     let name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+                     ComparedCourse::new(name, path.clone(), self.gpx_track.clone())
+                  });
+                  self.profile_comparison.open(current);
                }
+            }
+            if self.gpx_file.is_some()
+               && ui.add(egui::Button::new(egui::RichText::new("📊").size(24.0)).selected(self.gradient_statistics.is_open))
+                    .labelled("Gradient statistics")
+                    .on_hover_text("Distance and climbing in each gradient band and the steepest stretch of the course")
+                    .clicked()
+            {
+               self.gradient_statistics.is_open = !self.gradient_statistics.is_open;
+            }
+            if self.gpx_file.is_some()
+               && ui.add(egui::Button::new(egui::RichText::new("📈").size(24.0)).selected(self.climb_list.is_open))
+                    .labelled("Climbs")
+                    .on_hover_text("List the climbs on the course and show one in the views")
+                    .clicked()
+            {
+               self.climb_list.is_open = !self.climb_list.is_open;
+            }
+            if self.gpx_file.is_some()
+               && ui.add(egui::Button::new(egui::RichText::new("⚡").size(24.0)).selected(self.pacing_planner.is_open))
+                    .labelled("Pacing plan")
+                    .on_hover_text("Plan power targets for each climb, flat and descent from your FTP and a target intensity or time")
+                    .clicked()
+            {
+               self.pacing_planner.is_open = !self.pacing_planner.is_open;
+            }
 
-               if self.is_simulating.load(Ordering::Relaxed) && ! self.is_running.load(Ordering::Relaxed)
-               {
-                  if let Some((texture, size)) = self.textures.get("test-off")
-                     && ui.add(egui::Button::image(egui::Image::new(texture)
-                        .alt_text("Stop Test")
-                        .bg_fill(egui::Color32::from_rgb(190, 190, 190))
-                        .fit_to_exact_size((*size).into())).selected(true))
-                        .on_hover_text("Stop simulating movement along the GPX track.")
-                  .clicked()
-                  {  // Stop Simulation button
-                     self.is_simulating.store(false, Ordering::Relaxed);
-                     self.is_running.store(true, Ordering::Relaxed);
-                  }
-               }
-               else if  ! self.is_simulating.load(Ordering::Relaxed)
-                        && let Some((texture, size)) = self.textures.get("test-on")
-                        && self.total_distance > 0.0
-                        && ui.add(egui::Button::image(egui::Image::new(texture)
-                              .alt_text("Test")
-                              .bg_fill(egui::Color32::from_rgb(232, 227, 209))
-                              .fit_to_exact_size((*size).into())).selected(false))
-                              .on_hover_text("Start simulating movement along the GPX track at 45km/h.")
-               .clicked()
+            let is_fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+            if ui.add(egui::Button::new(egui::RichText::new("⛶").size(24.0)).selected(is_fullscreen))
+                 .labelled("Fullscreen")
+                 .on_hover_text("Toggle fullscreen (F11). F10 toggles the window border.")
+                 .clicked()
+            {
+               ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!is_fullscreen));
+            }
+            if self.gpx_file.is_some()
+               && ui.add_enabled(!self.view_capture.is_pending(), egui::Button::new(egui::RichText::new("📷").size(24.0)))
+                    .labelled("Screenshot")
+                    .on_hover_text("Save a screenshot of the view with the distance and gradient, and copy it to the clipboard")
+                    .clicked()
+            {
+               self.view_capture.request(ctx);
+            }
+            self.toast_manager.history_button(ui);
+            let problems = crate::logging::problem_count();
+            let log_text = if problems > 0 { format!("📋{problems}") } else { "📋".to_string() };
+            if ui.add(egui::Button::new(egui::RichText::new(log_text).size(20.0)).selected(self.show_log_console))
+                 .labelled(&format!("Log console, {problems} problems"))
+                 .on_hover_text("Show the log console")
+                 .clicked()
+            {
+               self.show_log_console = !self.show_log_console;
+            }
+
+            if self.gpx_file.is_some() && self.total_distance > 0.0
+            {
+               let items: Vec<ToolbarItem> = self.toolbar_items.iter().filter(|(_, is_visible)| *is_visible).map(|(item, _)| *item).collect();
+               for item in items
                {
-                  self.is_simulating.store(true, Ordering::Relaxed);
-                  self.is_running.store(false, Ordering::Relaxed);
-                  let updated_distance = self.updated_distance.clone();
-                  let rider_data = self.rider_data.clone();
-                  let requested_delta = self.requested_delta.clone();
-                  let gradient_delta = self.gradient_delta.clone();
-                  let simulated_speed = self.simulated_speed.clone();
-                  let total_distance = self.total_distance;
-                  let is_running = self.is_running.clone();
-                  let is_sim_running = self.is_simulating.clone();
-                  let current_mode = self.current_mode.clone();
-                  let track = self.gpx_track.clone();
-                  let ctxx = ctx.clone();
-                  std::thread::spawn(move ||
+                  ui.separator();
+                  match item
                   {
-                     GPXAssistUI::simulate_movement_thread(ctxx, updated_distance, track, requested_delta, gradient_delta, simulated_speed, rider_data, total_distance,
-                        current_mode, is_sim_running, is_running);
-                  });
+                     | ToolbarItem::Refresh => toolbar_refresh(self, ui),
+                     | ToolbarItem::Offset => toolbar_offset(self, ui),
+                     | ToolbarItem::Mode => toolbar_mode(self, ui),
+                     | ToolbarItem::Speed => toolbar_speed(self, ui),
+                     | ToolbarItem::Simulate => toolbar_simulate(self, ui),
+                  }
                }
             }
-         })
-      } );
+            ui.separator();
+            toolbar_customize_menu(self, ui);
+         });
+      });
+
+      if ! is_overlay_mode
+      {
+         crate::logging::show_log_console(ctx, &mut self.show_log_console);
+         self.profile_comparison.show(ctx, self.units);
+         self.gradient_statistics.show(ctx, &self.gpx_track, &self.theme, self.units);
+         match self.climb_list.show(ctx, &self.gpx_track, self.updated_distance.load(), self.climb_preview.is_some(), self.units)
+         {
+            | Some(ClimbAction::Show(climb)) => show_climb(self, climb),
+            | Some(ClimbAction::FollowRider) => follow_rider(self),
+            | None => (),
+         }
+         self.pacing_planner.show(ctx, &self.gpx_track, self.updated_distance.load(), &self.rider_model, self.units);
+         let is_simulating = self.is_simulating.load(Ordering::Relaxed);
+         let matched = if is_simulating { None }
+                       else { self.course_matcher.update(ctx, self.gpx_file.as_deref(), &self.gpx_track, self.units) };
+         if let Some(path) = self.course_library.show(ctx, self.units).or(matched)
+         {
+            let (sender, loading) = (self.open_dialog_channel.0.clone(), self.gpx_loading.clone());
+            let ctxx = ctx.clone();
+            std::thread::spawn(move || load_gpx_file(&path, &sender, &loading, &ctxx));
+         }
+      }
+      let overlay_fill = if self.is_overlay_transparent { Color32::TRANSPARENT } else { self.overlay_background };
+      if self.gpx_file.is_some() && self.total_distance > 0.0
+      {
+         let status_frame = if is_overlay_mode { Frame::new().fill(overlay_fill).inner_margin(8.0) }
+                            else { Frame::side_top_panel(&ctx.style()) };
+         egui::TopBottomPanel::bottom("status_bar").frame(status_frame).show(ctx, |ui| show_status_bar(self, ui));
+         egui::TopBottomPanel::top("progress_panel").exact_height(18.0).show_animated(ctx, !is_overlay_mode, |ui|
+         {
+            let distance = self.updated_distance.load();
+            self.ride_progress.update(distance);
+            self.ride_progress.show(ui, distance, self.total_distance, &self.theme, self.units);
+         });
+         let is_simulating = self.is_simulating.load(Ordering::Relaxed);
+         egui::TopBottomPanel::top("scrubber_panel").show_animated(ctx, is_simulating && !is_overlay_mode, |ui| show_scrubber(self, ui));
+      }
 
-      egui::CentralPanel::default()
+      let central_frame = if is_overlay_mode { Frame::new().fill(overlay_fill) } else { Frame::central_panel(&ctx.style()) };
+      let central = egui::CentralPanel::default().frame(central_frame)
       .show(ctx, |ui|
       {
+         if show_gpx_loading(self, ui)
+         {
+            return;
+         }
+         if self.is_touch_mode && self.gpx_file.is_some()
+         {
+            handle_touch_gestures(self, ctx, ui.max_rect());
+         }
          let (exists_broadcast_file, aged_broadcast_file) = self.check_broadcast_file();
          let broadcast_file = get_broadcast_file();
          let current_mode = self.current_mode.load();
@@ -271,7 +343,9 @@ impl eframe::App for GPXAssistUI
                   ! exists_broadcast_file || aged_broadcast_file)
          {
             let delta = self.requested_delta.load();
-            display_invalid_broadcast_directory(ui, aged_broadcast_file, delta);
+            display_invalid_broadcast_directory(ui, &self.textures, aged_broadcast_file, delta, self.units);
+            // Nothing else wakes the UI until broadcasting starts, so poll at the broadcast check interval.
+            ctx.request_repaint_after(BROADCAST_CHECK_INTERVAL);
          }
          else
          {
@@ -280,26 +354,32 @@ impl eframe::App for GPXAssistUI
             let requested_delta = self.requested_delta.load();
             let is_update = (self.updated_distance.load() - self.current_distance) >= requested_delta;
             let gradient_delta = self.gradient_delta.load();
+            let is_streetview_update = if self.streetview_delta > 0.0
+            {
+               (updated_distance - self.current_distance) >= self.streetview_delta
+            }
+            else
+            {
+               is_update
+            };
 
-            if current_mode == ViewMode::Map //&& is_update
-                  && let Some(current_position) = self.current_position
-                  && let (Some(tiles), Some(memory)) = (&mut self.tiles, &mut self.map_memory)
-                  && let (Some(position), _) = find_closest_point(&self.gpx_track, self.updated_distance.load())
+            if is_overlay_mode && current_mode != ViewMode::Gradient
             {
-               let point = lon_lat(position.point.lon, position.point.lat);
-               ui.add(
-                  Map::new(Some(tiles), memory, point)
-                     .with_plugin(DirectionalArrow
-                     {
-                        current_position: lon_lat(position.point.lon, position.point.lat),
-                        heading: position.heading,
-                        wind_angle: rider_data.wind_angle,
-                        wind_speed: rider_data.wind_speed.to_f64() / 1000.0 // wind speed is in mm/s so convert to m/s
-                     })
-               );
-               self.previous_position = self.current_position;
-               self.current_position = Some(position);
-               self.current_distance = updated_distance;
+               draw_overlay_arrow(self, ui, &rider_data, updated_distance);
+            }
+            else if !is_overlay_mode
+                    && let Some(view) = self.detached_view
+                    && view == current_mode
+            {
+               ui.centered_and_justified(|ui|
+               {
+                  ui.label(egui::RichText::new(format!("The {} view is open in a separate window.", view_name(view)))
+                                         .color(self.theme.label_color).strong());
+               });
+            }
+            else if current_mode == ViewMode::Map
+            {
+               display_map(self, ui, &rider_data, updated_distance);
             }
             else  if current_mode == ViewMode::StreetView
             {
@@ -307,121 +387,47 @@ impl eframe::App for GPXAssistUI
                {
                   display_streetview_info(ui);
                }
-               else  if self.gpx_file.is_some() && (is_update || self.is_first_street_frame)
+               else  if self.gpx_file.is_some() && (is_streetview_update || self.is_first_street_frame)
                {
+                  poll_streetview(self, ctx);
                   display_streetview(self, ctx, ui, requested_delta, updated_distance);
                }
                else if self.gpx_file.is_some()
-                  && let Some(texture) = &self.streetview_texture
-                  // && let Some(current_position) = self.current_position
-                  // && let Some(position) = find_closest_point(&self.gpx_track, updated_distance)
                {
-                  ui.centered_and_justified(|ui|
-                  {
-                     let available_size = ui.available_size();
-                     ui.add(Image::new(texture)
-                              .maintain_aspect_ratio(false)
-                              .fit_to_exact_size(available_size)
-                              .shrink_to_fit()
-                           );
-                  });
+                  poll_streetview(self, ctx);
+                  show_streetview_image(self, ui);
                }
             } // self.current_mode == ViewMode::StreetView
             else if  current_mode == ViewMode::Gradient
             {
-               let is_gradient_update = ! is_update && ( (gradient_delta < requested_delta) && (updated_distance - self.gradient_distance) >= gradient_delta );
-               // println!("Gradient: {gradient_delta} < {requested_delta} | {updated_distance} {} {} {} {}", self.gradient_distance, updated_distance, self.current_distance, self.gradient_distance);
-               if (is_update || self.is_first_gradient_frame) &&
-                  let (Some(position), _) = find_closest_point(&self.gpx_track, updated_distance)
-               {
-                  // println!("Gradient Regen {:?} {}", position, updated_distance);
-                  let available_size = ui.available_size();
-                  let mut errmsg = String::new();
-                  let gradient_image = match new_gradient_image(self, &position, available_size.x, available_size.y, 1000.0)
-                  {
-                     | Ok(img) => Some(img),
-                     | Err(msg) =>
-                     {
-                        eprintln!("Error calculating gradient image: {msg}");
-                        self.gradient_pixmap = None;
-                        errmsg = msg;
-                        None
-                     }
-                  };
-                  if let Some(color_image) = gradient_image
-                  {
-                     let texture_name = "gradient_image";
-                     if self.gradient_texture.is_some()
-                     {
-                        self.gradient_texture.as_mut().unwrap().set(color_image, egui::TextureOptions::LINEAR)
-                     }
-                     else
-                     {
-                        self.gradient_texture = Some(ctx.load_texture(texture_name, color_image, Default::default() ));
-                     }
-                  }
-                  else
-                  {
-                     ui.add(egui::Label::new(egui::RichText::new(errmsg).strong().color(egui::Color32::RED) ));
-                  }
-                  if self.gradient_texture.is_some()
-                  {
-                     render_current_gradient(self, ui);
-                  }
-                  self.previous_position = self.current_position;
-                  self.current_position = Some(position);
-                  self.current_distance = updated_distance;
-                  self.gradient_distance = updated_distance;
-                  self.is_first_gradient_frame = false;
-               }
-               else if is_gradient_update &&
-                  let (Some(position), _) = find_closest_point(&self.gpx_track, updated_distance)
-               {
-                  // println!("Gradient position Update {:?}", position);
-                  if position.distance > 0.0
-                  {
-                     let available_size = ui.available_size();
-                     let gradient_offset = self.gradient_offset.load();
-                     let offset = (self.gradient_start + gradient_offset).max(self.gradient_end);
-                     let gradient_image = match draw_gradient_marker(self, available_size.x, available_size.y, &position)
-                     {
-                        | Ok(img) => Some(img),
-                        | Err(msg) =>
-                        {
-                           eprintln!("Error recalculating gradient image: {msg}");
-                           None
-                        }
-                     };
-                     if let Some(color_image) = gradient_image
-                     {
-                        let texture_name = "gradient_image";
-                        if self.gradient_texture.is_some()
-                        {
-                           self.gradient_texture.as_mut().unwrap().set(color_image, egui::TextureOptions::LINEAR)
-                        }
-                        else
-                        {
-                           self.gradient_texture = Some(ctx.load_texture(texture_name, color_image, Default::default() ));
-                        }
-                        self.previous_position = self.current_position;
-                        self.current_position = Some(position);
-                        // self.current_distance = updated_distance;
-                        self.gradient_distance = updated_distance;
-                     }
-                     render_current_gradient(self, ui);
-
-                  }
-                  // self.render_gradient(ui, &texture);
-               }
-               else if self.gpx_file.is_some() //&& let Some(texture) = &self.gradient_texture
-               {
-                  // println!("Gradient redraw");
-                  render_current_gradient(self, ui);
-               }
+               display_gradient(self, ctx, ui, is_update, updated_distance, requested_delta, gradient_delta);
+            }
+            else if current_mode == ViewMode::Video
+            {
+               self.video.show(ctx, ui, updated_distance, &self.theme);
             }
          }
       });
 
+      if self.gpx_file.is_some()
+         && let (Some(position), _) = self.gpx_track.find_closest(self.updated_distance.load())
+      {
+         self.course_stats.show(ctx, central.response.rect, self.updated_distance.load(), &self.gpx_track, &self.theme, self.units);
+         if matches!(self.current_mode.load(), ViewMode::Map | ViewMode::StreetView)
+         {
+            self.turn_indicator.show(ctx, central.response.rect, self.updated_distance.load(), &self.gpx_track, &self.theme, self.units);
+         }
+         self.weather.show_panel(ctx, central.response.rect, &self.rider_data.load(), position.heading, &self.theme, self.units);
+         self.pacing_planner.show_target(ctx, central.response.rect, self.updated_distance.load(), &self.gpx_track, &self.rider_data.load(),
+                                         &self.theme, self.units);
+      }
+      capture_view(self, ctx, central.response.rect);
+
+      if ! is_overlay_mode
+      {
+         show_detached_view(self, ctx);
+      }
+
       if self.show_settings_dialog
       {
          let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
@@ -447,157 +453,715 @@ impl eframe::App for GPXAssistUI
          self.show_settings_dialog_err = false;
       }
 
-      self.toast_manager.show(ctx);
+      if self.gpx_file.is_some()
+      {
+         self.climb_alerter.update(self.updated_distance.load(), &self.gpx_track, &mut self.toast_manager, self.units);
+         if !self.is_simulating.load(Ordering::Relaxed)
+         {
+            self.slope_monitor.update(self.updated_distance.load(), &self.rider_data.load(), &self.gpx_track, &mut self.toast_manager);
+         }
+         self.ride_log.update(self.updated_distance.load(), &self.rider_data.load());
+         self.lap_timer.update(self.updated_distance.load(), &self.rider_data.load(), &self.gpx_track);
+         self.weather.update(ctx, self.gpx_track.find_closest(self.updated_distance.load()).0.as_ref());
+         self.surface_overlay.update(ctx, &self.gpx_track, self.updated_distance.load(), &mut self.toast_manager, self.units);
+         if let Some(course) = &self.gpx_file
+         {
+            self.milestones.update(self.updated_distance.load(), course, &self.gpx_track, self.is_simulating.load(Ordering::Relaxed),
+                                   self.units);
+            self.speech.update(self.updated_distance.load(), &self.gpx_track, self.units);
+            self.discord.update(self.updated_distance.load(), course, self.total_distance, self.units);
+         }
+      }
+      self.publish_live_state();
+      let gradient = if self.gpx_file.is_some() { self.gpx_track.gradient_at(self.updated_distance.load(), 100.0) } else { 0.0 };
+      self.fan_controller.update(&self.rider_data.load(), gradient);
+      self.trainer.update(gradient);
+      let is_final_lap = !self.is_simulating.load(Ordering::Relaxed) || self.sim_current_lap.load() >= self.sim_laps.load();
+      if self.gpx_file.is_some() && is_final_lap && self.ride_completion.update(self.updated_distance.load(), self.total_distance, &self.gpx_track)
+      {
+         log_info!("Course completed.");
+         self.lap_timer.finish(&self.gpx_track);
+         self.write_ride_summary(true);
+      }
+      self.ride_completion.show(ctx, self.lap_timer.laps(), &self.theme, self.units);
+      show_crash_report(self, ctx);
+      self.toast_manager.show(ctx, &self.theme);
+      track_window_geometry(self, ctx);
+   }
+
+   fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4]
+   //----------------------------------------------------------
+   {
+      if self.is_overlay_mode && self.is_overlay_transparent
+      {
+         [0.0, 0.0, 0.0, 0.0]
+      }
+      else
+      {
+         egui::Color32::from_rgba_unmultiplied(12, 12, 12, 180).to_normalized_gamma_f32()
+      }
+   }
+
+   fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>)
+   //---------------------------------------------------------
+   {
+      self.source_manager.shutdown();
+      if let Some(load) = self.gpx_loading.lock().take()
+      {
+         load.cancel.cancel();
+      }
+      self.write_ride_summary(false);
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let mut settings = settings.lock();
+      if let Some((position, size)) = self.window_geometry
+      {
+         settings.set_window_geometry(position, size);
+      }
+      settings.flush(true);
+   }
+}
+
+fn display_map(me: &mut GPXAssistUI, ui: &mut egui::Ui, rider_data: &RiderData, updated_distance: f64)
+//----------------------------------------------------------------------------------------------------
+{
+   if me.current_position.is_some()
+      && let (Some(tiles), Some(memory)) = (&mut me.tiles, &mut me.map_memory)
+      && let (Some(position), _) = me.gpx_track.find_closest(me.updated_distance.load())
+   {
+      let point = lon_lat(position.point.lon, position.point.lat);
+      let avatar = me.rider_avatar.texture(ui.ctx(), &me.rider_marker);
+      let mut map = Map::new(Some(tiles), memory, point);
+      if let Some(route) = me.surface_overlay.route()
+      {
+         map = map.with_plugin(route);
+      }
+      let response = ui.add(
+         map
+            .with_plugin(WaypointMarkers { waypoints: me.waypoints.clone(), color: Color32::from_rgb(40, 110, 200) })
+            .with_plugin(DirectionalArrow
+            {
+               current_position: lon_lat(position.point.lon, position.point.lat),
+               heading: position.heading,
+               wind_angle: rider_data.wind_angle,
+               wind_speed: rider_data.wind_speed.to_f64() / 1000.0, // wind speed is in mm/s so convert to m/s
+               marker: me.rider_marker.clone(),
+               avatar,
+            })
+      );
+      draw_attribution(ui, response.rect, "© OpenStreetMap contributors");
+      me.previous_position = me.current_position;
+      me.current_position = Some(position);
+      me.current_distance = updated_distance;
    }
 }
 
-fn display_streetview(me: &mut GPXAssistUI, ctx: &Context, ui: &mut egui::Ui, requested_delta: f64, updated_distance: f64)
-//-----------------------------------------------------------------------------------------------------------------------
+fn display_gradient(me: &mut GPXAssistUI, ctx: &Context, ui: &mut egui::Ui, is_update: bool, updated_distance: f64, requested_delta: f64,
+                    gradient_delta: f64)
+//---------------------------------------------------------------------------------------------------------------------------------------
 {
-   if let Some(current_position) = me.current_position
-      && let (Some(position), _) = find_closest_point(&me.gpx_track, updated_distance)
+   if me.climb_preview.is_some()
    {
+      display_climb_preview(me, ctx, ui);
+      return;
+   }
+   let is_gradient_update = ! is_update && ( (gradient_delta < requested_delta) && (updated_distance - me.gradient_distance) >= gradient_delta );
+   // println!("Gradient: {gradient_delta} < {requested_delta} | {updated_distance} {} {} {} {}", me.gradient_distance, updated_distance, me.current_distance, me.gradient_distance);
+   if (is_update || me.is_first_gradient_frame) &&
+      let (Some(position), _) = me.gpx_track.find_closest(updated_distance)
+   {
+      // println!("Gradient Regen {:?} {}", position, updated_distance);
       let available_size = ui.available_size();
       let mut errmsg = String::new();
-      println!("Streetview: {:.4} {:.4} {:.4}", updated_distance, me.current_distance,  requested_delta);
-
-      let streetview_image = match streetview(ctx, me.encrypted_api_key.as_ref().unwrap(), &current_position,
-         available_size.x, available_size.y, true, true)
+      let gradient_image = match new_gradient_image(me, &position, available_size.x, available_size.y, 1000.0)
       {
          | Ok(img) => Some(img),
          | Err(msg) =>
          {
-            eprintln!("Error fetching Street View image: {msg}");
+            log_error!("Error calculating gradient image: {msg}");
+            me.gradient_marker = None;
             errmsg = msg;
             None
-
          }
       };
-      if let Some(color_image) = streetview_image
+      if let Some(color_image) = gradient_image
       {
-         // save_tmp_image(&color_image);
-         let texture_name = "streetview_image";
-         if me.streetview_texture.is_some()
+         let texture_name = "gradient_image";
+         if me.gradient_texture.is_some()
          {
-            me.streetview_texture.as_mut().unwrap().set(color_image, egui::TextureOptions::LINEAR)
+            me.gradient_texture.as_mut().unwrap().set(color_image, egui::TextureOptions::LINEAR)
          }
          else
          {
-            me.streetview_texture = Some(ctx.load_texture(texture_name, color_image, Default::default() ));
+            me.gradient_texture = Some(ctx.load_texture(texture_name, color_image, Default::default() ));
          }
       }
       else
       {
          ui.add(egui::Label::new(egui::RichText::new(errmsg).strong().color(egui::Color32::RED) ));
       }
-
-      if let Some(texture) = &me.streetview_texture
+      if me.gradient_texture.is_some()
       {
-         // println!("Texture size: {:?})", texture.size());
-         ui.centered_and_justified(|ui|
-         {
-            // let img = Image::new(&self.streetview_texture);
-            // ui.image(texture);
-            ui.add(Image::new(texture)
-                     .maintain_aspect_ratio(false)
-                     .fit_to_exact_size(available_size)
-                     .shrink_to_fit()
-                  );
-         });
+         render_current_gradient(me, ui);
       }
       me.previous_position = me.current_position;
       me.current_position = Some(position);
       me.current_distance = updated_distance;
-      me.is_first_street_frame = false;
+      me.gradient_distance = updated_distance;
+      me.is_first_gradient_frame = false;
+   }
+   else if is_gradient_update &&
+      let (Some(position), _) = me.gpx_track.find_closest(updated_distance)
+   {
+      // println!("Gradient position Update {:?}", position);
+      if position.distance > 0.0
+      {
+         // Only the marker moves, it is painted over the profile texture so the texture is not uploaded again
+         match gradient_marker_position(me, &position)
+         {
+            | Ok(marker) =>
+            {
+               me.gradient_marker = Some(marker);
+               me.previous_position = me.current_position;
+               me.current_position = Some(position);
+               // me.current_distance = updated_distance;
+               me.gradient_distance = updated_distance;
+            }
+            | Err(msg) => log_error!("Error positioning the gradient marker: {msg}"),
+         }
+         render_current_gradient(me, ui);
+
+      }
+      // me.render_gradient(ui, &texture);
+   }
+   else if me.gpx_file.is_some() //&& let Some(texture) = &me.gradient_texture
+   {
+      // println!("Gradient redraw");
+      render_current_gradient(me, ui);
    }
 }
 
-fn display_streetview_info(ui: &mut egui::Ui)
-//---------------------------------------------
+fn view_name(mode: ViewMode) -> &'static str
 {
-   ui.add(egui::Label::new( egui::RichText::new("No Street View API key set in settings.")
-            .strong().color(egui::Color32::RED)));
-   ui.hyperlink_to( "Click to get a Google Maps API Key (https://console.cloud.google.com/google/maps-apis/)",
-      "https://console.cloud.google.com/google/maps-apis/" );
-   ui.separator();
-   ui.add(egui::Label::new( egui::RichText::new("First 10000 StreetViews per month are free.")
-            .color(egui::Color32::GREEN)
-   ));
-   ui.hyperlink_to( "Click for Pricing Details (https://developers.google.com/maps/billing-and-pricing/pricing#map-loads-pricing)",
-         "https://developers.google.com/maps/billing-and-pricing/pricing#map-loads-pricing" );
-   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-   let settings_dir = settings.lock().get_settings_path().unwrap_or(PathBuf::from("."));
-   ui.label(format!("After obtaining a key, click the settings button to add API key to settings or modify the settings file {:#?} directly.", settings_dir));
+   match mode
+   {
+      | ViewMode::Map => "Map",
+      | ViewMode::StreetView => "StreetView",
+      | ViewMode::Gradient => "Gradient",
+      | ViewMode::Video => "Video",
+      | ViewMode::NA => "",
+   }
 }
 
-// #[allow(clippy::too_many_arguments)]
-fn new_gradient_image(me: &mut GPXAssistUI, position: &TrackPoint, width: f32, height: f32, label_width: f64) -> Result<ColorImage, String>
-//----------------------------------------------------------------------------------------------------------------------------------
+/// Offer the report written when GPXAssist last crashed, until it is dismissed.
+fn show_crash_report(me: &mut GPXAssistUI, ctx: &Context)
+//-------------------------------------------------------
 {
-   let track = me.gpx_track.clone();
-   let total_distance = me.total_distance;
-   let gradient_length = me.gradient_length.load();
-   let flat_gradient = me.gradient_flat.load();
-   let extreme_gradient = me.gradient_extreme.load();
-   let gradient_offset = me.gradient_offset.load();
-   let extreme_start = extreme_gradient.abs() - 1.5;
-
-   me.gradient_start = (position.distance - gradient_offset).max(0.0);
-   me.gradient_end = (me.gradient_start + gradient_length).min(total_distance);
-   if me.gradient_end == total_distance
+   let Some(report) = me.crash_report.clone() else { return; };
+   let mut is_open = true;
+   egui::Window::new("GPXAssist crashed")
+      .open(&mut is_open)
+      .collapsible(false)
+      .resizable(false)
+      .anchor(egui::Align2::CENTER_CENTER, Vec2::ZERO)
+      .show(ctx, |ui|
+      {
+         ui.label("GPXAssist stopped unexpectedly last time it ran. A crash report was saved to");
+         ui.label(egui::RichText::new(report.display().to_string()).monospace());
+         ui.label("It has the error, where it happened and your settings without keys, tokens or passwords, and can be \
+                   attached to a GitHub issue to help fix the problem.");
+         ui.add_space(8.0);
+         ui.horizontal(|ui|
+         {
+            if ui.button("Open report").clicked()
+               && let Err(e) = open::that_detached(&report)
+            {
+               me.toast_manager.error(format!("Could not open {}: {}", report.display(), e), None);
+            }
+            if ui.button("Open folder").clicked()
+               && let Some(directory) = report.parent()
+               && let Err(e) = open::that_detached(directory)
+            {
+               me.toast_manager.error(format!("Could not open {}: {}", directory.display(), e), None);
+            }
+            if ui.button("Copy path").clicked()
+            {
+               ctx.copy_text(report.display().to_string());
+            }
+            if ui.button("Dismiss").clicked()
+            {
+               me.crash_report = None;
+            }
+         });
+      });
+   if !is_open
    {
-      me.gradient_start = (me.gradient_end - gradient_length).max(0.0);
+      me.crash_report = None;
    }
+}
 
-   //let mut segment_points: Vec<TrackPoint> = Vec::new();
-   let mut is_seg_loaded = false;
-   let i: i64;
-   (_, i) = find_closest_point(&track, me.gradient_start);
-   if i >= 0
+/// Shows the detached Map or Gradient view in a second native window (or an embedded window where the backend does not
+/// support multiple viewports). Closing the window re-attaches the view to the main window.
+fn show_detached_view(me: &mut GPXAssistUI, ctx: &Context)
+//---------------------------------------------------------
+{
+   let Some(view) = me.detached_view else { return; };
+   let viewport_id = egui::ViewportId::from_hash_of("detached_view");
+   let builder = egui::ViewportBuilder::default().with_title(format!("GPXAssist: {}", view_name(view)))
+                                                 .with_inner_size([800.0, 500.0]);
+   let mut is_closed = false;
+   ctx.show_viewport_immediate(viewport_id, builder, |ctx, class|
    {
-      let j: i64;
-      (_, j) = find_closest_point(&track, me.gradient_end);
-      if j >= i
+      if class == egui::ViewportClass::Embedded
       {
-         me.gradient_points = track[i as usize ..= j as usize].to_vec();
-         is_seg_loaded = true;
+         let mut is_open = true;
+         egui::Window::new(view_name(view)).open(&mut is_open).default_size([800.0, 500.0]).show(ctx, |ui| detached_view_contents(me, ctx, ui, view));
+         is_closed = !is_open;
       }
+      else
+      {
+         egui::CentralPanel::default().show(ctx, |ui| detached_view_contents(me, ctx, ui, view));
+         is_closed = ctx.input(|i| i.viewport().close_requested());
+      }
+   });
+   if is_closed
+   {
+      me.detached_view = None;
+      me.is_first_map_frame = true;
+      me.is_first_gradient_frame = true;
    }
-   if ! is_seg_loaded
+}
+
+fn detached_view_contents(me: &mut GPXAssistUI, ctx: &Context, ui: &mut egui::Ui, view: ViewMode)
+//-----------------------------------------------------------------------------------------------
+{
+   if me.gpx_file.is_none() || me.total_distance == 0.0
    {
-      me.gradient_points = Vec::new();
-      for point in track.iter()
+      return;
+   }
+   let updated_distance = me.updated_distance.load();
+   match view
+   {
+      | ViewMode::Map =>
       {
-         if point.distance >= me.gradient_start && point.distance <= me.gradient_end
+         let rider_data = me.rider_data.load();
+         display_map(me, ui, &rider_data, updated_distance);
+      }
+      | ViewMode::Gradient =>
+      {
+         // Track full redraws separately from the main window, whose view updates current_distance independently.
+         let requested_delta = me.requested_delta.load();
+         let gradient_delta = me.gradient_delta.load();
+         let is_update = (updated_distance - me.detached_distance) >= requested_delta;
+         if is_update || me.is_first_gradient_frame
          {
-            me.gradient_points.push(*point);
+            me.detached_distance = updated_distance;
          }
+         display_gradient(me, ctx, ui, is_update, updated_distance, requested_delta, gradient_delta);
       }
+      | _ => (),
    }
+}
 
-   if me.gradient_points.len() < 2
+/// F11 toggles fullscreen, F10 toggles window decorations (borderless), F9 toggles the streaming overlay and Escape
+/// leaves fullscreen or overlay mode.
+fn handle_window_shortcuts(me: &mut GPXAssistUI, ctx: &Context)
+//-------------------------------------------------------------
+{
+   let (toggle_fullscreen, toggle_borderless, toggle_overlay, escape, is_fullscreen) =
+      ctx.input(|i| (i.key_pressed(egui::Key::F11), i.key_pressed(egui::Key::F10), i.key_pressed(egui::Key::F9),
+                     i.key_pressed(egui::Key::Escape), i.viewport().fullscreen.unwrap_or(false)));
+   if toggle_overlay || (escape && me.is_overlay_mode)
    {
-      return Err("Insufficient points in segment".to_string());
+      me.is_overlay_mode = toggle_overlay && !me.is_overlay_mode;
+      me.is_first_gradient_frame = true; // Redraw the profile with the overlay background
+      if me.is_overlay_mode
+      {
+         me.toast_manager.info("Streaming overlay mode: press F9 or Escape to exit.", Some(Duration::from_secs(3)));
+      }
+   }
+   if toggle_fullscreen
+   {
+      ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!is_fullscreen));
+   }
+   else if escape && is_fullscreen
+   {
+      ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+   }
+   if toggle_borderless
+   {
+      me.is_borderless = !me.is_borderless;
+      ctx.send_viewport_cmd(egui::ViewportCommand::Decorations(!me.is_borderless));
    }
+}
+
+/// Remember the last windowed (not maximized/fullscreen) geometry so it can be persisted on exit.
+fn track_window_geometry(me: &mut GPXAssistUI, ctx: &Context)
+//------------------------------------------------------------
+{
+   let geometry = ctx.input(|i|
+   {
+      let viewport = i.viewport();
+      if viewport.fullscreen.unwrap_or(false) || viewport.maximized.unwrap_or(false) || viewport.minimized.unwrap_or(false)
+      {
+         return None;
+      }
+      match (viewport.outer_rect, viewport.inner_rect)
+      {
+         | (Some(outer), Some(inner)) => Some(([outer.min.x, outer.min.y], [inner.width(), inner.height()])),
+         | _ => None,
+      }
+   });
+   if geometry.is_some()
+   {
+      me.window_geometry = geometry;
+   }
+}
+
+/// Streaming overlay replacement for the Map and Street View: only the heading and wind arrows are drawn.
+fn draw_overlay_arrow(me: &mut GPXAssistUI, ui: &mut egui::Ui, rider_data: &RiderData, distance: f64)
+//-------------------------------------------------------------------------------------------------
+{
+   if let (Some(position), _) = me.gpx_track.find_closest(distance)
+   {
+      let center = ui.available_rect_before_wrap().center();
+      let avatar = me.rider_avatar.texture(ui.ctx(), &me.rider_marker);
+      draw_rider_marker(ui.painter(), center, Some(position.heading.to_radians() as f32), &me.rider_marker, avatar.as_ref());
+      let wind_speed = rider_data.wind_speed.to_f64() / 1000.0;
+      if wind_speed.abs() > 0.5
+      {
+         draw_wind_arrow(ui, center, (360.0 - rider_data.wind_angle as f64).to_radians() as f32, wind_speed as f32);
+      }
+   }
+}
+
+/// Bottom status bar with the current rider telemetry, visible in all view modes.
+fn show_status_bar(me: &GPXAssistUI, ui: &mut egui::Ui)
+//-----------------------------------------------------
+{
+   let rider = me.rider_data.load();
+   let distance = me.updated_distance.load();
+   let gradient = me.gpx_track.gradient_at(distance, 100.0);
+   let value_or_dash = |v: i32, unit: &str| if v > 0 { format!("{v} {unit}") } else { "--".to_string() };
+   let adjusted_speed = if rider.speed > 0 { me.rider_model.flat_speed(rider.speed_kmh() / 3.6, gradient) * 3.6 } else { 0.0 };
+   let items = [("Distance", me.units.format_distance(distance, 2)),
+                ("Speed", me.units.format_speed(rider.speed_kmh())),
+                ("Grade adj.", me.units.format_speed(adjusted_speed)),
+                ("Power", value_or_dash(rider.power, "W")),
+                ("HR", value_or_dash(rider.heartrate, "bpm")),
+                ("Cadence", value_or_dash(rider.cadence, "rpm")),
+                ("Gradient", format!("{:.1}%", gradient))];
+   let identity = me.rider_identity.load();
+   ui.horizontal(|ui|
+   {
+      if !identity.is_empty()
+      {
+         ui.label(egui::RichText::new(identity.describe()).color(me.theme.label_color).strong().size(16.0));
+         ui.separator();
+      }
+      for (i, (label, value)) in items.iter().enumerate()
+      {
+         if i > 0
+         {
+            ui.separator();
+         }
+         ui.label(egui::RichText::new(format!("{label}:")).color(me.theme.label_color).size(16.0));
+         ui.label(egui::RichText::new(value).strong().size(16.0));
+      }
+      if let Some(drift) = me.slope_monitor.drift()
+      {
+         let color = if drift >= DRIFT_WARNING { me.theme.toast_warning } else { ui.visuals().text_color() };
+         ui.separator();
+         ui.label(egui::RichText::new("Slope drift:").color(me.theme.label_color).size(16.0));
+         ui.label(egui::RichText::new(format!("{drift:.1}%")).color(color).strong().size(16.0))
+           .on_hover_text("Average difference between the gradient reported by the game and the course gradient over the last \
+                           kilometre. A large drift means the wrong course, or the course in the wrong direction, is loaded");
+      }
+      let laps = me.lap_timer.laps();
+      if !laps.is_empty()
+      {
+         ui.separator();
+         ui.menu_button(egui::RichText::new(format!("⏱ Laps: {}", laps.len())).size(16.0), |ui|
+         {
+            splits_grid(ui, "status_laps", laps, ui.visuals().text_color(), me.units);
+         }).response.on_hover_text("Lap splits");
+      }
+      me.weather.show(ui, &rider, &me.theme, me.units);
+      me.trainer.show(ui, &me.theme);
+   });
+}
+
+fn display_streetview(me: &mut GPXAssistUI, ctx: &Context, ui: &mut egui::Ui, requested_delta: f64, updated_distance: f64)
+//-----------------------------------------------------------------------------------------------------------------------
+{
+   if let Some(current_position) = me.current_position
+      && let (Some(position), _) = me.gpx_track.find_closest(updated_distance)
+   {
+      if me.streetview_fetch.is_some()
+      {  // Keep showing the previous image and update once the request in progress completes
+         show_streetview_image(me, ui);
+         return;
+      }
+      let available_size = ui.available_size();
+      log_debug!("Street View update at {:.4} from {:.4} (delta {:.4})", updated_distance, me.current_distance, requested_delta);
+
+      // Images downloaded by the precache command are used in place of a request when there is one nearby
+      let precached = me.streetview_precache.as_ref()
+                                            .and_then(|precache| precache.image_near(current_position.distance))
+                                            .and_then(|file| read_image_file(&file).inspect_err(|e| log_warn!("{e}")).ok());
+      match precached
+      {
+         | Some(image) =>
+         {
+            // Precached images are for riding offline so don't request the panorama copyright for them
+            cancel_streetview(me);
+            set_streetview_image(me, ctx, image, GOOGLE_ATTRIBUTION.to_string());
+         }
+         | None => fetch_streetview(me, ctx, current_position, available_size),
+      }
+      show_streetview_image(me, ui);
+      me.previous_position = me.current_position;
+      me.current_position = Some(position);
+      me.current_distance = updated_distance;
+      me.is_first_street_frame = false;
+   }
+}
+
+/// Fetch the Street View image at `position` for a view of `size` in the background. poll_streetview shows the image
+/// when it arrives, the previous one being shown until then.
+fn fetch_streetview(me: &mut GPXAssistUI, ctx: &Context, position: TrackPoint, size: Vec2)
+//----------------------------------------------------------------------------------------
+{
+   let Some(api_key) = me.encrypted_api_key.clone() else { return; };
+   cancel_streetview(me);
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   let (width, height) = settings.lock().streetview_size.request_size(size.x, size.y); // the image is stretched to the panel
+   let directory = settings.lock().cache_path(STREETVIEW_CACHE);
+   let fetch = StreetViewFetch { cancel: CancelToken::default(), result: Arc::default() };
+   let (cancel, result, ctx) = (fetch.cancel.clone(), fetch.result.clone(), ctx.clone());
+   me.streetview_fetch = Some(fetch);
+   http::spawn(async move
+   {
+      let fetched = http::cancellable(&cancel, async
+      {
+         let image = streetview(&api_key, &position, width, height, &directory).await;
+         Ok(match image
+         {
+            | Ok(image) => Ok((image, streetview_attribution(&api_key, &position, &directory).await)),
+            | Err(e) => Err(e),
+         })
+      }).await;
+      if let Ok(fetched) = fetched
+         && !cancel.is_cancelled()
+      {
+         *result.lock() = Some(fetched);
+         ctx.request_repaint();
+      }
+   });
+}
+
+/// Show the Street View image fetched in the background once it has arrived, or report why it failed.
+fn poll_streetview(me: &mut GPXAssistUI, ctx: &Context)
+//-----------------------------------------------------
+{
+   let Some(fetch) = &me.streetview_fetch else { return; };
+   let result = fetch.result.lock().take();
+   let Some(result) = result else { return; };
+   me.streetview_fetch = None;
+   match result
+   {
+      | Ok((image, attribution)) => set_streetview_image(me, ctx, image, attribution),
+      | Err(e) =>
+      {
+         log_error!("Error fetching Street View image: {e}");
+         report_streetview_error(me, &e);
+      }
+   }
+}
+
+/// Drop the Street View fetch in progress, e.g. when the course or view changes.
+fn cancel_streetview(me: &mut GPXAssistUI)
+//----------------------------------------
+{
+   if let Some(fetch) = me.streetview_fetch.take()
+   {
+      fetch.cancel.cancel();
+   }
+}
+
+fn set_streetview_image(me: &mut GPXAssistUI, ctx: &Context, image: ColorImage, attribution: String)
+//--------------------------------------------------------------------------------------------------
+{
+   me.streetview_error = None;
+   me.streetview_attribution = attribution;
+   match &mut me.streetview_texture
+   {
+      | Some(texture) => texture.set(image, egui::TextureOptions::LINEAR),
+      | None => me.streetview_texture = Some(ctx.load_texture("streetview_image", image, Default::default())),
+   }
+}
+
+/// Draw the last Street View image with its attribution, or why there is none yet.
+fn show_streetview_image(me: &GPXAssistUI, ui: &mut egui::Ui)
+//------------------------------------------------------------
+{
+   match (&me.streetview_texture, &me.streetview_error)
+   {
+      | (Some(texture), _) =>
+      {
+         let response = ui.centered_and_justified(|ui|
+         {
+            let available_size = ui.available_size();
+            ui.add(Image::new(texture)
+                     .maintain_aspect_ratio(false)
+                     .fit_to_exact_size(available_size)
+                     .shrink_to_fit()
+                  )
+         });
+         draw_attribution(ui, response.inner.rect, &me.streetview_attribution);
+      }
+      | (None, Some(e)) => { ui.add(egui::Label::new(egui::RichText::new(e.to_string()).strong().color(egui::Color32::RED))); }
+      | (None, None) => { ui.centered_and_justified(|ui| ui.spinner()); }
+   }
+}
+
+/// Draw the HUD over the view `rect` while a screenshot is pending, and save the screenshot once it arrives.
+fn capture_view(me: &mut GPXAssistUI, ctx: &Context, rect: egui::Rect)
+//---------------------------------------------------------------------
+{
+   if !me.view_capture.is_pending()
+   {
+      return;
+   }
+   let name = me.gpx_file.as_ref().and_then(|file| file.file_stem()).map_or("GPXAssist".to_string(), |s| s.to_string_lossy().to_string());
+   let (distance, rider) = (me.updated_distance.load(), me.rider_data.load());
+   let mut lines = vec![name.clone(),
+                        format!("{} of {}", me.units.format_distance(distance, 2), me.units.format_distance(me.total_distance, 1)),
+                        format!("Gradient {:.1}%", me.gpx_track.gradient_at(distance, 100.0))];
+   if rider.speed > 0
+   {
+      lines.push(format!("{}  {} W", me.units.format_speed(rider.speed_kmh()), rider.power.max(0)));
+   }
+   me.view_capture.show_hud(ctx, rect, &lines);
+   let directory = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())))
+                           .lock().screenshot_directory.clone();
+   match me.view_capture.take(ctx, &directory, &name)
+   {
+      | Some(Ok(path)) =>
+      {
+         log_info!("Saved screenshot {}", path.display());
+         me.toast_manager.success(format!("Screenshot saved to {} and copied to the clipboard.", path.display()), Some(Duration::from_secs(4)));
+      }
+      | Some(Err(e)) => me.toast_manager.error(format!("Could not save the screenshot: {e}"), None),
+      | None => ctx.request_repaint(), // until the screenshot arrives
+   }
+}
+
+/// Attribution required by the imagery or map provider, drawn small over the bottom right corner of `rect`.
+fn draw_attribution(ui: &egui::Ui, rect: egui::Rect, text: &str)
+//--------------------------------------------------------------
+{
+   if text.is_empty()
+   {
+      return;
+   }
+   let painter = ui.painter_at(rect);
+   let galley = painter.layout_no_wrap(text.to_string(), egui::FontId::proportional(11.0), Color32::from_white_alpha(220));
+   let margin = egui::vec2(4.0, 2.0);
+   let background = egui::Rect::from_min_size(rect.right_bottom() - galley.size() - margin * 2.0, galley.size() + margin * 2.0);
+   painter.rect_filled(background, 3.0, Color32::from_black_alpha(120));
+   painter.galley(background.min + margin, galley, Color32::WHITE);
+}
+
+/// Toast a Street View failure unless the previous request failed the same way, so a flaky connection or an exhausted
+/// quota does not raise a toast at every position.
+fn report_streetview_error(me: &mut GPXAssistUI, error: &HttpError)
+//-----------------------------------------------------------------
+{
+   let is_repeated = me.streetview_error.as_ref().is_some_and(|last| std::mem::discriminant(last) == std::mem::discriminant(error));
+   if !is_repeated
+   {
+      match error
+      {
+         | HttpError::Transient(_) =>
+         {
+            me.toast_manager.warning("Street View could not be reached, it will be tried again at the next position.",
+                                     Some(Duration::from_secs(5)));
+         }
+         | HttpError::Quota(_) =>
+         {
+            me.toast_manager.error("The Street View API quota or rate limit has been reached, check the Google Cloud console.",
+                                   None);
+         }
+         | HttpError::Permanent(e) => me.toast_manager.error(format!("Street View request failed: {e}"), Some(Duration::from_secs(10))),
+      }
+   }
+   me.streetview_error = Some(error.clone());
+}
+
+fn display_streetview_info(ui: &mut egui::Ui)
+//---------------------------------------------
+{
+   ui.add(egui::Label::new( egui::RichText::new("No Street View API key set in settings.")
+            .strong().color(egui::Color32::RED)));
+   ui.hyperlink_to( "Click to get a Google Maps API Key (https://console.cloud.google.com/google/maps-apis/)",
+      "https://console.cloud.google.com/google/maps-apis/" );
+   ui.separator();
+   ui.add(egui::Label::new( egui::RichText::new("First 10000 StreetViews per month are free.")
+            .color(egui::Color32::GREEN)
+   ));
+   ui.hyperlink_to( "Click for Pricing Details (https://developers.google.com/maps/billing-and-pricing/pricing#map-loads-pricing)",
+         "https://developers.google.com/maps/billing-and-pricing/pricing#map-loads-pricing" );
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   let settings_dir = settings.lock().get_settings_path().unwrap_or(PathBuf::from("."));
+   ui.label(format!("After obtaining a key, click the settings button to add API key to settings or modify the settings file {:#?} directly.", settings_dir));
+}
+
+/// Colours and scaling for `draw_profile`.
+pub(crate) struct ProfileStyle
+{
+   pub flat_gradient:         f64,
+   pub extreme_gradient:      f64,
+   pub vertical_exaggeration: f64,
+   pub background:            Color32,
+   pub label_color:           Color32,
+   pub label_width:           f64, // metres between distance labels
+   pub units:                 Units,
+   pub is_patterned:          bool, // hatch the gradient bands as well as colouring them
+   pub surfaces:              Vec<SurfaceSection>, // drawn as a band under the profile
+}
+
+/// Draw the gradient coloured elevation profile of `points` between the `start` and `end` distances. Used by the
+/// gradient view and the headless `render profile` command.
+pub(crate) fn draw_profile(points: &[TrackPoint], start: f64, end: f64, width: f32, height: f32, style: &ProfileStyle)
+   -> Result<Pixmap, String>
+//-------------------------------------------------------------------------------------------------------------------
+{
+   if points.len() < 2
+   {
+      return Err("Insufficient points in segment".to_string());
+   }
+   let flat_gradient = style.flat_gradient;
+   let extreme_gradient = style.extreme_gradient;
+   let extreme_start = extreme_gradient.abs() - 1.5;
 
       // Find min/max elevation for scaling
-   let min_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::INFINITY, f64::min);
-   let max_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::NEG_INFINITY, f64::max);
+   let min_elevation = points.iter().map(|p| p.altitude).fold(f64::INFINITY, f64::min);
+   let max_elevation = points.iter().map(|p| p.altitude).fold(f64::NEG_INFINITY, f64::max);
    let elevation_range = (max_elevation - min_elevation).max(10.0); // Minimum 10m range to avoid division by near-zero
 
    let pixmap_width = width as u32;
    let pixmap_height = height as u32;
    let mut pixmap = Pixmap::new(pixmap_width, pixmap_height).ok_or_else(|| "Failed to create pixmap".to_string())?;
 
-   pixmap.fill(tiny_skia::Color::from_rgba8(224, 224, 224, 255)); ////BGRA  Skyblue (253, 221, 212, 255) #f0f0f0 to #e0e0e0 or #1e1e1e - #2b2b2b (dark theme) or #222831 - #2a2f3a
+   pixmap.fill(Theme::skia_color(style.background));
 
    let padding = 60.0;
    let plot_width = width - 2.0 * padding;
    let plot_height = height - 2.0 * padding;
-   let distance_range = me.gradient_end - me.gradient_start;
+   let distance_range = end - start;
 
    // Calculate proper aspect ratio with vertical exaggeration
-   let vertical_exaggeration = me.vertical_scale.load();
+   let vertical_exaggeration = style.vertical_exaggeration;
    let actual_aspect_ratio = elevation_range / distance_range; // e.g., 50m / 3000m = 0.0167
    let display_aspect_ratio = actual_aspect_ratio * vertical_exaggeration; // e.g., 0.0167 * 10 = 0.167
 
@@ -608,7 +1172,7 @@ fn new_gradient_image(me: &mut GPXAssistUI, position: &TrackPoint, width: f32, h
 
    let map_to_screen = |dist: f64, elev: f64| -> (f32, f32)
    {
-      let x = padding as f64 + ((dist - me.gradient_start) / distance_range) * plot_width as f64;
+      let x = padding as f64 + ((dist - start) / distance_range) * plot_width as f64;
       let y = padding as f64 + elevation_offset as f64 + effective_plot_height as f64 - ((elev - min_elevation) / elevation_range) * effective_plot_height as f64;
       (x as f32, y as f32)
    };
@@ -665,202 +1229,348 @@ fn new_gradient_image(me: &mut GPXAssistUI, position: &TrackPoint, width: f32, h
          }
       };
 
-      // Draw filled areas and profile line
-      for i in 0..me.gradient_points.len() - 1
-      {
-         let p1 = &me.gradient_points[i];
-         let p2 = &me.gradient_points[i + 1];
-
-         let gradient_pct = calculate_gradient_percent(p1, p2);
-         let color = gradient_color(gradient_pct);
-         // println!("{i}: {}, {} - {}, {} {gradient_pct}", p2.distance, p2.altitude, p1.distance, p1.altitude);
-         // {
-         //    match OpenOptions::new().append(true).create(true).open("/tmp/gpxdata.txt")
-         //    {
-         //       | Ok(mut file) =>
-         //       {
-         //          use std::io::Write;
-         //          let log_line = format!("{},{},{},{},{:.2}\n", i, p1.distance, p1.altitude, p2.distance, gradient_pct);
-         //          let _ = file.write_all(log_line.as_bytes());
-         //       }
-         //       | Err(e) =>
-         //       {
-         //          eprintln!("Error writing to log file: {}", e);
-         //       }
-         //    }
-         // }
-
-         let (x1, y1) = map_to_screen(p1.distance, p1.altitude);
-         let (x2, y2) = map_to_screen(p2.distance, p2.altitude);
-
-         // Draw filled polygon below the profile
-         let bottom_y = padding + elevation_offset + effective_plot_height;
-         let mut path_builder = PathBuilder::new();
-         path_builder.move_to(x1, y1);
-         path_builder.line_to(x2, y2);
-         path_builder.line_to(x2, bottom_y);
-         path_builder.line_to(x1, bottom_y);
-         path_builder.close();
-
-         if let Some(path) = path_builder.finish()
+      // Pattern for the gradient band, so it can be read without telling the colours apart
+      let gradient_hatch = |gradient_pct: f64| -> Hatch
+      {
+         if !style.is_patterned || gradient_pct.abs() <= flat_gradient.abs() { Hatch::None }
+         else if gradient_pct < 0.0 { Hatch::Horizontal }
+         else if gradient_pct >= extreme_gradient.abs() { Hatch::Dense }
+         else if gradient_pct >= (flat_gradient.abs() + extreme_gradient.abs()) / 2.0 { Hatch::Grid }
+         else { Hatch::Vertical }
+      };
+
+      // Segment end points and colours, computed in parallel for dense tracks
+      let bottom_y = padding + elevation_offset + effective_plot_height;
+      let segments: Vec<ProfileSegment> = points.par_windows(2).map(|pair|
+      {
+         let (p1, p2) = (&pair[0], &pair[1]);
+         let gradient = calculate_gradient_percent(p1, p2);
+         ProfileSegment { start: map_to_screen(p1.distance, p1.altitude), end: map_to_screen(p2.distance, p2.altitude),
+                          color: gradient_color(gradient), hatch: gradient_hatch(gradient) }
+      }).collect();
+
+      // Long windows are rendered as vertical stripes on the rayon workers, each drawing only the segments crossing it,
+      // and then copied into the profile.
+      if segments.len() < PARALLEL_PROFILE_SEGMENTS
+      {
+         draw_profile_segments(&mut pixmap, &segments, bottom_y, 0);
+      }
+      else
+      {
+         let stripes = rayon::current_num_threads().clamp(1, 16) as u32;
+         let stripe_width = pixmap_width.div_ceil(stripes).max(1);
+         let rendered: Vec<(u32, Pixmap)> = (0 .. stripes).into_par_iter().filter_map(|stripe|
          {
-            let mut paint = Paint::default();
-            paint.set_color(color);
-            paint.anti_alias = true;
-            pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+            let x0 = stripe * stripe_width;
+            if x0 >= pixmap_width
+            {
+               return None;
+            }
+            let mut stripe_pixmap = Pixmap::new(stripe_width.min(pixmap_width - x0), pixmap_height)?;
+            draw_profile_segments(&mut stripe_pixmap, &segments, bottom_y, x0);
+            Some((x0, stripe_pixmap))
+         }).collect();
+         for (x0, stripe) in rendered
+         {
+            pixmap.draw_pixmap(x0 as i32, 0, stripe.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
          }
+      }
 
-         // Draw profile line segment
-         let mut path_builder = PathBuilder::new();
-         path_builder.move_to(x1, y1);
-         path_builder.line_to(x2, y2);
+   draw_surface_band(&mut pixmap, &style.surfaces, |distance| map_to_screen(distance.clamp(start, end), min_elevation).0, bottom_y);
+   super::frame::draw_distance_labels(&mut pixmap, start, end,
+                        style.label_width, padding, plot_width, plot_height, style.label_color, style.units);
+   Ok(pixmap)
+}
 
-         if let Some(path) = path_builder.finish()
-         {
-            let mut paint = Paint::default();
-            paint.set_color(color);
-            paint.anti_alias = true;
-            let stroke = Stroke { width: 3.0, ..Default::default() };
-            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
-         }
+/// Height of the road surface band under the profile, in pixels.
+const SURFACE_BAND_HEIGHT: f32 = 8.0;
+
+/// Draw the known `surfaces` as a band under the profile ending at `bottom_y`, placed along the profile by `to_x`.
+fn draw_surface_band(pixmap: &mut Pixmap, surfaces: &[SurfaceSection], to_x: impl Fn(f64) -> f32, bottom_y: f32)
+//-------------------------------------------------------------------------------------------------------------
+{
+   let mut paint = Paint::default();
+   for section in surfaces.iter().filter(|s| s.surface != Surface::Unknown)
+   {
+      let (x1, x2) = (to_x(section.start), to_x(section.end));
+      if let Some(rect) = tiny_skia::Rect::from_xywh(x1, bottom_y + 2.0, (x2 - x1).max(1.0), SURFACE_BAND_HEIGHT)
+      {
+         paint.set_color(Theme::skia_color(section.surface.color()));
+         pixmap.fill_rect(rect, &paint, Transform::identity(), None);
       }
+   }
+}
+
+/// Below this many segments the profile is drawn on the calling thread as splitting it costs more than it saves.
+const PARALLEL_PROFILE_SEGMENTS: usize = 400;
+
+/// A profile segment between two track points in pixmap coordinates, coloured by its gradient.
+#[derive(Clone, Copy)]
+struct ProfileSegment
+{
+   start: (f32, f32),
+   end:   (f32, f32),
+   color: tiny_skia::Color,
+   hatch: Hatch,
+}
 
-   super::frame::draw_distance_labels(&mut pixmap, me.gradient_start, me.gradient_end,
-                        label_width, padding, plot_width, plot_height);
-   me.gradient_pixmap = Some(Box::new(pixmap.clone()));
-   me.gradient_pixmap_width = pixmap_width;
-   me.gradient_pixmap_height = pixmap_height;
+/// Pattern drawn over a profile segment for colourblind riders (`ProfileStyle::is_patterned`): horizontal lines
+/// downhill, vertical lines climbing, a grid when steep and dense white lines at the extreme gradient.
+#[derive(Clone, Copy, PartialEq)]
+enum Hatch
+{
+   None,
+   Horizontal,
+   Vertical,
+   Grid,
+   Dense,
+}
 
-      let current_position = match me.current_position
+/// Draw the filled area below and the profile line of each segment crossing `pixmap`, which is the stripe of the
+/// profile starting `x0` pixels from the left.
+fn draw_profile_segments(pixmap: &mut Pixmap, segments: &[ProfileSegment], bottom_y: f32, x0: u32)
+//--------------------------------------------------------------------------------------------------
+{
+   const STROKE_WIDTH: f32 = 3.0;
+   let transform = Transform::from_translate(-(x0 as f32), 0.0);
+   let left = x0 as f32 - STROKE_WIDTH;
+   let right = (x0 + pixmap.width()) as f32 + STROKE_WIDTH;
+   for segment in segments.iter().filter(|segment| segment.end.0 >= left && segment.start.0 <= right)
+   {
+      let ((x1, y1), (x2, y2)) = (segment.start, segment.end);
+      let mut paint = Paint::default();
+      paint.set_color(segment.color);
+      paint.anti_alias = true;
+
+      // Draw filled polygon below the profile
+      let mut path_builder = PathBuilder::new();
+      path_builder.move_to(x1, y1);
+      path_builder.line_to(x2, y2);
+      path_builder.line_to(x2, bottom_y);
+      path_builder.line_to(x1, bottom_y);
+      path_builder.close();
+      if let Some(path) = path_builder.finish()
       {
-         | Some(pos) => pos,
-         | None =>
-         {
-         return Ok(super::frame::pixmap_to_image(&pixmap, pixmap_width, pixmap_height));
-         }
-      };
+         pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+      }
+      draw_hatch(pixmap, segment, bottom_y, transform);
 
+      // Draw profile line segment
+      let mut path_builder = PathBuilder::new();
+      path_builder.move_to(x1, y1);
+      path_builder.line_to(x2, y2);
+      if let Some(path) = path_builder.finish()
+      {
+         let stroke = Stroke { width: STROKE_WIDTH, ..Default::default() };
+         pixmap.stroke_path(&path, &paint, &stroke, transform, None);
+      }
+   }
+}
 
-   if current_position.distance >= 0.0
+/// Draw the `Hatch` pattern over the filled area below `segment`. Lines are spaced from the pixmap origin so the pattern
+/// continues across segments and stripes.
+fn draw_hatch(pixmap: &mut Pixmap, segment: &ProfileSegment, bottom_y: f32, transform: Transform)
+//-----------------------------------------------------------------------------------------------
+{
+   let (spacing, color) = match segment.hatch
+   {
+      | Hatch::None => return,
+      | Hatch::Horizontal | Hatch::Vertical => (10.0, tiny_skia::Color::from_rgba8(0, 0, 0, 110)),
+      | Hatch::Grid => (7.0, tiny_skia::Color::from_rgba8(0, 0, 0, 130)),
+      | Hatch::Dense => (4.0, tiny_skia::Color::from_rgba8(255, 255, 255, 160)),
+   };
+   let ((x1, y1), (x2, y2)) = (segment.start, segment.end);
+   let mut path_builder = PathBuilder::new();
+   if matches!(segment.hatch, Hatch::Vertical | Hatch::Grid | Hatch::Dense)
    {
-      match draw_gradient_marker(me, width, height, &current_position)
+      let mut x = (x1 / spacing).ceil() * spacing;
+      while x < x2
       {
-         | Ok(img) => return Ok(img),
-         | Err(msg) =>
-         {
-            eprintln!("Error recalculating gradient image: {msg}");
-         }
-      };
+         path_builder.move_to(x, y1 + (y2 - y1) * (x - x1) / (x2 - x1));
+         path_builder.line_to(x, bottom_y);
+         x += spacing;
+      }
+   }
+   if matches!(segment.hatch, Hatch::Horizontal | Hatch::Grid)
+   {
+      let mut y = (y1.max(y2) / spacing).ceil() * spacing; // below the profile line across the whole segment
+      while y < bottom_y
+      {
+         path_builder.move_to(x1, y);
+         path_builder.line_to(x2, y);
+         y += spacing;
+      }
+   }
+   if let Some(path) = path_builder.finish()
+   {
+      let mut paint = Paint::default();
+      paint.set_color(color);
+      pixmap.stroke_path(&path, &paint, &Stroke { width: 1.0, ..Default::default() }, transform, None);
    }
-   // Ok((pixmap, pixmap_width, pixmap_height))
-   Ok(super::frame::pixmap_to_image(&pixmap, pixmap_width, pixmap_height))
 }
 
-fn draw_gradient_marker(me: &mut GPXAssistUI, width: f32, height: f32, position: &TrackPoint) -> Result<ColorImage, String>
-//-----------------------------------------
+// #[allow(clippy::too_many_arguments)]
+fn new_gradient_image(me: &mut GPXAssistUI, position: &TrackPoint, width: f32, height: f32, label_width: f64) -> Result<ColorImage, String>
+//----------------------------------------------------------------------------------------------------------------------------------
 {
-   if let Some(gradient_pixmap) = &mut me.gradient_pixmap &&
-      me.gradient_points.len() > 0
-     //let Some(current_point) = me.gradient_points.iter().find(|p| (p.distance - offset).abs() < 1.0)
+   let track = me.gpx_track.clone();
+   let total_distance = me.total_distance;
+   let gradient_length = me.gradient_length.load();
+   let gradient_offset = me.gradient_offset.load();
+
+   me.gradient_start = (position.distance - gradient_offset).max(0.0);
+   me.gradient_end = (me.gradient_start + gradient_length).min(total_distance);
+   if me.gradient_end == total_distance
+   {
+      me.gradient_start = (me.gradient_end - gradient_length).max(0.0);
+   }
+
+   //let mut segment_points: Vec<TrackPoint> = Vec::new();
+   let mut is_seg_loaded = false;
+   let i: i64;
+   (_, i) = track.find_closest(me.gradient_start);
+   if i >= 0
    {
-      let search_result = me.gradient_points.binary_search_by(|probe|
-         probe.distance.partial_cmp(&position.distance).unwrap_or(core::cmp::Ordering::Equal));
-      let (mut pt, i) = match search_result
+      let j: i64;
+      (_, j) = track.find_closest(me.gradient_end);
+      if j >= i
       {
-         | Ok(index) => (Some(me.gradient_points[index]), index as i64),
-         | Err(index) =>
-         {
-            let chosen_index = if index == 0 { 0 } else if index >= me.gradient_points.len() { me.gradient_points.len() - 1 }
-            else
-            {
-               let prev = me.gradient_points[index - 1];
-               let next = me.gradient_points[index];
-               if (position.distance - prev.distance) <= (next.distance - position.distance) { index - 1 } else { index }
-            };
-            (Some(me.gradient_points[chosen_index]), chosen_index as i64)
-         }
-      };
-      if pt.is_none()
+         me.gradient_points = track.points(i as usize ..= j as usize);
+         is_seg_loaded = true;
+      }
+   }
+   if ! is_seg_loaded
+   {
+      me.gradient_points = Vec::new();
+      for point in track.iter()
       {
-         match me.gradient_points.iter().find(|p| (position.distance - p.distance).abs() < 1.0)
+         if point.distance >= me.gradient_start && point.distance <= me.gradient_end
          {
-            | Some(p) => pt = Some(*p),
-            | None => return Err("Current point not found in gradient points".to_string())
+            me.gradient_points.push(point);
          }
       }
-      if let Some(current_point) = pt
-      {
-         let mut pixmap = (*gradient_pixmap).clone();
-         let padding = 60.0;
-         let plot_width = width - 2.0 * padding;
-         let plot_height = height - 2.0 * padding;
-         let distance_range = me.gradient_end - me.gradient_start;
-         let min_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::INFINITY, f64::min);
-         let max_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::NEG_INFINITY, f64::max);
-         let elevation_range = (max_elevation - min_elevation).max(10.0); // Minimum 10m range
-
-         // Calculate proper aspect ratio with vertical exaggeration (same as new_gradient_image)
-         let vertical_exaggeration = me.vertical_scale.load();
-         let actual_aspect_ratio = elevation_range / distance_range;
-         let display_aspect_ratio = actual_aspect_ratio * vertical_exaggeration;
-         let effective_plot_height = (plot_width * display_aspect_ratio as f32).min(plot_height);
-         let elevation_offset = (plot_height - effective_plot_height) / 2.0;
-
-         let map_to_screen = |dist: f64, elev: f64| -> (f32, f32)
-         {
-            let x = padding as f64 + ((dist - me.gradient_start) / distance_range) * plot_width as f64;
-            let y = padding as f64 + elevation_offset as f64 + effective_plot_height as f64 - ((elev - min_elevation) / elevation_range) * effective_plot_height as f64;
-            (x as f32, y as f32)
-         };
-         let (marker_x, marker_y) = map_to_screen(current_point.distance, current_point.altitude);
+   }
 
-         let arrow_size = 15.0;
-         let arrow_elevation = 20.0;
-         let mut path_builder = PathBuilder::new();
-         // path_builder.move_to(marker_x, marker_y - arrow_size); // Top
-         // path_builder.line_to(marker_x - arrow_size * 0.6, marker_y + arrow_size * 0.5); // Bottom left
-         // path_builder.line_to(marker_x + arrow_size * 0.6, marker_y + arrow_size * 0.5); // Bottom right
+   if me.gradient_points.len() < 2
+   {
+      return Err("Insufficient points in segment".to_string());
+   }
 
-         path_builder.move_to(marker_x, marker_y + arrow_size * 0.5 - arrow_elevation); // Top
-         path_builder.line_to(marker_x - arrow_size * 0.6, marker_y - arrow_size - arrow_elevation); // Bottom left
-         path_builder.line_to(marker_x + arrow_size * 0.6, marker_y - arrow_size - arrow_elevation); // Bottom right
-         path_builder.close();
+   let style = profile_style(me, label_width, me.gradient_start, me.gradient_end);
+   let pixmap = draw_profile(&me.gradient_points, me.gradient_start, me.gradient_end, width, height, &style)?;
+   me.gradient_pixmap_width = pixmap.width();
+   me.gradient_pixmap_height = pixmap.height();
+   me.gradient_marker = match me.current_position
+   {
+      | Some(current_position) if current_position.distance >= 0.0 =>
+      {
+         gradient_marker_position(me, &current_position).inspect_err(|msg| log_error!("Error positioning the gradient marker: {msg}"))
+                                                        .ok()
+      }
+      | _ => None,
+   };
+   Ok(super::frame::pixmap_to_image(&pixmap, me.gradient_pixmap_width, me.gradient_pixmap_height))
+}
 
-         if let Some(path) = path_builder.finish()
-         {
-            let mut paint = Paint::default();
-            paint.set_color(tiny_skia::Color::from_rgba8(255, 100, 100, 255));
-            paint.anti_alias = true;
-            pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+/// The gradient view's profile style for the part of the course from `start` to `end` metres.
+fn profile_style(me: &GPXAssistUI, label_width: f64, start: f64, end: f64) -> ProfileStyle
+//----------------------------------------------------------------------------------------
+{
+   ProfileStyle
+   {
+      flat_gradient: me.gradient_flat.load(),
+      extreme_gradient: me.gradient_extreme.load(),
+      vertical_exaggeration: me.vertical_scale.load(),
+      background: if me.is_overlay_mode { me.overlay_background } else { me.theme.gradient_background },
+      label_color: me.theme.gradient_label,
+      label_width,
+      units: me.units,
+      is_patterned: me.is_gradient_patterned,
+      surfaces: me.surface_overlay.sections_between(start, end),
+   }
+}
 
-            // Draw outline
-            let stroke = Stroke { width: 2.0, ..Default::default() };
-            paint.set_color(tiny_skia::Color::from_rgba8(0, 0, 0, 255));
-            pixmap.stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+/// Where the rider marker for `position` goes on the gradient profile, in profile pixmap pixels.
+fn gradient_marker_position(me: &GPXAssistUI, position: &TrackPoint) -> Result<(f32, f32), String>
+//------------------------------------------------------------------------------------------------
+{
+   if me.gradient_points.is_empty() || me.gradient_pixmap_width == 0
+   {
+      return Err("No gradient profile or current point available".to_string());
+   }
+   let search_result = me.gradient_points.binary_search_by(|probe|
+      probe.distance.partial_cmp(&position.distance).unwrap_or(core::cmp::Ordering::Equal));
+   let index = match search_result
+   {
+      | Ok(index) => index,
+      | Err(index) =>
+      {
+         if index == 0 { 0 } else if index >= me.gradient_points.len() { me.gradient_points.len() - 1 }
+         else
+         {
+            let prev = me.gradient_points[index - 1];
+            let next = me.gradient_points[index];
+            if (position.distance - prev.distance) <= (next.distance - position.distance) { index - 1 } else { index }
          }
+      }
+   };
+   let current_point = me.gradient_points[index];
+   let width = me.gradient_pixmap_width as f32;
+   let height = me.gradient_pixmap_height as f32;
+   let padding = 60.0;
+   let plot_width = width - 2.0 * padding;
+   let plot_height = height - 2.0 * padding;
+   let distance_range = me.gradient_end - me.gradient_start;
+   let min_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::INFINITY, f64::min);
+   let max_elevation = me.gradient_points.iter().map(|p| p.altitude).fold(f64::NEG_INFINITY, f64::max);
+   let elevation_range = (max_elevation - min_elevation).max(10.0); // Minimum 10m range
 
-            // Draw circle at marker position
-         let mut path_builder = PathBuilder::new();
-         path_builder.push_circle(marker_x, marker_y, 5.0);
+   // Calculate proper aspect ratio with vertical exaggeration (same as new_gradient_image)
+   let vertical_exaggeration = me.vertical_scale.load();
+   let actual_aspect_ratio = elevation_range / distance_range;
+   let display_aspect_ratio = actual_aspect_ratio * vertical_exaggeration;
+   let effective_plot_height = (plot_width * display_aspect_ratio as f32).min(plot_height);
+   let elevation_offset = (plot_height - effective_plot_height) / 2.0;
 
-         if let Some(path) = path_builder.finish()
-         {
-            let mut paint = Paint::default();
-            paint.set_color(tiny_skia::Color::from_rgba8(255, 128, 192, 255));
-            paint.anti_alias = true;
-            pixmap.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
-         }
-         Ok(super::frame::pixmap_to_image(&pixmap, me.gradient_pixmap_width, me.gradient_pixmap_height))
+   let x = padding as f64 + ((current_point.distance - me.gradient_start) / distance_range) * plot_width as f64;
+   let y = padding as f64 + elevation_offset as f64 + effective_plot_height as f64
+           - ((current_point.altitude - min_elevation) / elevation_range) * effective_plot_height as f64;
+   Ok((x as f32, y as f32))
+}
+
+/// Paint the rider marker at `marker` (in profile pixmap pixels) over the profile image shown in `rect`, in the
+/// `rider_marker` style (a Dot on the profile, an avatar or arrow above it).
+fn draw_gradient_marker(painter: &egui::Painter, rect: egui::Rect, marker: (f32, f32), pixmap_width: u32, rider_marker: &RiderMarker,
+                        avatar: Option<&TextureHandle>)
+//--------------------------------------------------------------------------------------------------------------------------------
+{
+   let scale = rect.width() / (pixmap_width.max(1) as f32);
+   let to_screen = |x: f32, y: f32| rect.min + Vec2::new(x, y) * scale;
+   let (marker_x, marker_y) = marker;
+   match (rider_marker.style, avatar)
+   {
+      | (MarkerStyle::Dot, _) =>
+      {
+         draw_rider_marker(painter, to_screen(marker_x, marker_y), None, rider_marker, None);
+         return;
       }
-      else
+      | (MarkerStyle::Avatar, Some(_)) =>
       {
-         Err("No gradient pixmap or current point available".to_string())
+         let position = to_screen(marker_x, marker_y);
+         painter.line_segment([position, position - Vec2::new(0.0, 14.0)], egui::Stroke::new(2.0, rider_marker.color));
+         draw_rider_marker(painter, position - Vec2::new(0.0, 32.0), None, rider_marker, avatar);
+         painter.circle_filled(position, 4.0, rider_marker.color);
+         return;
       }
+      | _ => (),
    }
-   else
-   {
-      Err("No gradient pixmap or current point available".to_string())
-   }
+   let arrow_size = 15.0;
+   let arrow_elevation = 20.0;
+   let arrow = vec![to_screen(marker_x, marker_y + arrow_size * 0.5 - arrow_elevation), // Top
+                    to_screen(marker_x - arrow_size * 0.6, marker_y - arrow_size - arrow_elevation), // Bottom left
+                    to_screen(marker_x + arrow_size * 0.6, marker_y - arrow_size - arrow_elevation)]; // Bottom right
+   painter.add(egui::Shape::convex_polygon(arrow, rider_marker.color, egui::Stroke::new(2.0 * scale, Color32::BLACK)));
+   painter.circle_filled(to_screen(marker_x, marker_y), 5.0 * scale, rider_marker.color.gamma_multiply(0.8));
 }
 
 fn gradient_options(me: &mut GPXAssistUI, ui: &mut egui::Ui)
@@ -874,17 +1584,18 @@ fn gradient_options(me: &mut GPXAssistUI, ui: &mut egui::Ui)
    let mut extreme_gradient: f64 = me.gradient_extreme.load();
    ui.horizontal(|ui|
    {
-      ui.label(egui::RichText::new("Gradient Refresh:").color(egui::Color32::YELLOW).strong());
+      ui.label(egui::RichText::new("Gradient Refresh:").color(me.theme.label_color).strong());
       let delta_response = ui.add_sized(
          egui::Vec2::new(100.0, 30.0),
-         egui::DragValue::new(&mut gradient_delta)
-            .suffix("m")
+         length_drag_value(&mut gradient_delta, me.units)
             .range(1.0..=100.0)
             .speed(10.0))
-         .on_hover_text(format!("The distance in metres to travel before redrawing the gradient display with rider positioned at {:.2} (metres)", gradient_position));
+         .on_hover_text(format!("The distance to travel before redrawing the gradient display with rider positioned at {}",
+                                me.units.format_length(gradient_position)));
       if delta_response.dragged() || delta_response.changed()
       {
          me.gradient_delta.store(gradient_delta);
+         me.source_manager.notify();
          // me.is_first_gradient_frame = true;
       }
 
@@ -892,11 +1603,10 @@ fn gradient_options(me: &mut GPXAssistUI, ui: &mut egui::Ui)
       ui.label("Length:");
       let length_response = ui.add_sized(
          egui::Vec2::new(100.0, 30.0),
-         egui::DragValue::new(&mut gradient_length)
+         length_drag_value(&mut gradient_length, me.units)
          .range(100.0..=10000.0)
-         .suffix("m")
          .speed(10.0))
-         .on_hover_text("The length of the gradient section to display (metres)");
+         .on_hover_text("The length of the gradient section to display");
       if length_response.dragged() || length_response.changed()
       {
          me.gradient_length.store(gradient_length);
@@ -907,11 +1617,10 @@ fn gradient_options(me: &mut GPXAssistUI, ui: &mut egui::Ui)
       ui.label("Offset:");
       let position_response = ui.add_sized(
          egui::Vec2::new(100.0, 30.0),
-         egui::DragValue::new(&mut gradient_position)
-            .suffix("m")
+         length_drag_value(&mut gradient_position, me.units)
             .range(100.0..=2000.0)
             .speed(10.0))
-         .on_hover_text("The position within the gradient section where the rider currently is positioned (metres)");
+         .on_hover_text("The position within the gradient section where the rider currently is positioned");
       if position_response.dragged() || position_response.changed()
       {
          me.gradient_offset.store(gradient_position);
@@ -930,220 +1639,778 @@ fn gradient_options(me: &mut GPXAssistUI, ui: &mut egui::Ui)
          .on_hover_text("Vertical scaling for gradient");
       if scaling_response.dragged() || scaling_response.changed()
       {
-         me.vertical_scale.store(vertical_scale);
+         me.vertical_scale.store(vertical_scale);
+         me.is_first_gradient_frame = true;
+      }
+
+      ui.separator();
+      ui.label("Flat Gradient (%):");
+      let flat_gradient_response = ui.add_sized(
+         egui::Vec2::new(100.0, 30.0),
+         egui::DragValue::new(&mut flat_gradient)
+         .suffix("%")
+         .range(0.1..=2.5)
+         .speed(0.1)
+         .max_decimals(1))
+         .on_hover_text("The gradient considered to be 'flat', e.g if 0.5 then -0.5 to 0.5 is flat");
+      if flat_gradient_response.dragged() || flat_gradient_response.changed()
+      {
+         me.gradient_flat.store(flat_gradient);
+         me.is_first_gradient_frame = true;
+      }
+
+      ui.label("Extreme Gradient (%):");
+      let extreme_gradient_response =  ui.add_sized(
+         egui::Vec2::new(100.0, 30.0),
+         egui::DragValue::new(&mut extreme_gradient)
+         .range(10.0..=100.0)
+         .speed(0.5)
+         .max_decimals(1))
+         .on_hover_text("The gradient considered to be 'extreme' (black), e.g if > 16 then gradient color is black");
+      if extreme_gradient_response.dragged() || extreme_gradient_response.changed()
+      {
+         me.gradient_extreme.store(extreme_gradient);
+         me.is_first_gradient_frame = true;
+      }
+   });
+}
+
+fn render_current_gradient(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//------------------------------------------------------
+{
+   ui.vertical(|ui|
+   {
+      if ! me.is_overlay_mode
+      {
+         gradient_options(me, ui);
+      }
+      // ui.centered_and_justified(|ui|
+      // {
+      if let Some(texture) = &me.gradient_texture
+      {
+         let response = ui.add(Image::new(texture)
+                  .maintain_aspect_ratio(true)
+                  .fit_to_original_size(1.0)
+                  .shrink_to_fit()
+               );
+         if let Some(marker) = me.gradient_marker
+         {
+            let avatar = me.rider_avatar.texture(ui.ctx(), &me.rider_marker);
+            draw_gradient_marker(&ui.painter_at(response.rect), response.rect, marker, me.gradient_pixmap_width, &me.rider_marker,
+                                 avatar.as_ref());
+         }
+      }
+   });
+
+}
+
+/// The broadcast file help screen. The TrainingPeaks Virtual menu screenshots are loaded once into `textures` by
+/// `GPXAssistUI::new`.
+fn display_invalid_broadcast_directory(ui: &mut egui::Ui, textures: &HashMap<String, (TextureHandle, [f32; 2])>,
+                                       is_aged: bool, delta: f64, units: Units)
+//--------------------------------------------------------------------------------------------------------------
+{
+   let broadcast_file = match get_broadcast_file()
+   {
+      | Some(dir) => dir,
+      | None => PathBuf::from(""),
+   };
+   let err_color:  Color32;
+   let errmsg = if broadcast_file.is_file() && is_aged
+   {
+      err_color = Color32::YELLOW;
+      format!("The broadcast file {:?} has not been updated recently enough (try pedalling for more than {}).", broadcast_file, units.format_length(delta)).to_string()
+   }
+   else
+   {
+      err_color = Color32::RED;
+      format!("Could not find a valid TrainingPeaks Virtual broadcast file at {:#?}.", broadcast_file).to_string()
+   };
+
+   let image = |name: &str| textures.get(name).map(|(texture, _)| Image::new(texture));
+   let image_1 = image("menu_1").map(|image| image.maintain_aspect_ratio(true)
+                                                  .fit_to_fraction(Vec2 { x: 0.1, y: 0.5 })
+                                                  .shrink_to_fit());
+   let image_2 = image("menu_2").map(|image| image.max_size(Vec2 { x: 115.0, y: 142.0 }).shrink_to_fit());
+   let image_3 = image("menu_3").map(|image| image.maintain_aspect_ratio(true)
+                                                  .fit_to_fraction(Vec2 { x: 0.1, y: 0.5 })
+                                                  .shrink_to_fit());
+   ui.vertical(|ui|
+   {
+      ui.add_space(16.0);
+      ui.add(egui::Label::new( egui::RichText::new(errmsg)
+               .strong().color(err_color)));
+      ui.add_space(32.0);
+      ui.separator();
+      ui.add(egui::Label::new( egui::RichText::new("Try opening settings in TrainingPeaks Virtual")
+               .color(egui::Color32::GREEN)));
+      ui.add_space(5.0);
+      if let Some(image_1) = image_1
+      {
+         ui.add(image_1);
+      }
+      ui.add_space(10.0);
+      ui.add(egui::Label::new( egui::RichText::new("Then select Broadcast Settings")
+               .color(egui::Color32::GREEN)));
+      ui.add_space(5.0);
+      if let Some(image_2) = image_2
+      {
+         ui.add(image_2);
+      }
+      ui.add(egui::Label::new( egui::RichText::new("Finally enable Broadcasting to file, and click the Test button which should create test files")
+               .color(egui::Color32::GREEN)));
+      ui.add_space(5.0);
+      if let Some(image_3) = image_3
+      {
+         ui.add(image_3);
+      }
+      ui.add_space(10.0);
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let settings_dir = settings.lock().get_settings_path().unwrap_or(PathBuf::from("."));
+      let errmsg = format!(r#"If the broadcast file location is still incorrect, use the path below the "Save to Local File" as shown in the image above either in the settings dialog or modify the settings file {:#?} directly."#, settings_dir);
+      ui.add(egui::Label::new(
+         egui::RichText::new(errmsg)
+                  .color(egui::Color32::LIGHT_YELLOW)));
+   });
+}
+
+
+fn open_file_dialog(ctx: &Context, sender: Sender<LoadedCourse>, loading: Arc<parking_lot::Mutex<Option<GpxLoading>>>)
+//--------------------------------------------------------------------------------------------------------------------------------
+{
+   let pick_dir: PathBuf;
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      pick_dir = settings.lock().get_last_directorybuf();
+   }
+   let dialog_future = rfd::AsyncFileDialog::new().set_directory(pick_dir).pick_file();
+   let ctxx = ctx.clone();
+   execute(async move
+   {
+      let file_info = dialog_future.await;
+      if let Some(fileinfo) = file_info
+      {
+         load_gpx_file(fileinfo.path(), &sender, &loading, &ctxx);
+      }
+   });
+}
+
+/// Opens the first GPX file dropped onto the window, using the same channel as the file dialog.
+fn handle_dropped_files(me: &mut GPXAssistUI, ctx: &Context)
+//-----------------------------------------------------------
+{
+   let dropped: Vec<PathBuf> = ctx.input(|i| i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect());
+   if dropped.is_empty()
+   {
+      return;
+   }
+   match dropped.into_iter().find(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gpx")))
+   {
+      | Some(path) =>
+      {
+         let (sender, loading) = (me.open_dialog_channel.0.clone(), me.gpx_loading.clone());
+         let ctxx = ctx.clone();
+         std::thread::spawn(move || load_gpx_file(&path, &sender, &loading, &ctxx));
+      }
+      | None => me.toast_manager.error("Only .gpx files can be dropped onto GPXAssist.", Some(Duration::from_secs(4))),
+   }
+}
+
+/// Parse `path` (on the calling background thread) and send the track to the UI. Loading a file cancels any load
+/// still in progress so only the most recently opened file is shown.
+fn load_gpx_file(path: &Path, sender: &Sender<LoadedCourse>, loading: &Arc<parking_lot::Mutex<Option<GpxLoading>>>,
+                 ctx: &Context)
+//-------------------------------------------------------------------------------------------------------------------------------
+{
+   if let Some(d) = path.parent()
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      settings.lock().set_last_directorybuf(&d.to_path_buf());
+   }
+   let _span = tracing::info_span!("load_gpx").entered();
+   let load = GpxLoading { path: path.to_path_buf(), progress: Arc::new(crossbeam::atomic::AtomicCell::new(0.0)), cancel: CancelToken::default() };
+   if let Some(previous) = loading.lock().replace(load.clone())
+   {
+      previous.cancel.cancel();
+   }
+   ctx.request_repaint();
+   let result = load_track(path, &|fraction|
+   {
+      load.progress.store(fraction);
+      ctx.request_repaint();
+   }, &load.cancel);
+   {
+      let mut current = loading.lock();
+      if !current.as_ref().is_some_and(|l| Arc::ptr_eq(&l.progress, &load.progress))
+      {  // Cancelled from the UI or replaced by a file opened since
+         log_info!("Stopped loading {}", path.display());
+         return;
+      }
+      *current = None;
+   }
+   let file_path_disp = path.display().to_string();
+   let (track_data, waypoints) = match result
+   {
+      | Ok((trackdata, waypoints)) =>
+      {
+         log_debug!("Successfully processed {} points and {} waypoints.", trackdata.len(), waypoints.len());
+         (trackdata, waypoints)
+      }
+      | Err(e) =>
+      {
+         log_error!("Error processing GPX file {:?}: {}", path, e);
+         (Vec::new(), Vec::new())
+      }
+   };
+   let _ = sender.send((track_data, waypoints, file_path_disp));
+   ctx.request_repaint();
+}
+
+/// Show the progress of a GPX file being loaded in place of the views, returning false when nothing is loading.
+fn show_gpx_loading(me: &mut GPXAssistUI, ui: &mut egui::Ui) -> bool
+//-------------------------------------------------------------------
+{
+   let Some(load) = me.gpx_loading.lock().clone() else { return false; };
+   let name = load.path.file_name().map_or_else(|| load.path.display().to_string(), |name| name.to_string_lossy().to_string());
+   ui.vertical_centered(|ui|
+   {
+      ui.add_space(ui.available_height() / 3.0);
+      ui.spinner();
+      ui.add_space(10.0);
+      ui.label(egui::RichText::new(format!("Loading {name}…")).color(me.theme.label_color).strong());
+      ui.add_space(10.0);
+      ui.add(egui::ProgressBar::new(load.progress.load()).show_percentage().desired_width(300.0));
+      ui.add_space(10.0);
+      if ui.button("Cancel").clicked()
+      {
+         load.cancel.cancel();
+         *me.gpx_loading.lock() = None;
+      }
+   });
+   true
+}
+
+/// DragValue editing a length stored in metres, shown in metres or feet.
+pub(crate) fn length_drag_value(metres: &mut f64, units: Units) -> egui::DragValue<'_>
+//-------------------------------------------------------------------------------------
+{
+   egui::DragValue::new(metres)
+      .custom_formatter(move |v, _| format!("{:.0}", units.to_length(v)))
+      .custom_parser(move |s| s.trim().parse::<f64>().ok().map(|v| units.from_length(v)))
+      .suffix(units.length_unit())
+}
+
+/// DragValue editing a course distance stored in metres, shown in km or miles.
+fn distance_drag_value(metres: &mut f64, units: Units) -> egui::DragValue<'_>
+//----------------------------------------------------------------------------
+{
+   egui::DragValue::new(metres)
+      .custom_formatter(move |v, _| format!("{:.2}", units.to_distance(v)))
+      .custom_parser(move |s| s.trim().parse::<f64>().ok().map(|v| units.from_distance(v)))
+      .suffix(units.distance_unit())
+}
+
+/// DragValue editing a speed stored in km/h, shown in km/h or mph.
+fn speed_drag_value(kmh: &mut f64, units: Units) -> egui::DragValue<'_>
+//----------------------------------------------------------------------
+{
+   egui::DragValue::new(kmh)
+      .custom_formatter(move |v, _| format!("{:.0}", units.to_speed(v)))
+      .custom_parser(move |s| s.trim().parse::<f64>().ok().map(|v| units.from_speed(v)))
+      .suffix(units.speed_unit())
+}
+
+fn execute<F: Future<Output = ()> + Send + 'static>(f: F)
+{
+    std::thread::spawn(move || futures::executor::block_on(f));
+}
+
+fn toolbar_refresh(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//---------------------------------------------------------
+{
+   let mut dist: f64 = me.requested_delta.load();
+   ui.label(egui::RichText::new("Refresh:").color(me.theme.label_color).strong());
+   let distance_response = ui.add_sized(
+      egui::Vec2::new(80.0, 30.0), // Fixed size: width = 80, height = 30
+      length_drag_value(&mut dist, me.units)
+         .range(0.0..=1000.0)
+         .speed(1.0)
+         .clamp_existing_to_range(true))
+   .on_hover_text("The distance to travel before updating the current view. Drag with mouse or enter a value.");
+   if distance_response.dragged() || distance_response.changed()
+   {
+      me.requested_delta.store(dist);
+      me.source_manager.notify();
+      log_debug!("Requested Distance Delta set to {:.2} meters", dist);
+   }
+}
+
+fn toolbar_offset(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//--------------------------------------------------------
+{
+   let mut offset: f64 = me.start_offset.load();
+   ui.label(egui::RichText::new("Offset:").color(me.theme.label_color).strong());
+   let offset_response = ui.add_sized(
+      egui::Vec2::new(80.0, 30.0),
+      length_drag_value(&mut offset, me.units)
+         .range(-me.total_distance..=me.total_distance)
+         .speed(10.0)
+         .clamp_existing_to_range(true))
+   .on_hover_text("Distance added to the broadcast distance to align it with the GPX track, e.g. when joining mid-course \
+                   (positive) or when the game includes a roll-out the GPX file does not (negative).");
+   if offset_response.dragged() || offset_response.changed()
+   {
+      me.start_offset.store(offset);
+      me.source_manager.notify();
+   }
+}
+
+fn toolbar_mode(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//------------------------------------------------------
+{
+   let mut current_mode = me.current_mode.load();
+   let before_mode = me.current_mode.load();
+   ui.selectable_value(&mut current_mode, ViewMode::Map,
+      egui::RichText::new("Map").color(me.theme.mode_label_color));
+   ui.selectable_value(&mut current_mode, ViewMode::StreetView,
+      egui::RichText::new("StreetView").color(me.theme.mode_label_color));
+   ui.selectable_value(&mut current_mode, ViewMode::Gradient,
+      egui::RichText::new("Gradient").color(me.theme.mode_label_color));
+   ui.selectable_value(&mut current_mode, ViewMode::Video,
+      egui::RichText::new("Video").color(me.theme.mode_label_color))
+     .on_hover_text("Ride video kept in step with the course distance, for courses with recorded footage");
+   if before_mode != current_mode
+   {
+      change_view_mode(me, current_mode);
+   }
+   ui.menu_button(egui::RichText::new("⧉").size(20.0), |ui|
+   {
+      let before_detached = me.detached_view;
+      ui.selectable_value(&mut me.detached_view, None, "No separate window");
+      ui.selectable_value(&mut me.detached_view, Some(ViewMode::Gradient), "Gradient in separate window");
+      ui.selectable_value(&mut me.detached_view, Some(ViewMode::Map), "Map in separate window");
+      if before_detached != me.detached_view
+      {
+         me.is_first_map_frame = true;
          me.is_first_gradient_frame = true;
+         me.detached_distance = 0.0;
+         ui.close();
       }
+   }).response.labelled("Separate window").on_hover_text("Show the Gradient profile or Map in a second window, e.g. on another monitor.");
+}
 
-      ui.separator();
-      ui.label("Flat Gradient (%):");
-      let flat_gradient_response = ui.add_sized(
-         egui::Vec2::new(100.0, 30.0),
-         egui::DragValue::new(&mut flat_gradient)
-         .suffix("%")
-         .range(0.1..=2.5)
-         .speed(0.1)
-         .max_decimals(1))
-         .on_hover_text("The gradient considered to be 'flat', e.g if 0.5 then -0.5 to 0.5 is flat");
-      if flat_gradient_response.dragged() || flat_gradient_response.changed()
+fn toolbar_speed(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//-------------------------------------------------------
+{
+   let mut speed: f64 = me.simulated_speed.load();
+   ui.label(egui::RichText::new("Speed:").color(me.theme.label_color).strong());
+   let speed_response = ui.add_sized(
+      egui::Vec2::new(60.0, 30.0), // Fixed size: width = 60, height = 30
+      speed_drag_value(&mut speed, me.units)
+         .range(0.0..=200.0)
+         .speed(1.0)
+         .clamp_existing_to_range(true))
+   .on_hover_text("The speed when simulating. Drag with mouse or enter a value.");
+   if speed_response.dragged() || speed_response.changed()
+   {
+      me.simulated_speed.store(speed);
+      me.source_manager.notify();
+      log_debug!("Simulated speed set to {:.2} km/h", speed);
+   }
+}
+
+fn toolbar_simulate(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//----------------------------------------------------------
+{
+   let is_loop_changed = ui.checkbox(&mut me.is_sim_loop, "🔁")
+                           .on_hover_text("Loop the course: continue from the start when the end is reached")
+                           .changed();
+   let mut is_laps_changed = false;
+   if me.is_sim_loop
+   {
+      is_laps_changed = ui.add(egui::DragValue::new(&mut me.sim_loop_laps).range(2..=99).suffix(" laps"))
+                          .on_hover_text("Number of laps to simulate")
+                          .changed();
+      if me.is_simulating.load(Ordering::Relaxed)
       {
-         me.gradient_flat.store(flat_gradient);
-         me.is_first_gradient_frame = true;
+         ui.label(egui::RichText::new(format!("Lap {}", me.sim_current_lap.load())).color(me.theme.label_color));
       }
-
-      ui.label("Extreme Gradient (%):");
-      let extreme_gradient_response =  ui.add_sized(
-         egui::Vec2::new(100.0, 30.0),
-         egui::DragValue::new(&mut extreme_gradient)
-         .range(10.0..=100.0)
-         .speed(0.5)
-         .max_decimals(1))
-         .on_hover_text("The gradient considered to be 'extreme' (black), e.g if > 16 then gradient color is black");
-      if extreme_gradient_response.dragged() || extreme_gradient_response.changed()
+   }
+   if is_loop_changed || is_laps_changed
+   {
+      me.sim_laps.store(if me.is_sim_loop { me.sim_loop_laps } else { 1 });
+   }
+   replay_menu(me, ui);
+   if ! me.is_simulating.load(Ordering::Relaxed) && me.total_distance > 0.0
+   {
+      ui.add(distance_drag_value(&mut me.sim_start_distance, me.units).range(0.0..=me.total_distance).speed(100.0).prefix("From "))
+        .on_hover_text("Distance along the course at which the simulation starts");
+   }
+   if me.is_simulating.load(Ordering::Relaxed)
+   {
+      if let Some((texture, size)) = me.textures.get("test-off")
+         && ui.add(egui::Button::image(egui::Image::new(texture)
+            .alt_text("Stop Test")
+            .bg_fill(me.theme.button_active_fill)
+            .fit_to_exact_size((*size).into())).selected(true))
+            .on_hover_text("Stop simulating movement along the GPX track.")
+      .clicked()
+      {  // Stop Simulation button
+         let ctx = ui.ctx().clone();
+         me.stop_simulation(&ctx);
+      }
+      let is_paused = me.is_sim_paused.load(Ordering::Relaxed);
+      let (pause_text, pause_hover) = if is_paused { ("▶", "Resume the simulation from the current position.") }
+                                      else { ("⏸", "Pause the simulation.") };
+      if ui.add(egui::Button::new(egui::RichText::new(pause_text).size(24.0)).selected(is_paused))
+           .on_hover_text(pause_hover)
+           .clicked()
       {
-         me.gradient_extreme.store(extreme_gradient);
-         me.is_first_gradient_frame = true;
+         me.is_sim_paused.store(!is_paused, Ordering::Relaxed);
+         me.source_manager.notify();
       }
-   });
+   }
+   else if  ! me.is_simulating.load(Ordering::Relaxed)
+            && let Some((texture, size)) = me.textures.get("test-on")
+            && me.total_distance > 0.0
+            && ui.add(egui::Button::image(egui::Image::new(texture)
+                  .alt_text("Test")
+                  .bg_fill(me.theme.button_fill)
+                  .fit_to_exact_size((*size).into())).selected(false))
+                  .on_hover_text("Start simulating movement along the GPX track at the selected speed.")
+   .clicked()
+   {
+      let ctx = ui.ctx().clone();
+      me.start_simulation_source(&ctx);
+   }
 }
 
-fn render_current_gradient(me: &mut GPXAssistUI, ui: &mut egui::Ui)
-//------------------------------------------------------
+/// Menu for choosing a recorded ride (GPX with timestamps or CSV log) to replay instead of simulating a speed.
+fn replay_menu(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//-----------------------------------------------------
 {
-   ui.vertical(|ui|
+   let is_simulating = me.is_simulating.load(Ordering::Relaxed);
+   let text = if me.replay.is_some() { egui::RichText::new("⏺").size(20.0).color(me.theme.toast_error) } else { egui::RichText::new("⏺").size(20.0) };
+   ui.add_enabled_ui(!is_simulating, |ui|
    {
-      gradient_options(me, ui);
-      // ui.centered_and_justified(|ui|
-      // {
-      if let Some(texture) = &me.gradient_texture
+      ui.menu_button(text, |ui|
       {
-         ui.add(Image::new(texture)
-                  .maintain_aspect_ratio(true)
-                  .fit_to_original_size(1.0)
-                  .shrink_to_fit()
-               );
-      }
+         match &me.replay
+         {
+            | Some(recording) =>
+            {
+               let secs = recording.duration() as u64;
+               ui.label(format!("Replaying {} ({}:{:02}:{:02})", recording.name, secs / 3600, (secs % 3600) / 60, secs % 60));
+            }
+            | None => { ui.label("Simulating with the speed setting"); }
+         }
+         if ui.button("Choose recorded ride…").clicked()
+         {
+            ui.close();
+            let directory = {
+               let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+               settings.lock().get_last_directorybuf()
+            };
+            if let Some(path) = rfd::FileDialog::new().set_directory(directory).add_filter("Recorded ride", &["gpx", "csv"]).pick_file()
+            {
+               match RideRecording::load(&path)
+               {
+                  | Ok(recording) => me.replay = Some(Arc::new(recording)),
+                  | Err(e) => me.toast_manager.error(e, None),
+               }
+            }
+         }
+         if me.replay.is_some()
+         {
+            ui.horizontal(|ui|
+            {
+               ui.label("Rate:");
+               for rate in [1.0, 2.0, 5.0, 10.0, 30.0]
+               {
+                  ui.selectable_value(&mut me.replay_rate, rate, format!("{rate}×"));
+               }
+            });
+            if ui.button("Stop using recording").clicked()
+            {
+               me.replay = None;
+               ui.close();
+            }
+         }
+      }).response.labelled("Replay a recorded ride").on_hover_text("Replay a recorded ride through the simulator");
    });
-
 }
 
-/// Load an embedded PNG image as ColorImage
-fn load_embedded_png(asset_name: &str) -> Result<ColorImage, String>
-//--------------------------------------------------------------------
+/// Slider spanning the whole course which jumps the simulated position to any distance.
+fn show_scrubber(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//-------------------------------------------------------
 {
-   let png_data = super::ui::ASSETS_DIR
-      .get_file(asset_name)
-      .ok_or_else(|| format!("Failed to find embedded asset: {}", asset_name))?
-      .contents();
-
-   let img = image::load_from_memory(png_data)
-      .map_err(|e| format!("Failed to decode PNG: {}", e))?;
-
-   let rgba = img.to_rgba8();
-   let size = [rgba.width() as usize, rgba.height() as usize];
-   let pixels = rgba.into_raw();
-
-   Ok(ColorImage::from_rgba_unmultiplied(size, &pixels))
+   let units = me.units;
+   let mut distance = units.to_distance(me.updated_distance.load());
+   ui.spacing_mut().slider_width = (ui.available_width() - 120.0).max(100.0);
+   let response = ui.add(egui::Slider::new(&mut distance, 0.0..=units.to_distance(me.total_distance))
+                            .suffix(units.distance_unit())
+                            .max_decimals(2)
+                            .trailing_fill(true))
+                    .on_hover_text("Drag to jump to any point on the course");
+   if response.changed()
+   {
+      jump_to_distance(me, units.from_distance(distance));
+   }
 }
 
-fn display_invalid_broadcast_directory(ui: &mut egui::Ui, is_aged: bool, delta: f64)
-//----------------------------------------------------
+/// Move the current position to `distance` immediately, and tell the simulation thread to continue from there.
+fn jump_to_distance(me: &mut GPXAssistUI, distance: f64)
+//------------------------------------------------------
 {
-   let broadcast_file = match get_broadcast_file()
+   let distance = distance.clamp(0.0, me.total_distance);
+   me.seek_distance.store(distance);
+   me.source_manager.notify();
+   me.updated_distance.store(distance);
+   if let (Some(position), _) = me.gpx_track.find_closest(distance)
    {
-      | Some(dir) => dir,
-      | None => PathBuf::from(""),
-   };
-   let err_color:  Color32;
-   let errmsg = if broadcast_file.is_file() && is_aged
+      let mut rider = me.rider_data.load();
+      rider.distance = distance as i32;
+      rider.latitude = position.point.lat;
+      rider.longitude = position.point.lon;
+      rider.altitude = position.altitude;
+      me.rider_data.store(rider);
+   }
+   // Views only refresh when moving forward by the refresh distance, so force a redraw for backward or short jumps.
+   me.is_first_map_frame = true;
+   me.is_first_street_frame = true;
+   me.is_first_gradient_frame = true;
+}
+
+/// Show `climb` in the views: jump there when simulating, otherwise centre the map on it and show its profile in the
+/// gradient view until the rider is followed again.
+fn show_climb(me: &mut GPXAssistUI, climb: Climb)
+//-----------------------------------------------
+{
+   if me.is_simulating.load(Ordering::Relaxed)
    {
-      err_color = Color32::YELLOW;
-      format!("The broadcast file {:?} has not been updated recently enough (try pedalling for more than {} metres).", broadcast_file, delta).to_string()
+      jump_to_distance(me, climb.start);
+      return;
    }
-   else
+   if let Some(memory) = &mut me.map_memory
+      && let Some(middle) = me.gpx_track.position_at((climb.start + climb.end) / 2.0)
    {
-      err_color = Color32::RED;
-      format!("Could not find a valid TrainingPeaks Virtual broadcast file at {:#?}.", broadcast_file).to_string()
-   };
+      memory.center_at(lon_lat(middle.lon, middle.lat));
+   }
+   me.climb_preview = Some((climb, None));
+}
 
-   // Load embedded PNG images - unwrap is safe since assets are embedded at compile time
-   let color_img_1 = load_embedded_png("menu-1.png").expect("menu-1.png should be embedded");
-   let texture_1 = ui.ctx().load_texture("menu_1", color_img_1, Default::default());
-   let image_1 = Image::new(&texture_1)
-      .maintain_aspect_ratio(true)
-      .fit_to_fraction(Vec2 { x: 0.1, y: 0.5 })
-      .shrink_to_fit();
-
-   let color_img_2 = load_embedded_png("menu-2.png").expect("menu-2.png should be embedded");
-   let texture_2 = ui.ctx().load_texture("menu_2", color_img_2, Default::default());
-   let image_2 = Image::new(&texture_2)
-      .max_size(Vec2 { x: 115.0, y: 142.0 })
-      .shrink_to_fit();
-
-   let color_img_3 = load_embedded_png("menu-3.png").expect("menu-3.png should be embedded");
-   let texture_3 = ui.ctx().load_texture("menu_3", color_img_3, Default::default());
-   let image_3 = Image::new(&texture_3)
-      .maintain_aspect_ratio(true)
-      .fit_to_fraction(Vec2 { x: 0.1, y: 0.5 })
-      .shrink_to_fit();
-   ui.vertical(|ui|
+/// Stop showing a climb and follow the rider again in the map and gradient views.
+fn follow_rider(me: &mut GPXAssistUI)
+//-----------------------------------
+{
+   me.climb_preview = None;
+   if let Some(memory) = &mut me.map_memory
    {
-      ui.add_space(16.0);
-      ui.add(egui::Label::new( egui::RichText::new(errmsg)
-               .strong().color(err_color)));
-      ui.add_space(32.0);
-      ui.separator();
-      ui.add(egui::Label::new( egui::RichText::new("Try opening settings in TrainingPeaks Virtual")
-               .color(egui::Color32::GREEN)));
-      ui.add_space(5.0);
-      ui.add(image_1);
-      ui.add_space(10.0);
-      ui.add(egui::Label::new( egui::RichText::new("Then select Broadcast Settings")
-               .color(egui::Color32::GREEN)));
-      ui.add_space(5.0);
-      ui.add(image_2);
-      ui.add(egui::Label::new( egui::RichText::new("Finally enable Broadcasting to file, and click the Test button which should create test files")
-               .color(egui::Color32::GREEN)));
-      ui.add_space(5.0);
-      ui.add(image_3);
-      ui.add_space(10.0);
-      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-      let settings_dir = settings.lock().get_settings_path().unwrap_or(PathBuf::from("."));
-      let errmsg = format!(r#"If the broadcast file location is still incorrect, use the path below the "Save to Local File" as shown in the image above either in the settings dialog or modify the settings file {:#?} directly."#, settings_dir);
-      ui.add(egui::Label::new(
-         egui::RichText::new(errmsg)
-                  .color(egui::Color32::LIGHT_YELLOW)));
-   });
+      memory.follow_my_position();
+   }
+   me.is_first_gradient_frame = true;
 }
 
-
-fn open_file_dialog(ctx: &Context, sender: Sender<(Vec<TrackPoint>, String)>)
-//--------------------------------------------------------------------------
+/// The gradient view while a climb is shown: the profile of the climb with a little of the road either side.
+fn display_climb_preview(me: &mut GPXAssistUI, ctx: &Context, ui: &mut egui::Ui)
+//------------------------------------------------------------------------------
 {
-   let pick_dir: PathBuf;
+   let Some((climb, texture)) = me.climb_preview.clone() else { return; };
+   let margin = (climb.length() * 0.1).max(200.0);
+   let (start, end) = ((climb.start - margin).max(0.0), (climb.end + margin).min(me.total_distance));
+   ui.horizontal(|ui|
    {
-      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-      pick_dir = settings.lock().get_last_directorybuf();
+      ui.label(egui::RichText::new(format!("Climb at {}: {} at {:.1}%{}", me.units.format_distance(climb.start, 2),
+                                           me.units.format_distance(climb.length(), 2), climb.average_gradient,
+                                           climb.category().map(|c| format!(" ({c})")).unwrap_or_default()))
+                  .color(me.theme.label_color).strong());
+      if ui.button("⌖ Follow rider").clicked()
+      {
+         follow_rider(me);
+      }
+   });
+   if me.climb_preview.is_none()
+   {
+      return;
    }
-   let dialog_future = rfd::AsyncFileDialog::new().set_directory(pick_dir).pick_file();
-   let ctxx = ctx.clone();
-   execute(async move
+   let texture = match texture
    {
-      let file_info = dialog_future.await;
-      if let Some(fileinfo) = file_info
+      | Some(texture) => texture,
+      | None =>
       {
-         let path = fileinfo.path();
-         match path.parent()
+         let size = ui.available_size();
+         let points: Vec<TrackPoint> = me.gpx_track.section(start, end).iter().collect();
+         let style = profile_style(me, if end - start > 4000.0 { 1000.0 } else { 500.0 }, start, end);
+         match draw_profile(&points, start, end, size.x.max(200.0), size.y.max(150.0), &style)
          {
-            | Some(d) =>
+            | Ok(pixmap) =>
             {
-               let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
-               settings.lock().set_last_directorybuf(&d.to_path_buf());
-            },
-            | None => (),
-         };
-         let file_path_disp = &path.display();
-         let track_data: Vec<TrackPoint> = match process_gpx(file_path_disp.to_string().clone().as_str())
+               let image = pixmap_to_image(&pixmap, pixmap.width(), pixmap.height());
+               let texture = ctx.load_texture("climb_preview", image, Default::default());
+               me.climb_preview = Some((climb, Some(texture.clone())));
+               texture
+            }
+            | Err(e) =>
+            {
+               ui.label(egui::RichText::new(e).color(Color32::RED));
+               return;
+            }
+         }
+      }
+   };
+   ui.add(Image::new(&texture).maintain_aspect_ratio(true).fit_to_original_size(1.0).shrink_to_fit());
+}
+
+/// Menu for choosing which toolbar controls are shown and in what order. Changes are saved to the settings file.
+fn toolbar_customize_menu(me: &mut GPXAssistUI, ui: &mut egui::Ui)
+//-----------------------------------------------------------------
+{
+   ui.menu_button(egui::RichText::new("☰").size(20.0), |ui|
+   {
+      let mut is_changed = false;
+      let count = me.toolbar_items.len();
+      let mut swap: Option<(usize, usize)> = None;
+      for index in 0..count
+      {
+         ui.horizontal(|ui|
          {
-            | Ok(trackdata) =>
+            let (item, is_visible) = &mut me.toolbar_items[index];
+            is_changed |= ui.checkbox(is_visible, item.label()).changed();
+            if ui.add_enabled(index > 0, egui::Button::new("⏶").small()).labelled("Move left").on_hover_text("Move left").clicked()
             {
-               println!("Successfully processed {} points.", trackdata.len());
-               trackdata
+               swap = Some((index, index - 1));
             }
-            | Err(e) =>
+            if ui.add_enabled(index + 1 < count, egui::Button::new("⏷").small()).labelled("Move right").on_hover_text("Move right").clicked()
             {
-               eprintln!("Error processing GPX file {:?}: {}", fileinfo.path(), e);
-               Vec::new()
+               swap = Some((index, index + 1));
             }
-         };
-         let _ = sender.send((track_data, file_path_disp.to_string().clone()));
-         // let _ = sender.send(String::from_utf8_lossy(&text).to_string());
-         ctxx.request_repaint();
+         });
       }
-   });
+      if let Some((from, to)) = swap
+      {
+         me.toolbar_items.swap(from, to);
+         is_changed = true;
+      }
+      ui.separator();
+      if ui.button("Reset toolbar").clicked()
+      {
+         me.toolbar_items = ToolbarItem::defaults();
+         is_changed = true;
+      }
+      if is_changed
+      {
+         save_toolbar(me);
+      }
+   }).response.labelled("Customize the toolbar").on_hover_text("Customize the toolbar");
 }
 
-fn execute<F: Future<Output = ()> + Send + 'static>(f: F)
+fn save_toolbar(me: &GPXAssistUI)
+//-------------------------------
 {
-    std::thread::spawn(move || futures::executor::block_on(f));
+   let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+   settings.lock().set_toolbar(&me.toolbar_items, me.is_toolbar_collapsed);
+}
+
+/// Switch the main view mode, flagging the outgoing and incoming views so they redraw from scratch.
+fn change_view_mode(me: &mut GPXAssistUI, mode: ViewMode)
+//-------------------------------------------------------
+{
+   let before_mode = me.current_mode.load();
+   if before_mode == mode
+   {
+      return;
+   }
+   me.current_mode.store(mode);
+   match before_mode
+   {
+      | ViewMode::Map => me.is_first_map_frame = false,
+      | ViewMode::StreetView =>
+      {
+         me.is_first_street_frame = false;
+         cancel_streetview(me);
+      }
+      | ViewMode::Gradient => me.is_first_gradient_frame = false,
+      | ViewMode::Video | ViewMode::NA => (),
+   }
+   match mode
+   {
+      | ViewMode::Map => me.is_first_map_frame = true,
+      | ViewMode::StreetView => me.is_first_street_frame = true,
+      | ViewMode::Gradient => me.is_first_gradient_frame = true,
+      | ViewMode::Video | ViewMode::NA => (),
+   }
+}
+
+/// Touch mode gestures over the main view: a horizontal swipe cycles Map → StreetView → Gradient → Video (the map
+/// itself uses drags for panning and pinch for zooming so swipes are ignored there) and a pinch on the gradient profile
+/// changes the displayed profile length.
+fn handle_touch_gestures(me: &mut GPXAssistUI, ctx: &Context, rect: egui::Rect)
+//------------------------------------------------------------------------------
+{
+   const SWIPE_DISTANCE: f32 = 120.0;
+   const MODES: [ViewMode; 4] = [ViewMode::Map, ViewMode::StreetView, ViewMode::Gradient, ViewMode::Video];
+
+   let current_mode = me.current_mode.load();
+   let (is_pressed, is_released, position, zoom) =
+      ctx.input(|i| (i.pointer.any_pressed(), i.pointer.any_released(), i.pointer.interact_pos(), i.zoom_delta()));
+   let is_multi_touch = ctx.multi_touch().is_some();
+   if is_pressed && !is_multi_touch
+   {
+      me.swipe_start = position.filter(|p| rect.contains(*p));
+   }
+   if is_multi_touch
+   {
+      me.swipe_start = None;
+   }
+   if is_released
+      && let (Some(start), Some(end)) = (me.swipe_start.take(), position)
+   {
+      let delta = end - start;
+      if current_mode != ViewMode::Map && delta.x.abs() >= SWIPE_DISTANCE && delta.x.abs() > 2.0 * delta.y.abs()
+         && let Some(index) = MODES.iter().position(|m| *m == current_mode)
+      {
+         let next = if delta.x < 0.0 { (index + 1) % MODES.len() } else { (index + MODES.len() - 1) % MODES.len() };
+         change_view_mode(me, MODES[next]);
+      }
+   }
+   if current_mode == ViewMode::Gradient && (zoom - 1.0).abs() > 0.01
+   {
+      let length = (me.gradient_length.load() / zoom as f64).clamp(100.0, 10000.0);
+      me.gradient_length.store(length);
+      me.is_first_gradient_frame = true;
+   }
 }
 
-fn set_style(ctx: &Context)
-//--------------------
+fn set_style(ctx: &Context, theme: &Theme, is_touch_mode: bool)
+//-------------------------------------------------------------
 {
+   theme.apply(ctx);
    let mut style: egui::Style = (*ctx.style()).clone();
-   style.visuals.window_fill = egui::Color32::from_rgb(30, 30, 30);
    style.visuals.image_loading_spinners = true;
    style.text_styles = [(egui::TextStyle::Heading, egui::FontId::new(30.0, egui::FontFamily::Proportional)),
                         (egui::TextStyle::Body, egui::FontId::new(20.0, egui::FontFamily::Proportional)),
                         (egui::TextStyle::Monospace, egui::FontId::new(20.0, egui::FontFamily::Monospace)),
                         (egui::TextStyle::Button, egui::FontId::new(20.0, egui::FontFamily::Proportional)),
                         (egui::TextStyle::Small, egui::FontId::new(15.0, egui::FontFamily::Proportional))].into();
+   // Larger hit targets for finger use on a handlebar mounted tablet
+   style.spacing = egui::style::Spacing::default();
+   if is_touch_mode
+   {
+      style.spacing.interact_size = egui::vec2(56.0, 44.0);
+      style.spacing.button_padding = egui::vec2(14.0, 10.0);
+      style.spacing.item_spacing = egui::vec2(12.0, 10.0);
+      style.spacing.slider_width = 200.0;
+      style.spacing.icon_width = 28.0;
+      style.spacing.icon_width_inner = 18.0;
+      style.spacing.scroll.bar_width = 16.0;
+   }
    ctx.set_style(style);
 }
 
-pub fn streetview( ctx: &Context, api_key: &str, position: &TrackPoint, width: f32, height: f32,
-   use_heading: bool, is_debug: bool ) -> Result<ColorImage, String>
-//--------------------------
+/// Google Street View Static API request for a `width`x`height` image at `position`, looking along the track when
+/// `use_heading` is set.
+pub(crate) fn streetview_url(api_key: &str, position: &TrackPoint, width: u32, height: u32, use_heading: bool) -> String
+//-------------------------------------------------------------------------------------------------------------------
 {
    // Default parameters for Street View
    let fov = 90;      // Field of view (0-120 degrees)
@@ -1151,30 +2418,77 @@ pub fn streetview( ctx: &Context, api_key: &str, position: &TrackPoint, width: f
    let current_latitude = position.point.lat;
    let current_longitude = position.point.lon;
    let pitch = 0;     // Up/down angle (-90 to 90 degrees)
-   let w = width as u32; // width.min(640.0).round() as u32;
-   let h = height as u32; // height.min(640.0).round() as u32;
-
-   // Construct the Google Street View API URL
-   let url: String;
    if use_heading
    {
-      url = format!(
-         "https://maps.googleapis.com/maps/api/streetview?size={w}x{h}&location={current_latitude},{current_longitude}&fov={fov}&heading={heading}&pitch={pitch}&key={api_key}");
+      format!(
+         "https://maps.googleapis.com/maps/api/streetview?size={width}x{height}&location={current_latitude},{current_longitude}&fov={fov}&heading={heading}&pitch={pitch}&key={api_key}")
    }
    else
    {
-      url = format!(
-         "https://maps.googleapis.com/maps/api/streetview?size={w}x{h}&location={current_latitude},{current_longitude}&fov={fov}&pitch={pitch}&key={api_key}");
+      format!(
+         "https://maps.googleapis.com/maps/api/streetview?size={width}x{height}&location={current_latitude},{current_longitude}&fov={fov}&pitch={pitch}&key={api_key}")
+   }
+}
+
+/// The `width`x`height` Street View image at `position` looking along the track, from the cache in `directory` or
+/// else requested and added to it.
+async fn streetview(api_key: &str, position: &TrackPoint, width: u32, height: u32, directory: &Path) -> Result<ColorImage, HttpError>
+//-------------------------------------------------------------------------------------------------------------------------------
+{
+   let url = streetview_url(api_key, position, width, height, true);
+   log_debug!("Fetching Street View at {:.6},{:.6}", position.point.lat, position.point.lon); // the URL holds the API key
+
+   // Images are cached under a hash of the request so the API key isn't written to disk
+   let cache_file = directory.join(format!("{}.jpg", hex::encode(Sha256::digest(url.as_bytes()))));
+   if let Ok(bytes) = std::fs::read(&cache_file)
+      && let Ok(image) = decode_image(&bytes)
+   {
+      return Ok(image);
    }
-   println!("Fetching Street View from: {}", url);
+   let bytes = fetch_bytes_from_url(&url, false).await?;
+   if let Err(e) = cache_file.parent().map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(&cache_file, &bytes))
+   {
+      log_warn!("Could not cache Street View image {}: {}", cache_file.display(), e);
+   }
+   decode_image(&bytes).map_err(HttpError::Permanent)
+}
+
+/// Street View imagery always credits Google as its terms require.
+const GOOGLE_ATTRIBUTION: &str = "© Google";
 
-   // Fetch and load the image
-   fetch_image_from_url(&url)
+/// Copyright for the Street View panorama at `position` from the (unbilled) metadata request, cached in `directory`
+/// beside the images. When the request fails only Google is credited.
+async fn streetview_attribution(api_key: &str, position: &TrackPoint, directory: &Path) -> String
+//----------------------------------------------------------------------------------------------
+{
+   let url = format!("https://maps.googleapis.com/maps/api/streetview/metadata?location={},{}&key={api_key}", position.point.lat,
+                     position.point.lon);
+   let cache_file = directory.join(format!("{}.copyright", hex::encode(Sha256::digest(url.as_bytes()))));
+   let copyright = match std::fs::read_to_string(&cache_file)
+   {
+      | Ok(copyright) => copyright,
+      | Err(_) =>
+      {
+         let copyright = fetch_bytes_from_url(&url, false).await.ok()
+            .and_then(|bytes| serde_json::from_slice::<serde_json::Value>(&bytes).ok())
+            .and_then(|json| json["copyright"].as_str().map(str::to_string))
+            .unwrap_or_default();
+         if !copyright.is_empty()
+         {
+            let _ = std::fs::write(&cache_file, &copyright);
+         }
+         copyright
+      }
+   };
+   let copyright = copyright.trim();
+   if copyright.is_empty() || copyright.contains("Google") { GOOGLE_ATTRIBUTION.to_string() }
+   else { format!("{GOOGLE_ATTRIBUTION} · {copyright}") }
 }
 
 /// Helper function to draw distance labels on the gradient profile
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn draw_distance_labels(pixmap: &mut tiny_skia::Pixmap, segment_start_distance: f64, segment_end_distance: f64,
-                        label_width: f64, padding: f32, plot_width: f32, plot_height: f32)
+                        label_width: f64, padding: f32, plot_width: f32, plot_height: f32, label_color: Color32, units: Units)
 //---------------------------------------------------------------------------------------------------------------
 {
     use fontdue::{Font, FontSettings};
@@ -1202,9 +2516,7 @@ pub(crate) fn draw_distance_labels(pixmap: &mut tiny_skia::Pixmap, segment_start
             break;
         }
 
-        // Convert distance to km for display
-        let distance_km = distance_at_label / 1000.0;
-        let label_text = format!("{:.1}km", distance_km);
+        let label_text = format!("{:.1}{}", units.to_distance(distance_at_label), units.distance_unit());
 
         // Calculate x position for this label
         let x = padding as f64 + ((distance_at_label - segment_start_distance) / distance_range) * plot_width as f64;
@@ -1225,7 +2537,8 @@ pub(crate) fn draw_distance_labels(pixmap: &mut tiny_skia::Pixmap, segment_start
                         let pixel_y = (label_y + py as f32) as u32;
 
                         if pixel_x < pixmap_width && pixel_y < pixmap_height {
-                            let color = tiny_skia::Color::from_rgba8(0, 0, 0, alpha);
+                            let [r, g, b, _] = label_color.to_srgba_unmultiplied();
+                            let color = tiny_skia::Color::from_rgba8(b, g, r, alpha); // BGRA, see pixmap_to_image
                             pixmap.pixels_mut()[((pixel_y * pixmap_width + pixel_x) as usize)] =
                                 color.premultiply().to_color_u8();
                         }
@@ -1246,7 +2559,7 @@ pub(crate) fn draw_distance_labels(pixmap: &mut tiny_skia::Pixmap, segment_start
 
         if let Some(path) = path_builder.finish() {
             let mut paint = tiny_skia::Paint::default();
-            paint.set_color(tiny_skia::Color::from_rgba8(0, 0, 0, 255));
+            paint.set_color(Theme::skia_color(label_color));
             paint.anti_alias = true;
             let stroke = tiny_skia::Stroke { width: 2.0, ..Default::default() };
             pixmap.stroke_path(&path, &paint, &stroke, tiny_skia::Transform::identity(), None);
@@ -1275,37 +2588,110 @@ pub(crate) fn pixmap_to_image(pixmap: &tiny_skia::Pixmap, pixmap_width: u32, pix
    ColorImage::from_rgba_unmultiplied([pixmap_width as usize, pixmap_height as usize], &rgba_pixels)
 }
 
-/// Helper function to fetch an image from a URL
-fn fetch_image_from_url(url: &str) -> Result<ColorImage, String>
-//------------------------------------------------------------------
+/// Check a Street View API key with a metadata request (which is not billed) for a location known to have coverage,
+/// returning a description of the result or of why the key was rejected.
+pub(crate) async fn test_streetview_api_key(api_key: &str) -> Result<String, String>
+//-----------------------------------------------------------------------------------
 {
-   // Fetch the image using reqwest
-   let response = reqwest::blocking::get(url)
-      .map_err(|e| format!("Failed to fetch image: {}", e))?;
-
-   // Check response status
+   const URL: &str = "https://maps.googleapis.com/maps/api/streetview/metadata?location=48.8584,2.2945";
+   let response = http::client()?.get(URL).query(&[("key", api_key.trim())]).timeout(Duration::from_secs(10)).send().await
+      .map_err(|e| format!("Could not reach Google: {}", e))?;
    let status = response.status();
-   if !status.is_success() {
-      return Err(format!("HTTP error: {} - Check if location has Street View coverage", status));
+   let text = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
+   let json: serde_json::Value = serde_json::from_str(&text)
+      .map_err(|_| format!("Unexpected response from Google (HTTP {})", status))?;
+   let message = json["error_message"].as_str().unwrap_or_default();
+   match json["status"].as_str().unwrap_or_default()
+   {
+      | "OK" | "ZERO_RESULTS" | "NOT_FOUND" => Ok("The API key works.".to_string()),
+      | "REQUEST_DENIED" =>
+      {
+         let lower = message.to_lowercase();
+         let reason = if lower.contains("referer")
+         {
+            "the key has website (HTTP referrer) restrictions, use an unrestricted or IP restricted key"
+         }
+         else if lower.contains("billing")
+         {
+            "billing is not enabled for the Google Cloud project"
+         }
+         else if lower.contains("not authorized") || lower.contains("not activated")
+         {
+            "the Street View Static API is not enabled for the key"
+         }
+         else if lower.contains("invalid")
+         {
+            "the key is not valid"
+         }
+         else
+         {
+            message
+         };
+         Err(format!("Request denied: {reason}."))
+      }
+      | "OVER_QUERY_LIMIT" => Err("The key has exceeded its quota.".to_string()),
+      | status => Err(format!("Google returned {status}: {message}")),
    }
+}
 
-   let bytes = response.bytes()
-      .map_err(|e| format!("Failed to read response: {}", e))?;
+/// Street View requests for the view are tried once and give up after this long, as the rider moves on to the next
+/// position anyway.
+const VIEW_REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
 
-   // Check if we got actual image data
-   if bytes.len() < 100 {
-      return Err("Received suspiciously small response - location may not have Street View coverage".to_string());
-   }
+/// Helper function to fetch an image from a URL
+/// Transient network and server errors are retried when `is_retried` (e.g. when precaching), otherwise the request
+/// is tried once with VIEW_REQUEST_TIMEOUT.
+pub(crate) async fn fetch_bytes_from_url(url: &str, is_retried: bool) -> Result<Vec<u8>, HttpError>
+//-----------------------------------------------------------------------------------------------
+{
+   let client = &http::client().map_err(HttpError::Permanent)?;
+   let request = move || async move
+   {
+      let request = client.get(url);
+      let request = if is_retried { request } else { request.timeout(VIEW_REQUEST_TIMEOUT) };
+      let response = request.send().await
+         .map_err(|e| HttpError::from_request(&e, "Failed to fetch image"))?;
+
+      // Check response status
+      let status = response.status();
+      if !status.is_success() {
+         let body = response.text().await.unwrap_or_default();
+         return Err(match HttpError::from_status(status, &body, "Failed to fetch image")
+         {
+            | HttpError::Permanent(message) => HttpError::Permanent(format!("{message} - Check if location has Street View coverage")),
+            | error => error,
+         });
+      }
+
+      let bytes = response.bytes().await
+         .map_err(|e| HttpError::from_request(&e, "Failed to read response"))?;
 
-   // Decode the image
-   let img = image::load_from_memory(&bytes)
+      // Check if we got actual image data
+      if bytes.len() < 100 {
+         return Err(HttpError::Permanent("Received suspiciously small response - location may not have Street View coverage".to_string()));
+      }
+      Ok(bytes.to_vec())
+   };
+   if is_retried { http::with_retry("Street View request", request).await } else { request().await }
+}
+
+fn read_image_file(path: &Path) -> Result<ColorImage, String>
+//-----------------------------------------------------------
+{
+   decode_image(&std::fs::read(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?)
+}
+
+fn decode_image(bytes: &[u8]) -> Result<ColorImage, String>
+//---------------------------------------------------------
+{
+   let img = image::load_from_memory(bytes)
       .map_err(|e| format!("Failed to decode image: {}", e))?;
 
    let rgba = img.to_rgba8();
    let size = [rgba.width() as usize, rgba.height() as usize];
    let pixels = rgba.into_raw();
 
-   println!("Decoded image: {}x{}, {} bytes", size[0], size[1], pixels.len());
+   log_debug!("Decoded image: {}x{}, {} bytes", size[0], size[1], pixels.len());
 
    Ok(ColorImage::from_rgba_unmultiplied(size, &pixels))
 }
@@ -1424,9 +2810,10 @@ pub(crate) fn read_rider_data(parse_retries: i64, retry_duration: Duration) -> O
 
       // println!("Process rider JSON: {}", rider_json);
 
-      if let Ok(rider_data) = RiderDataJSON::from_json(&rider_json)
+      match RiderDataJSON::from_json(&rider_json)
       {
-         return Some(rider_data);
+         | Ok(rider_data) => return Some(rider_data),
+         | Err(e) => log_debug!("{e}"),
       }
       std::thread::sleep(retry_duration);
    }
@@ -1,6 +1,8 @@
 // Public modules
 pub mod ui;
 pub mod frame;
+pub mod theme;
 
 // Re-export key types and functions
-pub use ui::{GPXAssistUI, ViewMode, get_broadcast_directory_or_default};
+pub use ui::{GPXAssistUI, ToolbarItem, ViewMode, get_broadcast_directory_or_default};
+pub use theme::{Theme, ThemeKind};
@@ -0,0 +1,216 @@
+use eframe::egui::{self, Color32, Context};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ThemeKind
+{
+   #[default]
+   Dark,
+   Light,
+   HighContrast,
+   Custom
+}
+
+impl ThemeKind
+{
+   pub const ALL: [ThemeKind; 4] = [ThemeKind::Dark, ThemeKind::Light, ThemeKind::HighContrast, ThemeKind::Custom];
+
+   pub fn label(&self) -> &'static str
+   {
+      match self
+      {
+         | ThemeKind::Dark => "Dark",
+         | ThemeKind::Light => "Light",
+         | ThemeKind::HighContrast => "High contrast",
+         | ThemeKind::Custom => "Custom",
+      }
+   }
+}
+
+/// All the colours used by the UI in one place. Colours are serialized as "#rrggbb" or "#rrggbbaa" strings so a custom
+/// palette can be edited by hand in the settings file.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Theme
+//===============
+{
+   pub dark_mode:             bool,
+   #[serde(default)]
+   pub high_contrast:         bool, // bold outlines and pure black/white text for low vision
+   #[serde(with = "hex_color")]
+   pub window_fill:           Color32,
+   #[serde(with = "hex_color")]
+   pub top_panel_fill:        Color32,
+   #[serde(with = "hex_color")]
+   pub button_fill:           Color32,
+   #[serde(with = "hex_color")]
+   pub button_active_fill:    Color32,
+   #[serde(with = "hex_color")]
+   pub label_color:           Color32, // Toolbar labels such as "Refresh:"
+   #[serde(with = "hex_color")]
+   pub mode_label_color:      Color32, // View mode selector labels
+   #[serde(with = "hex_color")]
+   pub toast_background:      Color32,
+   #[serde(with = "hex_color")]
+   pub toast_text:            Color32,
+   #[serde(with = "hex_color")]
+   pub toast_info:            Color32,
+   #[serde(with = "hex_color")]
+   pub toast_warning:         Color32,
+   #[serde(with = "hex_color")]
+   pub toast_error:           Color32,
+   #[serde(with = "hex_color")]
+   pub toast_success:         Color32,
+   #[serde(with = "hex_color")]
+   pub gradient_background:   Color32,
+   #[serde(with = "hex_color")]
+   pub gradient_label:        Color32,
+}
+
+impl Default for Theme
+{
+   fn default() -> Self { Theme::dark() }
+}
+
+impl Theme
+//========
+{
+   pub fn dark() -> Self
+   //-------------------
+   {
+      Self
+      {
+         dark_mode:           true,
+         high_contrast:       false,
+         window_fill:         Color32::from_rgb(30, 30, 30),
+         top_panel_fill:      Color32::from_rgb(169, 157, 133),
+         button_fill:         Color32::from_rgb(232, 227, 209),
+         button_active_fill:  Color32::from_rgb(190, 190, 190),
+         label_color:         Color32::YELLOW,
+         mode_label_color:    Color32::LIGHT_YELLOW,
+         toast_background:    Color32::from_black_alpha(230),
+         toast_text:          Color32::WHITE,
+         toast_info:          Color32::from_rgb(60, 120, 216),
+         toast_warning:       Color32::from_rgb(255, 165, 0),
+         toast_error:         Color32::from_rgb(220, 53, 69),
+         toast_success:       Color32::from_rgb(40, 167, 69),
+         gradient_background: Color32::from_rgb(224, 224, 224),
+         gradient_label:      Color32::BLACK,
+      }
+   }
+
+   pub fn light() -> Self
+   //--------------------
+   {
+      Self
+      {
+         dark_mode:           false,
+         high_contrast:       false,
+         window_fill:         Color32::from_rgb(245, 245, 240),
+         top_panel_fill:      Color32::from_rgb(214, 206, 188),
+         button_fill:         Color32::from_rgb(250, 248, 240),
+         button_active_fill:  Color32::from_rgb(200, 200, 200),
+         label_color:         Color32::from_rgb(140, 70, 0),
+         mode_label_color:    Color32::from_rgb(60, 40, 0),
+         toast_background:    Color32::from_white_alpha(240),
+         toast_text:          Color32::BLACK,
+         toast_info:          Color32::from_rgb(30, 90, 190),
+         toast_warning:       Color32::from_rgb(200, 120, 0),
+         toast_error:         Color32::from_rgb(190, 30, 45),
+         toast_success:       Color32::from_rgb(30, 130, 55),
+         gradient_background: Color32::from_rgb(250, 250, 250),
+         gradient_label:      Color32::BLACK,
+      }
+   }
+
+   /// White and yellow on black with strong outlines, for riders with low vision.
+   pub fn high_contrast() -> Self
+   //----------------------------
+   {
+      Self
+      {
+         dark_mode:           true,
+         high_contrast:       true,
+         window_fill:         Color32::BLACK,
+         top_panel_fill:      Color32::BLACK,
+         button_fill:         Color32::WHITE,
+         button_active_fill:  Color32::YELLOW,
+         label_color:         Color32::YELLOW,
+         mode_label_color:    Color32::WHITE,
+         toast_background:    Color32::BLACK,
+         toast_text:          Color32::WHITE,
+         toast_info:          Color32::from_rgb(0, 200, 255),
+         toast_warning:       Color32::YELLOW,
+         toast_error:         Color32::from_rgb(255, 80, 80),
+         toast_success:       Color32::from_rgb(0, 255, 0),
+         gradient_background: Color32::WHITE,
+         gradient_label:      Color32::BLACK,
+      }
+   }
+
+   /// Returns the built-in palette for `kind`, or `custom` for [`ThemeKind::Custom`].
+   pub fn for_kind(kind: ThemeKind, custom: &Theme) -> Self
+   //------------------------------------------------------
+   {
+      match kind
+      {
+         | ThemeKind::Dark => Theme::dark(),
+         | ThemeKind::Light => Theme::light(),
+         | ThemeKind::HighContrast => Theme::high_contrast(),
+         | ThemeKind::Custom => *custom,
+      }
+   }
+
+   /// Apply the theme to the egui style (font sizes are left as they are).
+   pub fn apply(&self, ctx: &Context)
+   //--------------------------------
+   {
+      let mut style: egui::Style = (*ctx.style()).clone();
+      style.visuals = if self.dark_mode { egui::Visuals::dark() } else { egui::Visuals::light() };
+      style.visuals.window_fill = self.window_fill;
+      style.visuals.panel_fill = if self.dark_mode { style.visuals.panel_fill } else { self.window_fill };
+      if self.high_contrast
+      {
+         let text = if self.dark_mode { Color32::WHITE } else { Color32::BLACK };
+         style.visuals.panel_fill = self.window_fill;
+         style.visuals.override_text_color = Some(text);
+         let widgets = &mut style.visuals.widgets;
+         for visuals in [&mut widgets.noninteractive, &mut widgets.inactive, &mut widgets.hovered, &mut widgets.active, &mut widgets.open]
+         {
+            visuals.bg_stroke = egui::Stroke::new(1.5, text);
+            visuals.fg_stroke = egui::Stroke::new(1.5, text);
+         }
+         widgets.hovered.bg_stroke = egui::Stroke::new(2.5, self.label_color);
+         style.visuals.selection.stroke = egui::Stroke::new(2.0, self.label_color);
+      }
+      ctx.set_style(style);
+   }
+
+   /// Convert a theme colour to a tiny_skia colour for drawing into pixmaps converted by `frame::pixmap_to_image`,
+   /// which swaps the red and blue channels.
+   pub fn skia_color(color: Color32) -> tiny_skia::Color
+   //---------------------------------------------------
+   {
+      let [r, g, b, a] = color.to_srgba_unmultiplied();
+      tiny_skia::Color::from_rgba8(b, g, r, a)
+   }
+}
+
+/// Serde helper for storing a `Color32` as a hex string, usable with `#[serde(with = "crate::ui::theme::hex_color")]`.
+pub(crate) mod hex_color
+{
+   use eframe::egui::Color32;
+   use serde::{Deserialize, Deserializer, Serializer};
+
+   pub fn serialize<S: Serializer>(color: &Color32, serializer: S) -> Result<S::Ok, S::Error>
+   {
+      let [r, g, b, a] = color.to_srgba_unmultiplied();
+      let s = if a == 255 { format!("#{r:02x}{g:02x}{b:02x}") } else { format!("#{r:02x}{g:02x}{b:02x}{a:02x}") };
+      serializer.serialize_str(&s)
+   }
+
+   pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color32, D::Error>
+   {
+      let s = String::deserialize(deserializer)?;
+      Color32::from_hex(s.trim()).map_err(|e| serde::de::Error::custom(format!("Invalid colour {s}: {e:?}")))
+   }
+}
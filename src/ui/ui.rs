@@ -1,18 +1,41 @@
-use std::{collections::HashMap, fs::OpenOptions, path::PathBuf, sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::{Receiver, Sender, channel}}, time::Duration};
+use std::{collections::HashMap, fs::OpenOptions, path::PathBuf, sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::{Receiver, Sender, channel}}, time::{Duration, Instant, SystemTime}};
 
 use tempfile::NamedTempFile;
 use crossbeam::atomic::AtomicCell;
-use tiny_skia::{Pixmap, Paint, PathBuilder, Stroke, Transform, FillRule};
+use tiny_skia::{Paint, PathBuilder, Stroke, Transform, FillRule};
 
-use chrono::{Local, DateTime};
 use eframe::{CreationContext, egui::{self, Color32, ColorImage, Context, Image, TextureHandle, Vec2}, emath::Numeric};
-use walkers::{HttpTiles, Map, MapMemory, lon_lat, sources::OpenStreetMap};
+use walkers::{HttpOptions, HttpTiles, Map, MapMemory, lon_lat, sources::OpenStreetMap};
 use include_dir::{include_dir, Dir};
+use serde::{Deserialize, Serialize};
 
-use crate::{ STARTUP_PARAMS, components::{self, DirectionalArrow, ToastManager}, data::{RiderData, RiderDataJSON}, gpx::{ TrackPoint, find_closest_point, process_gpx } };
+use crate::{ STARTUP_PARAMS, components::{self, ClimbAlerter, CourseStats, DirectionalArrow, RideCompletion, RideProgress, RiderAvatar, ToastManager, TurnIndicator, ViewCapture}, ui::Theme, data::{RiderData, RiderDataJSON, RiderIdentity, SharedIdentity}, gpx::{ Climb, Track, TrackCursor, TrackPoint, Waypoint, process_gpx } };
 use crate::SETTINGS;
-use crate::settings::Settings;
+use crate::settings::{BroadcastPolling, LiveServerSettings, RiderMarker, Settings, TelemetryCalibration, TILE_CACHE};
+use crate::server::{CourseState, LiveServer, PositionState, TelemetryState};
 use crate::ut;
+use crate::http::{self, HttpError};
+use crate::update::{ReleaseInfo, check_for_update};
+use crate::library::CourseLibrary;
+use crate::compare::ProfileComparison;
+use crate::gradients::GradientStatistics;
+use crate::climbs::ClimbList;
+use crate::pacing::PacingPlanner;
+use crate::precache::StreetViewPrecache;
+use crate::summary::{LapTimer, RideLog, write_report};
+use crate::simulation::{PhysicsModel, RideRecording, SimulationOptions, SpeedNoise};
+use crate::source::{CancelToken, SourceKind, SourceManager, watch_file};
+use crate::units::Units;
+use crate::weather::WeatherMonitor;
+use crate::discord::DiscordPresence;
+use crate::fan::FanController;
+use crate::trainer::TrainerControl;
+use crate::video::VideoPlayer;
+use crate::milestones::MilestoneNotifier;
+use crate::speech::SpeechCues;
+use crate::slope::SlopeMonitor;
+use crate::surface::SurfaceOverlay;
+use crate::automatch::CourseMatcher;
 
 // Embed the entire assets directory at compile time
 pub(crate) static ASSETS_DIR: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/assets");
@@ -23,10 +46,65 @@ pub enum ViewMode
    NA,
    Map,
    StreetView,
-   Gradient
+   Gradient,
+   Video
+}
+
+/// Optional controls on the main toolbar, which can be hidden and reordered.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ToolbarItem
+{
+   Refresh,
+   Offset,
+   Mode,
+   Speed,
+   Simulate
+}
+
+impl ToolbarItem
+{
+   pub const ALL: [ToolbarItem; 5] = [ToolbarItem::Refresh, ToolbarItem::Offset, ToolbarItem::Mode, ToolbarItem::Speed, ToolbarItem::Simulate];
+
+   pub fn label(&self) -> &'static str
+   {
+      match self
+      {
+         | ToolbarItem::Refresh => "Refresh distance",
+         | ToolbarItem::Offset => "Start offset",
+         | ToolbarItem::Mode => "View mode",
+         | ToolbarItem::Speed => "Simulation speed",
+         | ToolbarItem::Simulate => "Simulate",
+      }
+   }
+
+   /// All items in their default order, all visible.
+   pub fn defaults() -> Vec<(ToolbarItem, bool)> { ToolbarItem::ALL.iter().map(|item| (*item, true)).collect() }
+
+   /// Drop duplicates from a saved toolbar layout and append any items it doesn't know about (e.g. added in a newer
+   /// version).
+   pub fn normalize(items: &[(ToolbarItem, bool)]) -> Vec<(ToolbarItem, bool)>
+   {
+      let mut normalized: Vec<(ToolbarItem, bool)> = Vec::with_capacity(ToolbarItem::ALL.len());
+      for (item, is_visible) in items.iter().chain(ToolbarItem::defaults().iter())
+      {
+         if !normalized.iter().any(|(existing, _)| existing == item)
+         {
+            normalized.push((*item, *is_visible));
+         }
+      }
+      normalized
+   }
 }
 
 const MENU_HEIGHT: u32 = 48;
+pub(crate) const BROADCAST_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+const SETTINGS_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Longest wait between reads of the broadcast file while it is being watched for changes (in case an event is missed,
+/// e.g. on a network drive). Without a watcher it is read at the broadcast polling interval.
+const BROADCAST_WATCHED_INTERVAL: Duration = Duration::from_secs(5);
+/// Bounds on the wait between simulation steps, which otherwise lasts until the next update distance is due.
+const SIMULATION_MIN_STEP: f64 = 0.05; // seconds
+const SIMULATION_MAX_STEP: f64 = 1.0;  // seconds
 
 pub struct GPXAssistUI
 //====================
@@ -38,20 +116,29 @@ pub struct GPXAssistUI
    pub(crate) is_first_street_frame:         bool,
    pub(crate) is_first_gradient_frame:       bool,
    pub(crate) gpx_file:                      Option<PathBuf>,
-   pub(crate) gpx_track:                     Arc<Vec<TrackPoint>>,
+   pub(crate) gpx_track:                     Arc<Track>,
    pub(crate) total_distance:                f64,
    pub(crate) current_distance:              f64,
    pub(crate) gradient_distance:             f64,
    pub(crate) updated_distance:              Arc<AtomicCell<f64>>,
    pub(crate) requested_delta:               Arc<AtomicCell<f64>>,
+   pub(crate) broadcast_polling:             Arc<AtomicCell<BroadcastPolling>>,
+   pub(crate) telemetry_calibration:         Arc<AtomicCell<TelemetryCalibration>>,
    pub(crate) simulated_speed:               Arc<AtomicCell<f64>>,
+   pub(crate) start_offset:                  Arc<AtomicCell<f64>>, // metres added to the broadcast distance
+   pub(crate) seek_distance:                 Arc<AtomicCell<f64>>, // requested simulation position, negative when none
    pub(crate) textures:                      HashMap<String, (TextureHandle, [f32; 2])>,
    pub(crate) previous_position:             Option<TrackPoint>,
    pub(crate) current_position:              Option<TrackPoint>,
-   pub(crate) open_dialog_channel:           (Sender<(Vec<TrackPoint>, String)>, Receiver<(Vec<TrackPoint>, String)>),
+   pub(crate) open_dialog_channel:           (Sender<LoadedCourse>, Receiver<LoadedCourse>),
+   pub(crate) gpx_loading:                   Arc<parking_lot::Mutex<Option<GpxLoading>>>, // file being parsed, if any
    pub(crate) tiles:                         Option<HttpTiles>,
    pub(crate) map_memory:                    Option<MapMemory>,
    pub(crate) streetview_texture:            Option<TextureHandle>,
+   pub(crate) streetview_error:              Option<HttpError>, // how the last Street View request failed
+   pub(crate) streetview_attribution:        String, // copyright of the panorama shown
+   pub(crate) streetview_fetch:              Option<StreetViewFetch>, // image being fetched in the background, if any
+   pub(crate) streetview_precache:           Option<StreetViewPrecache>,
 
    pub(crate) gradient_start:                f64,
    pub(crate) gradient_end:                  f64,
@@ -60,21 +147,102 @@ pub struct GPXAssistUI
    pub(crate) gradient_length:               Arc<AtomicCell<f64>>,
    pub(crate) gradient_offset:               Arc<AtomicCell<f64>>,
    pub(crate) gradient_delta:                Arc<AtomicCell<f64>>,
+   pub(crate) streetview_delta:              f64, // metres between Street View requests, 0 to use requested_delta
    pub(crate) gradient_flat:                 Arc<AtomicCell<f64>>,
    pub(crate) gradient_extreme:              Arc<AtomicCell<f64>>,
+   pub(crate) is_gradient_patterned:         bool,
    pub(crate) vertical_scale:                Arc<AtomicCell<f64>>,
-   pub(crate) gradient_pixmap:               Option<Box<Pixmap>>,
+   pub(crate) gradient_marker:               Option<(f32, f32)>, // rider marker position in profile pixmap pixels
    pub(crate) gradient_pixmap_width:         u32,
    pub(crate) gradient_pixmap_height:        u32,
    pub(crate) is_simulating:                 Arc<AtomicBool>,
-   pub(crate) is_running:                    Arc<AtomicBool>,
+   pub(crate) is_sim_paused:                 Arc<AtomicBool>,
+   pub(crate) is_sim_loop:                   bool,
+   pub(crate) sim_loop_laps:                 u32,
+   pub(crate) sim_laps:                      Arc<AtomicCell<u32>>, // laps to simulate, 1 when not looping
+   pub(crate) sim_current_lap:               Arc<AtomicCell<u32>>,
+   pub(crate) replay:                        Option<Arc<RideRecording>>, // recorded ride driving the simulation
+   pub(crate) replay_rate:                   f64,
+   pub(crate) sim_start_distance:            f64, // distance (metres) at which the simulation starts
+   pub(crate) source_manager:                SourceManager, // owns the broadcast or simulation thread
+   pub(crate) units:                         Units,
+   pub(crate) settings_status:               (Instant, Option<SystemTime>), // (checked at, settings file modified)
    pub(crate) rider_data:                    Arc<AtomicCell<RiderData>>,
+   pub(crate) rider_identity:                SharedIdentity, // name, team and country from the broadcast
+   pub(crate) window_title:                  String,
+   pub(crate) window_geometry:               Option<([f32; 2], [f32; 2])>, // (outer position, inner size)
+   pub(crate) is_borderless:                 bool,
+   pub(crate) theme:                         Theme,
+   pub(crate) ride_progress:                 RideProgress,
+   pub(crate) course_stats:                  CourseStats,
+   pub(crate) turn_indicator:                TurnIndicator,
+   pub(crate) surface_overlay:               SurfaceOverlay,
+   pub(crate) rider_marker:                  RiderMarker,
+   pub(crate) rider_avatar:                  RiderAvatar,
+   pub(crate) waypoints:                     Arc<Vec<Waypoint>>,
+   pub(crate) crash_report:                  Option<PathBuf>, // from a crash since the last run, until dismissed
+   pub(crate) ride_completion:               RideCompletion,
+   pub(crate) ride_log:                      RideLog,
+   pub(crate) lap_timer:                     LapTimer,
+   pub(crate) ride_summary:                  Option<Option<PathBuf>>, // --ride-summary, inner None to write beside the GPX file
+   pub(crate) live_server:                   Option<LiveServer>,
+   pub(crate) climb_alerter:                 ClimbAlerter,
+   pub(crate) slope_monitor:                 SlopeMonitor,
+   pub(crate) course_matcher:                CourseMatcher,
+   pub(crate) rider_model:                   PhysicsModel, // mass, CdA and Crr for the grade-adjusted speed
+   pub(crate) weather:                       WeatherMonitor,
+   pub(crate) milestones:                    MilestoneNotifier,
+   pub(crate) speech:                        SpeechCues,
+   pub(crate) discord:                       DiscordPresence,
+   pub(crate) fan_controller:                FanController,
+   pub(crate) trainer:                       TrainerControl,
+   pub(crate) video:                         VideoPlayer,
+   pub(crate) is_overlay_mode:               bool,
+   pub(crate) overlay_background:            Color32,
+   pub(crate) is_overlay_transparent:        bool, // Only set at startup as the window must be created transparent
+   pub(crate) show_log_console:              bool,
+   pub(crate) update_channel:                (Sender<ReleaseInfo>, Receiver<ReleaseInfo>),
+   pub(crate) course_library:                CourseLibrary,
+   pub(crate) profile_comparison:            ProfileComparison,
+   pub(crate) gradient_statistics:           GradientStatistics,
+   pub(crate) climb_list:                    ClimbList,
+   pub(crate) climb_preview:                 Option<(Climb, Option<TextureHandle>)>, // climb shown instead of the rider, with its profile
+   pub(crate) pacing_planner:                PacingPlanner,
+   pub(crate) view_capture:                  ViewCapture,
+   pub(crate) detached_view:                 Option<ViewMode>, // Map or Gradient shown in a second viewport
+   pub(crate) detached_distance:             f64,
+   pub(crate) is_touch_mode:                 bool,
+   pub(crate) swipe_start:                   Option<egui::Pos2>,
+   pub(crate) broadcast_status:              Option<(Instant, (bool, bool))>, // (checked at, (exists, aged))
+   pub(crate) toolbar_items:                 Vec<(ToolbarItem, bool)>, // (item, is visible) in display order
+   pub(crate) is_toolbar_collapsed:          bool,
+   pub(crate) startup_view:                  Option<ViewMode>, // --view, applied to the first course opened
 
    pub show_settings_dialog:     bool,
    pub show_settings_dialog_err: bool,
    pub settings_dialog_message:  String,
 }
 
+/// A parsed GPX file sent to the UI: the track points, waypoints and file path.
+pub(crate) type LoadedCourse = (Vec<TrackPoint>, Vec<Waypoint>, String);
+
+/// A GPX file being parsed on a background thread, shown with its progress in the central panel until it is sent on
+/// the open dialog channel.
+#[derive(Clone)]
+pub(crate) struct GpxLoading
+{
+   pub(crate) path:     PathBuf,
+   pub(crate) progress: Arc<AtomicCell<f32>>, // fraction of the file parsed
+   pub(crate) cancel:   CancelToken,
+}
+
+/// A Street View image and the copyright of its panorama being fetched in the background.
+pub(crate) struct StreetViewFetch
+{
+   pub(crate) cancel: CancelToken,
+   pub(crate) result: Arc<parking_lot::Mutex<Option<Result<(ColorImage, String), HttpError>>>>, // set when it completes
+}
+
 impl Default for GPXAssistUI
 //===========================
 {
@@ -98,7 +266,7 @@ impl Default for GPXAssistUI
          {
             | Ok(track_data) =>
             {
-               println!("Successfully processed {} points.", track_data.len());
+               log_debug!("Successfully processed {} points.", track_data.len());
                total_distance = track_data.last().map_or(0.0, |p| p.distance);
                current_position = track_data.first().map(|p| *p);
                previous_position = current_position;
@@ -106,7 +274,7 @@ impl Default for GPXAssistUI
             }
             | Err(e) =>
             {
-               eprintln!("Error processing GPX file {file_path}: {e}");
+               log_error!("Error processing GPX file {file_path}: {e}");
                Vec::new()
             }
          };
@@ -116,8 +284,21 @@ impl Default for GPXAssistUI
       {
          track_data_opt = None;
       }
+      let streetview_precache = filepath_opt.as_deref().and_then(StreetViewPrecache::load);
       let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
       let mut api_key =  settings.lock().get_streetview_api_key().ok();
+      let theme = settings.lock().active_theme();
+      let (overlay_background, is_overlay_transparent, is_touch_mode, toolbar_items, is_toolbar_collapsed, climb_alerts, units,
+           broadcast_polling, telemetry_calibration, streetview_delta, weather, notifications, speech, discord, fan_control,
+           trainer, rider_model) =
+      {
+         let settings_lock = settings.lock();
+         (settings_lock.overlay_background, settings_lock.overlay_transparent, settings_lock.touch_mode,
+          ToolbarItem::normalize(&settings_lock.toolbar_items), settings_lock.toolbar_collapsed, settings_lock.climb_alerts, settings_lock.units,
+          settings_lock.broadcast_polling, settings_lock.telemetry_calibration, settings_lock.streetview_delta, settings_lock.weather,
+          settings_lock.notifications, settings_lock.speech, settings_lock.discord.clone(), settings_lock.fan_control.clone(), settings_lock.trainer.clone(),
+          settings_lock.simulation_physics)
+      };
       if api_key.is_some() && api_key.as_ref().unwrap().is_empty()
       {
          api_key = None
@@ -132,19 +313,28 @@ impl Default for GPXAssistUI
          is_first_street_frame : true,
          is_first_gradient_frame : true,
          gpx_file: filepath_opt,
-         gpx_track: Arc::new(track_data_opt.unwrap_or_default()),
+         gpx_track: Arc::new(Track::from(track_data_opt.unwrap_or_default())),
          total_distance,
          current_distance: 0.0,
          updated_distance: Arc::new(AtomicCell::new(0.0)),
          requested_delta: Arc::new(AtomicCell::new(100.0)),
+         broadcast_polling: Arc::new(AtomicCell::new(broadcast_polling)),
+         telemetry_calibration: Arc::new(AtomicCell::new(telemetry_calibration)),
          simulated_speed: Arc::new(AtomicCell::new(45.0)),
+         start_offset: Arc::new(AtomicCell::new(0.0)),
+         seek_distance: Arc::new(AtomicCell::new(-1.0)),
          textures: HashMap::new(),
          previous_position,
          current_position,
          open_dialog_channel: channel(),
+         gpx_loading: Arc::new(parking_lot::Mutex::new(None)),
          tiles: tiles_opt,
          map_memory: map_memory_opt,
          streetview_texture: None,
+         streetview_error: None,
+         streetview_attribution: String::new(),
+         streetview_fetch: None,
+         streetview_precache,
          gradient_start:               0.0,
          gradient_end:                 0.0,
          gradient_texture: None,
@@ -152,16 +342,77 @@ impl Default for GPXAssistUI
          gradient_length:              Arc::new(AtomicCell::new(3000.0)),
          gradient_offset:              Arc::new(AtomicCell::new(100.0)),
          gradient_delta:               Arc::new(AtomicCell::new(10.0)),
+         streetview_delta:             streetview_delta,
          gradient_flat:                Arc::new(AtomicCell::new(0.2)),
          gradient_extreme:             Arc::new(AtomicCell::new(16.0)),
+         is_gradient_patterned:        settings.lock().gradient_patterns,
          vertical_scale:        Arc::new(AtomicCell::new(10.0)),
          gradient_distance: 0.0,
-         gradient_pixmap: None,
+         gradient_marker: None,
          gradient_pixmap_width: 0,
          gradient_pixmap_height: 0,
          is_simulating: Arc::new(AtomicBool::new(false)),
-         is_running: Arc::new(AtomicBool::new(false)),
+         is_sim_paused: Arc::new(AtomicBool::new(false)),
+         is_sim_loop: false,
+         sim_loop_laps: 3,
+         sim_laps: Arc::new(AtomicCell::new(1)),
+         sim_current_lap: Arc::new(AtomicCell::new(1)),
+         replay: None,
+         replay_rate: 1.0,
+         sim_start_distance: 0.0,
+         units,
+         settings_status: (Instant::now(), settings.lock().settings_modified()),
+         source_manager: SourceManager::default(),
          rider_data: Arc::new(AtomicCell::new(RiderData::default())),
+         rider_identity: SharedIdentity::default(),
+         window_title: String::new(),
+         window_geometry: None,
+         is_borderless: false,
+         theme,
+         ride_progress: RideProgress::default(),
+         course_stats: CourseStats::new(settings.lock().course_stats),
+         turn_indicator: TurnIndicator::new(settings.lock().turn_indicator),
+         surface_overlay: SurfaceOverlay::new(settings.lock().surface_overlay),
+         rider_marker: settings.lock().rider_marker.clone(),
+         rider_avatar: RiderAvatar::default(),
+         waypoints: Arc::new(Vec::new()),
+         crash_report: None,
+         ride_completion: RideCompletion::default(),
+         ride_log: RideLog::default(),
+         lap_timer: LapTimer::default(),
+         ride_summary: cmdline_opts.as_ref().and_then(|opts| opts.ride_summary.clone()),
+         live_server: None,
+         climb_alerter: ClimbAlerter::new(climb_alerts),
+         slope_monitor: SlopeMonitor::default(),
+         course_matcher: CourseMatcher::default(),
+         rider_model,
+         weather: WeatherMonitor::new(weather),
+         milestones: MilestoneNotifier::new(notifications),
+         speech: SpeechCues::new(speech),
+         discord: DiscordPresence::new(discord),
+         fan_controller: FanController::new(fan_control),
+         trainer: TrainerControl::new(trainer),
+         video: VideoPlayer::default(),
+         is_overlay_mode: false,
+         overlay_background,
+         is_overlay_transparent,
+         show_log_console: false,
+         update_channel: channel(),
+         course_library: CourseLibrary::default(),
+         profile_comparison: ProfileComparison::default(),
+         gradient_statistics: GradientStatistics::default(),
+         climb_list: ClimbList::default(),
+         climb_preview: None,
+         pacing_planner: PacingPlanner::new(settings.lock().pacing),
+         view_capture: ViewCapture::default(),
+         detached_view: None,
+         detached_distance: 0.0,
+         is_touch_mode,
+         swipe_start: None,
+         broadcast_status: None,
+         toolbar_items,
+         is_toolbar_collapsed,
+         startup_view: None,
          show_settings_dialog: false,
          show_settings_dialog_err: false,
          settings_dialog_message: String::new()
@@ -176,6 +427,7 @@ impl GPXAssistUI
 //----------------------
    {
       let mut app = GPXAssistUI::default();
+      app.crash_report = Settings::new().get_config_path().ok().and_then(|directory| crate::crash::take_pending(&directory));
       match load_svg_texture(&cc.egui_ctx, "open_icon", "open_icon.svg", MENU_HEIGHT, MENU_HEIGHT)
       {
          | Ok(texture) =>
@@ -185,7 +437,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load open icon texture {e}.");
+            log_error!("Failed to load open icon texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "test_on_icon", "test_icon.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -197,7 +449,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load test icon texture {e}.");
+            log_error!("Failed to load test icon texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "test_off_icon", "test_off_icon.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -209,7 +461,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load test off icon texture {e}.");
+            log_error!("Failed to load test off icon texture {e}.");
          }
       }
 
@@ -222,7 +474,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load map on texture {e}.");
+            log_error!("Failed to load map on texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "map_off_icon", "globe-off.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -234,7 +486,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load map off texture {e}.");
+            log_error!("Failed to load map off texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "street_on_icon", "streetview-on.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -246,7 +498,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load streetview on icon texture {e}.");
+            log_error!("Failed to load streetview on icon texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "street_off_icon", "streetview-off.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -258,7 +510,7 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load streetview off icon texture {e}.");
+            log_error!("Failed to load streetview off icon texture {e}.");
          }
       }
       match load_svg_texture(&cc.egui_ctx, "settings_icon", "settings.svg", MENU_HEIGHT, MENU_HEIGHT)
@@ -270,12 +522,74 @@ impl GPXAssistUI
          }
          | Err(e) =>
          {
-            eprintln!("Failed to load settings icon texture {e}.");
+            log_error!("Failed to load settings icon texture {e}.");
+         }
+      }
+      // TrainingPeaks Virtual menu screenshots for the broadcast file help screen
+      for (name, asset_name) in [("menu_1", "menu-1.png"), ("menu_2", "menu-2.png"), ("menu_3", "menu-3.png")]
+      {
+         match load_png_texture(&cc.egui_ctx, name, asset_name)
+         {
+            | Ok(texture) =>
+            {
+               app.textures.insert(name.to_string(), texture);
+            }
+            | Err(e) =>
+            {
+               log_error!("Failed to load help image {asset_name} {e}.");
+            }
          }
       }
-      app.tiles = Some(HttpTiles::new(OpenStreetMap, cc.egui_ctx.clone()));
+      let is_check_updates = SETTINGS.get().is_some_and(|settings| settings.lock().check_for_updates);
+      if is_check_updates
+      {
+         let sender = app.update_channel.0.clone();
+         let ctx = cc.egui_ctx.clone();
+         http::spawn(async move
+         {
+            match check_for_update(env!("CARGO_PKG_VERSION")).await
+            {
+               | Ok(Some(release)) =>
+               {
+                  let _ = sender.send(release);
+                  ctx.request_repaint();
+               }
+               | Ok(None) => (),
+               | Err(e) => log_warn!("Update check failed: {e}"),
+            }
+         });
+      }
+      let tile_cache = SETTINGS.get().map(|settings| settings.lock().cache_path(TILE_CACHE));
+      app.tiles = Some(HttpTiles::with_options(OpenStreetMap, HttpOptions { cache: tile_cache, ..Default::default() }, cc.egui_ctx.clone()));
       app.map_memory = Some(MapMemory::default());
 
+      let live_server = SETTINGS.get().map(|settings| settings.lock().live_server).unwrap_or_default();
+      app.configure_live_server(live_server);
+      let (simulate_speed, view) = STARTUP_PARAMS.lock().borrow().as_ref().map_or((None, None), |opts| (opts.simulate_speed, opts.view));
+      app.startup_view = view;
+      if app.total_distance > 0.0
+         && let Some(view) = app.startup_view.take()
+      {  // --view with a command line course
+         app.current_mode.store(view);
+         if simulate_speed.is_none()
+         {
+            app.start_broadcast_source(&cc.egui_ctx);
+         }
+      }
+      if let Some(speed) = simulate_speed
+         && app.total_distance > 0.0
+      {  // --simulate: start moving along the command line course straight away
+         if let Some(speed) = speed
+         {
+            app.simulated_speed.store(speed);
+         }
+         if app.current_mode.load() == ViewMode::NA
+         {
+            app.current_mode.store(ViewMode::Map);
+         }
+         app.start_simulation_source(&cc.egui_ctx);
+      }
+
       // // Initialize streetview_texture with a 1x1 transparent placeholder
       // let placeholder = ColorImage::from_rgba_unmultiplied([1, 1], &[0, 0, 0, 0]);
       // app.streetview_texture = cc.egui_ctx.load_texture(
@@ -287,33 +601,208 @@ impl GPXAssistUI
       app
    }
 
+   /// Start, stop or move the live state server to match the settings.
+   pub(crate) fn configure_live_server(&mut self, settings: LiveServerSettings)
+   //--------------------------------------------------------------------------
+   {
+      let address = settings.is_enabled.then_some((settings.port, settings.is_lan));
+      if self.live_server.as_ref().map(|server| (server.port, server.is_lan)) == address
+      {
+         return;
+      }
+      self.live_server = None; // dropping the server stops it
+      if let Some((port, is_lan)) = address
+      {
+         match LiveServer::start(port, is_lan)
+         {
+            | Ok(server) =>
+            {
+               server.set_course(self.gpx_file.as_ref().map(|file| CourseState::new(file, &self.gpx_track)));
+               self.live_server = Some(server);
+            }
+            | Err(e) =>
+            {
+               log_error!("{e}");
+               self.toast_manager.error(&e, None);
+            }
+         }
+      }
+   }
+
+   /// Publish the rider position and telemetry to the live state server, if it is running.
+   pub(crate) fn publish_live_state(&self)
+   //-------------------------------------
+   {
+      let Some(server) = &self.live_server else { return; };
+      let distance = self.updated_distance.load();
+      let position = if self.gpx_file.is_some() { self.gpx_track.find_closest(distance).0 } else { None };
+      let position = position.map(|p| PositionState
+      {
+         distance,
+         remaining: (self.total_distance - distance).max(0.0),
+         latitude: p.point.lat,
+         longitude: p.point.lon,
+         altitude: p.altitude,
+         heading: p.heading,
+      });
+      let gradient = if position.is_some() { self.gpx_track.gradient_at(distance, 100.0) } else { 0.0 };
+      server.update(position, gradient, TelemetryState::from(&self.rider_data.load()), self.units);
+   }
+
+   /// Write the ride summary requested with --ride-summary, once per ride.
+   pub(crate) fn write_ride_summary(&mut self, is_complete: bool)
+   //------------------------------------------------------------
+   {
+      let (Some(target), Some(course)) = (&self.ride_summary, &self.gpx_file) else { return; };
+      if self.ride_log.is_written()
+      {
+         return;
+      }
+      let Some(report) = self.ride_log.report(course, &self.gpx_track, self.lap_timer.laps(), is_complete) else { return; };
+      match write_report(&report, target.as_deref())
+      {
+         | Ok(path) =>
+         {
+            log_info!("Wrote ride summary {}", path.display());
+            self.ride_log.set_written();
+         }
+         | Err(e) => log_error!("{e}"),
+      }
+   }
+
+   /// Read the rider position from the broadcast file, replacing any running source.
+   pub(crate) fn start_broadcast_source(&mut self, ctx: &Context)
+   //------------------------------------------------------------
+   {
+      let current_mode = self.current_mode.clone();
+      let updated_distance = self.updated_distance.clone();
+      let requested_delta = self.requested_delta.clone();
+      let gradient_delta = self.gradient_delta.clone();
+      let polling = self.broadcast_polling.clone();
+      let calibration = self.telemetry_calibration.clone();
+      let rider_data = self.rider_data.clone();
+      let identity = self.rider_identity.clone();
+      let total_distance = self.total_distance;
+      let start_offset = self.start_offset.clone();
+      let track = self.gpx_track.clone();
+      let ctxx = ctx.clone();
+      self.source_manager.start(SourceKind::Broadcast, move |cancel|
+      {
+         GPXAssistUI::update_distance_thread(ctxx, updated_distance, track, requested_delta, gradient_delta, rider_data, identity,
+                                             total_distance, current_mode, cancel, start_offset, polling, calibration);
+      });
+   }
+
+   /// Simulate (or replay) movement along the course, replacing any running source.
+   pub(crate) fn start_simulation_source(&mut self, ctx: &Context)
+   //-------------------------------------------------------------
+   {
+      self.is_simulating.store(true, Ordering::Relaxed);
+      self.rider_identity.store(RiderIdentity::default()); // the simulated rider has no name
+      let updated_distance = self.updated_distance.clone();
+      let rider_data = self.rider_data.clone();
+      let requested_delta = self.requested_delta.clone();
+      let gradient_delta = self.gradient_delta.clone();
+      let simulated_speed = self.simulated_speed.clone();
+      let total_distance = self.total_distance;
+      let is_sim_running = self.is_simulating.clone();
+      let is_sim_paused = self.is_sim_paused.clone();
+      is_sim_paused.store(false, Ordering::Relaxed);
+      let seek_distance = self.seek_distance.clone();
+      let start_distance = self.sim_start_distance.clamp(0.0, self.total_distance);
+      seek_distance.store(if start_distance > 0.0 { start_distance } else { -1.0 });
+      let laps = self.sim_laps.clone();
+      let current_lap = self.sim_current_lap.clone();
+      current_lap.store(1);
+      let current_mode = self.current_mode.clone();
+      let track = self.gpx_track.clone();
+      let ctxx = ctx.clone();
+      let (physics, variation) =
+      {
+         let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+         let settings = settings.lock();
+         (settings.simulation_physics, settings.simulation_variation)
+      };
+      let kind = if self.replay.is_some() { SourceKind::Replay } else { SourceKind::Simulation };
+      let options = SimulationOptions { physics, variation, replay: self.replay.clone(), replay_rate: self.replay_rate,
+                                        trainer: Some(self.trainer.readings()) };
+      self.source_manager.start(kind, move |cancel|
+      {
+         GPXAssistUI::simulate_movement_thread(ctxx, updated_distance, track, requested_delta, gradient_delta, simulated_speed, rider_data, total_distance,
+            current_mode, is_sim_running, is_sim_paused, cancel, options, seek_distance,
+            laps, current_lap);
+      });
+   }
+
+   /// Stop the simulation and go back to reading the broadcast file.
+   pub(crate) fn stop_simulation(&mut self, ctx: &Context)
+   //-----------------------------------------------------
+   {
+      self.is_simulating.store(false, Ordering::Relaxed);
+      self.is_sim_paused.store(false, Ordering::Relaxed);
+      self.start_broadcast_source(ctx);
+   }
+
    #[allow(clippy::too_many_arguments)]
-   pub(crate) fn update_distance_thread(ctx: Context, updated_distance: Arc<AtomicCell<f64>>,  track: Arc<Vec<TrackPoint>>,
-     requested_delta: Arc<AtomicCell<f64>>, gradient_delta: Arc<AtomicCell<f64>>, rider_data: Arc<AtomicCell<RiderData>>,
-     total_distance: f64, mode:Arc<AtomicCell<ViewMode>>, is_running: Arc<AtomicBool> )
+   pub(crate) fn update_distance_thread(ctx: Context, updated_distance: Arc<AtomicCell<f64>>,  track: Arc<Track>,
+     requested_delta: Arc<AtomicCell<f64>>, gradient_delta: Arc<AtomicCell<f64>>, rider_data: Arc<AtomicCell<RiderData>>, identity: SharedIdentity,
+     total_distance: f64, mode:Arc<AtomicCell<ViewMode>>, cancel: CancelToken, start_offset: Arc<AtomicCell<f64>>,
+     polling: Arc<AtomicCell<BroadcastPolling>>, calibration: Arc<AtomicCell<TelemetryCalibration>> )
    //--------------------------------------------------------------------------------------------------------------------
    {
       let mut last_distance: f64 = 0.0;
       let mut last_gradient_distance: f64 = 0.0;
       let mut distance: f64 = 0.0;
-      while distance < total_distance
+      let mut last_offset = start_offset.load();
+      let mut last_calibration = calibration.load();
+      let mut is_broadcast_missing = false;
+      let mut cursor = TrackCursor::default();
+      // Read as soon as the game writes the file instead of waiting for the next poll
+      let watcher = super::frame::get_broadcast_file().and_then(|file|
       {
-         if !is_running.load(Ordering::Relaxed)
-         {
-            std::thread::sleep(Duration::from_secs(1));
-            continue;
-         }
-         let mut rider = match super::frame::read_rider_data(3, Duration::from_millis(300))
+         watch_file(&file, &cancel).inspect_err(|e| log_warn!("{e}, polling the broadcast file instead")).ok()
+      });
+      let wait_time = || if watcher.is_some() { polling.load().interval().max(BROADCAST_WATCHED_INTERVAL) }
+                         else { polling.load().interval() };
+      while distance < total_distance && !cancel.is_cancelled()
+      {
+         let mut rider = match super::frame::read_rider_data(3, polling.load().retry())
          {
-            | Some(r) => r,
+            | Some(r) =>
+            {
+               if is_broadcast_missing
+               {
+                  log_info!("Broadcast data is available again.");
+                  is_broadcast_missing = false;
+               }
+               identity.store(RiderIdentity::from(&r));
+               r
+            },
             | None =>
             {
-               std::thread::sleep(Duration::from_secs(1));
+               if ! is_broadcast_missing
+               {
+                  log_warn!("Could not read valid rider data from the broadcast file {:?}", super::frame::get_broadcast_file());
+                  is_broadcast_missing = true;
+               }
+               cancel.wait(wait_time());
                continue;
             }
          };
 
-         distance = rider.distance_meters();
+         // The offset maps the broadcast distance onto the GPX track, e.g. when joining an event mid-course or when the
+         // file contains a roll-out the game skips, and the calibration corrects a course length that differs from the
+         // GPX file. A changed offset or calibration forces an immediate update even if it moved backwards.
+         let (offset, calibrated) = (start_offset.load(), calibration.load());
+         if offset != last_offset || calibrated != last_calibration
+         {
+            last_offset = offset;
+            last_calibration = calibrated;
+            last_distance = f64::MIN;
+            last_gradient_distance = f64::MIN;
+         }
+         distance = (calibrated.distance(rider.distance_meters()) + offset).max(0.0);
+         rider.height = calibrated.altitude(rider.height);
          // println!("Read distance: {:.2} meters ({:.2}km)", distance, distance / 1000.0);
          if distance > last_distance
          {
@@ -322,7 +811,7 @@ impl GPXAssistUI
                updated_distance.store(distance);
                last_distance = distance;
                last_gradient_distance = distance;
-               if let (Some(position), _) = find_closest_point(&track, distance)
+               if let (Some(position), _) = cursor.find(&track, distance)
                {
                   rider.latitude = position.point.lat;
                   rider.longitude = position.point.lon;
@@ -332,12 +821,12 @@ impl GPXAssistUI
                let rider_copy = RiderData::from(rider);
                rider_data.store(rider_copy);
                ctx.request_repaint();
-               println!("Sent distance: {:.2} meters ({:.2}km)", distance, distance / 1000.0);
+               log_debug!("Sent distance: {:.2} meters ({:.2}km)", distance, distance / 1000.0);
             } else if mode.load() == ViewMode::Gradient && (distance - last_gradient_distance) >= gradient_delta.load()
             {
                updated_distance.store(distance);
                last_gradient_distance = distance;
-               if let (Some(position), _) = find_closest_point(&track, distance)
+               if let (Some(position), _) = cursor.find(&track, distance)
                {
                   rider.latitude = position.point.lat;
                   rider.longitude = position.point.lon;
@@ -351,39 +840,65 @@ impl GPXAssistUI
             }
          }
 
-         // if !is_running.load(Ordering::Relaxed) { break; }
-         std::thread::sleep(Duration::from_secs(1));
+         cancel.wait(wait_time());
       }
    }
 
-   /// Simulates movement along a GPX track at 45km/h
+   /// Simulates movement along a GPX track at the toolbar speed, the physics model speed when enabled or by replaying a
+   /// recorded ride. The toolbar and physics speeds can be randomly varied (`SpeedVariation`).
    #[allow(clippy::too_many_arguments)]
-   pub(crate) fn simulate_movement_thread( ctx: Context, updated_distance: Arc<AtomicCell<f64>>, track: Arc<Vec<TrackPoint>>,
+   pub(crate) fn simulate_movement_thread( ctx: Context, updated_distance: Arc<AtomicCell<f64>>, track: Arc<Track>,
       requested_delta: Arc<AtomicCell<f64>>, gradient_delta: Arc<AtomicCell<f64>>,
       simulated_speed: Arc<AtomicCell<f64>>, rider_data: Arc<AtomicCell<RiderData>>,
       total_distance: f64, mode:Arc<AtomicCell<ViewMode>>,
-      is_sim_running: Arc<AtomicBool>, is_running: Arc<AtomicBool> )
+      is_sim_running: Arc<AtomicBool>, is_sim_paused: Arc<AtomicBool>, cancel: CancelToken, options: SimulationOptions,
+      seek_distance: Arc<AtomicCell<f64>>, laps: Arc<AtomicCell<u32>>, current_lap: Arc<AtomicCell<u32>> )
    //-------------------------------------------------------------------------------------------------
    {
       let mut distance: f64 = 0.0;
       let mut last_gradient_distance: f64 = 0.0;
       let mut distance_delta = requested_delta.load();
       let mut last_distance: f64 = -distance_delta;
-      let speed = simulated_speed.load();
-      let speed: f64 = 45.0 * 1000.0 / (60.0 * 60.0); // km/h to m/s
-      let start: DateTime<Local> = Local::now();
+      let mut velocity = simulated_speed.load() / 3.6; // current speed in m/s
+      let mut last_tick = Instant::now();
+      let physics = options.physics;
+      let mut replay_time = 0.0; // seconds into the replayed recording
+      let mut noise = SpeedNoise::new(options.variation);
+      let mut cursor = TrackCursor::default();
+      let (mut power, mut heartrate, mut cadence) = (if physics.is_enabled { physics.power.round() as i32 } else { 0 }, 0, 0);
       while distance < total_distance
       {
-         if is_running.load(Ordering::Relaxed)
+         if cancel.is_cancelled()
          {
             break;
          }
+         let seek = seek_distance.swap(-1.0);
+         if seek >= 0.0
+         {  // Jumped with the scrubber: continue from there and refresh the views immediately
+            distance = seek;
+            if let Some(recording) = &options.replay
+            {
+               replay_time = recording.time_at_distance(seek);
+            }
+            last_distance = f64::MIN;
+            last_gradient_distance = f64::MIN;
+         }
+         if is_sim_paused.load(Ordering::Relaxed)
+         {  // Hold the distance and restart the elapsed time accounting when resumed
+            if !is_sim_running.load(Ordering::Relaxed)
+            {
+               break;
+            }
+            cancel.wait(Duration::from_secs(1)); // woken when resumed
+            last_tick = Instant::now();
+            continue;
+         }
          if (distance - last_distance) >= distance_delta
          {
             updated_distance.store(distance);
-            let mut rider = RiderData { distance: distance as i32, ..Default::default() }; //::default();
+            let mut rider = RiderData { distance: distance as i32, speed: (velocity * 1000.0) as i32, power, heartrate, cadence, ..Default::default() };
             // rider.distance = distance as i32;
-            if let (Some(position), _) = find_closest_point(&track, distance)
+            if let (Some(position), _) = cursor.find(&track, distance)
             {
                rider.latitude = position.point.lat;
                rider.longitude = position.point.lon;
@@ -399,8 +914,8 @@ impl GPXAssistUI
          {
             updated_distance.store(distance);
             last_gradient_distance = distance;
-            let mut rider = RiderData { distance: distance as i32, ..Default::default() };
-            if let (Some(position), _) = find_closest_point(&track, distance)
+            let mut rider = RiderData { distance: distance as i32, speed: (velocity * 1000.0) as i32, power, heartrate, cadence, ..Default::default() };
+            if let (Some(position), _) = cursor.find(&track, distance)
             {
                rider.latitude = position.point.lat;
                rider.longitude = position.point.lon;
@@ -411,28 +926,203 @@ impl GPXAssistUI
             rider_data.store(rider);
             last_gradient_distance = distance;
             ctx.request_repaint();
-            println!("Sent gradient distance: {:.2} meters ({:.2}km)", distance, distance / 1000.0);
+            log_debug!("Sent gradient distance: {:.2} meters ({:.2}km)", distance, distance / 1000.0);
          }
 
-         let now: DateTime<Local> = Local::now();
-         let total_time = (now - start).num_seconds() as f64;
-         distance = speed * total_time;
+         if let Some(recording) = &options.replay
+         {
+            replay_time += last_tick.elapsed().as_secs_f64() * options.replay_rate;
+            if replay_time > recording.duration()
+            {
+               log_info!("Finished replaying {}", recording.name);
+               break;
+            }
+            let sample = recording.sample_at(replay_time);
+            (distance, velocity) = (sample.distance, sample.speed);
+            (power, heartrate, cadence) = (sample.power, sample.heartrate, sample.cadence);
+         }
+         else if let Some(reading) = options.trainer.as_ref().map(|trainer| trainer.load()).filter(|reading| reading.is_connected)
+         {  // Riding the course on a smart trainer: its power drives the physics model in place of the simulated effort
+            let effort = PhysicsModel { power: reading.power.max(0) as f64, ..physics };
+            (distance, velocity) = effort.ride(&track, distance, velocity, last_tick.elapsed().as_secs_f64());
+            (power, heartrate, cadence) = (reading.power, reading.heartrate, reading.cadence);
+         }
+         else if physics.is_enabled
+         {  // The physics already slows on climbs so only the rider's effort varies
+            let elapsed = last_tick.elapsed().as_secs_f64();
+            let factor = noise.factor(0.0, elapsed);
+            if factor > 0.0
+            {
+               let effort = PhysicsModel { power: physics.power * factor, ..physics };
+               (distance, velocity) = effort.ride(&track, distance, velocity, elapsed);
+            }
+            else
+            {
+               velocity = 0.0;
+            }
+         }
+         else
+         {  // Re-read the toolbar speed (km/h) every tick so changes apply while the simulation is running
+            let elapsed = last_tick.elapsed().as_secs_f64();
+            let gradient = if options.variation.is_enabled { track.gradient_at(distance, 100.0) } else { 0.0 };
+            velocity = simulated_speed.load() / 3.6 * noise.factor(gradient, elapsed);
+            distance += velocity * elapsed;
+         }
+         last_tick = Instant::now();
+         if distance >= total_distance && current_lap.load() < laps.load()
+         {  // Loop mode: wrap to the start for the next lap
+            distance -= total_distance;
+            replay_time = 0.0;
+            current_lap.store(current_lap.load() + 1);
+            last_distance = f64::MIN;
+            last_gradient_distance = f64::MIN;
+         }
          updated_distance.store(distance);
 
          if !is_sim_running.load(Ordering::Relaxed)
          {
             break;
          }
-         std::thread::sleep(Duration::from_secs(1));
          distance_delta = requested_delta.load();
+         // Wait until the next update distance is due rather than a fixed time, so the views follow the simulated
+         // rider closely. Speed changes, pausing and seeking notify the token to plan the next step again.
+         let mut remaining = last_distance + distance_delta - distance;
+         if mode.load() == ViewMode::Gradient
+         {
+            remaining = remaining.min(last_gradient_distance + gradient_delta.load() - distance);
+         }
+         let rate = velocity * if options.replay.is_some() { options.replay_rate } else { 1.0 };
+         let step = if rate > 0.01 { (remaining.max(0.0) / rate).clamp(SIMULATION_MIN_STEP, SIMULATION_MAX_STEP) } else { SIMULATION_MAX_STEP };
+         cancel.wait(Duration::from_secs_f64(step));
       }
       is_sim_running.store(false, Ordering::Relaxed);
-      is_running.store(true, Ordering::Relaxed);
+      ctx.request_repaint(); // lets the UI switch back to the broadcast source
    }
 
+   /// Apply settings that were edited outside GPXAssist without needing a restart.
+   pub(crate) fn check_settings_reload(&mut self)
+   //--------------------------------------------
+   {
+      let (checked_at, last_modified) = self.settings_status;
+      if self.show_settings_dialog || checked_at.elapsed() < SETTINGS_CHECK_INTERVAL
+      {
+         return;
+      }
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      let modified = settings.lock().settings_modified();
+      self.settings_status = (Instant::now(), modified);
+      if modified.is_none() || modified == last_modified
+      {
+         return;
+      }
+      let reloaded = settings.lock().reload();
+      match reloaded
+      {
+         | Ok(Some(new_settings)) =>
+         {
+            self.apply_settings(&new_settings);
+            *settings.lock() = new_settings;
+            log_info!("Reloaded the edited settings file.");
+            self.toast_manager.info("Settings file changed, settings reloaded.", Some(Duration::from_secs(3)));
+         }
+         | Ok(None) => (),
+         | Err(e) => self.toast_manager.error(format!("The edited settings file could not be loaded: {e}"), None),
+      }
+   }
+
+   /// Write settings changed while riding once they have settled, waking the UI for the write if nothing else does.
+   pub(crate) fn flush_settings(&self, ctx: &Context)
+   //------------------------------------------------
+   {
+      let settings = SETTINGS.get_or_init(|| Arc::new(parking_lot::Mutex::new(Settings::new().get_settings_or_default())));
+      if let Some(delay) = settings.lock().flush(false)
+      {
+         ctx.request_repaint_after(delay);
+      }
+   }
+
+   /// Show the course and the broadcasting rider in the window title, updating it only when either changes.
+   pub(crate) fn update_title(&mut self, ctx: &Context)
+   //--------------------------------------------------
+   {
+      let mut title = match self.gpx_file.as_ref().and_then(|file| file.file_name())
+      {
+         | Some(name) => format!("GPXAssist: {}", name.to_string_lossy()),
+         | None => "GPXAssist".to_string(),
+      };
+      let identity = self.rider_identity.load();
+      if !identity.is_empty()
+      {
+         title = format!("{title} - {}", identity.describe());
+      }
+      if title != self.window_title
+      {
+         ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+         self.window_title = title;
+      }
+   }
+
+   /// Update the live state from `settings`.
+   fn apply_settings(&mut self, settings: &Settings)
+   //-----------------------------------------------
+   {
+      self.theme = settings.active_theme();
+      self.overlay_background = settings.overlay_background;
+      self.is_touch_mode = settings.touch_mode;
+      self.units = settings.units;
+      self.course_stats.set_enabled(settings.course_stats);
+      self.turn_indicator.set_enabled(settings.turn_indicator);
+      self.surface_overlay.set_enabled(settings.surface_overlay);
+      self.rider_marker = settings.rider_marker.clone();
+      self.climb_alerter.configure(settings.climb_alerts);
+      self.weather.configure(settings.weather);
+      self.milestones.configure(settings.notifications);
+      self.speech.configure(settings.speech);
+      self.discord.configure(settings.discord.clone());
+      self.fan_controller.configure(settings.fan_control.clone());
+      self.trainer.configure(settings.trainer.clone());
+      self.broadcast_polling.store(settings.broadcast_polling);
+      self.telemetry_calibration.store(settings.telemetry_calibration);
+      self.rider_model = settings.simulation_physics;
+      self.streetview_delta = settings.streetview_delta;
+      self.configure_live_server(settings.live_server);
+      self.toolbar_items = ToolbarItem::normalize(&settings.toolbar_items);
+      self.is_toolbar_collapsed = settings.toolbar_collapsed;
+      if settings.gradient_length > 0.0 && settings.gradient_length < 20000.0
+      {
+         self.gradient_length.store(settings.gradient_length);
+      }
+      if settings.gradient_offset >= 0.0 && settings.gradient_offset < self.gradient_length.load()
+      {
+         self.gradient_offset.store(settings.gradient_offset);
+      }
+      if settings.flat_gradient_percentage >= 0.0 && settings.flat_gradient_percentage < 5.0
+      {
+         self.gradient_flat.store(settings.flat_gradient_percentage);
+      }
+      if settings.extreme_gradient_percentage >= 5.0 && settings.extreme_gradient_percentage <= 100.0
+      {
+         self.gradient_extreme.store(settings.extreme_gradient_percentage);
+      }
+      if settings.vertical_exaggeration >= 1.0 && settings.vertical_exaggeration <= 50.0
+      {
+         self.vertical_scale.store(settings.vertical_exaggeration);
+      }
+      self.is_gradient_patterned = settings.gradient_patterns;
+      self.is_first_gradient_frame = true;
+      self.broadcast_status = None; // the broadcast directory may have changed
+   }
+
+   /// Whether the broadcast file exists and whether it is stale. The result is cached for `BROADCAST_CHECK_INTERVAL`
+   /// so idle repaints don't hit the filesystem every frame.
    pub(crate) fn check_broadcast_file(&mut self) -> (bool, bool)
    //----------------------------------
    {
+      if let Some((checked_at, status)) = self.broadcast_status
+         && checked_at.elapsed() < BROADCAST_CHECK_INTERVAL
+      {
+         return status;
+      }
       let broadcast_file = super::frame::get_broadcast_file();
       let is_exists = broadcast_file.is_some() && broadcast_file.as_ref().unwrap().is_file();
       let mut age: chrono::Duration = chrono::Duration::zero();
@@ -443,12 +1133,13 @@ impl GPXAssistUI
             | Ok(d) => d,
             | Err(e) =>
             {
-               eprintln!("Error getting broadcast file age: {}", e);
+               log_error!("Error getting broadcast file age: {}", e);
                chrono::Duration::zero()
             }
          };
       }
       let is_aged = age.num_minutes() > 1;
+      self.broadcast_status = Some((Instant::now(), (is_exists, is_aged)));
       (is_exists, is_aged)
    }
 }
@@ -507,6 +1198,24 @@ pub fn load_svg_texture(ctx: &Context, name: &str, asset_name: &str, width: u32,
    Ok(ctx.load_texture(name, color_image, egui::TextureOptions::LINEAR))
 }
 
+/// Load a PNG texture from embedded assets, with its size in pixels.
+pub fn load_png_texture(ctx: &Context, name: &str, asset_name: &str) -> Result<(TextureHandle, [f32; 2]), String>
+//--------------------------------------------------------------------------------------------------------------
+{
+   let png_data = ASSETS_DIR
+      .get_file(asset_name)
+      .ok_or_else(|| format!("Failed to find embedded asset: {}", asset_name))?
+      .contents();
+
+   let rgba = image::load_from_memory(png_data)
+      .map_err(|e| format!("Failed to decode PNG: {}", e))?
+      .to_rgba8();
+   let size = [rgba.width() as usize, rgba.height() as usize];
+   let color_image = ColorImage::from_rgba_unmultiplied(size, rgba.as_raw());
+
+   Ok((ctx.load_texture(name, color_image, Default::default()), [size[0] as f32, size[1] as f32]))
+}
+
 fn save_tmp_image(color_image: &ColorImage)
 //------------------------------------------------------
 {
@@ -517,23 +1226,22 @@ fn save_tmp_image(color_image: &ColorImage)
          let image_path = tempfile.path().to_string_lossy().to_string() + "_streetview_debug.png";
          if let Err(e) = save_image(&color_image, image_path.clone())
          {
-            eprintln!("Failed to save debug image: {}", e);
+            log_error!("Failed to save debug image: {}", e);
          }
          else
          {
-            println!("Saved debug image: {}", image_path);
-            println!("Debug: Image dimensions: {}x{}", color_image.size[0], color_image.size[1]);
-            println!("Debug: First pixel RGBA: {:?}", color_image.pixels.first());
+            log_debug!("Saved debug image {}: {}x{}, first pixel {:?}", image_path, color_image.size[0], color_image.size[1],
+                       color_image.pixels.first());
          }
       }
       | Err(e) =>
       {
-         eprintln!("Failed to create temporary file for debug image: {}", e);
+         log_error!("Failed to create temporary file for debug image: {}", e);
       }
    }
 }
 
-fn save_image(color_image: &ColorImage, path: String) -> Result<(), String>
+pub(crate) fn save_image(color_image: &ColorImage, path: String) -> Result<(), String>
 //-----------------------------------------------------------------------------------
 {
    // Convert ColorImage to image::RgbaImage
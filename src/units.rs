@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+const METRES_PER_MILE: f64 = 1609.344;
+const METRES_PER_FOOT: f64 = 0.3048;
+const KMH_PER_MPH: f64 = 1.609344;
+
+/// Units used to display distances, elevations and speeds. Values are always stored and computed in metres and km/h;
+/// these helpers only convert for display and input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Units
+{
+   #[default]
+   Metric,
+   Imperial,
+}
+
+impl Units
+{
+   pub fn label(self) -> &'static str
+   //--------------------------------
+   {
+      match self
+      {
+         | Units::Metric => "Metric (km, m, km/h)",
+         | Units::Imperial => "Imperial (mi, ft, mph)",
+      }
+   }
+
+   /// Unit for course distances (km or mi).
+   pub fn distance_unit(self) -> &'static str { if self == Units::Metric { "km" } else { "mi" } }
+
+   /// Unit for short distances and elevations (m or ft).
+   pub fn length_unit(self) -> &'static str { if self == Units::Metric { "m" } else { "ft" } }
+
+   pub fn speed_unit(self) -> &'static str { if self == Units::Metric { "km/h" } else { "mph" } }
+
+   /// Metres to km or miles.
+   pub fn to_distance(self, metres: f64) -> f64
+   //------------------------------------------
+   {
+      match self
+      {
+         | Units::Metric => metres / 1000.0,
+         | Units::Imperial => metres / METRES_PER_MILE,
+      }
+   }
+
+   /// km or miles to metres.
+   pub fn from_distance(self, value: f64) -> f64
+   //-------------------------------------------
+   {
+      match self
+      {
+         | Units::Metric => value * 1000.0,
+         | Units::Imperial => value * METRES_PER_MILE,
+      }
+   }
+
+   /// Metres to metres or feet.
+   pub fn to_length(self, metres: f64) -> f64 { if self == Units::Metric { metres } else { metres / METRES_PER_FOOT } }
+
+   /// Metres or feet to metres.
+   pub fn from_length(self, value: f64) -> f64 { if self == Units::Metric { value } else { value * METRES_PER_FOOT } }
+
+   /// km/h to km/h or mph.
+   pub fn to_speed(self, kmh: f64) -> f64 { if self == Units::Metric { kmh } else { kmh / KMH_PER_MPH } }
+
+   /// km/h or mph to km/h.
+   pub fn from_speed(self, value: f64) -> f64 { if self == Units::Metric { value } else { value * KMH_PER_MPH } }
+
+   /// e.g. "12.3 km" or "7.6 mi".
+   pub fn format_distance(self, metres: f64, decimals: usize) -> String
+   //------------------------------------------------------------------
+   {
+      format!("{:.*} {}", decimals, self.to_distance(metres), self.distance_unit())
+   }
+
+   /// e.g. "300 m" or "984 ft", for elevations and distances under a kilometre.
+   pub fn format_length(self, metres: f64) -> String { format!("{:.0} {}", self.to_length(metres), self.length_unit()) }
+
+   pub fn format_speed(self, kmh: f64) -> String { format!("{:.1} {}", self.to_speed(kmh), self.speed_unit()) }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use crate::http;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/donaldmunro/GPXAssist/releases/latest";
+
+/// A newer release found on GitHub.
+#[derive(Debug, Clone)]
+pub struct ReleaseInfo
+{
+   pub version: String,
+   pub url:     String,
+}
+
+/// Query the GitHub releases API and return the latest release if it is newer than `current_version`.
+pub async fn check_for_update(current_version: &str) -> Result<Option<ReleaseInfo>, String>
+//-----------------------------------------------------------------------------------------
+{
+   let response = http::client()?.get(RELEASES_URL)
+      .header("Accept", "application/vnd.github+json")
+      .timeout(Duration::from_secs(10))
+      .send()
+      .await
+      .map_err(|e| format!("Failed to query latest release: {}", e))?;
+   if !response.status().is_success()
+   {
+      return Err(format!("HTTP error {} querying latest release", response.status()));
+   }
+   let text = response.text().await.map_err(|e| format!("Failed to read release response: {}", e))?;
+   let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("Failed to parse release response: {}", e))?;
+   let tag = json["tag_name"].as_str().ok_or("Release response has no tag_name")?.to_string();
+   let url = json["html_url"].as_str().unwrap_or("https://github.com/donaldmunro/GPXAssist/releases").to_string();
+   if is_newer(&tag, current_version)
+   {
+      Ok(Some(ReleaseInfo { version: tag, url }))
+   }
+   else
+   {
+      Ok(None)
+   }
+}
+
+/// Compare dotted version strings such as "v0.3" and "0.2.1" numerically.
+fn is_newer(latest: &str, current: &str) -> bool
+//---------------------------------------------
+{
+   let parse = |v: &str| -> Vec<u64>
+   {
+      v.trim().trim_start_matches(['v', 'V'])
+       .split(['.', '-'])
+       .map_while(|part| part.parse::<u64>().ok())
+       .collect()
+   };
+   let (mut l, mut c) = (parse(latest), parse(current));
+   let len = l.len().max(c.len());
+   l.resize(len, 0);
+   c.resize(len, 0);
+   l > c
+}
@@ -1,4 +1,4 @@
-use std::{error::Error, path::PathBuf};
+use std::{error::Error, path::PathBuf, sync::OnceLock};
 use std::env;
 
 use aes_gcm::{
@@ -7,17 +7,135 @@ use aes_gcm::{
 };
 use chrono::Duration;
 use hex;
+use sha2::{Digest, Sha256};
 
 type EncryptedData = Vec<u8>;
 
-const KEY: &str = "b93597749e7e4c5eac98b14c8530d788b93597749e7e4c5eac98b14c8530d788";
+/// Key used before keys were derived per machine, only kept to migrate existing settings.
+const LEGACY_KEY: &str = "b93597749e7e4c5eac98b14c8530d788b93597749e7e4c5eac98b14c8530d788";
+const KEY_SALT: &[u8] = b"GPXAssist settings key v1";
+const KEY_ITERATIONS: u32 = 100_000;
+/// System keyring entry holding the settings file key.
+const KEYRING_SERVICE: &str = "GPXAssist";
+const KEYRING_USER: &str = "settings-key";
+
+/// Identifier of this machine: the OS machine id where available, otherwise the host name.
+fn machine_id() -> String
+//-----------------------
+{
+   let id = if cfg!(target_os = "windows")
+   {
+      std::process::Command::new("reg").args(["query", r"HKLM\SOFTWARE\Microsoft\Cryptography", "/v", "MachineGuid"]).output().ok()
+         .and_then(|out| String::from_utf8_lossy(&out.stdout).split_whitespace().last().map(|s| s.to_string()))
+   }
+   else if cfg!(target_os = "macos")
+   {
+      std::process::Command::new("ioreg").args(["-rd1", "-c", "IOPlatformExpertDevice"]).output().ok()
+         .and_then(|out| String::from_utf8_lossy(&out.stdout).lines()
+                            .find(|line| line.contains("IOPlatformUUID"))
+                            .and_then(|line| line.split('"').nth(3).map(|s| s.to_string())))
+   }
+   else
+   {
+      ["/etc/machine-id", "/var/lib/dbus/machine-id"].iter().find_map(|path| std::fs::read_to_string(path).ok())
+   };
+   match id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty())
+   {
+      | Some(id) => id,
+      | None =>
+      {
+         log_warn!("Could not read a machine id, deriving the settings key from the host name.");
+         whoami::fallible::hostname().unwrap_or_default()
+      }
+   }
+}
+
+/// AES key derived with PBKDF2-HMAC-SHA256 (salted with KEY_SALT) from the machine id and user name. Neither is
+/// secret, so any program running as this user can derive the same key: this is only obfuscation so a settings file
+/// copied to another machine or account can't be decrypted there, not protection from other local programs.
+fn machine_key() -> &'static [u8; 32]
+//-----------------------------------
+{
+   static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+   KEY.get_or_init(||
+   {
+      let mut key = [0u8; 32];
+      pbkdf2::pbkdf2_hmac::<Sha256>(key_material().as_bytes(), KEY_SALT, KEY_ITERATIONS, &mut key);
+      key
+   })
+}
+
+fn key_material() -> String { format!("{}:{}", machine_id(), whoami::username()) }
+
+/// Machine key derived by iterating SHA-256 before PBKDF2 was used, only kept to read data encrypted with it.
+fn previous_machine_key() -> &'static [u8; 32]
+//--------------------------------------------
+{
+   static KEY: OnceLock<[u8; 32]> = OnceLock::new();
+   KEY.get_or_init(||
+   {
+      let material = key_material();
+      let mut digest: [u8; 32] = Sha256::new().chain_update(KEY_SALT).chain_update(material.as_bytes()).finalize().into();
+      for _ in 1..KEY_ITERATIONS
+      {
+         digest = Sha256::new().chain_update(digest).chain_update(material.as_bytes()).finalize().into();
+      }
+      digest
+   })
+}
+
+/// Random AES key for the settings file held in the system keyring (Keychain, Credential Manager or Secret Service),
+/// created on first use, so other programs can't derive it from the machine. None without a keyring, e.g. on a
+/// headless Linux without a Secret Service, where the machine key has to do.
+pub fn keyring_key() -> Option<&'static [u8; 32]>
+//-----------------------------------------------
+{
+   static KEY: OnceLock<Option<[u8; 32]>> = OnceLock::new();
+   KEY.get_or_init(||
+   {
+      let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+         .inspect_err(|e| log_warn!("No system keyring for the settings key, using the machine key: {e}"))
+         .ok()?;
+      match entry.get_password()
+      {
+         | Ok(stored) =>
+         {
+            let key = hex::decode(stored.trim()).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+            if key.is_none()
+            {
+               log_warn!("The settings key in the system keyring is invalid, using the machine key.");
+            }
+            key
+         }
+         | Err(keyring::Error::NoEntry) =>
+         {
+            let mut key = [0u8; 32];
+            key.copy_from_slice(&Aes256Gcm::generate_key(&mut OsRng));
+            entry.set_password(&hex::encode(key))
+                 .inspect_err(|e| log_warn!("Could not store the settings key in the system keyring, using the machine key: {e}"))
+                 .ok()?;
+            log_info!("Created the settings key in the system keyring");
+            Some(key)
+         }
+         | Err(e) =>
+         {
+            log_warn!("No system keyring for the settings key, using the machine key: {e}");
+            None
+         }
+      }
+   }).as_ref()
+}
 
 pub fn encrypt(password: &str) -> Result<EncryptedData, aes_gcm::Error> 
 //-----------------------------------------------------------------------------------------------
 {
-   // let key = Aes256Gcm::generate_key(&mut OsRng);
-   let key_bytes = hex::decode(KEY).expect("Invalid hex key");
-   let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+   encrypt_with(machine_key(), password)
+}
+
+pub fn encrypt_with(key_bytes: &[u8], password: &str) -> Result<EncryptedData, aes_gcm::Error>
+//--------------------------------------------------------------------------------------------
+{
+   let key = Key::<Aes256Gcm>::from_slice(key_bytes);
    let cipher = Aes256Gcm::new(key);
    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
     
@@ -32,9 +150,22 @@ pub fn encrypt(password: &str) -> Result<EncryptedData, aes_gcm::Error>
 pub fn decrypt(data: &[u8]) -> Result<String, Box<dyn Error>> 
 //---------------------------------------------------------------------------------------
 {
-   // let key = Aes256Gcm::generate_key(&mut OsRng);
-   let key_bytes = hex::decode(KEY).expect("Invalid hex key");
-   let key = Key::<Aes256Gcm>::from_slice(&key_bytes);
+   // Data written before the key was derived with PBKDF2 is re-encrypted with the current key when next changed
+   decrypt_with(machine_key(), data).or_else(|_| decrypt_with(previous_machine_key(), data))
+}
+
+/// Decrypt data written with the key formerly built into the binary.
+pub fn decrypt_legacy(data: &[u8]) -> Result<String, Box<dyn Error>>
+//------------------------------------------------------------------
+{
+   let key_bytes = hex::decode(LEGACY_KEY).expect("Invalid hex key");
+   decrypt_with(&key_bytes, data)
+}
+
+pub fn decrypt_with(key_bytes: &[u8], data: &[u8]) -> Result<String, Box<dyn Error>>
+//------------------------------------------------------------------------------
+{
+   let key = Key::<Aes256Gcm>::from_slice(key_bytes);
    const NONCE_LEN: usize = 12; // GCM nonce size
     
    if data.len() < NONCE_LEN 
@@ -58,4 +189,45 @@ pub fn get_file_age(path: &PathBuf) -> Result<Duration, Box<dyn Error>>
    let duration_since_modified = modified_time.elapsed()?;
    let chrono_duration = Duration::from_std(duration_since_modified)?;
    Ok(chrono_duration)
-}
\ No newline at end of file
+}
+/// Total size in bytes of a file or directory tree (0 if it doesn't exist).
+pub fn disk_usage(path: &std::path::Path) -> u64
+//-----------------------------------------------
+{
+   let Ok(metadata) = std::fs::symlink_metadata(path) else { return 0; };
+   if !metadata.is_dir()
+   {
+      return metadata.len();
+   }
+   std::fs::read_dir(path).map_or(0, |entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+}
+
+/// Play a short alert sound using whatever the platform provides, without blocking the caller.
+pub fn play_alert_sound()
+//-----------------------
+{
+   let command = if cfg!(target_os = "windows")
+   {
+      std::process::Command::new("powershell").args(["-NoProfile", "-Command", "[console]::beep(880,300)"]).spawn()
+   }
+   else if cfg!(target_os = "macos")
+   {
+      std::process::Command::new("afplay").arg("/System/Library/Sounds/Ping.aiff").spawn()
+   }
+   else
+   {
+      std::process::Command::new("canberra-gtk-play").args(["--id", "bell"]).spawn()
+   };
+   if let Err(e) = command
+   {
+      log_warn!("Could not play alert sound: {}", e);
+   }
+}
+
+/// `name` with characters that are not safe in file names on every platform replaced by underscores.
+pub fn safe_file_name(name: &str) -> String
+//-----------------------------------------
+{
+   let name: String = name.chars().map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '_' }).collect();
+   name.trim().to_string()
+}
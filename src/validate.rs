@@ -0,0 +1,134 @@
+use gpx::Gpx;
+
+use crate::gpx::{TrackPoint, track_data_from_gpx};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity
+{
+   Warning,
+   Error,
+}
+
+/// A problem found in a course file, optionally at a distance along the course.
+#[derive(Clone, Debug)]
+pub struct Issue
+{
+   pub severity: Severity,
+   pub distance: Option<f64>, // metres
+   pub message:  String,
+}
+
+impl Issue
+{
+   fn new(severity: Severity, distance: Option<f64>, message: impl Into<String>) -> Self
+   //----------------------------------------------------------------------------------
+   {
+      Self { severity, distance, message: message.into() }
+   }
+}
+
+/// Limits used when checking a course.
+#[derive(Clone, Copy, Debug)]
+pub struct ValidationLimits
+{
+   pub max_gap:   f64, // metres between consecutive points
+   pub max_spike: f64, // metres a single point may rise above or drop below both neighbours
+}
+
+impl Default for ValidationLimits
+{
+   fn default() -> Self { Self { max_gap: 200.0, max_spike: 20.0 } }
+}
+
+/// Check a parsed GPX file for structures GPXAssist does not read, missing elevation, gaps between points, single
+/// point elevation spikes and zero length segments.
+pub fn validate_gpx(gpx: &Gpx, limits: ValidationLimits) -> Vec<Issue>
+//--------------------------------------------------------------------
+{
+   let mut issues = Vec::new();
+   let segment_count: usize = gpx.tracks.iter().map(|t| t.segments.len()).sum();
+   if segment_count == 0
+   {
+      let message = if !gpx.routes.is_empty()
+      {
+         "The file contains a route (rte) but no track; only tracks are supported"
+      }
+      else
+      {
+         "The file contains no track"
+      };
+      issues.push(Issue::new(Severity::Error, None, message));
+      return issues;
+   }
+   if segment_count > 1
+   {
+      issues.push(Issue::new(Severity::Warning, None,
+                             format!("The file has {segment_count} track segments; only the first is used")));
+   }
+
+   let Some(segment) = gpx.tracks.iter().flat_map(|t| t.segments.iter()).next()
+   else
+   {
+      return issues;
+   };
+   let track: Vec<TrackPoint> = match track_data_from_gpx(gpx)
+   {
+      | Ok(track) => track,
+      | Err(e) =>
+      {
+         issues.push(Issue::new(Severity::Error, None, e.to_string()));
+         return issues;
+      }
+   };
+   if track.len() < 2
+   {
+      issues.push(Issue::new(Severity::Error, None, format!("The track has {} point(s)", track.len())));
+      return issues;
+   }
+
+   let missing_elevation = segment.points.iter().filter(|p| p.elevation.is_none()).count();
+   if missing_elevation == segment.points.len()
+   {
+      issues.push(Issue::new(Severity::Error, None, "The track has no elevation data"));
+   }
+   else if missing_elevation > 0
+   {
+      let first = segment.points.iter().position(|p| p.elevation.is_none()).map(|i| track[i].distance);
+      issues.push(Issue::new(Severity::Error, first,
+                             format!("{missing_elevation} point(s) have no elevation and are treated as 0 m")));
+   }
+
+   let mut zero_length = 0;
+   for pair in track.windows(2)
+   {
+      let length = pair[1].distance - pair[0].distance;
+      if length < 0.01
+      {
+         zero_length += 1;
+      }
+      else if length > limits.max_gap
+      {
+         issues.push(Issue::new(Severity::Error, Some(pair[0].distance), format!("Gap of {:.0} m between points", length)));
+      }
+   }
+   if zero_length > 0
+   {
+      issues.push(Issue::new(Severity::Warning, None, format!("{zero_length} zero length segment(s) (repeated points)")));
+   }
+
+   if missing_elevation < segment.points.len()
+   {
+      for (i, triple) in track.windows(3).enumerate()
+      {
+         let rise = triple[1].altitude - triple[0].altitude;
+         let fall = triple[1].altitude - triple[2].altitude;
+         let is_elevation_known = segment.points[i .. i + 3].iter().all(|p| p.elevation.is_some());
+         if is_elevation_known && rise.signum() == fall.signum() && rise.abs().min(fall.abs()) > limits.max_spike
+         {
+            issues.push(Issue::new(Severity::Error, Some(triple[1].distance),
+                                   format!("Elevation spike of {:.0} m at a single point", rise.abs().min(fall.abs()))));
+         }
+      }
+   }
+   issues
+}
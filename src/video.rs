@@ -0,0 +1,295 @@
+use std::{fs, io::Read, path::{Path, PathBuf}, process::{Command, Stdio}, sync::Arc, time::Duration};
+
+use crossbeam::atomic::AtomicCell;
+use eframe::egui::{self, ColorImage, Context, Image, TextureHandle, TextureOptions};
+
+use crate::{source::CancelToken, ui::Theme};
+
+/// Video files looked for beside a course with the same name.
+pub const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "mkv", "mov", "webm", "avi"];
+/// Decoded frames are scaled and letterboxed to this size.
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const FPS: f64 = 15.0;
+/// How far the wanted video time can move away from the decoder before ffmpeg is restarted at the new time, as
+/// decoding forward is faster than seeking for small gaps.
+const SEEK_AHEAD: f64 = 3.0; // seconds
+const SEEK_BACK: f64 = 1.0;  // seconds
+
+/// Distance to video time mapping for a ride video, read from a CSV file of `distance,time` rows (metres along the
+/// course and seconds or [h:]mm:ss into the video). A header row and blank lines are ignored.
+#[derive(Debug, Clone)]
+pub struct VideoSync
+{
+   points: Vec<(f64, f64)>, // (distance, time) in distance order
+}
+
+impl VideoSync
+{
+   /// The mapping file for `video`, e.g. ride.sync.csv for ride.mp4.
+   pub fn path_for(video: &Path) -> PathBuf { video.with_extension("sync.csv") }
+
+   pub fn load(path: &Path) -> Result<Self, String>
+   //----------------------------------------------
+   {
+      let text = fs::read_to_string(path).map_err(|e| format!("Error reading {}: {}", path.display(), e))?;
+      let mut points = Vec::new();
+      for (i, line) in text.lines().enumerate()
+      {
+         let line = line.trim();
+         if line.is_empty() || line.starts_with('#')
+         {
+            continue;
+         }
+         let mut columns = line.split([',', ';', '\t']).map(str::trim);
+         let (Some(distance), Some(time)) = (columns.next(), columns.next()) else
+         {
+            return Err(format!("{} line {}: expected distance,time", path.display(), i + 1));
+         };
+         match (distance.parse::<f64>(), parse_time(time))
+         {
+            | (Ok(distance), Some(time)) => points.push((distance, time)),
+            | _ if points.is_empty() => (), // header
+            | _ => return Err(format!("{} line {}: invalid distance or time", path.display(), i + 1)),
+         }
+      }
+      if points.len() < 2
+      {
+         return Err(format!("{} needs at least two distance,time rows", path.display()));
+      }
+      points.sort_by(|a, b| a.0.total_cmp(&b.0));
+      Ok(Self { points })
+   }
+
+   /// Video time in seconds at `distance` metres, interpolated between the mapping points and extrapolated at the
+   /// rate of the nearest pair beyond them.
+   pub fn time_at(&self, distance: f64) -> f64
+   //-----------------------------------------
+   {
+      let i = self.points.partition_point(|p| p.0 < distance).clamp(1, self.points.len() - 1);
+      let ((d0, t0), (d1, t1)) = (self.points[i - 1], self.points[i]);
+      let time = if d1 > d0 { t0 + (distance - d0) / (d1 - d0) * (t1 - t0) } else { t0 };
+      time.max(0.0)
+   }
+}
+
+/// Seconds from "123.4", "mm:ss" or "h:mm:ss(.f)".
+fn parse_time(text: &str) -> Option<f64>
+//--------------------------------------
+{
+   text.split(':').try_fold(0.0, |total, part| Some(total * 60.0 + part.parse::<f64>().ok()?))
+}
+
+/// A ride video beside `course` with the same name, e.g. alpe.mp4 for alpe.gpx.
+pub fn find_video(course: &Path) -> Option<PathBuf>
+//-------------------------------------------------
+{
+   VIDEO_EXTENSIONS.iter().map(|extension| course.with_extension(extension)).find(|path| path.is_file())
+}
+
+/// Plays a ride video in step with the rider distance using a `VideoSync` mapping. Frames are decoded by an ffmpeg
+/// process on a background thread, which only decodes ahead as far as the rider has reached, so the video plays at
+/// the rider's pace and pauses when they stop.
+#[derive(Default)]
+pub struct VideoPlayer
+//=====================
+{
+   video:   Option<PathBuf>,
+   sync:    Option<VideoSync>,
+   error:   Arc<parking_lot::Mutex<Option<String>>>,
+   target:  Arc<AtomicCell<f64>>, // wanted video time in seconds
+   frame:   Arc<parking_lot::Mutex<Option<ColorImage>>>, // latest decoded frame not yet shown
+   texture: Option<TextureHandle>,
+   cancel:  Option<CancelToken>,
+}
+
+impl VideoPlayer
+{
+   /// Use the video found beside `course`, if any, stopping the previous one.
+   pub fn open_for_course(&mut self, course: &Path)
+   //----------------------------------------------
+   {
+      self.close();
+      if let Some(video) = find_video(course)
+      {
+         self.open(&video);
+      }
+   }
+
+   /// Play `video` with the mapping from its sync file.
+   pub fn open(&mut self, video: &Path)
+   //----------------------------------
+   {
+      self.close();
+      self.video = Some(video.to_path_buf());
+      match VideoSync::load(&VideoSync::path_for(video))
+      {
+         | Ok(sync) => self.sync = Some(sync),
+         | Err(e) => *self.error.lock() = Some(e),
+      }
+   }
+
+   fn close(&mut self)
+   //-----------------
+   {
+      if let Some(cancel) = self.cancel.take()
+      {
+         cancel.cancel();
+      }
+      self.video = None;
+      self.sync = None;
+      self.texture = None;
+      *self.error.lock() = None;
+      *self.frame.lock() = None;
+   }
+
+   /// Show the frame for `distance` metres along the course, or how to add a video when there is none.
+   pub fn show(&mut self, ctx: &Context, ui: &mut egui::Ui, distance: f64, theme: &Theme)
+   //------------------------------------------------------------------------------------
+   {
+      let error = self.error.lock().clone();
+      if error.is_some() || self.video.is_none() || self.sync.is_none()
+      {
+         self.show_instructions(ui, error.as_deref(), theme);
+         return;
+      }
+      let (Some(video), Some(sync)) = (&self.video, &self.sync) else { return; };
+      self.target.store(sync.time_at(distance));
+      if self.cancel.is_none()
+      {
+         let cancel = CancelToken::default();
+         let (video, target, frame, error, ctx, thread_cancel) =
+            (video.clone(), self.target.clone(), self.frame.clone(), self.error.clone(), ctx.clone(), cancel.clone());
+         std::thread::spawn(move || decode(&video, &target, &frame, &error, &ctx, &thread_cancel));
+         self.cancel = Some(cancel);
+      }
+      if let Some(image) = self.frame.lock().take()
+      {
+         match &mut self.texture
+         {
+            | Some(texture) => texture.set(image, TextureOptions::LINEAR),
+            | None => self.texture = Some(ctx.load_texture("ride_video", image, TextureOptions::LINEAR)),
+         }
+      }
+      ui.centered_and_justified(|ui|
+      {
+         match &self.texture
+         {
+            | Some(texture) => { ui.add(Image::new(texture).fit_to_exact_size(ui.available_size()).shrink_to_fit()); }
+            | None => { ui.spinner(); }
+         }
+      });
+   }
+
+   fn show_instructions(&mut self, ui: &mut egui::Ui, error: Option<&str>, theme: &Theme)
+   //-------------------------------------------------------------------------------------
+   {
+      ui.vertical_centered(|ui|
+      {
+         ui.add_space(40.0);
+         if let Some(error) = error
+         {
+            ui.label(egui::RichText::new(error).color(egui::Color32::RED).strong());
+            ui.add_space(10.0);
+         }
+         else
+         {
+            ui.label(egui::RichText::new("No ride video for this course.").color(theme.label_color).strong());
+         }
+         ui.label("A video beside the course with the same name (e.g. alpe.mp4 for alpe.gpx) is opened automatically. It \
+                   is kept in step with the ride using a sync file beside the video (alpe.sync.csv) with a distance in \
+                   metres and a video time (seconds or mm:ss) on each line. ffmpeg must be installed.");
+         ui.add_space(10.0);
+         if ui.button("Open video…").clicked()
+            && let Some(path) = rfd::FileDialog::new().add_filter("Video", &VIDEO_EXTENSIONS).pick_file()
+         {
+            self.open(&path);
+         }
+      });
+   }
+}
+
+impl Drop for VideoPlayer
+{
+   fn drop(&mut self) { self.close(); }
+}
+
+/// Start ffmpeg decoding `video` from `start` seconds as raw RGBA frames of WIDTH x HEIGHT at FPS.
+fn spawn_ffmpeg(video: &Path, start: f64) -> Result<std::process::Child, String>
+//------------------------------------------------------------------------------
+{
+   let filter = format!("fps={FPS},scale={WIDTH}:{HEIGHT}:force_original_aspect_ratio=decrease,\
+                         pad={WIDTH}:{HEIGHT}:(ow-iw)/2:(oh-ih)/2");
+   Command::new("ffmpeg").args(["-loglevel", "error", "-ss", &format!("{start:.3}"), "-i"])
+                         .arg(video)
+                         .args(["-an", "-vf", &filter, "-pix_fmt", "rgba", "-f", "rawvideo", "-"])
+                         .stdin(Stdio::null())
+                         .stdout(Stdio::piped())
+                         .stderr(Stdio::null())
+                         .spawn()
+                         .map_err(|e| format!("Could not run ffmpeg, is it installed and on the PATH? ({e})"))
+}
+
+/// Background thread following the wanted video time: frames are read from ffmpeg up to the wanted time (older ones
+/// are skipped) and ffmpeg is restarted when the wanted time jumps too far away.
+fn decode(video: &Path, target: &AtomicCell<f64>, frame: &parking_lot::Mutex<Option<ColorImage>>,
+          error: &parking_lot::Mutex<Option<String>>, ctx: &Context, cancel: &CancelToken)
+//-------------------------------------------------------------------------------------------------------------
+{
+   let mut buffer = vec![0u8; WIDTH * HEIGHT * 4];
+   while !cancel.is_cancelled()
+   {
+      let start = target.load();
+      let mut child = match spawn_ffmpeg(video, start)
+      {
+         | Ok(child) => child,
+         | Err(e) =>
+         {
+            *error.lock() = Some(e);
+            ctx.request_repaint();
+            return;
+         }
+      };
+      let Some(mut stdout) = child.stdout.take() else { return; };
+      let mut index = 0u64;
+      while !cancel.is_cancelled()
+      {
+         let frame_time = start + index as f64 / FPS;
+         let wanted = target.load();
+         if wanted < frame_time - SEEK_BACK || wanted > frame_time + SEEK_AHEAD
+         {
+            break;
+         }
+         if frame_time > wanted
+         {  // Ahead of the rider
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+         }
+         if stdout.read_exact(&mut buffer).is_err()
+         {
+            if index == 0 && start < 1.0
+            {
+               *error.lock() = Some(format!("ffmpeg could not decode {}", video.display()));
+               ctx.request_repaint();
+               let _ = child.kill();
+               let _ = child.wait();
+               return;
+            }
+            // End of the video: hold the last frame until the rider moves back into it
+            while !cancel.is_cancelled() && target.load() >= frame_time - SEEK_BACK
+            {
+               std::thread::sleep(Duration::from_millis(100));
+            }
+            break;
+         }
+         index += 1;
+         if frame_time + 1.0 / FPS >= wanted
+         {
+            *frame.lock() = Some(ColorImage::from_rgba_unmultiplied([WIDTH, HEIGHT], &buffer));
+            ctx.request_repaint();
+         }
+      }
+      let _ = child.kill();
+      let _ = child.wait();
+   }
+}
@@ -0,0 +1,290 @@
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+use eframe::egui;
+
+use crate::{data::RiderData, gpx::TrackPoint, http, settings::WeatherSettings, source::CancelToken, ui::Theme, units::Units};
+
+const OPEN_METEO_URL: &str = "https://api.open-meteo.com/v1/forecast";
+/// Distance moved along the course after which the weather is fetched again without waiting for the refresh interval.
+const REFETCH_DISTANCE: f64 = 10000.0; // metres
+/// Broadcast wind below this is shown as calm.
+const CALM_WIND: f64 = 2.0; // km/h
+const DIAL_SIZE: f32 = 44.0;
+
+/// Current weather at a point on the course.
+#[derive(Debug, Clone, Copy)]
+pub struct WeatherReport
+{
+   pub temperature:    f64, // °C
+   pub wind_speed:     f64, // km/h at 10m
+   pub wind_direction: f64, // degrees the wind blows from
+   pub precipitation:  f64, // mm in the last hour
+}
+
+/// Query Open-Meteo (no API key needed) for the current weather at `latitude`, `longitude`.
+pub async fn fetch_weather(latitude: f64, longitude: f64) -> Result<WeatherReport, String>
+//----------------------------------------------------------------------------------------
+{
+   let url = format!("{OPEN_METEO_URL}?latitude={latitude:.4}&longitude={longitude:.4}\
+                      &current=temperature_2m,precipitation,wind_speed_10m,wind_direction_10m&wind_speed_unit=kmh");
+   let response = http::client()?.get(&url)
+                                 .timeout(Duration::from_secs(10))
+                                 .send()
+                                 .await
+                                 .map_err(|e| format!("Failed to query the weather: {}", e))?;
+   if !response.status().is_success()
+   {
+      return Err(format!("HTTP error {} querying the weather", response.status()));
+   }
+   let text = response.text().await.map_err(|e| format!("Failed to read weather response: {}", e))?;
+   let json: serde_json::Value = serde_json::from_str(&text).map_err(|e| format!("Failed to parse weather response: {}", e))?;
+   let current = &json["current"];
+   let value = |name: &str| current[name].as_f64().ok_or_else(|| format!("Weather response has no {name}"));
+   Ok(WeatherReport
+   {
+      temperature: value("temperature_2m")?,
+      wind_speed: value("wind_speed_10m")?,
+      wind_direction: value("wind_direction_10m")?,
+      precipitation: value("precipitation").unwrap_or(0.0),
+   })
+}
+
+/// Eight point compass name for a bearing in degrees.
+fn compass_point(degrees: f64) -> &'static str
+//--------------------------------------------
+{
+   const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+   POINTS[((degrees.rem_euclid(360.0) + 22.5) / 45.0) as usize % 8]
+}
+
+/// The in-game wind relative to the direction of travel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindKind
+{
+   Calm,
+   Head,
+   Tail,
+   CrossLeft, // blowing from the left
+   CrossRight,
+}
+
+impl WindKind
+{
+   pub fn label(self) -> &'static str
+   //--------------------------------
+   {
+      match self
+      {
+         | WindKind::Calm => "Calm",
+         | WindKind::Head => "Headwind",
+         | WindKind::Tail => "Tailwind",
+         | WindKind::CrossLeft => "Crosswind from left",
+         | WindKind::CrossRight => "Crosswind from right",
+      }
+   }
+}
+
+/// The broadcast wind as (speed km/h, bearing in degrees the wind blows towards relative to `heading`, so 0 is a
+/// tailwind and 180 a headwind, classification).
+pub fn relative_wind(rider: &RiderData, heading: f64) -> (f64, f64, WindKind)
+//----------------------------------------------------------------------------
+{
+   let speed = rider.wind_speed_kmh().abs();
+   // The map wind arrow points the way the wind blows (360 - wind_angle)
+   let relative = (360.0 - rider.wind_angle as f64 - heading).rem_euclid(360.0);
+   let kind = if speed < CALM_WIND { WindKind::Calm }
+              else if relative <= 45.0 || relative >= 315.0 { WindKind::Tail }
+              else if (135.0..=225.0).contains(&relative) { WindKind::Head }
+              else if relative < 180.0 { WindKind::CrossLeft } // blowing towards the right
+              else { WindKind::CrossRight };
+   (speed, relative, kind)
+}
+
+/// Keeps the real weather at the rider position up to date in the background for the status bar weather widget.
+#[derive(Default)]
+pub struct WeatherMonitor
+//========================
+{
+   config:      WeatherSettings,
+   report:      Arc<parking_lot::Mutex<Option<WeatherReport>>>,
+   is_fetching: Arc<AtomicBool>,
+   requested:   Option<(Instant, f64)>, // time and course distance of the last request
+   cancel:      Option<CancelToken>, // of the request in progress
+}
+
+impl WeatherMonitor
+{
+   pub fn new(config: WeatherSettings) -> Self { Self { config, ..Default::default() } }
+
+   pub fn configure(&mut self, config: WeatherSettings)
+   //--------------------------------------------------
+   {
+      if config.is_enabled && !self.config.is_enabled
+      {
+         self.requested = None;
+      }
+      self.config = config;
+   }
+
+   /// Forget the weather for the previous course.
+   pub fn reset(&mut self)
+   //---------------------
+   {
+      if let Some(cancel) = self.cancel.take()
+      {  // The report would be for the previous course
+         cancel.cancel();
+      }
+      *self.report.lock() = None;
+      self.requested = None;
+   }
+
+   /// Fetch the weather for `position` when enabled and the last report is older than the refresh interval or was for
+   /// a point more than REFETCH_DISTANCE back along the course.
+   pub fn update(&mut self, ctx: &egui::Context, position: Option<&TrackPoint>)
+   //--------------------------------------------------------------------------
+   {
+      let Some(position) = position else { return; };
+      if !self.config.is_enabled || self.is_fetching.load(Ordering::Relaxed)
+      {
+         return;
+      }
+      let is_due = self.requested.is_none_or(|(time, distance)| time.elapsed() >= self.config.refresh_interval()
+                                                                 || (position.distance - distance).abs() >= REFETCH_DISTANCE);
+      if !is_due
+      {
+         return;
+      }
+      self.requested = Some((Instant::now(), position.distance));
+      self.is_fetching.store(true, Ordering::Relaxed);
+      let (report, is_fetching, ctx) = (self.report.clone(), self.is_fetching.clone(), ctx.clone());
+      let (latitude, longitude) = (position.point.lat, position.point.lon);
+      let cancel = CancelToken::default();
+      self.cancel = Some(cancel.clone());
+      http::spawn(async move
+      {
+         match http::cancellable(&cancel, fetch_weather(latitude, longitude)).await
+         {
+            | Ok(weather) => *report.lock() = Some(weather),
+            | Err(_) if cancel.is_cancelled() => (),
+            | Err(e) => log_warn!("{e}"),
+         }
+         is_fetching.store(false, Ordering::Relaxed);
+         ctx.request_repaint();
+      });
+   }
+
+   /// Show the temperature, wind and precipitation, followed by the in-game wind from the broadcast when comparing.
+   pub fn show(&self, ui: &mut egui::Ui, rider: &RiderData, theme: &Theme, units: Units)
+   //-----------------------------------------------------------------------------------
+   {
+      if !self.config.is_enabled
+      {
+         return;
+      }
+      let Some(weather) = *self.report.lock() else { return; };
+      let temperature = match units
+      {
+         | Units::Metric => format!("{:.0}°C", weather.temperature),
+         | Units::Imperial => format!("{:.0}°F", weather.temperature * 9.0 / 5.0 + 32.0),
+      };
+      let mut text = format!("{temperature}, wind {} {}", units.format_speed(weather.wind_speed), compass_point(weather.wind_direction));
+      if weather.precipitation > 0.0
+      {
+         text += &format!(", rain {:.1} mm", weather.precipitation);
+      }
+      ui.separator();
+      ui.label(egui::RichText::new("Weather:").color(theme.label_color).size(16.0));
+      ui.label(egui::RichText::new(text).strong().size(16.0))
+        .on_hover_text("Current weather at the rider position from Open-Meteo");
+      if self.config.is_wind_compared
+      {
+         let game_speed = rider.wind_speed as f64 * 0.0036; // mm/s to km/h
+         // The map wind arrow points the way the wind blows (360 - wind_angle), so it blows from the opposite bearing
+         let game_direction = 540.0 - rider.wind_angle as f64;
+         ui.separator();
+         ui.label(egui::RichText::new("Game wind:").color(theme.label_color).size(16.0));
+         ui.label(egui::RichText::new(format!("{} {}", units.format_speed(game_speed), compass_point(game_direction))).strong().size(16.0))
+           .on_hover_text(format!("Wind in the game from the broadcast, {} than the real wind",
+                                  if game_speed > weather.wind_speed { "stronger" } else { "weaker" }));
+      }
+   }
+   /// Compact panel in the top right corner of the view `rect` with the in-game wind relative to the direction of
+   /// travel (`heading`), and the real weather when enabled. Shown in every view mode.
+   pub fn show_panel(&self, ctx: &egui::Context, rect: egui::Rect, rider: &RiderData, heading: f64, theme: &Theme, units: Units)
+   //---------------------------------------------------------------------------------------------------------------------------
+   {
+      if !self.config.is_panel_shown
+      {
+         return;
+      }
+      let (speed, relative, kind) = relative_wind(rider, heading);
+      let weather = if self.config.is_enabled { *self.report.lock() } else { None };
+      egui::Area::new(egui::Id::new("weather_panel"))
+         .order(egui::Order::Foreground)
+         .pivot(egui::Align2::RIGHT_TOP)
+         .fixed_pos(rect.right_top() + egui::vec2(-12.0, 12.0))
+         .interactable(false)
+         .show(ctx, |ui|
+         {
+            egui::Frame::new().fill(egui::Color32::from_black_alpha(160)).corner_radius(8.0).inner_margin(8.0).show(ui, |ui|
+            {
+               ui.horizontal(|ui|
+               {
+                  let (dial, painter) = ui.allocate_painter(egui::vec2(DIAL_SIZE, DIAL_SIZE), egui::Sense::hover());
+                  draw_wind_dial(&painter, dial.rect, relative, kind);
+                  ui.vertical(|ui|
+                  {
+                     let color = match kind
+                     {
+                        | WindKind::Head => theme.toast_warning,
+                        | WindKind::Tail => theme.toast_success,
+                        | _ => egui::Color32::WHITE,
+                     };
+                     ui.label(egui::RichText::new(kind.label()).color(color).strong().size(16.0));
+                     if kind != WindKind::Calm
+                     {
+                        ui.label(egui::RichText::new(units.format_speed(speed)).color(egui::Color32::WHITE).size(14.0));
+                     }
+                     if let Some(weather) = weather
+                     {
+                        let mut text = match units
+                        {
+                           | Units::Metric => format!("{:.0}°C", weather.temperature),
+                           | Units::Imperial => format!("{:.0}°F", weather.temperature * 9.0 / 5.0 + 32.0),
+                        };
+                        if weather.precipitation > 0.0
+                        {
+                           text += &format!(", rain {:.1} mm", weather.precipitation);
+                        }
+                        ui.label(egui::RichText::new(text).color(egui::Color32::LIGHT_GRAY).size(14.0));
+                     }
+                  });
+               });
+            });
+         });
+   }
+}
+
+/// Dial with the rider direction up and an arrow the way the wind blows (`relative` degrees from the direction of travel).
+fn draw_wind_dial(painter: &egui::Painter, rect: egui::Rect, relative: f64, kind: WindKind)
+//----------------------------------------------------------------------------------------
+{
+   let (center, radius) = (rect.center(), rect.width() / 2.0 - 2.0);
+   painter.circle_stroke(center, radius, egui::Stroke::new(1.5, egui::Color32::GRAY));
+   // Direction of travel
+   painter.add(egui::Shape::convex_polygon(vec![center + egui::vec2(0.0, -radius), center + egui::vec2(-4.0, -radius + 7.0),
+                                                center + egui::vec2(4.0, -radius + 7.0)],
+                                           egui::Color32::from_rgb(255, 100, 100), egui::Stroke::NONE));
+   if kind == WindKind::Calm
+   {
+      return;
+   }
+   let angle = (relative as f32).to_radians();
+   let direction = egui::vec2(angle.sin(), -angle.cos());
+   let (tail, tip) = (center - direction * (radius - 6.0), center + direction * (radius - 6.0));
+   let stroke = egui::Stroke::new(2.5, egui::Color32::from_rgb(100, 180, 255));
+   painter.line_segment([tail, tip], stroke);
+   let normal = egui::vec2(-direction.y, direction.x);
+   painter.line_segment([tip, tip - direction * 7.0 + normal * 5.0], stroke);
+   painter.line_segment([tip, tip - direction * 7.0 - normal * 5.0], stroke);
+}